@@ -0,0 +1,52 @@
+//! How fast hashes are compared, and how fast many images are grouped by them.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+
+use img_dup::config::parse_args;
+use img_dup::img::Image;
+use img_dup::processing;
+use img_dup::synthetic::{self, Lcg};
+
+use std::hint::black_box;
+use std::path::PathBuf;
+
+fn distance(c: &mut Criterion) {
+    let mut rng = Lcg::new(0);
+
+    let mut group = c.benchmark_group("distance");
+
+    for hash_size in [8, 16, 32] {
+        let (left, right) = (synthetic::hash(&mut rng, hash_size), synthetic::hash(&mut rng, hash_size));
+
+        group.bench_function(BenchmarkId::from_parameter(hash_size), |b| {
+            b.iter(|| black_box(&left).dist(black_box(&right)))
+        });
+    }
+
+    group.finish();
+}
+
+fn collation(c: &mut Criterion) {
+    let settings = parse_args(&[]);
+
+    let mut group = c.benchmark_group("collation");
+    group.sample_size(10);
+
+    for count in [10_000, 100_000] {
+        let mut rng = Lcg::new(count as u64);
+
+        let images: Vec<Image> = (0..count)
+            .map(|i| Image::new(PathBuf::from(format!("{}.png", i)), synthetic::hash(&mut rng, settings.hash_size), 0, 0, 0))
+            .collect();
+
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter_batched(|| images.clone(), |images| processing::group_images(images, &settings), BatchSize::LargeInput)
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, distance, collation);
+criterion_main!(benches);
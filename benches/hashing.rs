@@ -0,0 +1,30 @@
+//! How fast images are hashed, with each hash type and size.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use img_dup::hash::ImageHash;
+use img_dup::synthetic;
+
+use image::DynamicImage;
+
+use std::hint::black_box;
+
+fn hashing(c: &mut Criterion) {
+    let img = DynamicImage::ImageRgb8(synthetic::image(1, 1024, 768));
+
+    let mut group = c.benchmark_group("hash");
+    group.throughput(Throughput::Elements(1));
+
+    for (name, fast) in [("dct", false), ("fast", true)] {
+        for hash_size in [8, 16, 32] {
+            group.bench_with_input(BenchmarkId::new(name, hash_size), &hash_size, |b, &hash_size| {
+                b.iter(|| ImageHash::hash(black_box(&img), hash_size, fast))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, hashing);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+//! Find similar images with a custom hash: the mean of each block of a 10x10 grid,
+//! compared with the mean of the whole image.
+//!
+//! `cargo run --example block_mean -- [options]`, with the same options as `img_dup`.
+
+use img_dup::config::parse_args;
+use img_dup::hash::PerceptualHasher;
+use img_dup::{processing, search};
+
+use bit_vec::BitVec;
+
+use image::DynamicImage;
+use image::imageops::FilterType;
+
+use std::env;
+
+struct BlockMean;
+
+impl PerceptualHasher for BlockMean {
+    fn hash(&self, img: &DynamicImage) -> BitVec {
+        let blocks = img.resize_exact(10, 10, FilterType::Triangle).to_luma8();
+        let mean = blocks.pixels().map(|pixel| pixel.0[0] as u32).sum::<u32>() / 100;
+
+        blocks.pixels().map(|pixel| pixel.0[0] as u32 >= mean).collect()
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let mut settings = parse_args(&args);
+    settings.set_hasher(BlockMean);
+
+    let results = processing::process(&settings, search::find_images(&settings));
+
+    for unique in results.groups(&settings) {
+        println!("{}", unique.img.path.display());
+
+        for similar in unique.similars() {
+            println!("    {} ({:.1}%)", similar.img.path.display(), similar.similarity());
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Find similar images with the hashing workers running on a rayon thread pool the
+//! application already has, instead of threads of their own.
+//!
+//! `cargo run --example shared_pool -- [options]`, with the same options as `img_dup`.
+//! `--threads` is how many workers are started on the pool.
+
+use img_dup::config::parse_args;
+use img_dup::{processing, search};
+
+use rayon::ThreadPoolBuilder;
+
+use std::env;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let mut settings = parse_args(&args);
+
+    // Shared with the rest of the application, so img_dup can't use more than this many cores
+    let pool = ThreadPoolBuilder::new().num_threads(settings.threads).build().unwrap();
+    settings.set_spawner(move |task| pool.spawn(task));
+
+    let results = processing::process(&settings, search::find_images(&settings));
+
+    for unique in results.groups(&settings) {
+        println!("{}", unique.img.path.display());
+
+        for similar in unique.similars() {
+            println!("    {} ({:.1}%)", similar.img.path.display(), similar.similarity());
+        }
+    }
+}
@@ -0,0 +1,49 @@
+/*
+ * Print the groups of similar images in a directory:
+ *
+ *     cargo build --release -p img-dup-ffi
+ *     cc examples/scan.c -Iinclude -L../target/release -limg_dup_ffi -o scan
+ *     LD_LIBRARY_PATH=../target/release ./scan ~/Pictures -r
+ *
+ * The arguments are passed on to img_dup_settings_new(), so any option works.
+ */
+
+#include <stdio.h>
+
+#include "img_dup.h"
+
+int main(int argc, char **argv) {
+    ImgDupSettings *settings = img_dup_settings_new((const char *const *) argv + 1, argc - 1);
+
+    if (!settings) {
+        fprintf(stderr, "Invalid arguments: %s\n", img_dup_last_error());
+        return 3;
+    }
+
+    ImgDupResults *results = img_dup_scan(settings);
+
+    if (!results) {
+        fprintf(stderr, "Scan failed: %s\n", img_dup_last_error());
+        img_dup_settings_free(settings);
+        return 2;
+    }
+
+    for (size_t group = 0; group < img_dup_results_group_count(results); group++) {
+        printf("%s\n", img_dup_results_path(results, group, 0));
+
+        for (size_t i = 1; i < img_dup_results_group_len(results, group); i++) {
+            printf("    %.2f%% %s\n", img_dup_results_similarity(results, group, i),
+                   img_dup_results_path(results, group, i));
+        }
+    }
+
+    for (size_t i = 0; i < img_dup_results_error_count(results); i++) {
+        fprintf(stderr, "%s: %s\n", img_dup_results_error_path(results, i),
+                img_dup_results_error_message(results, i));
+    }
+
+    img_dup_results_free(results);
+    img_dup_settings_free(settings);
+
+    return 0;
+}
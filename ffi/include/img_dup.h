@@ -0,0 +1,123 @@
+#ifndef IMG_DUP_H
+#define IMG_DUP_H
+
+/* Generated by cbindgen from src/lib.rs, don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The groups of similar images and the errors from a scan or query.
+typedef struct ImgDupResults ImgDupResults;
+
+// Settings parsed from command line arguments.
+typedef struct ImgDupSettings ImgDupSettings;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Parse `len` arguments, the same as the command line takes but without the program name,
+// e.g. `{"--dir", "/home/me/Pictures", "-r"}`. `args` may be `NULL` if `len` is 0.
+// Returns `NULL` if the arguments are invalid.
+//
+// Like the command line, `--help` prints the usage and exits the process.
+//
+// # Safety
+//
+// `args` must point to `len` valid, NUL-terminated strings.
+struct ImgDupSettings *img_dup_settings_new(const char *const *args, size_t len);
+
+// # Safety
+//
+// `settings` must come from `img_dup_settings_new()` and not have been freed, or be `NULL`.
+void img_dup_settings_free(struct ImgDupSettings *settings);
+
+// Search for images with `settings` and group the similar ones, like running `img_dup`
+// with the arguments `settings` was made from. Blocks until it's done.
+//
+// # Safety
+//
+// `settings` must be a live handle from `img_dup_settings_new()`.
+struct ImgDupResults *img_dup_scan(const struct ImgDupSettings *settings);
+
+// Find the images similar to the one at `image` in `against`, which is either a directory
+// to search with `settings` or a hash database made with `--cache` or `update`.
+// The results have a single group: `image`, followed by its matches, closest first.
+//
+// # Safety
+//
+// `settings` must be a live handle from `img_dup_settings_new()`, and `image` and `against`
+// valid, NUL-terminated strings.
+struct ImgDupResults *img_dup_query(const struct ImgDupSettings *settings,
+                                    const char *image,
+                                    const char *against);
+
+// # Safety
+//
+// `results` must come from `img_dup_scan()` or `img_dup_query()` and not have been freed,
+// or be `NULL`.
+void img_dup_results_free(struct ImgDupResults *results);
+
+// How many groups there are, after `--dup-only`, `--min-group-size` and `--max-groups`.
+//
+// # Safety
+//
+// `results` must be a live handle.
+size_t img_dup_results_group_count(const struct ImgDupResults *results);
+
+// How many images are in `group`: its original, then the images similar to it.
+// 0 if there's no such group.
+//
+// # Safety
+//
+// `results` must be a live handle.
+size_t img_dup_results_group_len(const struct ImgDupResults *results, size_t group);
+
+// The full path of image `index` in `group`, or `NULL` if there's no such image.
+//
+// # Safety
+//
+// `results` must be a live handle.
+const char *img_dup_results_path(const struct ImgDupResults *results, size_t group, size_t index);
+
+// How similar image `index` in `group` is to the group's original, in percent: 100 for
+// the original itself. Negative if there's no such image.
+//
+// # Safety
+//
+// `results` must be a live handle.
+double img_dup_results_similarity(const struct ImgDupResults *results, size_t group, size_t index);
+
+// How many files couldn't be processed.
+//
+// # Safety
+//
+// `results` must be a live handle.
+size_t img_dup_results_error_count(const struct ImgDupResults *results);
+
+// The full path of the file of error `index`, or `NULL` if there's no such error.
+//
+// # Safety
+//
+// `results` must be a live handle.
+const char *img_dup_results_error_path(const struct ImgDupResults *results, size_t index);
+
+// Why the file of error `index` couldn't be processed, or `NULL` if there's no such error.
+//
+// # Safety
+//
+// `results` must be a live handle.
+const char *img_dup_results_error_message(const struct ImgDupResults *results, size_t index);
+
+// Why the last call on this thread that returned `NULL` failed, or `NULL` if none has.
+// Valid until the next failing call on this thread.
+const char *img_dup_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* IMG_DUP_H */
@@ -0,0 +1,296 @@
+//! A C API for `img_dup`, so other languages can embed it. See `include/img_dup.h`.
+//!
+//! Everything is behind opaque handles. Each handle returned by a `_new`, `img_dup_scan()`
+//! or `img_dup_query()` call must be passed to its `_free` function exactly once. Strings
+//! returned by accessors belong to the handle they came from, and live as long as it does.
+//!
+//! Functions that can fail return `NULL`, and `img_dup_last_error()` explains why.
+
+use img_dup::config::{parse_args, ProgramSettings};
+use img_dup::db::HashDb;
+use img_dup::processing::{self, Results};
+use img_dup::query;
+use img_dup::search;
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::slice;
+
+/// Settings parsed from command line arguments.
+pub struct ImgDupSettings {
+    settings: ProgramSettings,
+}
+
+/// The groups of similar images and the errors from a scan or query.
+pub struct ImgDupResults {
+    groups: Vec<Vec<ResultImage>>,
+    errors: Vec<ResultError>,
+}
+
+struct ResultImage {
+    path: CString,
+    similarity: f64,
+}
+
+struct ResultError {
+    path: CString,
+    message: CString,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Parse `len` arguments, the same as the command line takes but without the program name,
+/// e.g. `{"--dir", "/home/me/Pictures", "-r"}`. `args` may be `NULL` if `len` is 0.
+/// Returns `NULL` if the arguments are invalid.
+///
+/// Like the command line, `--help` prints the usage and exits the process.
+///
+/// # Safety
+///
+/// `args` must point to `len` valid, NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_settings_new(args: *const *const c_char, len: usize) -> *mut ImgDupSettings {
+    let args: Vec<String> = if len == 0 {
+        Vec::new()
+    } else {
+        if args.is_null() { return fail("args is NULL"); }
+
+        slice::from_raw_parts(args, len).iter()
+            .map(|&arg| CStr::from_ptr(arg).to_string_lossy().into_owned())
+            .collect()
+    };
+
+    catch(|| ImgDupSettings { settings: parse_args(&args) })
+        .map_or(ptr::null_mut(), |settings| Box::into_raw(Box::new(settings)))
+}
+
+/// # Safety
+///
+/// `settings` must come from `img_dup_settings_new()` and not have been freed, or be `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_settings_free(settings: *mut ImgDupSettings) {
+    if !settings.is_null() {
+        drop(Box::from_raw(settings));
+    }
+}
+
+/// Search for images with `settings` and group the similar ones, like running `img_dup`
+/// with the arguments `settings` was made from. Blocks until it's done.
+///
+/// # Safety
+///
+/// `settings` must be a live handle from `img_dup_settings_new()`.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_scan(settings: *const ImgDupSettings) -> *mut ImgDupResults {
+    let Some(settings) = settings.as_ref() else { return fail("settings is NULL") };
+    let settings = &settings.settings;
+
+    into_handle(settings, catch(|| {
+        let paths = search::find_images(settings);
+        processing::process(settings, paths)
+    }))
+}
+
+/// Find the images similar to the one at `image` in `against`, which is either a directory
+/// to search with `settings` or a hash database made with `--cache` or `update`.
+/// The results have a single group: `image`, followed by its matches, closest first.
+///
+/// # Safety
+///
+/// `settings` must be a live handle from `img_dup_settings_new()`, and `image` and `against`
+/// valid, NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_query(settings: *const ImgDupSettings, image: *const c_char,
+                                       against: *const c_char) -> *mut ImgDupResults {
+    let Some(settings) = settings.as_ref() else { return fail("settings is NULL") };
+    let settings = &settings.settings;
+
+    if image.is_null() || against.is_null() { return fail("image or against is NULL"); }
+
+    let image = path_arg(image);
+    let against = path_arg(against);
+
+    into_handle(settings, catch(|| {
+        let (haystack, errors) = if against.is_dir() {
+            let paths = search::find_images_in(settings, &against).0;
+            processing::hash_all(settings, paths)
+        } else {
+            let db = HashDb::load(&against)
+                .unwrap_or_else(|err| panic!("Could not load {}: {}", against.display(), err));
+            (db.images(&settings.hash_settings()), Vec::new())
+        };
+
+        query::query(settings, image, haystack, errors)
+    }))
+}
+
+/// # Safety
+///
+/// `results` must come from `img_dup_scan()` or `img_dup_query()` and not have been freed,
+/// or be `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_results_free(results: *mut ImgDupResults) {
+    if !results.is_null() {
+        drop(Box::from_raw(results));
+    }
+}
+
+/// How many groups there are, after `--dup-only`, `--min-group-size` and `--max-groups`.
+///
+/// # Safety
+///
+/// `results` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_results_group_count(results: *const ImgDupResults) -> usize {
+    borrow(results).groups.len()
+}
+
+/// How many images are in `group`: its original, then the images similar to it.
+/// 0 if there's no such group.
+///
+/// # Safety
+///
+/// `results` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_results_group_len(results: *const ImgDupResults, group: usize) -> usize {
+    borrow(results).groups.get(group).map_or(0, Vec::len)
+}
+
+/// The full path of image `index` in `group`, or `NULL` if there's no such image.
+///
+/// # Safety
+///
+/// `results` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_results_path(results: *const ImgDupResults, group: usize, index: usize)
+    -> *const c_char {
+    result_image(results, group, index).map_or(ptr::null(), |image| image.path.as_ptr())
+}
+
+/// How similar image `index` in `group` is to the group's original, in percent: 100 for
+/// the original itself. Negative if there's no such image.
+///
+/// # Safety
+///
+/// `results` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_results_similarity(results: *const ImgDupResults, group: usize, index: usize)
+    -> f64 {
+    result_image(results, group, index).map_or(-1.0, |image| image.similarity)
+}
+
+/// How many files couldn't be processed.
+///
+/// # Safety
+///
+/// `results` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_results_error_count(results: *const ImgDupResults) -> usize {
+    borrow(results).errors.len()
+}
+
+/// The full path of the file of error `index`, or `NULL` if there's no such error.
+///
+/// # Safety
+///
+/// `results` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_results_error_path(results: *const ImgDupResults, index: usize)
+    -> *const c_char {
+    borrow(results).errors.get(index).map_or(ptr::null(), |error| error.path.as_ptr())
+}
+
+/// Why the file of error `index` couldn't be processed, or `NULL` if there's no such error.
+///
+/// # Safety
+///
+/// `results` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn img_dup_results_error_message(results: *const ImgDupResults, index: usize)
+    -> *const c_char {
+    borrow(results).errors.get(index).map_or(ptr::null(), |error| error.message.as_ptr())
+}
+
+/// Why the last call on this thread that returned `NULL` failed, or `NULL` if none has.
+/// Valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn img_dup_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |msg| msg.as_ptr()))
+}
+
+unsafe fn result_image<'a>(results: *const ImgDupResults, group: usize, index: usize)
+    -> Option<&'a ResultImage> {
+    borrow(results).groups.get(group)?.get(index)
+}
+
+unsafe fn borrow<'a>(results: *const ImgDupResults) -> &'a ImgDupResults {
+    &*results
+}
+
+fn into_handle(settings: &ProgramSettings, results: Option<Results>) -> *mut ImgDupResults {
+    let Some(results) = results else { return ptr::null_mut() };
+
+    let groups = results.groups(settings).iter()
+        .map(|unique| {
+            let mut images = vec![ResultImage { path: c_path(&unique.img.path), similarity: 100.0 }];
+
+            images.extend(unique.similars().iter().map(|similar| ResultImage {
+                path: c_path(&similar.img.path),
+                similarity: similar.similarity() as f64,
+            }));
+
+            images
+        })
+        .collect();
+
+    let errors = results.errors.iter()
+        .map(|error| ResultError { path: c_path(error.path()), message: c_string(error.err_msg()) })
+        .collect();
+
+    Box::into_raw(Box::new(ImgDupResults { groups, errors }))
+}
+
+/// Run `f`, turning a panic into the last error: it must never unwind into C.
+fn catch<T, F: FnOnce() -> T>(f: F) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => Some(value),
+        Err(payload) => {
+            set_last_error(panic_message(&*payload));
+            None
+        },
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload.downcast_ref::<String>().cloned()
+        .or_else(|| payload.downcast_ref::<&str>().map(|msg| msg.to_string()))
+        .unwrap_or_else(|| "unknown error".to_string())
+}
+
+fn fail<T>(msg: &str) -> *mut T {
+    set_last_error(msg.to_string());
+    ptr::null_mut()
+}
+
+fn set_last_error(msg: String) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(c_string(msg)));
+}
+
+unsafe fn path_arg(path: *const c_char) -> PathBuf {
+    PathBuf::from(CStr::from_ptr(path).to_string_lossy().into_owned())
+}
+
+fn c_path(path: &Path) -> CString {
+    c_string(path.display().to_string())
+}
+
+// Paths and messages can't contain NUL on any platform img_dup runs on, but don't panic over it
+fn c_string(string: String) -> CString {
+    CString::new(string.replace('\0', "")).unwrap()
+}
@@ -0,0 +1,252 @@
+//! `pyimg_dup`: Python bindings for `img_dup`. See `README.md`.
+//!
+//! Options are passed as keyword arguments with the long names of the command line options,
+//! like the keys of a config file: `recurse=True`, `threshold=2.5`, `ext=["jpg", "png"]`.
+//! Results are the same dicts and lists as the JSON output, described in `JSON.md`.
+
+use img_dup::config::{parse_args, ProgramSettings};
+use img_dup::db::HashDb as Db;
+use img_dup::processing::{self, Results};
+use img_dup::query;
+use img_dup::search;
+use img_dup::serialize::{ErrorEntry, Report};
+
+use pyo3::exceptions::{PyIOError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+/// Search `dir` for images and group the similar ones, like running `img_dup --dir=[dir]`
+/// with `options`. Returns the same report as `--format=json`, with paths relative to `dir`.
+#[pyfunction]
+#[pyo3(signature = (dir, **options))]
+fn scan(py: Python<'_>, dir: PathBuf, options: Option<&Bound<'_, PyDict>>) -> PyResult<Py<PyAny>> {
+    let settings = settings(Some(&dir), options)?;
+
+    let results = py.detach(|| catch(|| {
+        let paths = search::find_images(&settings);
+        processing::process(&settings, paths)
+    }))?;
+
+    to_py(py, &Report::new(&settings, &results))
+}
+
+/// Find the images similar to the one at `image` in `haystack`, a `HashDb` or a directory
+/// to search with `options`. Returns them closest first, as the similars in the JSON output.
+#[pyfunction]
+#[pyo3(signature = (image, haystack, **options))]
+fn find_matches(py: Python<'_>, image: PathBuf, haystack: &Bound<'_, PyAny>, options: Option<&Bound<'_, PyDict>>)
+    -> PyResult<Py<PyAny>> {
+    let settings = settings(None, options)?;
+
+    let results: Results = if let Ok(db) = haystack.cast::<HashDb>() {
+        let haystack = db.borrow().db.images(&settings.hash_settings());
+
+        py.detach(|| catch(|| query::query(&settings, image, haystack, Vec::new())))?
+    } else {
+        let dir: PathBuf = haystack.extract()
+            .map_err(|_| PyTypeError::new_err("haystack must be a HashDb or a directory"))?;
+
+        py.detach(|| catch(|| {
+            let paths = search::find_images_in(&settings, &dir).0;
+            let (haystack, errors) = processing::hash_all(&settings, paths);
+
+            query::query(&settings, image, haystack, errors)
+        }))?
+    };
+
+    match (results.uniques.into_iter().next(), results.errors.last()) {
+        (Some(needle), _) => to_py(py, &needle.similars),
+        // The image itself couldn't be hashed, which is the last error
+        (None, Some(err)) => Err(PyValueError::new_err(format!("{}: {}", err.path().display(), err.err_msg()))),
+        (None, None) => unreachable!(),
+    }
+}
+
+/// A hash database, the same as the ones made with `--cache` and `update`.
+#[pyclass]
+struct HashDb {
+    db: Db,
+    path: Option<PathBuf>,
+}
+
+#[pymethods]
+impl HashDb {
+
+    /// Load the database at `path`, or start an empty one. A missing file is an empty database.
+    #[new]
+    #[pyo3(signature = (path=None))]
+    fn new(path: Option<PathBuf>) -> PyResult<HashDb> {
+        let db = match path {
+            Some(ref path) => Db::load(path).map_err(|err| io_error(path, err))?,
+            None => Db::new(),
+        };
+
+        Ok(HashDb { db, path })
+    }
+
+    /// Hash the images in `dir` that are new or modified since they were added, with `options`,
+    /// and remove the ones in `dir` that no longer exist. Returns the images that couldn't be
+    /// hashed, like `errors` in the JSON output.
+    #[pyo3(signature = (dir, **options))]
+    fn update(&mut self, py: Python<'_>, dir: PathBuf, options: Option<&Bound<'_, PyDict>>) -> PyResult<Py<PyAny>> {
+        let settings = settings(Some(&dir), options)?;
+        let db = &mut self.db;
+
+        let errors = py.detach(|| catch(|| {
+            db.remove_missing(&settings.dir);
+
+            let paths = search::find_images(&settings);
+            let (_, changed) = db.partition(paths, &settings.hash_settings());
+            let (hashed, errors) = processing::hash_all(&settings, changed);
+
+            for image in &hashed {
+                db.insert(image, &settings.hash_settings());
+            }
+
+            errors
+        }))?;
+
+        let errors: Vec<_> = errors.iter()
+            .map(|err| ErrorEntry::new(err, Path::new("")))
+            .collect();
+
+        to_py(py, &errors)
+    }
+
+    /// The images in the database hashed with `options` (`hash_size` and `fast`).
+    #[pyo3(signature = (**options))]
+    fn images(&self, py: Python<'_>, options: Option<&Bound<'_, PyDict>>) -> PyResult<Py<PyAny>> {
+        let settings = settings(None, options)?;
+
+        let mut images = self.db.images(&settings.hash_settings());
+        processing::sort_by_path(&mut images);
+
+        to_py(py, &images)
+    }
+
+    /// Save the database to `path`, or where it was loaded from.
+    #[pyo3(signature = (path=None))]
+    fn save(&self, path: Option<PathBuf>) -> PyResult<()> {
+        let path = path.or_else(|| self.path.clone())
+            .ok_or_else(|| PyValueError::new_err("the database wasn't loaded from a file, so save needs a path"))?;
+
+        self.db.save(&path).map_err(|err| io_error(&path, err))
+    }
+
+    fn __len__(&self) -> usize {
+        self.db.len()
+    }
+}
+
+#[pymodule]
+fn pyimg_dup(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(scan, module)?)?;
+    module.add_function(wrap_pyfunction!(find_matches, module)?)?;
+    module.add_class::<HashDb>()?;
+
+    Ok(())
+}
+
+/// Parse `options` as command line options, with `dir` as `--dir`.
+fn settings(dir: Option<&Path>, options: Option<&Bound<'_, PyDict>>) -> PyResult<ProgramSettings> {
+    let mut args = Vec::new();
+
+    if let Some(dir) = dir {
+        args.push(format!("--dir={}", dir.display()));
+    }
+
+    for (key, value) in options.into_iter().flat_map(|options| options.iter()) {
+        let name = key.extract::<String>()?.replace('_', "-");
+
+        if name == "help" || name == "dir" {
+            return Err(PyTypeError::new_err(format!("{} can't be given as an option", name)));
+        }
+
+        args.extend(option_args(&name, &value)?);
+    }
+
+    catch(|| parse_args(&args))
+}
+
+/// The same as for a config file: `True` sets a flag, and lists give an option more than once.
+fn option_args(name: &str, value: &Bound<'_, PyAny>) -> PyResult<Vec<String>> {
+    if let Ok(flag) = value.cast::<PyBool>() {
+        return Ok(if flag.is_true() { vec![format!("--{}", name)] } else { Vec::new() });
+    }
+
+    if let Ok(values) = value.cast::<PyList>() {
+        return values.iter().map(|value| option_arg(name, &value)).collect();
+    }
+
+    Ok(vec![option_arg(name, value)?])
+}
+
+fn option_arg(name: &str, value: &Bound<'_, PyAny>) -> PyResult<String> {
+    if value.is_instance_of::<PyString>() || value.is_instance_of::<PyInt>() || value.is_instance_of::<PyFloat>() {
+        Ok(format!("--{}={}", name, value.str()?))
+    } else if let Ok(path) = value.extract::<PathBuf>() {
+        Ok(format!("--{}={}", name, path.display()))
+    } else {
+        Err(PyTypeError::new_err(format!("{} must be a str, number, bool, path or list", name)))
+    }
+}
+
+/// Arguments are checked by panicking, so report the panic as a `ValueError`.
+fn catch<T, F: FnOnce() -> T>(f: F) -> PyResult<T> {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .map_err(|payload| PyValueError::new_err(panic_message(&*payload)))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload.downcast_ref::<String>().cloned()
+        .or_else(|| payload.downcast_ref::<&str>().map(|msg| msg.to_string()))
+        .unwrap_or_else(|| "unknown error".to_string())
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> PyErr {
+    PyIOError::new_err(format!("{}: {}", path.display(), err))
+}
+
+/// The same value the JSON output would have, as dicts, lists, strs and numbers.
+fn to_py<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<Py<PyAny>> {
+    let value = serde_json::to_value(value).map_err(|err| PyValueError::new_err(err.to_string()))?;
+
+    json_to_py(py, &value)
+}
+
+fn json_to_py(py: Python<'_>, value: &Value) -> PyResult<Py<PyAny>> {
+    Ok(match *value {
+        Value::Null => py.None(),
+        Value::Bool(flag) => PyBool::new(py, flag).to_owned().into_any().unbind(),
+        Value::Number(ref num) => match num.as_i64() {
+            Some(int) => int.into_pyobject(py)?.into_any().unbind(),
+            None => num.as_f64().unwrap_or(f64::NAN).into_pyobject(py)?.into_any().unbind(),
+        },
+        Value::String(ref string) => PyString::new(py, string).into_any().unbind(),
+        Value::Array(ref values) => {
+            let list = PyList::empty(py);
+
+            for value in values {
+                list.append(json_to_py(py, value)?)?;
+            }
+
+            list.into_any().unbind()
+        },
+        Value::Object(ref map) => {
+            let dict = PyDict::new(py);
+
+            for (key, value) in map {
+                dict.set_item(key, json_to_py(py, value)?)?;
+            }
+
+            dict.into_any().unbind()
+        },
+    })
+}
@@ -0,0 +1,409 @@
+use crate::audit::{self, AuditLog};
+use crate::config::{Action, CrossDevice, ProgramSettings, Tier};
+use crate::img::{self, Image, UniqueImage};
+use crate::processing::Results;
+use crate::quarantine::Quarantine;
+use crate::trash;
+
+use log::{error, info, warn};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What applying an action needs besides the action itself.
+#[derive(Clone)]
+pub struct ActionOptions {
+    /// Only log what would be done, from `--dry-run`
+    pub dry_run: bool,
+    /// Where `Action::Quarantine` moves images, from `--quarantine-dir`
+    pub quarantine: Option<Quarantine>,
+    /// What to do instead of a hard link across filesystems, from `--cross-device`
+    pub cross_device: CrossDevice,
+    /// Where every action is recorded, from `--audit-log`
+    pub audit: Option<AuditLog>,
+}
+
+impl ActionOptions {
+
+    pub fn new(settings: &ProgramSettings) -> ActionOptions {
+        ActionOptions {
+            dry_run: settings.dry_run,
+            quarantine: settings.quarantine.clone(),
+            cross_device: settings.cross_device,
+            audit: settings.audit_log.clone().map(AuditLog::new),
+        }
+    }
+}
+
+/// Resolve every group of similar images by keeping one of them according to `--keep`
+/// and applying `--action` to the rest. With `--dry-run`, only log what would be done.
+pub fn apply_actions(settings: &ProgramSettings, results: &Results) {
+    apply_to_groups(settings, &results.uniques);
+}
+
+/// The same as `apply_actions()`, for groups that may have been read back from a report:
+/// images that no longer exist, or were modified since, are left out. Returns how many
+/// images and other paths failed.
+pub fn apply_to_groups(settings: &ProgramSettings, groups: &[UniqueImage]) -> u32 {
+    let action = match settings.action {
+        Some(action) => action,
+        None => return 0,
+    };
+
+    let options = ActionOptions::new(settings);
+
+    let mut done = 0u32;
+    let mut failed = 0u32;
+    // Hard links across filesystems
+    let (mut symlinked, mut skipped) = (0u32, 0u32);
+
+    for unique in groups {
+        let mut group = group_images(unique, settings.action_tier);
+        group.retain(|image| unchanged(image) && !settings.is_protected(image));
+        if group.len() < 2 { continue; }
+
+        let keep = img::choose_representative(&group, &settings.keep, &settings.prefer);
+
+        for (keep, image) in settings.action_pairs(&group, keep) {
+            let (keep, image) = (group[keep], group[image]);
+
+            match apply_to_image(action, keep, image, &options) {
+                Ok((Applied::Done(applied), failed_paths)) => {
+                    done += 1;
+                    failed += failed_paths;
+                    if applied != action { symlinked += 1; }
+                },
+                Ok((Applied::Skipped, _)) => skipped += 1,
+                Err(err) => {
+                    error!("Failed to {} {}: {}", action.verb(), image.path.display(), err);
+                    failed += 1;
+                },
+            }
+        }
+    }
+
+    if settings.dry_run {
+        info!("Dry run, {} images would be affected.", done);
+    } else {
+        info!("{} images affected, {} failed.", done, failed);
+    }
+
+    if symlinked > 0 || skipped > 0 {
+        info!("On a different filesystem than the image kept: {} {} symlinked instead of hardlinked, {} skipped.",
+              symlinked, if settings.dry_run { "would be" } else { "were" }, skipped);
+    }
+
+    failed
+}
+
+/// If `image` is still there with the same size, so it's not mistaken for what replaced it.
+/// Images hashed from memory have no size to compare.
+fn unchanged(image: &Image) -> bool {
+    match image.path.metadata() {
+        Ok(meta) if image.file_size == 0 || meta.len() == image.file_size => true,
+        Ok(_) => {
+            warn!("Skipping {}: it was modified since it was processed", image.path.display());
+            false
+        },
+        Err(err) => {
+            warn!("Skipping {}: {}", image.path.display(), err);
+            false
+        },
+    }
+}
+
+/// What `apply_to_image()` did to an image.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum Applied {
+    /// The action, or a symbolic link instead of a hard link across filesystems
+    Done(Action),
+    /// A hard link across filesystems, with `--cross-device=skip`
+    Skipped,
+}
+
+impl Applied {
+
+    /// To show next to the image, e.g. "Trashed" or "Would trash".
+    pub fn describe(&self, dry_run: bool) -> String {
+        match *self {
+            Applied::Done(action) if dry_run => format!("Would {}", action.verb()),
+            Applied::Done(action) => action.past_tense().to_string(),
+            Applied::Skipped if dry_run => "Would skip, on a different filesystem".to_string(),
+            Applied::Skipped => "Skipped, on a different filesystem".to_string(),
+        }
+    }
+}
+
+/// Apply `action` to `image`, keeping `keep`, then to its other paths and, if it's removed,
+/// its paired RAW files, logging each one. With `options.dry_run`, only log what would be done.
+/// A hard link to `keep` from a different filesystem is made a symbolic link or skipped
+/// instead, by `options.cross_device`.
+///
+/// Returns what was done to `image`, and how many of the other paths and RAW files failed,
+/// which are logged; if `image` itself failed, they're left alone.
+pub fn apply_to_image(action: Action, keep: &Image, image: &Image, options: &ActionOptions)
+    -> io::Result<(Applied, u32)> {
+    let kept = if options.dry_run { "keeping" } else { "kept" };
+    let applied = apply_to_path(action, &keep.path, &image.path, &format!("{} {}", kept, keep.path.display()), options)?;
+
+    // Its other paths are on the same filesystem
+    if applied == Applied::Skipped { return Ok((applied, 0)); }
+
+    let mut failed = 0;
+
+    // The file is only gone once every path to it is
+    for link in &image.links {
+        let note = format!("same file as {}", image.path.display());
+
+        if let Err(err) = apply_to_path(action, &keep.path, link, &note, options) {
+            error!("Failed to {} {}: {}", action.verb(), link.display(), err);
+            failed += 1;
+        }
+    }
+
+    // A paired RAW file can't be linked to a different photo, so it's only removed
+    if !action.removes() { return Ok((applied, failed)); }
+
+    for sidecar in &image.sidecars {
+        let note = format!("paired with {}", image.path.display());
+
+        if let Err(err) = apply_to_path(action, &keep.path, sidecar, &note, options) {
+            error!("Failed to {} {}: {}", action.verb(), sidecar.display(), err);
+            failed += 1;
+        }
+    }
+
+    Ok((applied, failed))
+}
+
+/// Apply `action` to `path`, or what `--cross-device` says to do instead, and log it with `note`.
+fn apply_to_path(action: Action, keep: &Path, path: &Path, note: &str, options: &ActionOptions)
+    -> io::Result<Applied> {
+    let applied = match action {
+        Action::Hardlink if !same_filesystem(keep, path)? => match options.cross_device {
+            CrossDevice::Symlink => Applied::Done(Action::Symlink),
+            CrossDevice::Skip => Applied::Skipped,
+        },
+        _ => Applied::Done(action),
+    };
+
+    let note = if applied == Applied::Done(action) {
+        note.to_string()
+    } else {
+        format!("{}, on a different filesystem", note)
+    };
+
+    match applied {
+        Applied::Done(action) if options.dry_run => info!("Would {}: {} ({})", action.verb(), path.display(), note),
+        Applied::Done(action) => {
+            // Before the contents are gone, to tell on undo whether they're still the same
+            let checksum = match options.audit {
+                Some(_) => Some(audit::checksum(path)?),
+                None => None,
+            };
+
+            let target = apply_action(action, keep, path, options)?;
+            info!("{}: {} ({})", action.past_tense(), path.display(), note);
+
+            if let (Some(audit), Some(checksum)) = (&options.audit, checksum) {
+                if let Err(err) = audit.record(action, path, target.as_deref(), checksum) {
+                    error!("Failed to add {} to the audit log: {}", path.display(), err);
+                }
+            }
+        },
+        Applied::Skipped if options.dry_run => info!("Would skip: {} ({})", path.display(), note),
+        Applied::Skipped => info!("Skipped: {} ({})", path.display(), note),
+    }
+
+    Ok(applied)
+}
+
+/// If a hard link to `keep` can be made at `path`, which may not exist.
+#[cfg(unix)]
+fn same_filesystem(keep: &Path, path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    Ok(keep.metadata()?.dev() == dir.metadata()?.dev())
+}
+
+/// Without device numbers, go by the drive, which misses volumes mounted in folders.
+#[cfg(not(unix))]
+fn same_filesystem(keep: &Path, path: &Path) -> io::Result<bool> {
+    let drive = |path: &Path| std::path::absolute(path).map(|path| path.components().next().map(|c| c.as_os_str().to_os_string()));
+
+    Ok(drive(keep)? == drive(path)?)
+}
+
+/// The original and its similars up to `max_tier`.
+fn group_images(unique: &UniqueImage, max_tier: Tier) -> Vec<&Image> {
+    Some(&unique.img).into_iter()
+        .chain(unique.similars.iter()
+            .filter(|similar| similar.tier <= max_tier)
+            .map(|similar| &similar.img))
+        .collect()
+}
+
+/// Returns where `path` was moved to, or the image it was replaced with; nothing if it was deleted.
+fn apply_action(action: Action, keep: &Path, path: &Path, options: &ActionOptions) -> io::Result<Option<PathBuf>> {
+    match action {
+        Action::Delete => fs::remove_file(path).map(|_| None),
+        Action::Trash => trash::move_to_trash(path).map(Some),
+        Action::Quarantine => match options.quarantine {
+            Some(ref quarantine) => quarantine.move_in(path).map(Some),
+            None => Err(io::Error::other("No quarantine directory was given with --quarantine-dir")),
+        },
+        Action::Hardlink => replace_with(path, |tmp| fs::hard_link(keep, tmp)).map(|_| Some(keep.to_path_buf())),
+        Action::Reflink => replace_with(path, |tmp| reflink(keep, tmp)).map(|_| Some(keep.to_path_buf())),
+        Action::Symlink => {
+            let target = std::path::absolute(keep)?;
+            replace_with(path, |tmp| symlink(&target, tmp)).map(|_| Some(target))
+        },
+    }
+}
+
+/// Create the replacement next to `path` first, so `path` is only overwritten if that succeeded.
+fn replace_with<F: FnOnce(&Path) -> io::Result<()>>(path: &Path, create: F) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".img_dup_tmp");
+    let tmp: PathBuf = path.with_file_name(tmp_name);
+
+    create(&tmp)?;
+
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// A copy of `keep` at `copy` that shares its data until either is modified.
+/// Fails if the filesystem can't do that, rather than making a full copy.
+fn reflink(keep: &Path, copy: &Path) -> io::Result<()> {
+    reflink_copy::reflink(keep, copy).map_err(|err| match err.kind() {
+        io::ErrorKind::Unsupported | io::ErrorKind::CrossesDevices | io::ErrorKind::InvalidInput =>
+            io::Error::new(io::ErrorKind::Unsupported, format!(
+                "{} (reflinks need a filesystem that supports them, like btrfs, XFS or APFS, \
+                 with both files on the same one)", err)),
+        _ => err,
+    })
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_args;
+    use crate::hash::ImageHash;
+    use crate::img::SimilarImage;
+
+    use std::env;
+    use std::process;
+
+    /// An empty directory of its own for each test, which run at the same time.
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("img_dup_actions_{}_{}", process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A file at `path` of `size` bytes, hashed as if it were an image.
+    fn image(path: PathBuf, size: usize) -> Image {
+        fs::write(&path, vec![0u8; size]).unwrap();
+        Image::new(path, ImageHash::from_bytes(&[0; 8], 8).unwrap(), 100, 100, size as u64)
+    }
+
+    fn group(original: Image, similar: Image) -> UniqueImage {
+        let mut unique = UniqueImage::from_image(original);
+        unique.similars.push(SimilarImage::from_image(similar, 0.0, 0, 0.0));
+        unique
+    }
+
+    fn settings(dir: &Path, args: &[&str]) -> ProgramSettings {
+        let mut all = vec!["scan".to_string(), dir.display().to_string()];
+        all.extend(args.iter().map(|arg| arg.to_string()));
+        parse_args(&all)
+    }
+
+    #[test]
+    fn keeps_the_representative() {
+        let dir = temp_dir("keep");
+        let (small, large) = (dir.join("small.png"), dir.join("large.png"));
+        let groups = [group(image(small.clone(), 10), image(large.clone(), 20))];
+
+        let failed = apply_to_groups(&settings(&dir, &["--action=delete", "--keep=largest-file"]), &groups);
+        let (small_exists, large_exists) = (small.exists(), large.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(failed, 0);
+        assert!(!small_exists, "the smaller file should have been deleted");
+        assert!(large_exists, "the larger file should have been kept");
+    }
+
+    #[test]
+    fn dry_run_leaves_files_alone() {
+        let dir = temp_dir("dry_run");
+        let (a, b) = (dir.join("a.png"), dir.join("b.png"));
+        let groups = [group(image(a.clone(), 10), image(b.clone(), 10))];
+
+        let failed = apply_to_groups(&settings(&dir, &["--action=delete", "--dry-run"]), &groups);
+        let (a_exists, b_exists) = (a.exists(), b.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(failed, 0);
+        assert!(a_exists && b_exists);
+    }
+
+    #[test]
+    fn replace_with_keeps_the_original_if_create_fails() {
+        let dir = temp_dir("replace_with");
+        let path = dir.join("a.png");
+        fs::write(&path, b"original").unwrap();
+
+        let result = replace_with(&path, |_| Err(io::Error::other("can't create")));
+        let contents = fs::read(&path).unwrap();
+        let leftovers = fs::read_dir(&dir).unwrap().count();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_err());
+        assert_eq!(contents, b"original");
+        assert_eq!(leftovers, 1, "no temporary file should be left behind");
+    }
+
+    #[test]
+    fn skips_modified_images() {
+        let dir = temp_dir("unchanged");
+        let (a, b) = (dir.join("a.png"), dir.join("b.png"));
+        let groups = [group(image(a.clone(), 10), image(b.clone(), 10))];
+
+        // Modified since it was hashed
+        fs::write(&b, vec![0u8; 15]).unwrap();
+
+        let is_unchanged = (unchanged(&groups[0].img), unchanged(&groups[0].similars[0].img));
+        let failed = apply_to_groups(&settings(&dir, &["--action=delete"]), &groups);
+        let (a_exists, b_exists) = (a.exists(), b.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(is_unchanged, (true, false));
+        assert_eq!(failed, 0);
+        // Only one image of the group is left, so there's nothing to resolve
+        assert!(a_exists && b_exists);
+    }
+}
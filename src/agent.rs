@@ -0,0 +1,257 @@
+//! With the `server` feature, `agent` and `collate`: hash images on the machines they're on,
+//! and find the duplicates among all of them on another, without mounting their directories.
+//!
+//! An agent answers `GET /hashes?hash_size=[size]&fast=[true|false]` with the images it finds
+//! in its `dir`, hashed with the given settings, as `{"images": [...], "errors": [...]}`.
+//! Images are written the same way as in `--format=json`, with absolute paths, and errors
+//! as `{"path": "...", "error": "...", "kind": "..."}`. Only hashes and metadata are sent,
+//! never the images themselves. Errors with the request are returned as `{"error": "..."}`.
+
+use crate::config::{JsonSettings, ProgramSettings};
+use crate::img::Image;
+use crate::processing::{self, Phases, ProcessingError, Results};
+use crate::search;
+use crate::serialize;
+
+use chrono::Local;
+
+use log::{info, warn};
+
+use serde::{Deserialize, Serialize};
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::{self, Path, PathBuf};
+use std::thread;
+use std::time::Instant;
+
+#[derive(Serialize)]
+struct HashesBody<'a> {
+    images: &'a [Image],
+    errors: Vec<serialize::ErrorEntry>,
+}
+
+#[derive(Deserialize)]
+struct Hashes {
+    images: Vec<Image>,
+    errors: Vec<RemoteError>,
+}
+
+#[derive(Deserialize)]
+struct RemoteError {
+    path: PathBuf,
+    error: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+/// Answer requests for hashes on `settings.listen` until the process is stopped,
+/// each on its own thread.
+pub fn serve_agent(settings: &ProgramSettings) -> io::Result<()> {
+    let server = Server::http(&settings.listen).map_err(io::Error::other)?;
+
+    info!("Agent for {} listening on http://{}", settings.dir.display(), settings.listen);
+
+    for request in server.incoming_requests() {
+        let settings = settings.clone();
+
+        thread::spawn(move || handle(&settings, request));
+    }
+
+    Ok(())
+}
+
+fn handle(settings: &ProgramSettings, request: Request) {
+    info!("{} {} from {}", request.method(), request.url(),
+          request.remote_addr().map_or("unknown".to_string(), |addr| addr.to_string()));
+
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+
+    let response = match (request.method(), path) {
+        (Method::Get, "/hashes") => hashes(settings, query),
+        (_, "/hashes") => error(405, "Method not allowed"),
+        _ => error(404, "Not found"),
+    };
+
+    if let Err(err) = request.respond(response) {
+        warn!("Could not send response: {}", err);
+    }
+}
+
+type JsonResponse = Response<io::Cursor<Vec<u8>>>;
+
+fn hashes(settings: &ProgramSettings, query: &str) -> JsonResponse {
+    let mut settings = settings.clone();
+
+    for (key, value) in query.split('&').filter_map(|param| param.split_once('=')) {
+        let parsed = match key {
+            "hash_size" => value.parse().map(|hash_size| settings.hash_size = hash_size).is_ok(),
+            "fast" => value.parse().map(|fast| settings.fast = fast).is_ok(),
+            _ => true,
+        };
+
+        if !parsed {
+            return error(400, &format!("Invalid value for {}: {}", key, value));
+        }
+    }
+
+    let start = Instant::now();
+
+    info!("Hashing the images in {}...", settings.dir.display());
+
+    let paths = search::find_images(&settings);
+    let (images, errors) = processing::hash_all(&settings, paths);
+
+    info!("Hashed {} images in {:.2}s, {} failed.", images.len(), start.elapsed().as_secs_f64(), errors.len());
+
+    // Absolute, so the coordinator can tell where they are without knowing `dir`
+    let images: Vec<Image> = images.into_iter().map(|image| with_paths(image, absolute)).collect();
+    let errors = errors.iter()
+        .map(|error| serialize::ErrorEntry {
+            path: absolute(error.path()).display().to_string(),
+            error: error.err_msg(),
+            kind: error.kind(),
+        })
+        .collect();
+
+    let mut body = Vec::new();
+
+    match serialize::write_json(&JsonSettings::CompactJson, &HashesBody { images: &images, errors }, &mut body) {
+        Ok(()) => json_response(200, body),
+        Err(err) => error(500, &err.to_string()),
+    }
+}
+
+fn error(status: u16, msg: &str) -> JsonResponse {
+    json_response(status, serde_json::to_vec(&ErrorBody { error: msg.to_string() }).unwrap())
+}
+
+fn json_response(status: u16, body: Vec<u8>) -> JsonResponse {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+
+    Response::from_data(body).with_status_code(status).with_header(content_type)
+}
+
+/// Have every agent in `agents` hash its images at the same time, and group them all together.
+/// Paths are prefixed with the address of the agent they're from, like `nas:8080:/photos/a.jpg`.
+/// An agent that can't be reached is reported as an error with its address as the path.
+pub fn collate(settings: &ProgramSettings, agents: &[String]) -> Results {
+    let start_time = Local::now();
+    let hashing_start = Instant::now();
+
+    let fetched: Vec<(&String, io::Result<Hashes>)> = thread::scope(|scope| {
+        let handles: Vec<_> = agents.iter()
+            .map(|agent| (agent, scope.spawn(move || fetch_hashes(settings, agent))))
+            .collect();
+
+        handles.into_iter()
+            .map(|(agent, handle)| (agent, handle.join().unwrap_or_else(|_| Err(io::Error::other("The request panicked")))))
+            .collect()
+    });
+
+    let mut images = Vec::new();
+    let mut errors = Vec::new();
+
+    for (agent, hashes) in fetched {
+        match hashes {
+            Ok(hashes) => {
+                info!("Received {} images and {} errors from {}", hashes.images.len(), hashes.errors.len(), agent);
+
+                images.extend(hashes.images.into_iter().map(|image| on_agent(agent, image)));
+                errors.extend(hashes.errors.into_iter()
+                    .map(|remote| ProcessingError::Misc(remote_path(agent, &remote.path), remote.error)));
+            },
+            Err(err) => {
+                warn!("Could not get hashes from {}: {}", agent, err);
+                errors.push(ProcessingError::Io(PathBuf::from(agent), err));
+            },
+        }
+    }
+
+    let total = images.len() + errors.len();
+
+    processing::sort_by_path(&mut images);
+    processing::sort_errors_by_path(&mut errors);
+
+    let hashing_secs = hashing_start.elapsed().as_secs_f64();
+    let grouping_start = Instant::now();
+
+    let uniques = processing::group_images(images, settings);
+
+    let phases = Phases {
+        search_secs: None,
+        hashing_secs: Some(hashing_secs),
+        grouping_secs: Some(grouping_start.elapsed().as_secs_f64()),
+    };
+
+    Results {
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors,
+        phases,
+    }
+}
+
+/// A plain HTTP/1.0 request, so the agent closes the connection after the body.
+fn fetch_hashes(settings: &ProgramSettings, agent: &str) -> io::Result<Hashes> {
+    info!("Requesting hashes from {}...", agent);
+
+    let mut stream = TcpStream::connect(agent)?;
+
+    write!(stream, "GET /hashes?hash_size={}&fast={} HTTP/1.0\r\nHost: {}\r\n\r\n",
+           settings.hash_size, settings.fast, agent)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    let (head, body) = split_response(&response)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid HTTP response"))?;
+
+    match head.split(' ').nth(1) {
+        Some("200") => serde_json::from_slice(body).map_err(io::Error::from),
+        Some(status) => {
+            let msg = serde_json::from_slice::<ErrorBody>(body)
+                .map_or_else(|_| format!("HTTP status {}", status), |body| body.error);
+
+            Err(io::Error::other(msg))
+        },
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid HTTP response")),
+    }
+}
+
+/// The status line and headers, and the body.
+fn split_response(response: &[u8]) -> Option<(&str, &[u8])> {
+    let end = response.windows(4).position(|window| window == b"\r\n\r\n")?;
+
+    Some((std::str::from_utf8(&response[..end]).ok()?, &response[end + 4..]))
+}
+
+/// Thumbnails are on the agent, and can't be shown here.
+fn on_agent(agent: &str, image: Image) -> Image {
+    Image { thumbnail: None, ..with_paths(image, |path| remote_path(agent, path)) }
+}
+
+/// `image` with `map` applied to its path and its other paths.
+fn with_paths<F: Fn(&Path) -> PathBuf>(image: Image, map: F) -> Image {
+    Image {
+        path: map(&image.path),
+        sidecars: image.sidecars.iter().map(|path| map(path)).collect(),
+        links: image.links.iter().map(|path| map(path)).collect(),
+        ..image
+    }
+}
+
+fn remote_path(agent: &str, path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}:{}", agent, path.display()))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,120 @@
+//! `--archives`: images inside zip, cbz and tar archives, named by the path of the archive
+//! followed by `!` and the path inside it, like `comics/issue1.cbz!/page01.jpg`.
+//!
+//! Naming an image like that is enough to read it; the archive is opened again for each one.
+//! Reading the archives needs the `archives` feature, but the names can be told apart without it.
+
+use std::path::{Path, PathBuf};
+
+/// Extensions of the archives that are searched, ignoring case.
+pub const ARCHIVE_EXTS: &[&str] = &["zip", "cbz", "tar"];
+
+/// Follows the file name of the archive in the paths of the images inside it.
+const SEPARATOR: char = '!';
+
+/// If `path` is a file that can be searched as an archive, by its extension.
+pub fn is_archive(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ARCHIVE_EXTS.iter().any(|known| known.eq_ignore_ascii_case(ext)))
+}
+
+/// The path of `member` inside `archive`.
+pub fn member_path(archive: &Path, member: &str) -> PathBuf {
+    let mut name = archive.as_os_str().to_os_string();
+    name.push(SEPARATOR.to_string());
+
+    member.split('/').filter(|&part| !part.is_empty() && part != ".")
+        .fold(PathBuf::from(name), |path, part| path.join(part))
+}
+
+/// The archive an image is inside, and its path inside it, with `/` between directories.
+/// `None` if it's an ordinary file.
+pub fn split(path: &Path) -> Option<(PathBuf, String)> {
+    let archive = path.ancestors().skip(1)
+        .find(|ancestor| ancestor.file_name().and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(SEPARATOR))
+            .is_some_and(|name| is_archive(Path::new(name))))?;
+
+    let member: Vec<String> = path.strip_prefix(archive).ok()?
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+
+    let name = archive.file_name()?.to_str()?;
+
+    Some((archive.with_file_name(name.strip_suffix(SEPARATOR)?), member.join("/")))
+}
+
+#[cfg(feature = "archives")]
+pub use self::read::{members, read_member};
+
+#[cfg(feature = "archives")]
+mod read {
+    use super::*;
+
+    use std::fs::File;
+    use std::io::{self, Read};
+
+    /// The files in the archive at `path`, with their paths inside it and their sizes.
+    pub fn members(path: &Path) -> io::Result<Vec<(String, u64)>> {
+        if is_tar(path) {
+            let mut archive = tar::Archive::new(File::open(path)?);
+            let mut members = Vec::new();
+
+            for entry in archive.entries_with_seek()? {
+                let entry = entry?;
+
+                if entry.header().entry_type().is_file() {
+                    members.push((entry.path()?.to_string_lossy().into_owned(), entry.size()));
+                }
+            }
+
+            Ok(members)
+        } else {
+            let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+            let mut members = Vec::new();
+
+            for idx in 0..archive.len() {
+                let entry = archive.by_index_raw(idx)?;
+
+                if entry.is_file() {
+                    members.push((entry.name()?.to_string(), entry.size()));
+                }
+            }
+
+            Ok(members)
+        }
+    }
+
+    /// The contents of `member` in the archive at `path`.
+    pub fn read_member(path: &Path, member: &str) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+
+        if is_tar(path) {
+            let mut archive = tar::Archive::new(File::open(path)?);
+
+            for entry in archive.entries_with_seek()? {
+                let mut entry = entry?;
+
+                // Tar files often start their paths with `./`, which `split()` leaves out
+                let path = entry.path()?.to_string_lossy().into_owned();
+
+                if path.trim_start_matches("./") == member {
+                    entry.read_to_end(&mut data)?;
+                    return Ok(data);
+                }
+            }
+
+            Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not in the archive", member)))
+        } else {
+            let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+            archive.by_name(member)?.read_to_end(&mut data)?;
+
+            Ok(data)
+        }
+    }
+
+    fn is_tar(path: &Path) -> bool {
+        path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("tar"))
+    }
+}
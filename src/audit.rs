@@ -0,0 +1,185 @@
+//! `--audit-log`: a record of every file `--action` changed, one JSON object per line,
+//! and `img-dup undo` to reverse what can be reversed from it.
+
+use crate::config::Action;
+use crate::exact;
+use crate::quarantine;
+use crate::trash;
+
+use log::{error, info};
+
+use serde::{Deserialize, Serialize};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// A file that actions are appended to.
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+/// A line of the audit log.
+#[derive(Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the action was applied, in RFC 3339
+    pub timestamp: String,
+    pub action: Action,
+    /// The path the action was applied to
+    pub source: PathBuf,
+    /// The image linked to, or where the file was moved; none if it was deleted
+    pub target: Option<PathBuf>,
+    /// Of the contents of `source` before the action, from `checksum()`
+    pub checksum: String,
+}
+
+impl AuditLog {
+
+    pub fn new(path: PathBuf) -> AuditLog {
+        AuditLog { path }
+    }
+
+    /// Append that `action` was applied to `source`, which had the contents `checksum`.
+    pub fn record(&self, action: Action, source: &Path, target: Option<&Path>, checksum: String) -> io::Result<()> {
+        let entry = AuditEntry {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            action,
+            source: std::path::absolute(source)?,
+            target: target.map(std::path::absolute).transpose()?,
+            checksum,
+        };
+
+        let mut log = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(log, "{}", serde_json::to_string(&entry)?)
+    }
+}
+
+/// The 64-bit FNV-1a hash of the file's contents, in hex: enough to tell whether a file still
+/// has the same contents, not to stand up to deliberate collisions.
+pub fn checksum(path: &Path) -> io::Result<String> {
+    Ok(format!("{:016x}", exact::fnv1a(&fs::read(path)?)))
+}
+
+/// Reverse every action in the audit log at `path`, the last first, as far as it can be:
+/// moved files are moved back, and links are replaced with copies of what they link to if
+/// that still has the contents the file had. Deleted files are gone. With `dry_run`, only
+/// log what would be done. Returns how many actions couldn't be undone.
+pub fn undo(path: &Path, dry_run: bool) -> io::Result<u32> {
+    let mut entries = Vec::new();
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() { continue; }
+
+        entries.push(serde_json::from_str::<AuditEntry>(&line)?);
+    }
+
+    let (mut undone, mut failed) = (0u32, 0u32);
+
+    for entry in entries.iter().rev() {
+        match undo_entry(entry, dry_run) {
+            Ok(true) => undone += 1,
+            Ok(false) => (),
+            Err(err) => {
+                error!("Can't undo {} {}: {}", entry.action.verb(), entry.source.display(), err);
+                failed += 1;
+            },
+        }
+    }
+
+    if dry_run {
+        info!("Dry run, {} actions would be undone, {} can't be.", undone, failed);
+    } else {
+        info!("{} actions undone, {} can't be.", undone, failed);
+    }
+
+    Ok(failed)
+}
+
+/// Returns whether anything was done, or would be with `dry_run`.
+fn undo_entry(entry: &AuditEntry, dry_run: bool) -> io::Result<bool> {
+    let source = &entry.source;
+
+    let target = match (entry.action, &entry.target) {
+        (Action::Delete, _) => return Err(io::Error::other("it was deleted")),
+        (_, Some(target)) => target,
+        (_, None) => return Err(io::Error::other("the audit log doesn't say where it went")),
+    };
+
+    if entry.action == Action::Trash || entry.action == Action::Quarantine {
+        if !target.exists() {
+            if source.exists() && checksum(source)? == entry.checksum {
+                info!("Already undone: {}", source.display());
+                return Ok(false);
+            }
+
+            return Err(io::Error::other(format!("{} no longer exists", target.display())));
+        }
+
+        if source.exists() {
+            return Err(io::Error::other(format!("{} exists", source.display())));
+        }
+
+        if dry_run {
+            info!("Would move back: {} (from {})", source.display(), target.display());
+            return Ok(true);
+        }
+
+        if let Some(parent) = source.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        quarantine::move_file(target, source)?;
+
+        if entry.action == Action::Trash {
+            trash::forget(target)?;
+        }
+
+        info!("Moved back: {} (from {})", source.display(), target.display());
+        return Ok(true);
+    }
+
+    // A link, or a clone that may have been modified since
+    if !is_shared(source)? && checksum(source)? == entry.checksum {
+        info!("Already undone: {} has its own copy of its contents", source.display());
+        return Ok(false);
+    }
+
+    if checksum(target)? != entry.checksum {
+        // A similar image, not a copy, or it was modified since: either way the contents are gone
+        return Err(io::Error::other(format!("its contents were different from {}, which is all that's left", target.display())));
+    }
+
+    if dry_run {
+        info!("Would copy back: {} (from {})", source.display(), target.display());
+        return Ok(true);
+    }
+
+    let mut tmp_name = source.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".img_dup_tmp");
+    let tmp = source.with_file_name(tmp_name);
+
+    fs::copy(target, &tmp)?;
+
+    fs::rename(&tmp, source).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })?;
+
+    info!("Copied back: {} (from {})", source.display(), target.display());
+    Ok(true)
+}
+
+/// If the file at `path` is a link, or has other paths.
+#[cfg(unix)]
+fn is_shared(path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = path.symlink_metadata()?;
+    Ok(meta.file_type().is_symlink() || meta.nlink() > 1)
+}
+
+#[cfg(not(unix))]
+fn is_shared(path: &Path) -> io::Result<bool> {
+    Ok(path.symlink_metadata()?.file_type().is_symlink())
+}
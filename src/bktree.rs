@@ -0,0 +1,105 @@
+use crate::hash::ImageHash;
+use crate::img::Image;
+
+/// A BK-tree over image hashes, for finding every hash within a given Hamming distance
+/// without comparing against all of them.
+///
+/// Each hash is tagged with an index, e.g. into a `Vec<UniqueImage>`.
+#[derive(Default)]
+pub struct BkTree {
+    root: Option<Node>,
+}
+
+struct Node {
+    hash: ImageHash,
+    idx: usize,
+    // Keyed by distance from this node's hash
+    children: Vec<(usize, Node)>,
+}
+
+impl BkTree {
+
+    pub fn new() -> BkTree {
+        BkTree { root: None }
+    }
+
+    pub fn insert(&mut self, hash: ImageHash, idx: usize) {
+        match self.root {
+            Some(ref mut root) => root.insert(hash, idx),
+            None => self.root = Some(Node::new(hash, idx)),
+        }
+    }
+
+    /// Get the indices of all hashes at most `max_dist` bits away from `hash`, in no particular order.
+    pub fn find(&self, hash: &ImageHash, max_dist: usize) -> Vec<usize> {
+        let mut found = Vec::new();
+
+        if let Some(ref root) = self.root {
+            root.find(hash, max_dist, &mut found);
+        }
+
+        found
+    }
+
+    /// Insert every hash of `image` that other images are looked up against:
+    /// its main hash, and its rotated and mirrored variants and crops.
+    pub fn insert_image(&mut self, image: &Image, idx: usize) {
+        for hash in image.variants.iter().chain(&image.crops) {
+            self.insert(hash.clone(), idx);
+        }
+
+        self.insert(image.hash.clone(), idx);
+    }
+
+    /// Get the indices of all images with a hash at most `max_dist` bits away from the main hash
+    /// of `image` or one of its crops, each only once, in no particular order.
+    pub fn find_image(&self, image: &Image, max_dist: usize) -> Vec<usize> {
+        let mut found: Vec<usize> = Some(&image.hash).into_iter().chain(&image.crops)
+            .flat_map(|hash| self.find(hash, max_dist))
+            .collect();
+
+        found.sort_unstable();
+        found.dedup();
+
+        found
+    }
+}
+
+impl Node {
+
+    fn new(hash: ImageHash, idx: usize) -> Node {
+        Node {
+            hash,
+            idx,
+            children: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, hash: ImageHash, idx: usize) {
+        let dist = self.hash.dist(&hash);
+
+        for child in self.children.iter_mut() {
+            if child.0 == dist {
+                return child.1.insert(hash, idx);
+            }
+        }
+
+        self.children.push((dist, Node::new(hash, idx)));
+    }
+
+    fn find(&self, hash: &ImageHash, max_dist: usize, found: &mut Vec<usize>) {
+        let dist = self.hash.dist(hash);
+
+        if dist <= max_dist {
+            found.push(self.idx);
+        }
+
+        // By the triangle inequality, matches can only be under children
+        // in the range `dist - max_dist ... dist + max_dist`
+        for &(child_dist, ref child) in self.children.iter() {
+            if child_dist + max_dist >= dist && child_dist <= dist + max_dist {
+                child.find(hash, max_dist, found);
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::config::ProgramSettings;
+use crate::db::HashDb;
+use crate::processing::{self, Phases, Results};
+use crate::update;
+
+use chrono::Local;
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Find the groups of similar images in `paths` that weren't already in the `baseline`
+/// hash database, without changing it. Images in the baseline are only loaded if they
+/// were modified since.
+///
+/// Without a baseline, every group of similar images is new.
+pub fn check(settings: &ProgramSettings, baseline: Option<&Path>, paths: Vec<PathBuf>)
+    -> io::Result<Results> {
+    let start_time = Local::now();
+
+    let (mut known, new) = match baseline {
+        Some(path) => HashDb::load(path)?.partition(paths, &settings.hash_settings()),
+        None => (Vec::new(), paths),
+    };
+    processing::sort_by_path(&mut known);
+
+    let ((hashed, errors), hashing_secs) = processing::timed(|| processing::hash_all(settings, new));
+
+    let new: HashSet<PathBuf> = hashed.iter().map(|image| image.path.clone()).collect();
+    let total = known.len() + hashed.len();
+
+    let (uniques, grouping_secs) = processing::timed(|| update::changed_groups(settings, known, hashed, &new));
+
+    Ok(Results {
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors,
+        phases: Phases { search_secs: None, hashing_secs: Some(hashing_secs), grouping_secs: Some(grouping_secs) },
+    })
+}
+
+/// One line for each new duplicate and each error, then a count, e.g.:
+///
+/// ```text
+/// + icons/save_copy.png ~ icons/save.png (100.00% similar, exact)
+/// ! icons/broken.png: Loading error: ...
+/// 1 new duplicate, 1 error
+/// ```
+pub fn write_diff(settings: &ProgramSettings, results: &Results, out: &mut dyn Write) -> io::Result<()> {
+    let dir = &settings.dir;
+    let mut duplicates = 0;
+
+    for unique in results.groups(settings) {
+        for similar in &unique.similars() {
+            writeln!(out, "+ {} ~ {} ({:.2}% similar, {})",
+                     similar.img.relative_path(dir).display(), unique.img.relative_path(dir).display(),
+                     similar.similarity(), similar.tier.name())?;
+            duplicates += 1;
+        }
+    }
+
+    for error in &results.errors {
+        writeln!(out, "! {}: {}", error.relative_path(dir).display(), error.err_msg())?;
+    }
+
+    writeln!(out, "{} new duplicate{}, {} error{}",
+             duplicates, plural(duplicates), results.errors.len(), plural(results.errors.len()))
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
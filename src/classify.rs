@@ -0,0 +1,131 @@
+//! `--screenshots`: tell probable screenshots apart from photos, by their dimensions and content,
+//! and `--blank-threshold`: find images of a single color.
+//!
+//! None of the signs is enough on its own: photos are taken at screen resolutions too, and
+//! scans of documents have flat backgrounds and sharp text. A screenshot is an image at the exact
+//! size of a common screen with either of the others, or one with both, e.g. a cropped screenshot.
+
+use image::{DynamicImage, GrayImage};
+
+/// Common screen resolutions of monitors, laptops, phones and tablets, in landscape.
+const SCREEN_SIZES: &[(u32, u32)] = &[
+    (1280, 720), (1280, 800), (1280, 1024), (1366, 768), (1440, 900), (1536, 864), (1600, 900),
+    (1680, 1050), (1920, 1080), (1920, 1200), (2048, 1152), (2560, 1080), (2560, 1440),
+    (2560, 1600), (2880, 1800), (3024, 1964), (3440, 1440), (3456, 2234), (3840, 2160),
+    (5120, 2880),
+    (1334, 750), (1792, 828), (2208, 1242), (2340, 1080), (2400, 1080), (2436, 1125),
+    (2532, 1170), (2556, 1179), (2688, 1242), (2778, 1284), (2796, 1290), (2960, 1440),
+    (3120, 1440), (3200, 1440),
+    (2048, 1536), (2160, 1620), (2224, 1668), (2360, 1640), (2388, 1668), (2732, 2048),
+];
+
+/// The content is looked at scaled down to fit in this many pixels.
+const SAMPLE_SIZE: u32 = 512;
+
+/// Enough to tell how much a blank image varies, which is the same at any size.
+const BLANK_SAMPLE_SIZE: u32 = 64;
+
+/// Blocks of this many pixels square whose brightness varies by no more than `FLAT_RANGE`
+/// count as flat, like the background of a window.
+const BLOCK_SIZE: u32 = 8;
+const FLAT_RANGE: u8 = 6;
+
+/// Neighbouring pixels differing in brightness by more than this are an edge as sharp as text,
+/// which photos rarely have once scaled down.
+const SHARP_EDGE: i16 = 96;
+
+/// The fraction of flat blocks, and of pixels on sharp edges, that counts as a sign.
+const FLAT_FRACTION: f32 = 0.35;
+const EDGE_FRACTION: f32 = 0.02;
+
+/// If `img` is probably a screenshot, or an image of text like one.
+pub fn is_screenshot(img: &DynamicImage) -> bool {
+    let screen_size = is_screen_size(img.width(), img.height());
+
+    let sample = img.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_luma8();
+    let flat = flat_fraction(&sample) >= FLAT_FRACTION;
+    let text = edge_fraction(&sample) >= EDGE_FRACTION;
+
+    [screen_size, flat, text].into_iter().filter(|&sign| sign).count() >= 2
+}
+
+/// If every channel of `img` has a standard deviation below `threshold`, out of 255:
+/// a blank page, a black frame, a solid placeholder. The hashes of such images are all about
+/// the same, whatever the color, so they would otherwise be grouped as duplicates of each other.
+pub fn is_blank(img: &DynamicImage, threshold: f32) -> bool {
+    let sample = img.thumbnail(BLANK_SAMPLE_SIZE, BLANK_SAMPLE_SIZE).to_rgb8();
+    let pixels = (sample.width() * sample.height()) as f64;
+    if pixels == 0f64 { return true; }
+
+    let (mut sums, mut squares) = ([0f64; 3], [0f64; 3]);
+
+    for pixel in sample.pixels() {
+        for channel in 0..3 {
+            let value = pixel[channel] as f64;
+            sums[channel] += value;
+            squares[channel] += value * value;
+        }
+    }
+
+    (0..3).all(|channel| {
+        let mean = sums[channel] / pixels;
+        let variance = (squares[channel] / pixels - mean * mean).max(0f64);
+
+        variance.sqrt() < threshold as f64
+    })
+}
+
+/// If the dimensions are those of a common screen, at 1x or 2x, in either orientation.
+fn is_screen_size(width: u32, height: u32) -> bool {
+    let (long, short) = (width.max(height), width.min(height));
+
+    SCREEN_SIZES.iter().any(|&(screen_long, screen_short)|
+        (long, short) == (screen_long, screen_short) || (long, short) == (screen_long * 2, screen_short * 2))
+}
+
+/// The fraction of whole blocks of the image that are flat.
+fn flat_fraction(img: &GrayImage) -> f32 {
+    let (cols, rows) = (img.width() / BLOCK_SIZE, img.height() / BLOCK_SIZE);
+    if cols == 0 || rows == 0 { return 0f32; }
+
+    let mut flat = 0u32;
+
+    for row in 0..rows {
+        for col in 0..cols {
+            let (mut min, mut max) = (u8::MAX, u8::MIN);
+
+            for y in row * BLOCK_SIZE..(row + 1) * BLOCK_SIZE {
+                for x in col * BLOCK_SIZE..(col + 1) * BLOCK_SIZE {
+                    let luma = img.get_pixel(x, y)[0];
+                    min = min.min(luma);
+                    max = max.max(luma);
+                }
+            }
+
+            if max - min <= FLAT_RANGE { flat += 1; }
+        }
+    }
+
+    flat as f32 / (cols * rows) as f32
+}
+
+/// The fraction of pixels that differ sharply from the next pixel to the right or below.
+fn edge_fraction(img: &GrayImage) -> f32 {
+    let (width, height) = img.dimensions();
+    if width < 2 || height < 2 { return 0f32; }
+
+    let luma = |x, y| img.get_pixel(x, y)[0] as i16;
+    let mut edges = 0u32;
+
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            let here = luma(x, y);
+
+            if (luma(x + 1, y) - here).abs() > SHARP_EDGE || (luma(x, y + 1) - here).abs() > SHARP_EDGE {
+                edges += 1;
+            }
+        }
+    }
+
+    edges as f32 / ((width - 1) * (height - 1)) as f32
+}
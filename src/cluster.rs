@@ -0,0 +1,88 @@
+use crate::bktree::BkTree;
+use crate::config::ProgramSettings;
+use crate::img::{Image, UniqueImage};
+use crate::processing;
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+/// Group `images` into clusters of images that are connected by a chain of similar pairs,
+/// so if A is similar to B and B to C, all three end up together, whatever order they came in.
+///
+/// The first image of each cluster is reported as the original. Images at the far end
+/// of a chain can be more different from it than the threshold.
+pub fn cluster(images: Vec<Image>, settings: &ProgramSettings) -> Vec<UniqueImage> {
+    let mut index = BkTree::new();
+
+    for (idx, image) in images.iter().enumerate() {
+        index.insert_image(image, idx);
+    }
+
+    let mut sets = DisjointSets::new(images.len());
+
+    for (idx, image) in images.iter().enumerate() {
+        let max_dist = processing::max_dist(settings, image);
+
+        for other in index.find_image(image, max_dist) {
+            if other != idx && images[other].dist_ratio(image) < settings.threshold
+                    && settings.may_match(&images[other], image) {
+                sets.union(idx, other);
+            }
+        }
+    }
+
+    // Where the cluster of each root is in `uniques`
+    let mut clusters: HashMap<usize, usize> = HashMap::new();
+    let mut uniques: Vec<UniqueImage> = Vec::new();
+
+    for (idx, image) in images.into_iter().enumerate() {
+        match clusters.entry(sets.find(idx)) {
+            Entry::Occupied(entry) => uniques[*entry.get()].add_similar(image, settings.near_threshold),
+            Entry::Vacant(entry) => {
+                entry.insert(uniques.len());
+                uniques.push(UniqueImage::from_image(image));
+            },
+        }
+    }
+
+    uniques
+}
+
+/// Union-find over indices, with path compression and union by rank.
+struct DisjointSets {
+    parents: Vec<usize>,
+    ranks: Vec<u8>,
+}
+
+impl DisjointSets {
+
+    fn new(len: usize) -> DisjointSets {
+        DisjointSets {
+            parents: (0..len).collect(),
+            ranks: vec![0; len],
+        }
+    }
+
+    fn find(&mut self, idx: usize) -> usize {
+        let parent = self.parents[idx];
+        if parent == idx { return idx; }
+
+        let root = self.find(parent);
+        self.parents[idx] = root;
+        root
+    }
+
+    fn union(&mut self, left: usize, right: usize) {
+        let (left, right) = (self.find(left), self.find(right));
+        if left == right { return; }
+
+        if self.ranks[left] < self.ranks[right] {
+            self.parents[left] = right;
+        } else if self.ranks[left] > self.ranks[right] {
+            self.parents[right] = left;
+        } else {
+            self.parents[right] = left;
+            self.ranks[left] += 1;
+        }
+    }
+}
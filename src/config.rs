@@ -1,144 +1,842 @@
-use getopts::{OptGroup, optopt, optmulti, optflag, optflagopt, Matches, usage, getopts};
+use crate::archives;
+use crate::config_file::{self, ConfigFile};
+use crate::decoders::{Decoders, ImageDecoder};
+use crate::hash::{GpuHash, Hasher, ImageHash, PerceptualHasher};
+use crate::ignore::IgnoreList;
+use crate::img::Image;
+use crate::processing::{Spawner, Task};
+use crate::raw;
+use crate::search;
+use crate::storage;
+use crate::quarantine::Quarantine;
+use crate::throttle::Throttle;
 
-use serialize::json::{ToJson, Json};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
 
-use std::borrow::ToOwned;
+use getopts::{Matches, Options};
 
-use std::collections::BTreeMap;
+use glob::Pattern;
 
-use std::fmt::{Show, Formatter};
-use std::fmt::Result as FormatResult;
+use image::DynamicImage;
 
-use std::io::fs::PathExtensions;
+use log::LevelFilter;
 
-use std::os;
+use regex::Regex;
 
-#[deriving(Clone)]
+use serde::{Deserialize, Serialize};
+
+use std::env;
+
+use std::fmt;
+
+use std::io;
+
+use std::path::{Path, PathBuf};
+
+use std::sync::Arc;
+
+use std::time::SystemTime;
+
+#[derive(Clone)]
 pub struct ProgramSettings {
-    pub threads: uint,
-    pub dir: Path,
+    pub threads: usize,
+    pub queue_depth: usize,
+    pub dir: PathBuf,
+    /// The directories to search in Search mode: `dir`, or every directory given to scan
+    pub roots: Vec<Root>,
+    /// A file listing the paths to process instead of searching, `-` for stdin
+    pub files_from: Option<PathBuf>,
+    /// The S3 bucket and prefix to hash the objects of instead of searching, from `--s3`
+    pub s3: Option<String>,
+    /// Generate this many images to process instead of searching, if not 0
+    pub bench_synthetic: usize,
+    pub mode: Mode,
     pub recurse: bool,
+    pub follow_symlinks: bool,
+    /// Skip files and directories whose names start with a dot
+    pub skip_hidden: bool,
+    /// Also search inside zip, cbz and tar files, from `--archives`
+    pub archives: bool,
+    /// Names of directories that are never searched, like `.git` or `@eaDir`
+    pub skip_dirs: Vec<String>,
+    /// Also skip what `.gitignore` files list, not only `.imgdupignore` files
+    pub gitignore: bool,
+    /// Files smaller than this many bytes aren't searched for
+    pub min_size: u64,
+    /// Files larger than this many bytes aren't searched for, 0 for no limit
+    pub max_size: u64,
+    /// Only search for files modified at or after this time
+    pub modified_after: Option<SystemTime>,
+    /// Only search for files modified before this time
+    pub modified_before: Option<SystemTime>,
     pub exts: Vec<String>,    
+    /// Only match extensions exactly as given, without aliases like `jpe` for `jpg`
+    pub strict_ext: bool,
+    /// Find and decode images by their contents, whatever their extension
+    pub sniff: bool,
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Regex>,
     pub hash_size: u32,
     pub threshold: f32,
+    pub near_threshold: f32,
+    /// Group the originals less different than this into bursts, from `--bursts`
+    pub bursts: Option<f32>,
+    pub ignore: IgnoreList,
     pub fast: bool,
-    pub outfile: Option<Path>,
+    /// Where the main hashes are made, from `--backend` or `set_backend()`
+    pub backend: Backend,
+    pub combine: Vec<(HashType, f32)>,
+    pub exact_first: bool,
+    pub cluster: bool,
+    pub rotations: bool,
+    /// The most that's cropped off each side to match cropped copies, from 0 (off) to 0.4
+    pub crop: f32,
+    pub frames: FrameStrategy,
+    /// What to do with probable screenshots, from `--screenshots`
+    pub screenshots: Option<ScreenshotPolicy>,
+    /// Skip images of a single color, from `--blank-threshold`
+    pub blank_threshold: f32,
+    pub pair_raw: bool,
+    pub outfile: Option<PathBuf>,
+    /// Where to write every image of the run and its group as a Parquet table, from `--export-parquet`
+    pub export_parquet: Option<PathBuf>,
+    pub cache: Option<PathBuf>,
+    /// A SQLite database, or the URL of a PostgreSQL one, to keep hashes and results in,
+    /// from `--db` outside serve
+    pub results_db: Option<PathBuf>,
+    pub checkpoint: u64,
+    pub interval: u64,
+    /// With serve and agent, the address to listen on
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub listen: String,
+    pub timeout: u64,
+    pub max_pixels: u64,
+    pub max_file_size: u64,
+    /// Map image files into memory instead of reading them
+    pub mmap: bool,
+    /// Lower the priority of the workers and cap how fast they read, from `--throttle`
+    pub throttle: Option<Arc<Throttle>>,
+    pub thumbnails: Option<PathBuf>,
     pub dup_only: bool,
-    pub limit: uint,
+    pub min_group_size: usize,
+    pub max_groups: usize,
+    pub limit: usize,
+    pub action: Option<Action>,
+    pub original: RepresentativePolicy,
+    pub keep: RepresentativePolicy,
+    /// Where the original and the image kept come from before anything else, from
+    /// `--prefer-keep` and `--prefer-remove`
+    pub prefer: DirPreference,
+    pub action_tier: Tier,
+    /// Only apply `--action` to thumbnails of other images in the group, from `--thumbnails-only`
+    pub thumbnails_only: bool,
+    /// With dataset, where to write the paths of the images to drop, from `--drop-list`
+    pub drop_list: Option<PathBuf>,
+    /// With dataset, chooses between images `--keep` ranks the same, from `--seed`
+    pub seed: Option<u64>,
+    pub dry_run: bool,
+    /// Where `--action=quarantine` moves images, from `--quarantine-dir`
+    pub quarantine: Option<Quarantine>,
+    /// Where every file `--action` changes is recorded for `img-dup undo`, from `--audit-log`
+    pub audit_log: Option<PathBuf>,
+    /// What `--action=hardlink` does across filesystems, from `--cross-device`
+    pub cross_device: CrossDevice,
+    /// Review the results in a window, from `--gui`
+    pub gui: bool,
     pub json: JsonSettings,
-	pub gui: bool,
+    pub format: OutputFormat,
+    /// The shell `--format=script` writes for, from `--script-style`
+    pub script_style: ScriptStyle,
+    /// Which status messages are printed to stderr, from `--verbose` and `--quiet`
+    pub log_level: LevelFilter,
+    /// Record and report how long each image and each phase took, from `--timings`
+    pub timings: bool,
+    pub decoders: Decoders,
+    /// Replaces the DCT or fast hash, from `set_hasher()`
+    pub hasher: Option<Hasher>,
+    /// Makes the DCT or fast hash with `Backend::Gpu`
+    pub gpu: Option<GpuHash>,
+    /// Runs the hashing workers instead of new threads, from `set_spawner()`
+    pub spawner: Option<Spawner>,
 }
 
-unsafe impl Send for ProgramSettings {}
-
 impl ProgramSettings {
 
-    fn opts() -> Vec<OptGroup> {
-        vec!(
-            optopt("t", "threads",
+    fn opts() -> Options {
+        let mut opts = Options::new();
+
+        opts.optopt("t", "threads",
                    "How many threads the program should use to process images.
                    Defaults to the number of cores reported by the OS.",
-                   "[1+]"),
-            optopt("d", "dir",
+                   "[1+]")
+            .optopt("", "queue-depth",
+                   "How many processed images may wait to be collated before the threads
+                   pause. Lower it to save memory with very large directories. Default is 64.",
+                   "[1+]")
+            .optopt("d", "dir",
                    "The directory the program should search in. 
                    Default is the current working directory.",
-                   "[directory]"),
-            optflag("r", "recurse",
-                    "If present, the program will search subdirectories."),
-            optopt("h", "hash-size",
+                   "[directory]")
+            .optflag("r", "recurse",
+                    "If present, the program will search subdirectories.")
+            .optopt("", "files-from",
+                   "Process the files listed in the given file, or stdin with -, instead of
+                   searching. Paths are separated by newlines, or by NUL characters if there
+                   are any, as from find -print0. Every file listed is processed, whatever
+                   its extension. Relative paths are relative to the current directory.",
+                   "[file|-]")
+            .optopt("", "s3",
+                   "Process the objects in the given S3 bucket, under the prefix if one is
+                   given, instead of searching. Only with img_dup built with --features s3.
+                   Objects are downloaded into memory as they're hashed.",
+                   "[s3://bucket/prefix]")
+            .optopt("", "bench-synthetic",
+                   "Instead of searching, generate the given number of synthetic images,
+                   with resized and JPEG copies of some, in a temporary directory and
+                   process those, to measure performance. They're deleted afterwards.",
+                   "[1+]")
+            .optmulti("", "shallow",
+                     "With scan, also search the given directory, but not its subdirectories,
+                     even with --recurse. May be given more than once.",
+                     "[dir]")
+            .optflag("", "follow-symlinks",
+                    "Follow symbolic links to files and directories.
+                    By default they are skipped.")
+            .optflag("", "skip-hidden",
+                    "Skip files and directories whose names start with a dot.")
+            .optflag("", "archives",
+                    "Also search inside zip, cbz and tar files, for images named like
+                    archive.zip!/inner/path.jpg. --action leaves them alone. Only with
+                    img_dup built with --features archives.")
+            .optmulti("", "skip-dir",
+                     "Never search directories with the given name, in addition to
+                     .git, node_modules, @eaDir, .thumbnails, $RECYCLE.BIN and other
+                     folders of thumbnails and metadata made by NAS, OS and tools.
+                     May be given more than once.",
+                     "[name]")
+            .optflag("", "no-default-skip-dirs",
+                    "Also search the folders skipped by default, listed under --skip-dir.")
+            .optflag("", "gitignore",
+                    "Skip the files and directories listed in .gitignore files,
+                    as well as those in .imgdupignore files.")
+            .optopt("h", "hash-size",
                    "Helps the program decide the number of bits to use for the hash.
                    A higher number means more detail, but greater memory usage.
                    Default is 8",
-                   "[1+]"),
-            optopt("s", "threshold",
+                   "[1+]")
+            .optopt("s", "threshold",
                    "The amount in percentage that an image must be different from
                    another to qualify as unique. Default is 3",
-                   "[0.01 - 99.99]"),
-            optflag("f", "fast",
+                   "[0.01 - 99.99]")
+            .optopt("", "similarity",
+                   "Instead of --threshold, how similar in percentage an image must be to another
+                   to be reported, e.g. 95%. The same percentage works with any --hash-size.",
+                   "[0.01 - 99.99][%]")
+            .optopt("", "near-threshold",
+                   "Similar images that are less than this percentage different are tagged near,
+                   the rest possible. Images with the same hash are tagged exact. Default is 1",
+                   "[0.01 - 99.99]")
+            .optflagopt("", "bursts",
+                    "Also group the images that are more different than --threshold, but less
+                    than this percentage, e.g. photos taken in a burst, and report them apart
+                    from the groups of duplicates, under bursts. Default is 15",
+                    "[0.01 - 99.99] (optional)")
+            .optopt("", "ignore",
+                   "Never report images as similar if their hashes are listed in the given file.
+                   Each line is either one hash, to ignore every match of images with that hash,
+                   or two hashes separated by a space, to ignore matches between those two.
+                   Hashes are the same as in the JSON output.
+                   If not absolute, it will be relative to the search directory.",
+                   "[file]")
+            .optflag("f", "fast",
                     "Use a faster, less accurate algorithm.
                     Really only useful for finding duplicates.
-                    Using a low threshold and/or a larger hash is recommended."),
-            optmulti("e", "ext",
+                    Using a low threshold and/or a larger hash is recommended.")
+            .optopt("", "backend",
+                    "Where to resize images and take the DCT of their main hashes: cpu (default)
+                    or gpu, in batches of the images decoded at the same time. Combined hashes
+                    are still made on the CPU. Requires img_dup compiled with the gpu feature.",
+                    "[cpu|gpu]")
+            .optmulti("", "combine",
+                     "Also compute the given type of hash for every image,
+                     and compare images by the weighted average of the differences.
+                     The main hash (set by --fast) has a weight of 1.
+                     The weight defaults to 1 if omitted.
+                     color hashes the colors instead of the brightness, so black
+                     and white or recolored copies are no longer duplicates.
+                     May be given more than once.",
+                     "[dct|fast|color][:weight]")
+            .optflag("", "cluster",
+                    "Group images that are connected by a chain of similar images,
+                    so the groups don't depend on which image comes first.
+                    Groups may then contain images more different than the threshold.")
+            .optflag("", "exact-first",
+                    "Before hashing, find files with exactly the same contents
+                    and only hash one of each. Their copies are reported with 0% difference.")
+            .optflag("", "rotations",
+                    "Also match images that were rotated by a multiple of 90 degrees or mirrored.
+                    Hashing takes about 8 times as long.")
+            .optopt("", "crop",
+                   "Also match copies that were cropped by up to the given percentage on each
+                   side, or had borders of up to that size added, by hashing 6 central crops
+                   of every image. Such matches are reported as cropped. 10 is a good start.",
+                   "[percent]")
+            .optopt("", "screenshots",
+                   "Tag images that are probably screenshots, by their dimensions matching
+                   a common screen and large flat areas or sharp edges like text.
+                   tag: only tag them in the results.
+                   protect: also leave them alone with --action.
+                   separate: also only group screenshots with other screenshots.",
+                   "[tag|protect|separate]")
+            .optopt("", "blank-threshold",
+                   "Skip images where the brightness of every color varies less than this
+                   standard deviation, out of 255, as blank, and list them apart from the
+                   results. They would all be grouped together otherwise. 0 keeps them.
+                   Default is 2",
+                   "[0+]")
+            .optopt("", "frames",
+                   "Which frames of animated GIFs to hash.
+                   first: only the first frame (default).
+                   middle: only the frame in the middle of the animation.
+                   average: the average of the given number of evenly spaced frames, 5 if omitted.",
+                   "[first|middle|average[:N]]")
+            .optflag("", "video",
+                    "Also search for videos (mp4, webm, mkv), and match clips by frames taken
+                    across each one. Requires ffmpeg, and img_dup compiled with the video feature.")
+            .optflag("", "pair-raw",
+                    "Also search for camera RAW files (cr2, nef, arw, dng), and treat each one 
+                    with a JPEG of the same name in the same directory as part of that image,
+                    instead of hashing it. With --action delete or trash, they are removed together.")
+            .optmulti("e", "ext",
                      "Search for filenames with the given extension.
-                     Defaults are jpeg, jpg, png, and gif.",
-                     "[extension]"), 
-            optopt("o", "outfile",
+                     Defaults are jpeg, jpg, png, webp, tif, tiff, bmp and avif.
+                     AVIF and HEIC (heic, heif) images are decoded with the avifdec 
+                     and heif-convert programs, which must be installed separately.",
+                     "[extension]") 
+            .optflag("", "strict-ext",
+                    "Only search for files with exactly the given extensions, in the same case.
+                    By default, case is ignored and e.g. jpe and jfif files are found with jpg.")
+            .optflag("", "sniff",
+                    "Recognize images by their contents instead of trusting their extension,
+                    so files without one, or with the wrong one, are found and decoded too.
+                    Every file that doesn't have one of the extensions is opened to check it.")
+            .optopt("", "min-size",
+                   "Only search for files of at least the given number of bytes,
+                   e.g. to leave out icons and thumbnails.",
+                   "[0+]")
+            .optopt("", "max-size",
+                   "Only search for files of at most the given number of bytes.
+                   Unlike --max-file-size, larger files aren't reported.",
+                   "[1+]")
+            .optopt("", "modified-after",
+                   "Only search for files modified on or after the given date, 
+                   or date and time in RFC 3339 format.",
+                   "[YYYY-MM-DD|RFC 3339]")
+            .optopt("", "modified-before",
+                   "Only search for files modified before the given date,
+                   or date and time in RFC 3339 format.",
+                   "[YYYY-MM-DD|RFC 3339]")
+            .optmulti("i", "include",
+                     "Only search for files whose path (relative to the search directory) 
+                     matches the given glob pattern, e.g. \"**/photos/**\".
+                     May be given more than once. Default is to include all files.",
+                     "[glob]")
+            .optmulti("x", "exclude",
+                     "Skip files and directories whose path (relative to the search directory)
+                     matches the given regular expression. Directory paths end with a slash.
+                     Excluded directories aren't searched at all.
+                     May be given more than once.",
+                     "[regex]")
+            .optopt("o", "outfile",
                    "Output to the given file. If omitted, will print to stdout.
                    If not absolute, it will be relative to the search directory.",
-                   "[file]"),
-            optflag("", "help",
-                   "Display this help."),
-            optflag("u", "dup-only",
-                    "Only output images with similars or duplicates."),
-            optopt("l", "limit",
+                   "[file]")
+            .optopt("", "export-parquet",
+                   "Also write every image of this run, with its hash and group, to the given file
+                   as a Parquet table. Only with img_dup built with --features parquet.
+                   If not absolute, it will be relative to the search directory.",
+                   "[file]")
+            .optopt("", "against",
+                   "With query, the directory to search for matches, 
+                   or a hash database from --cache to look them up in without loading any images.",
+                   "[dir|hash database]")
+            .optopt("", "baseline",
+                   "With check, a hash database from --cache or update with the images that
+                   were already there. Only duplicates involving other images are reported.",
+                   "[hash database]")
+            .optopt("", "drop-list",
+                   "With dataset, write the paths of the images to drop to the given file,
+                   one per line. If not absolute, it will be relative to the search directory.",
+                   "[file]")
+            .optopt("", "seed",
+                   "With dataset, choose between the images of a group that --keep ranks the
+                   same at random, in an order that only depends on this number and their
+                   paths, instead of keeping the original.",
+                   "[0+]")
+            .optopt("c", "cache",
+                   "Reuse hashes of unchanged images from the given hash database (e.g. hashes.imgdup), 
+                   and save new hashes to it. 
+                   If not absolute, it will be relative to the search directory.",
+                   "[file]")
+            .optopt("", "checkpoint",
+                   "With --cache or --db, save the hashes every given number of seconds while
+                   processing, so an interrupted run can be resumed by running again with the same
+                   cache.
+                   0 only saves at the end. Default is 60.",
+                   "[0+]")
+            .optopt("", "timeout",
+                   "Give up on an image if loading and hashing it takes longer than the given 
+                   number of seconds, and report it separately from other errors.
+                   0 waits forever (default).",
+                   "[0+]")
+            .optopt("", "max-pixels",
+                   "Skip images with more than the given number of pixels (width times height),
+                   e.g. huge panoramas, and report them separately from other errors.
+                   The dimensions are read before decoding where the format allows it.
+                   0 means no limit (default).",
+                   "[0+]")
+            .optopt("", "max-file-size",
+                   "Skip files larger than the given number of bytes without reading them,
+                   and report them separately from other errors. 0 means no limit (default).",
+                   "[0+]")
+            .optflag("", "mmap",
+                    "Map image files into memory to decode them, instead of reading them,
+                    which saves copying and allocating for large files and scans.
+                    Files must not be changed or truncated while img_dup runs.")
+            .optflagopt("", "throttle",
+                    "Run in the background: lower the priority of the threads processing images,
+                    so other programs get the CPU first, and if a number is given, read images
+                    at no more than that many megabytes per second in total.",
+                    "[MB/s]")
+            .optopt("", "db",
+                   "With serve, the hash database to look uploaded images up in, 
+                   and to update when rescanning. Otherwise, a SQLite database to reuse hashes
+                   from like --cache, and to write the groups of similar images to. Only with
+                   img_dup built with --features sqlite. If not absolute, it will be relative
+                   to the search directory. A postgres:// URL uses a PostgreSQL database
+                   instead, shared with other machines, with --features postgres.",
+                   "[hash database|file|URL]")
+            .optopt("", "listen",
+                   "With serve and agent, the address and port to listen on. Default is 127.0.0.1:8080.",
+                   "[address:port]")
+            .optopt("", "interval",
+                   "With watch, how many seconds to wait between checks for new images if the directory can't be watched for changes. Default is 5.",
+                   "[1+]")
+            .optopt("", "thumbnails",
+                   "Save a small thumbnail of each image to the given directory while hashing,
+                   reusing thumbnails of unchanged images from previous runs.
+                   The HTML report embeds them, so it can be viewed anywhere.
+                   If not absolute, it will be relative to the search directory.",
+                   "[dir]")
+            .optopt("", "config",
+                   "Read options from the given TOML file, with the long option names as keys,
+                   e.g. recurse = true or ext = [\"jpg\", \"png\"].
+                   Options given on the command line override it.
+                   Default is img-dup.toml in the current directory, if it exists.",
+                   "[file]")
+            .optflag("", "help",
+                   "Display this help.")
+            .optflag("u", "dup-only",
+                    "Only output images with similars or duplicates.")
+            .optopt("", "min-group-size",
+                   "Only output groups of at least the given number of similar images,
+                   counting the original. --dup-only is the same as 2.",
+                   "[1+]")
+            .optopt("", "max-groups",
+                   "Only output the given number of groups, the ones that waste the most space first.
+                   0 outputs every group (default).",
+                   "[0+]")
+            .optopt("l", "limit",
                    "Only process the given number of images.",
-                   "[1+]"),
-            optflagopt("j", "json",
+                   "[1+]")
+            .optflagopt("j", "json",
                        "Output the results in JSON format.
-                       If outputting to stdout, normal output is suppressed.
                        An integer may optionally be passed with this flag,
                        indicating the number of spaces to indent per level.
                        Otherwise, the JSON will be in compact format.
                        See the README for details.",
-                       "[1+] (optional)"),
-			optflag("g", "gui",
-				"Open the GUI. Given command-line flags will be set
-				in the configuration dialog."),
-        )
+                       "[1+] (optional)")
+            .optopt("", "format",
+                   "The format to output the results in.
+                   text: human-readable (default).
+                   json: a single JSON document, same as --json.
+                   jsonl: JSON Lines, one JSON object per line for each image and its similars,
+                   then one for each error.
+                   csv: one row for each pair of an image and one of its similars.
+                   html: a page showing each group of similar images side-by-side,
+                   best used with -o report.html.
+                   toml: the same document as json, in TOML.
+                   bincode: the same document as json, in bincode, best used with -o.
+                   script: a shell script that applies --action (delete by default) to
+                   every group, keeping the image chosen by --keep, to look over and run.",
+                   "[text|json|jsonl|csv|html|toml|bincode|script]")
+            .optopt("", "script-style",
+                   "The shell --format=script writes for. Default is posix.",
+                   "[posix|powershell]")
+            .optopt("", "action",
+                   "After processing, keep one image out of each group of similar images
+                   and delete the rest, move them to the trash or to --quarantine-dir,
+                   or replace them with hard or symbolic links or reflink copies
+                   (btrfs, XFS and APFS only) of the kept image.
+                   Only trash and quarantine can be undone! Use --dry-run first.",
+                   &Action::choices())
+            .optopt("", "original",
+                   "Which image in each group to report as the original the others are measured from.
+                   largest: the highest resolution, then the largest file.
+                   largest-file: the largest file.
+                   oldest: the earliest modification time.
+                   shortest-path: the shortest path.
+                   dir:[dir]: an image in the given directory, e.g. your main photo library.
+                   first: the first image by path (default).",
+                   "[largest|largest-file|oldest|shortest-path|dir:[dir]|first]")
+            .optopt("", "keep",
+                   "Which image in each group to keep with --action,
+                   out of the same choices as --original. Default is the original.",
+                   "[largest|largest-file|oldest|shortest-path|dir:[dir]|first]")
+            .optmulti("", "prefer-keep",
+                     "Keep, and report as the original, an image in the given directory or its
+                     subdirectories over the others in its group, before --keep and --original
+                     choose between the rest. May be given more than once.",
+                     "[dir]")
+            .optmulti("", "prefer-remove",
+                     "The opposite of --prefer-keep: only keep, or report as the original,
+                     an image in the given directory if the whole group is in such directories.
+                     May be given more than once.",
+                     "[dir]")
+            .optopt("", "action-tier",
+                   "Only apply --action to images up to the given tier, so e.g. exact copies
+                   can be removed without reviewing the near and possible matches.
+                   Default is possible (all similar images).",
+                   "[exact|near|possible]")
+            .optflag("", "thumbnails-only",
+                    "Only apply --action to images that are scaled-down copies of a larger
+                    image in their group, in favor of that image rather than --keep's.")
+            .optflag("", "dry-run",
+                    "With --action, only print what would be done.")
+            .optopt("", "cross-device",
+                   "With --action=hardlink, what to do with an image on a different filesystem
+                   than the one kept, which can't be hard linked: symlink: make a symbolic link
+                   instead. skip: leave it alone (default).",
+                   "[symlink|skip]")
+            .optopt("", "quarantine-dir",
+                   "With --action=quarantine, the directory to move images into, under their
+                   paths relative to --dir. A manifest there lets img-dup restore put them back.",
+                   "[dir]")
+            .optopt("", "audit-log",
+                   "With --action, append every file changed to this file, one JSON object
+                   per line, with what was done and a checksum of what was there before.
+                   img-dup undo reverses what it can from it.",
+                   "[file]")
+            .optflag("", "gui",
+                    "After the results are written, review the groups of similar images in
+                    a window, where each image can be kept or have --action (trash by default)
+                    applied to it, instead of applying --action to every group.
+                    Requires img_dup compiled with the gui feature.")
+            .optflag("", "timings",
+                    "Record how long each image took to load and to hash, and how long searching,
+                    hashing and grouping took, and report them with percentiles in the results.")
+            .optflagmulti("v", "verbose",
+                    "Print more status messages to stderr: how long each image took with -v,
+                    and everything with -vv. RUST_LOG overrides this and --quiet.")
+            .optflag("q", "quiet",
+                    "Only print warnings and errors to stderr.");
+
+        opts
+    }
+
+    /// Use `decoder` for images with its extensions, instead of the `image` crate 
+    /// or a built-in decoder, and search for files with those extensions.
+    pub fn add_decoder<D: ImageDecoder + Send + Sync + 'static>(&mut self, decoder: D) {
+        for ext in decoder.extensions() {
+            if !self.exts.iter().any(|known| known == ext) {
+                self.exts.push(ext.to_string());
+            }
+        }
+
+        self.decoders.push(Arc::new(decoder));
+    }
+
+    /// Hash images with `hasher` instead of the DCT or fast hash. `--combine` and `--rotations`
+    /// still apply, but the hash database isn't used, since it can't tell the hashes apart.
+    pub fn set_hasher<H: PerceptualHasher + Send + Sync + 'static>(&mut self, hasher: H) {
+        self.hasher = Some(Arc::new(hasher));
+    }
+
+    /// Like `--backend`: with `Backend::Gpu`, open the GPU to make the main hashes on.
+    /// Fails if there's no GPU that can be used or img_dup wasn't compiled with the gpu feature.
+    pub fn set_backend(&mut self, backend: Backend) -> io::Result<()> {
+        self.gpu = open_gpu(backend)?;
+        self.backend = backend;
+        Ok(())
+    }
+
+    /// Run the `threads` hashing workers with `spawn` instead of on threads of their own,
+    /// e.g. `|task| pool.spawn(task)` to share a rayon thread pool with the rest of an application.
+    ///
+    /// Each worker runs until every image is hashed, so `spawn` must run them all at once to use
+    /// `threads` cores, and the results must not be waited for on a thread `spawn` needs to run
+    /// the workers. Decoders that can hang still get a thread of their own with `--timeout`.
+    pub fn set_spawner<F: Fn(Task) + Send + Sync + 'static>(&mut self, spawn: F) {
+        self.spawner = Some(Arc::new(spawn));
+    }
+
+    /// Like `--throttle`: lower the priority of the threads processing images, and unless
+    /// `max_read_rate` is 0, read images at no more than that many bytes per second in total.
+    pub fn set_throttle(&mut self, max_read_rate: u64) {
+        self.throttle = Some(Arc::new(Throttle::new(max_read_rate)));
+    }
+
+    /// If `a` and `b` may be reported as similar: they aren't ignored with `--ignore`, and with
+    /// `--screenshots=separate`, they're both screenshots or neither is.
+    pub fn may_match(&self, a: &Image, b: &Image) -> bool {
+        !self.ignore.is_ignored(a, b)
+            && (self.screenshots != Some(ScreenshotPolicy::Separate) || a.screenshot == b.screenshot)
+    }
+
+    /// If `--action` should leave `image` alone: it's a screenshot, with `--screenshots=protect`,
+    /// or it's inside an archive, which can't be changed in place.
+    pub fn is_protected(&self, image: &Image) -> bool {
+        (image.screenshot && self.screenshots == Some(ScreenshotPolicy::Protect))
+            || (self.archives && archives::split(&image.path).is_some())
+    }
+
+    /// The images in `group` to apply `--action` to, each with the image kept in its place, by index:
+    /// every image but `keep`, or with `--thumbnails-only`, every thumbnail with the image it's a thumbnail of.
+    pub fn action_pairs(&self, group: &[&Image], keep: usize) -> Vec<(usize, usize)> {
+        if !self.thumbnails_only {
+            return (0..group.len()).filter(|&idx| idx != keep).map(|idx| (keep, idx)).collect();
+        }
+
+        group.iter().enumerate()
+            .filter_map(|(idx, image)| {
+                let larger = image.thumbnail_of.as_ref()?;
+                group.iter().position(|other| other.path == *larger).map(|larger| (larger, idx))
+            })
+            .collect()
     }
 
     pub fn hash_settings(&self) -> HashSettings {
         HashSettings {
             hash_size: self.hash_size,
             fast: self.fast,
+            combine: self.combine.clone(),
+            rotations: self.rotations,
+            crop: self.crop,
+            frames: self.frames,
+            screenshots: self.screenshots.is_some(),
+            blank_threshold: self.blank_threshold,
+            timeout: self.timeout,
+            max_pixels: self.max_pixels,
+            max_file_size: self.max_file_size,
+            mmap: self.mmap,
+            throttle: self.throttle.clone(),
+            thumbnails: self.thumbnails.clone(),
+            sniff: self.sniff,
+            decoders: self.decoders.clone(),
+            hasher: self.hasher.clone(),
+            gpu: self.gpu.clone(),
         }          
     }
+}
 
-    pub fn silent_stdout(&self) -> bool {
-        self.outfile.is_none() && self.json.is_json()
+impl fmt::Display for ProgramSettings {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "Threads: {}", self.threads)?;
+        writeln!(fmt, "Directory: {}", &self.dir.display())?;
+        writeln!(fmt, "Recursive: {}", self.recurse)?;
+        writeln!(fmt, "Extensions: {:?}", self.exts)?;
+        writeln!(fmt, "Hash size: {}", self.hash_size)?;
+        writeln!(fmt, "Threshold: {0:.2}%", self.threshold * 100f32)?;
+        writeln!(fmt, "Fast: {}", self.fast)
     }
 }
 
-impl Show for ProgramSettings {
-    fn fmt(&self, fmt: &mut Formatter) -> FormatResult {
-        try!(writeln!(fmt, "Threads: {}", self.threads));
-        try!(writeln!(fmt, "Directory: {}", &self.dir.display()));
-        try!(writeln!(fmt, "Recursive: {}", self.recurse));
-        try!(writeln!(fmt, "Extensions: {}", self.exts.as_slice()));
-        try!(writeln!(fmt, "Hash size: {}", self.hash_size));
-        try!(writeln!(fmt, "Threshold: {0:.2}%", self.threshold * 100f32));
-        writeln!(fmt, "Fast: {}", self.fast)
+#[derive(Clone)]
+pub struct HashSettings {
+    pub hash_size: u32,
+    pub fast: bool,
+    pub combine: Vec<(HashType, f32)>,
+    pub rotations: bool,
+    pub crop: f32,
+    pub frames: FrameStrategy,
+    /// Tag probable screenshots
+    pub screenshots: bool,
+    /// Skip images whose channels vary less than this, or none if 0
+    pub blank_threshold: f32,
+    pub timeout: u64,
+    pub max_pixels: u64,
+    pub max_file_size: u64,
+    pub mmap: bool,
+    pub throttle: Option<Arc<Throttle>>,
+    pub thumbnails: Option<PathBuf>,
+    pub sniff: bool,
+    pub decoders: Decoders,
+    pub hasher: Option<Hasher>,
+    pub gpu: Option<GpuHash>,
+}
+
+impl HashSettings {
+
+    /// Only the hash size and algorithm, without combined hashes, rotations, limits or decoders.
+    pub fn new(hash_size: u32, fast: bool) -> HashSettings {
+        HashSettings {
+            hash_size,
+            fast,
+            combine: Vec::new(),
+            rotations: false,
+            crop: 0f32,
+            frames: FrameStrategy::First,
+            screenshots: false,
+            blank_threshold: 0f32,
+            timeout: 0,
+            max_pixels: 0,
+            max_file_size: 0,
+            mmap: false,
+            throttle: None,
+            thumbnails: None,
+            sniff: false,
+            decoders: Vec::new(),
+            hasher: None,
+            gpu: None,
+        }
+    }
+
+    /// The main hash of `img`: from the custom hasher if there is one, or else on the GPU
+    /// with `Backend::Gpu`.
+    pub fn hash(&self, img: &DynamicImage) -> ImageHash {
+        match (&self.hasher, &self.gpu) {
+            (Some(hasher), _) => ImageHash::from_bits(hasher.hash(img)),
+            (None, Some(gpu)) => gpu(img, self.hash_size, self.fast),
+            (None, None) => ImageHash::hash(img, self.hash_size, self.fast),
+        }
+    }
+
+    /// Only the main hash of the first frame is stored in the hash database,
+    /// and only if it's a DCT or fast hash. Screenshots aren't tagged in it.
+    pub fn is_cacheable(&self) -> bool {
+        self.combine.is_empty() && !self.rotations && self.crop == 0f32
+            && self.frames == FrameStrategy::First && self.hasher.is_none() && !self.screenshots
     }
 }
 
-impl ToJson for ProgramSettings {
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashType {
+    Dct,
+    Fast,
+    /// Only for `--combine`: the colors of the image, see `ImageHash::color_hash()`
+    Color,
+}
+
+impl HashType {
 
-    fn to_json(&self) -> Json {
-        let mut my_json = BTreeMap::new();
-        json_insert!(my_json, "threads", self.threads);
-        json_insert!(my_json, "dir", self.dir.display().to_string());
-        json_insert!(my_json, "recurse", self.recurse);
-        json_insert!(my_json, "exts", self.exts.as_slice());
-        json_insert!(my_json, "hash_size", self.hash_size);
-        json_insert!(my_json, "threshold", self.threshold);
-        json_insert!(my_json, "fast", self.fast);
-        json_insert!(my_json, "limit", self.limit);
+    pub fn is_fast(&self) -> bool {
+        *self == HashType::Fast
+    }
+
+    pub fn hash(&self, img: &DynamicImage, hash_size: u32) -> ImageHash {
+        match *self {
+            HashType::Dct => ImageHash::hash(img, hash_size, false),
+            HashType::Fast => ImageHash::hash(img, hash_size, true),
+            HashType::Color => ImageHash::color_hash(img, hash_size),
+        }
+    }
 
-        Json::Object(my_json)
+    fn from_str(hash_type: &str) -> Option<HashType> {
+        match hash_type {
+            "dct" => Some(HashType::Dct),
+            "fast" => Some(HashType::Fast),
+            "color" => Some(HashType::Color),
+            _ => None,
+        }
     }
 }
 
-#[deriving(Copy)]
-pub struct HashSettings {
-    pub hash_size: u32,
-    pub fast: bool,
+/// Which frames of an animated image are hashed.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum FrameStrategy {
+    First,
+    Middle,
+    /// The average of this many evenly spaced frames
+    Average(usize),
 }
 
-#[deriving(PartialEq, Eq, Copy, Clone)]
+impl FrameStrategy {
+
+    fn from_str(frames: &str) -> Option<FrameStrategy> {
+        let mut parts = frames.splitn(2, ':');
+
+        match (parts.next(), parts.next()) {
+            (Some("first"), None) => Some(FrameStrategy::First),
+            (Some("middle"), None) => Some(FrameStrategy::Middle),
+            (Some("average"), count) => count.map_or(Some(5), |count| count.parse::<usize>().ok())
+                .and_then(|count| if count > 0 { Some(FrameStrategy::Average(count)) } else { None }),
+            _ => None,
+        }
+    }
+}
+
+/// A directory to search, and whether to search its subdirectories.
+#[derive(Clone)]
+pub struct Root {
+    pub dir: PathBuf,
+    pub recurse: bool,
+}
+
+/// What to do with the images that are found, from the command before the options.
+#[derive(Clone)]
+pub enum Mode {
+    /// Find similar images in `dir` (default), or with `scan [dir]...`, 
+    /// in every one of the `roots` together
+    Search,
+    /// `compare [dir] [other dir]`: only match images in `dir` with images in the other directory
+    Compare(PathBuf),
+    /// `query [image] --against [dir|hash database]`: find the images in `dir`, 
+    /// or the hash database, that are similar to the image
+    Query(PathBuf, PathBuf),
+    /// `update [hash database] [dir]`: hash only new or modified images into the database,
+    /// and only report groups of similar images that they're in
+    Update(PathBuf),
+    /// `watch [dir]`: keep checking `dir` for new images and report the ones that are duplicates
+    Watch,
+    /// `check [dir] [--baseline hash database]`: report only the groups of similar images
+    /// that weren't already in the baseline, e.g. to stop duplicates being added in CI
+    Check(Option<PathBuf>),
+    /// `serve --db [hash database]`: answer queries for uploaded images over HTTP,
+    /// and rescan `dir` into the database on request
+    Serve(PathBuf),
+    /// `review [results.json]`: go through the groups of a report from `--format=json`
+    /// in the terminal, and delete the images marked for it
+    Review(PathBuf),
+    /// `apply [results.json] --action [action]`: apply `--action` to the groups of a report
+    /// from `--format=json` instead of searching again
+    Apply(PathBuf),
+    /// `restore [quarantine dir]`: move the images in a directory from `--action=quarantine`
+    /// back to where they were
+    Restore(PathBuf),
+    /// `undo [audit log]`: reverse the actions recorded with `--audit-log`, as far as possible
+    Undo(PathBuf),
+    /// `cross [hash database] [other hash database]`: only match images in the first database
+    /// with images in the other, e.g. from another machine, without reading the images
+    Cross(PathBuf, PathBuf),
+    /// `agent [--listen address:port]`: hash the images in `dir` on request, for `collate`
+    /// on another machine
+    Agent,
+    /// `collate [agent address]...`: have every agent hash its images, and find the similar
+    /// images among all of them. Without agents, `collate --db [file|postgres URL]` finds them
+    /// among every image in the database instead
+    Collate(Vec<String>),
+    /// `dataset [dir] [--drop-list file]`: output which images to keep and which to drop
+    /// to clean a dataset of duplicates, one path per line
+    Dataset,
+}
+
+#[derive(PartialEq, Eq, Copy, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum JsonSettings {
     NoJson,
     CompactJson,
-    PrettyJson(uint),
+    PrettyJson(u32),
 }
 
 impl JsonSettings {
@@ -148,37 +846,558 @@ impl JsonSettings {
     }
 }
 
+#[derive(PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Text,
+    Json,
+    #[serde(rename = "jsonl")]
+    JsonLines,
+    Csv,
+    Html,
+    Toml,
+    Bincode,
+    Script,
+}
+
+impl OutputFormat {
+
+    fn from_str(format: &str) -> Option<OutputFormat> {
+        match format {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "jsonl" => Some(OutputFormat::JsonLines),
+            "csv" => Some(OutputFormat::Csv),
+            "html" => Some(OutputFormat::Html),
+            "toml" => Some(OutputFormat::Toml),
+            "bincode" => Some(OutputFormat::Bincode),
+            "script" => Some(OutputFormat::Script),
+            _ => None,
+        }
+    }
+}
+
+/// What `--action=hardlink` does with an image on a different filesystem than the one kept.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum CrossDevice {
+    Symlink,
+    Skip,
+}
+
+impl CrossDevice {
+
+    fn from_str(cross_device: &str) -> Option<CrossDevice> {
+        match cross_device {
+            "symlink" => Some(CrossDevice::Symlink),
+            "skip" => Some(CrossDevice::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// What `--screenshots` does with the images it tags.
+#[derive(PartialEq, Eq, Copy, Clone)]
+pub enum ScreenshotPolicy {
+    Tag,
+    /// Left out of `--action`
+    Protect,
+    /// Never in the same group as an image that isn't one
+    Separate,
+}
+
+impl ScreenshotPolicy {
+
+    fn from_str(policy: &str) -> Option<ScreenshotPolicy> {
+        match policy {
+            "tag" => Some(ScreenshotPolicy::Tag),
+            "protect" => Some(ScreenshotPolicy::Protect),
+            "separate" => Some(ScreenshotPolicy::Separate),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptStyle {
+    Posix,
+    PowerShell,
+}
+
+impl ScriptStyle {
+
+    fn from_str(style: &str) -> Option<ScriptStyle> {
+        match style {
+            "posix" => Some(ScriptStyle::Posix),
+            "powershell" => Some(ScriptStyle::PowerShell),
+            _ => None,
+        }
+    }
+}
+
+/// Where the main hashes are made, from `--backend`.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Backend {
+    Cpu,
+    /// Requires the gpu feature
+    Gpu,
+}
+
+impl Backend {
+
+    fn from_str(backend: &str) -> Option<Backend> {
+        match backend {
+            "cpu" => Some(Backend::Cpu),
+            "gpu" => Some(Backend::Gpu),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Delete,
+    Trash,
+    Hardlink,
+    Symlink,
+    /// Replace with a copy-on-write clone, on filesystems that support it
+    Reflink,
+    Quarantine,
+}
+
+impl Action {
+
+    const ALL: [Action; 6] = [Action::Delete, Action::Trash, Action::Hardlink, Action::Symlink,
+                              Action::Reflink, Action::Quarantine];
+
+    /// Every action as it's given to `--action`, e.g. "[delete|trash|...]".
+    fn choices() -> String {
+        let verbs: Vec<&str> = Action::ALL.iter().map(Action::verb).collect();
+        format!("[{}]", verbs.join("|"))
+    }
+
+    fn from_str(action: &str) -> Option<Action> {
+        match action {
+            "delete" => Some(Action::Delete),
+            "trash" => Some(Action::Trash),
+            "hardlink" => Some(Action::Hardlink),
+            "symlink" => Some(Action::Symlink),
+            "reflink" => Some(Action::Reflink),
+            "quarantine" => Some(Action::Quarantine),
+            _ => None,
+        }
+    }
+
+    pub fn verb(&self) -> &'static str {
+        match *self {
+            Action::Delete => "delete",
+            Action::Trash => "trash",
+            Action::Hardlink => "hardlink",
+            Action::Symlink => "symlink",
+            Action::Reflink => "reflink",
+            Action::Quarantine => "quarantine",
+        }
+    }
+
+    pub fn past_tense(&self) -> &'static str {
+        match *self {
+            Action::Delete => "Deleted",
+            Action::Trash => "Trashed",
+            Action::Hardlink => "Hardlinked",
+            Action::Symlink => "Symlinked",
+            Action::Reflink => "Reflinked",
+            Action::Quarantine => "Quarantined",
+        }
+    }
+
+    /// If the image is removed, rather than replaced with a link.
+    pub fn removes(&self) -> bool {
+        *self == Action::Delete || *self == Action::Trash || *self == Action::Quarantine
+    }
+}
+
+/// Which image of a group is reported as the original (`--original`), or kept (`--keep`).
+#[derive(PartialEq, Eq, Clone)]
+pub enum RepresentativePolicy {
+    /// The highest resolution, then the largest file
+    Largest,
+    LargestFile,
+    /// The earliest modification time
+    Oldest,
+    ShortestPath,
+    /// Any image in this directory or its subdirectories
+    InDir(PathBuf),
+    /// The image the others were compared to
+    First,
+}
+
+impl RepresentativePolicy {
+
+    fn from_str(policy: &str) -> Option<RepresentativePolicy> {
+        match policy {
+            "largest" => Some(RepresentativePolicy::Largest),
+            "largest-file" => Some(RepresentativePolicy::LargestFile),
+            "oldest" => Some(RepresentativePolicy::Oldest),
+            "shortest-path" => Some(RepresentativePolicy::ShortestPath),
+            "first" => Some(RepresentativePolicy::First),
+            _ if policy.starts_with("dir:") => std::path::absolute(&policy[4..]).ok()
+                .map(RepresentativePolicy::InDir),
+            _ => None,
+        }
+    }
+}
+
+/// Directories to choose the original and the image kept from, or not to, over what
+/// `--original` and `--keep` would choose.
+#[derive(PartialEq, Eq, Clone, Default)]
+pub struct DirPreference {
+    pub keep: Vec<PathBuf>,
+    pub remove: Vec<PathBuf>,
+}
+
+impl DirPreference {
+
+    pub fn is_empty(&self) -> bool {
+        self.keep.is_empty() && self.remove.is_empty()
+    }
+
+    /// 2 for an image in a directory to keep, 0 for one in a directory to remove from, and 1
+    /// otherwise. For an image in both, e.g. a directory to keep inside one to remove from,
+    /// the one closest to it counts.
+    pub fn rank(&self, path: &Path) -> u64 {
+        if self.is_empty() { return 1; }
+
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+        let deepest = |dirs: &[PathBuf]| dirs.iter()
+            .filter(|dir| path.starts_with(dir))
+            .map(|dir| dir.components().count())
+            .max();
+
+        match (deepest(&self.keep), deepest(&self.remove)) {
+            (Some(keep), Some(remove)) if keep >= remove => 2,
+            (Some(_), Some(_)) => 0,
+            (Some(_), None) => 2,
+            (None, Some(_)) => 0,
+            (None, None) => 1,
+        }
+    }
+}
+
+/// How close a similar image is to its original.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tier {
+    /// The hashes are identical
+    Exact,
+    /// Less different than `--near-threshold`
+    Near,
+    /// Less different than `--threshold`
+    Possible,
+}
+
+impl Tier {
+
+    pub fn of(dist_ratio: f32, near_threshold: f32) -> Tier {
+        if dist_ratio == 0f32 {
+            Tier::Exact
+        } else if dist_ratio < near_threshold {
+            Tier::Near
+        } else {
+            Tier::Possible
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Tier::Exact => "exact",
+            Tier::Near => "near",
+            Tier::Possible => "possible",
+        }
+    }
+
+    fn from_str(tier: &str) -> Option<Tier> {
+        match tier {
+            "exact" => Some(Tier::Exact),
+            "near" => Some(Tier::Near),
+            "possible" => Some(Tier::Possible),
+            _ => None,
+        }
+    }
+}
+
+/// `args` doesn't include the program name.
 pub fn parse_args(args: &[String]) -> ProgramSettings {
     let settings_opts = ProgramSettings::opts();
     
-    let ref opts = getopts(args, settings_opts.as_slice()).unwrap();
+    let cli_opts = settings_opts.parse(args).unwrap_or_else(|err| panic!("{}", err));
+
+    // Options from the config file go first, for the ones not given on the command line
+    let opts = &match config_file::config_path(&cli_opts) {
+        Some(path) => {
+            let config = ConfigFile::load(&path, &settings_opts)
+                .unwrap_or_else(|err| panic!("Could not read config file: {}: {}", path.display(), err));
+
+            let mut all_args = config.args_not_in(&cli_opts);
+            all_args.extend(args.iter().cloned());
+
+            settings_opts.parse(&all_args).unwrap_or_else(|err| panic!("{}", err))
+        },
+        None => cli_opts,
+    };
     
     if opts.opt_present("help") {
-        print_help_and_exit(settings_opts.as_slice());    
+        print_help_and_exit(&settings_opts);    
     }
 
-    let exts_default = vec!("jpeg", "jpg", "png");
+    let exts_default = vec!("jpeg", "jpg", "png", "webp", "tif", "tiff", "bmp", "avif");
 
-    let dir = dir_arg(opts, "dir", os::getcwd().unwrap());
+    let (mode, mode_dir) = mode_args(opts);
+
+    let recurse = opts.opt_present("recurse");
+    let scan_roots = scan_roots_args(opts, recurse);
+
+    let dir = match (mode_dir, scan_roots.first()) {
+        (Some(dir), _) => dir,
+        // Paths in the other roots are output in full, like the other directory in compare
+        (None, Some(root)) => root.dir.clone(),
+        (None, None) => dir_arg(opts, "dir", env::current_dir().unwrap()),
+    };
+
+    let files_from = opts.opt_str("files-from").map(PathBuf::from);
+
+    if files_from.is_some() {
+        assert!(matches!(mode, Mode::Search | Mode::Update(_) | Mode::Check(_) | Mode::Dataset) && scan_roots.is_empty(),
+                "files-from can only be given without a mode, or with update, check or dataset");
+    }
+
+    let s3 = opts.opt_str("s3");
+
+    if let Some(ref url) = s3 {
+        if !cfg!(feature = "s3") {
+            panic!("img_dup was not compiled with S3 support!");
+        }
+
+        assert!(url.starts_with("s3://"), "Value passed to s3 is not an s3:// URL: {}", url);
+        assert!(matches!(mode, Mode::Search) && files_from.is_none() && scan_roots.is_empty(),
+                "s3 can only be given without a mode, files-from or scan");
+        // The objects aren't files to change
+        assert!(!opts.opt_present("action") && !opts.opt_present("gui"), "action and gui can't be given with s3");
+    }
+
+    let bench_synthetic = usize_arg(opts, "bench-synthetic", 0);
+
+    if bench_synthetic > 0 {
+        assert!(matches!(mode, Mode::Search) && files_from.is_none() && s3.is_none() && scan_roots.is_empty(),
+                "bench-synthetic can only be given without a mode, files-from, s3 or scan");
+    }
+
+    let roots = if scan_roots.is_empty() {
+        vec![Root { dir: dir.clone(), recurse }]
+    } else {
+        scan_roots
+    };
+
+    let pair_raw = opts.opt_present("pair-raw");
+
+    let mut exts = exts_args(opts, "ext", exts_default);
+
+    if pair_raw {
+        exts.extend(raw::RAW_EXTS.iter().map(|ext| ext.to_string()));
+    }
+
+    if opts.opt_present("video") {
+        exts.extend(video_exts().iter().map(|ext| ext.to_string()));
+    }
+
+    let (min_size, max_size) = (u64_arg(opts, "min-size", 0), u64_arg(opts, "max-size", 0));
+
+    assert!(max_size == 0 || min_size <= max_size, 
+            "Value passed to min-size must not be larger than max-size");
+
+    let backend = opts.opt_str("backend").map_or(Backend::Cpu, |backend|
+        Backend::from_str(&backend)
+            .unwrap_or_else(|| panic!("Unknown value passed to backend: {}", backend))
+    );
+
+    if backend == Backend::Gpu && !cfg!(feature = "gpu") {
+        panic!("img_dup was not compiled with GPU support!");
+    }
+
+    let json = json_arg(opts, "json", JsonSettings::NoJson);
+    let format = format_arg(opts, "format", json);
+
+    let gui = opts.opt_present("gui");
+
+    let threshold = threshold_arg(opts);
+    let bursts = bursts_arg(opts, threshold);
+
+    let quarantine = outfile_arg(opts, "quarantine-dir", &dir).map(|quarantine_dir| Quarantine::new(quarantine_dir, dir.clone()));
+
+    if opts.opt_str("action").as_deref() == Some("quarantine") {
+        assert!(quarantine.is_some(), "action quarantine needs --quarantine-dir [dir]");
+    }
+
+    let script_style = opts.opt_str("script-style").map_or(ScriptStyle::Posix, |style|
+        ScriptStyle::from_str(&style)
+            .unwrap_or_else(|| panic!("Unknown value passed to script-style: {}", style))
+    );
+
+    if format == OutputFormat::Script && script_style == ScriptStyle::PowerShell {
+        assert!(opts.opt_str("action").as_deref() != Some("reflink"),
+                "action reflink can't be written as a PowerShell script");
+    }
+
+    if gui {
+        if !cfg!(feature = "gui") {
+            panic!("img_dup was not compiled with GUI support!");
+        }
+
+        assert!(!matches!(mode, Mode::Watch | Mode::Serve(_) | Mode::Review(_) | Mode::Apply(_) | Mode::Restore(_)
+                              | Mode::Undo(_) | Mode::Cross(..) | Mode::Agent | Mode::Collate(_) | Mode::Dataset),
+                "gui can't be given with watch, serve, review, apply, restore, undo, cross, agent, collate or dataset");
+    }
+
+    // The images may be on other machines
+    if let Mode::Cross(..) | Mode::Collate(_) = mode {
+        assert!(!opts.opt_present("action"), "action can't be given with cross or collate");
+    }
+
+    let seed = opts.opt_str("seed").map(|seed| seed.parse::<u64>()
+        .unwrap_or_else(|_| panic!("Value passed to seed is not a number: {}", seed)));
+
+    let results_db = results_db_arg(opts, &mode, &dir);
+
+    if let Mode::Dataset = mode {
+        // The keep list is the output, and the drop list is what an action would apply to
+        assert!(format == OutputFormat::Text, "format and json can't be given with dataset");
+        assert!(!opts.opt_present("action"), "action can't be given with dataset");
+    } else {
+        assert!(!opts.opt_present("drop-list") && seed.is_none(), "drop-list and seed can only be given with dataset");
+    }
 
     ProgramSettings {
-        threads: uint_arg(opts, "threads", os::num_cpus()),
+        threads: usize_arg(opts, "threads", std::thread::available_parallelism().map_or(1, |cpus| cpus.get())),
+        queue_depth: usize_arg(opts, "queue-depth", 64),
         dir: dir.clone(),
-        recurse: opts.opt_present("recurse"),
-        hash_size: uint_arg(opts, "hash-size", 8) as u32,
-        threshold: pos_f32_arg(opts, "threshold", 3f32) / 100f32,
+        roots,
+        files_from,
+        s3,
+        bench_synthetic,
+        mode,
+        recurse,
+        follow_symlinks: opts.opt_present("follow-symlinks"),
+        skip_hidden: opts.opt_present("skip-hidden"),
+        archives: archives_arg(opts),
+        skip_dirs: skip_dirs_args(opts, "skip-dir"),
+        gitignore: opts.opt_present("gitignore"),
+        min_size,
+        max_size,
+        modified_after: time_arg(opts, "modified-after"),
+        modified_before: time_arg(opts, "modified-before"),
+        hash_size: usize_arg(opts, "hash-size", 8) as u32,
+        threshold,
+        near_threshold: pos_f32_arg(opts, "near-threshold", 1f32) / 100f32,
+        bursts,
+        ignore: outfile_arg(opts, "ignore", &dir).map_or(IgnoreList::new(), |path|
+            IgnoreList::load(&path)
+                .unwrap_or_else(|err| panic!("Could not read ignore file: {}: {}", path.display(), err))
+        ),
         fast: opts.opt_present("fast"),
-        exts: exts_args(opts, "ext", exts_default),
+        backend,
+        combine: combine_args(opts, "combine"),
+        exact_first: opts.opt_present("exact-first"),
+        cluster: opts.opt_present("cluster"),
+        pair_raw,
+        rotations: opts.opt_present("rotations"),
+        crop: crop_arg(opts),
+        frames: opts.opt_str("frames").map_or(FrameStrategy::First, |frames|
+            FrameStrategy::from_str(&frames)
+                .unwrap_or_else(|| panic!("Unknown value passed to frames: {}", frames))
+        ),
+        blank_threshold: opts.opt_str("blank-threshold").map_or(2f32, |threshold| {
+            let threshold = threshold.parse::<f32>()
+                .unwrap_or_else(|_| panic!("Value passed to blank-threshold is not a number: {}", threshold));
+            assert!(threshold >= 0f32, "Value passed to blank-threshold must not be negative");
+            threshold
+        }),
+        screenshots: opts.opt_str("screenshots").map(|screenshots|
+            ScreenshotPolicy::from_str(&screenshots)
+                .unwrap_or_else(|| panic!("Unknown value passed to screenshots: {}", screenshots))
+        ),
+        exts,
+        strict_ext: opts.opt_present("strict-ext"),
+        sniff: opts.opt_present("sniff"),
+        include: glob_args(opts, "include"),
+        exclude: regex_args(opts, "exclude"),
         outfile: outfile_arg(opts, "outfile", &dir),
+        export_parquet: export_parquet_arg(opts, &dir),
+        cache: outfile_arg(opts, "cache", &dir),
+        results_db,
+        checkpoint: u64_arg(opts, "checkpoint", 60),
+        interval: u64_arg(opts, "interval", 5),
+        listen: opts.opt_str("listen").unwrap_or_else(|| "127.0.0.1:8080".to_string()),
+        timeout: u64_arg(opts, "timeout", 0),
+        max_pixels: u64_arg(opts, "max-pixels", 0),
+        max_file_size: u64_arg(opts, "max-file-size", 0),
+        mmap: opts.opt_present("mmap"),
+        throttle: throttle_arg(opts),
+        thumbnails: outfile_arg(opts, "thumbnails", &dir),
         dup_only: opts.opt_present("dup-only"),
-        limit: uint_arg(opts, "limit", 0),
-        json: json_arg(opts, "json", JsonSettings::NoJson),
-		gui: opts.opt_present("gui"), 
+        min_group_size: usize_arg(opts, "min-group-size", 1),
+        max_groups: usize_arg(opts, "max-groups", 0),
+        limit: usize_arg(opts, "limit", 0),
+        action: opts.opt_str("action").map(|action| 
+            Action::from_str(&action)
+                .unwrap_or_else(|| panic!("Unknown value passed to action: {}", action))
+        ),
+        original: opts.opt_str("original").map_or(RepresentativePolicy::First, |original|
+            RepresentativePolicy::from_str(&original)
+                .unwrap_or_else(|| panic!("Unknown value passed to original: {}", original))
+        ),
+        keep: opts.opt_str("keep").map_or(RepresentativePolicy::First, |keep|
+            RepresentativePolicy::from_str(&keep)
+                .unwrap_or_else(|| panic!("Unknown value passed to keep: {}", keep))
+        ),
+        prefer: DirPreference {
+            keep: dirs_args(opts, "prefer-keep"),
+            remove: dirs_args(opts, "prefer-remove"),
+        },
+        action_tier: opts.opt_str("action-tier").map_or(Tier::Possible, |tier|
+            Tier::from_str(&tier)
+                .unwrap_or_else(|| panic!("Unknown value passed to action-tier: {}", tier))
+        ),
+        thumbnails_only: opts.opt_present("thumbnails-only"),
+        drop_list: outfile_arg(opts, "drop-list", &dir),
+        seed,
+        dry_run: opts.opt_present("dry-run"),
+        quarantine,
+        audit_log: outfile_arg(opts, "audit-log", &dir),
+        cross_device: opts.opt_str("cross-device").map_or(CrossDevice::Skip, |cross_device|
+            CrossDevice::from_str(&cross_device)
+                .unwrap_or_else(|| panic!("Unknown value passed to cross-device: {}", cross_device))
+        ),
+        gui,
+        // `--format=json` without `--json` means compact JSON
+        json: if format == OutputFormat::Json && !json.is_json() { 
+            JsonSettings::CompactJson 
+        } else { 
+            json 
+        },
+        format,
+        script_style,
+        log_level: log_level_arg(opts),
+        timings: opts.opt_present("timings"),
+        decoders: Vec::new(),
+        hasher: None,
+        gpu: open_gpu(backend).unwrap_or_else(|err| panic!("Could not use the GPU: {}", err)),
+        spawner: None,
     }    
 }
 
-fn dir_arg(args: &Matches, arg: &str, default: Path) -> Path {
-    let dir = args.opt_str(arg).map_or(default, |path| Path::new(path) );
+fn dir_arg(args: &Matches, arg: &str, default: PathBuf) -> PathBuf {
+    let dir = args.opt_str(arg).map_or(default, PathBuf::from);
 
     assert!(dir.is_dir(), "Value passed to {} is not a directory: {}", 
             arg, dir.display());
@@ -186,9 +1405,237 @@ fn dir_arg(args: &Matches, arg: &str, default: Path) -> Path {
     dir
 }
 
-fn outfile_arg(args: &Matches, arg: &str, dir: &Path) -> Option<Path> {
+/// `--db` outside serve, which reads it as the hash database in `mode_args()`.
+fn results_db_arg(args: &Matches, mode: &Mode, dir: &Path) -> Option<PathBuf> {
+    if let Mode::Serve(_) = *mode { return None; }
+
+    let db = args.opt_str("db")?;
+
+    if storage::is_postgres_url(&db) {
+        if !cfg!(feature = "postgres") {
+            panic!("img_dup was not compiled with PostgreSQL support!");
+        }
+    } else if !cfg!(feature = "sqlite") {
+        panic!("img_dup was not compiled with SQLite support!");
+    }
+
+    assert!(!args.opt_present("cache"), "cache and db can't be given together");
+
+    // Not a path, so not relative to anything
+    if storage::is_postgres_url(&db) { return Some(PathBuf::from(db)); }
+
+    outfile_arg(args, "db", dir)
+}
+
+fn export_parquet_arg(args: &Matches, dir: &Path) -> Option<PathBuf> {
+    if args.opt_present("export-parquet") && !cfg!(feature = "parquet") {
+        panic!("img_dup was not compiled with Parquet support!");
+    }
+
+    outfile_arg(args, "export-parquet", dir)
+}
+
+fn archives_arg(args: &Matches) -> bool {
+    let archives = args.opt_present("archives");
+
+    if archives && !cfg!(feature = "archives") {
+        panic!("img_dup was not compiled with archive support!");
+    }
+
+    archives
+}
+
+/// Also returns the directory to search instead of `--dir`, if the mode has one.
+fn mode_args(args: &Matches) -> (Mode, Option<PathBuf>) {
+    let absolute = |path: &String| std::path::absolute(path).unwrap();
+
+    match args.free.as_slice() {
+        [] => (Mode::Search, None),
+        // The directories are read by `scan_roots_args()`
+        [mode, ..] if mode == "scan" => (Mode::Search, None),
+        [mode, dir, other] if mode == "compare" => {
+            let (dir, other) = (absolute(dir), absolute(other));
+
+            for path in [&dir, &other] {
+                assert!(path.is_dir(), "Value passed to compare is not a directory: {}", path.display());
+            }
+
+            (Mode::Compare(other), Some(dir))
+        },
+        [mode, image] if mode == "query" => {
+            let image = absolute(image);
+            assert!(image.is_file(), "Value passed to query is not a file: {}", image.display());
+
+            let against = absolute(&args.opt_str("against")
+                .expect("query needs --against [dir|hash database]"));
+            assert!(against.exists(), "Value passed to against does not exist: {}", against.display());
+
+            // Paths in a hash database are absolute, so output them relative to where it is 
+            let dir = if against.is_dir() { against.clone() } else { against.parent().unwrap().to_path_buf() };
+
+            (Mode::Query(image, against), Some(dir))
+        },
+        [mode, db, dir] if mode == "update" => {
+            let dir = absolute(dir);
+            assert!(dir.is_dir(), "Value passed to update is not a directory: {}", dir.display());
+
+            (Mode::Update(absolute(db)), Some(dir))
+        },
+        [mode, dir] if mode == "watch" => {
+            let dir = absolute(dir);
+            assert!(dir.is_dir(), "Value passed to watch is not a directory: {}", dir.display());
+
+            (Mode::Watch, Some(dir))
+        },
+        [mode, dir] if mode == "check" => {
+            let dir = absolute(dir);
+            assert!(dir.is_dir(), "Value passed to check is not a directory: {}", dir.display());
+
+            let baseline = args.opt_str("baseline").map(|baseline| absolute(&baseline));
+
+            if let Some(ref baseline) = baseline {
+                assert!(baseline.is_file(), "Value passed to baseline is not a file: {}", baseline.display());
+            }
+
+            (Mode::Check(baseline), Some(dir))
+        },
+        [mode] if mode == "serve" => {
+            if !cfg!(feature = "server") {
+                panic!("img_dup was not compiled with server support!");
+            }
+
+            let db = absolute(&args.opt_str("db").expect("serve needs --db [hash database]"));
+
+            (Mode::Serve(db), None)
+        },
+        [mode, report] if mode == "review" => {
+            if !cfg!(feature = "tui") {
+                panic!("img_dup was not compiled with TUI support!");
+            }
+
+            let report = absolute(report);
+            assert!(report.is_file(), "Value passed to review is not a file: {}", report.display());
+
+            (Mode::Review(report), None)
+        },
+        [mode, report] if mode == "apply" => {
+            let report = absolute(report);
+            assert!(report.is_file(), "Value passed to apply is not a file: {}", report.display());
+            assert!(args.opt_present("action"), "apply needs --action {}", Action::choices());
+
+            (Mode::Apply(report), None)
+        },
+        [mode, quarantine_dir] if mode == "restore" => {
+            let quarantine_dir = absolute(quarantine_dir);
+            assert!(quarantine_dir.is_dir(), "Value passed to restore is not a directory: {}", quarantine_dir.display());
+
+            (Mode::Restore(quarantine_dir), None)
+        },
+        [mode, audit_log] if mode == "undo" => {
+            let audit_log = absolute(audit_log);
+            assert!(audit_log.is_file(), "Value passed to undo is not a file: {}", audit_log.display());
+
+            (Mode::Undo(audit_log), None)
+        },
+        [mode, db, other] if mode == "cross" => {
+            let (db, other) = (absolute(db), absolute(other));
+
+            for path in [&db, &other] {
+                assert!(path.is_file(), "Value passed to cross is not a file: {}", path.display());
+            }
+
+            (Mode::Cross(db, other), None)
+        },
+        [mode] if mode == "agent" => {
+            if !cfg!(feature = "server") {
+                panic!("img_dup was not compiled with server support!");
+            }
+
+            (Mode::Agent, None)
+        },
+        [mode] if mode == "collate" => {
+            assert!(args.opt_present("db"), "collate needs agent addresses or --db [file|postgres URL]");
+
+            (Mode::Collate(Vec::new()), None)
+        },
+        [mode, agents @ ..] if mode == "collate" => {
+            if !cfg!(feature = "server") {
+                panic!("img_dup was not compiled with server support!");
+            }
+
+            (Mode::Collate(agents.to_vec()), None)
+        },
+        [mode, dir] if mode == "dataset" => {
+            let dir = absolute(dir);
+            assert!(dir.is_dir(), "Value passed to dataset is not a directory: {}", dir.display());
+
+            (Mode::Dataset, Some(dir))
+        },
+        _ => panic!("Unexpected arguments. Usage:
+    img-dup [options]
+    img-dup scan [dir]... [--shallow dir] [options]
+    img-dup compare [dir] [other dir] [options]
+    img-dup query [image] --against [dir|hash database] [options]
+    img-dup update [hash database] [dir] [options]
+    img-dup watch [dir] [options]
+    img-dup check [dir] [--baseline hash database] [options]
+    img-dup serve --db [hash database] [--listen address:port] [options]
+    img-dup review [results.json] [options]
+    img-dup apply [results.json] --action [action] [options]
+    img-dup restore [quarantine dir] [options]
+    img-dup undo [audit log] [options]
+    img-dup cross [hash database] [other hash database] [options]
+    img-dup agent [--listen address:port] [options]
+    img-dup collate [agent address:port]... [options]
+    img-dup collate --db [file|postgres URL] [options]
+    img-dup dataset [dir] [--drop-list file] [--seed 0+] [options]"),
+    }
+}
+
+/// With scan, the directories after it, and those from `--shallow` without their subdirectories.
+/// Empty for every other mode.
+fn scan_roots_args(args: &Matches, recurse: bool) -> Vec<Root> {
+    let shallow = args.opt_strs("shallow");
+
+    if args.free.first().is_none_or(|mode| mode != "scan") {
+        assert!(shallow.is_empty(), "shallow can only be given with scan");
+        return Vec::new();
+    }
+
+    let roots: Vec<Root> = args.free[1..].iter().map(|dir| (dir, recurse))
+        .chain(shallow.iter().map(|dir| (dir, false)))
+        .map(|(dir, recurse)| Root { dir: std::path::absolute(dir).unwrap(), recurse })
+        .collect();
+
+    assert!(!roots.is_empty(), "scan needs at least one directory");
+
+    for root in &roots {
+        assert!(root.dir.is_dir(), "Value passed to scan is not a directory: {}", root.dir.display());
+    }
+
+    roots
+}
+
+/// `--similarity` is the complement of `--threshold`, so only one of them may be given.
+fn threshold_arg(args: &Matches) -> f32 {
+    match args.opt_str("similarity") {
+        Some(similarity) => {
+            assert!(!args.opt_present("threshold"), "Only one of threshold and similarity may be given");
+
+            let percent = similarity.trim_end_matches('%').parse::<f32>()
+                .unwrap_or_else(|_| panic!("Value passed to similarity is not a number: {}", similarity));
+            assert!(percent > 0f32 && percent < 100f32,
+                    "Value passed to similarity must be between 0 and 100: {}", similarity);
+
+            (100f32 - percent) / 100f32
+        },
+        None => pos_f32_arg(args, "threshold", 3f32) / 100f32,
+    }
+}
+
+fn outfile_arg(args: &Matches, arg: &str, dir: &Path) -> Option<PathBuf> {
     args.opt_str(arg).map(|path| {
-        let path = Path::new(path);
+        let path = PathBuf::from(path);
         if path.is_relative() {
             dir.join(path)
         } else {
@@ -197,12 +1644,67 @@ fn outfile_arg(args: &Matches, arg: &str, dir: &Path) -> Option<Path> {
     })
 }
 
-fn uint_arg(args: &Matches, arg: &str, default: uint) -> uint {
-    let val = args.opt_str(arg).map_or(default, |arg_str|   
-                arg_str.parse::<uint>().unwrap()
-        );
+fn usize_arg(args: &Matches, arg: &str, default: usize) -> usize {
+    args.opt_str(arg).map_or(default, |arg_str|   
+        arg_str.parse::<usize>().unwrap()
+    )
+}
 
-    val
+fn u64_arg(args: &Matches, arg: &str, default: u64) -> u64 {
+    args.opt_str(arg).map_or(default, |arg_str|
+        arg_str.parse::<u64>()
+            .unwrap_or_else(|_| panic!("Value passed to {} is not a number: {}", arg, arg_str))
+    )
+}
+
+/// In megabytes (1,000,000 bytes) per second, if given.
+fn throttle_arg(args: &Matches) -> Option<Arc<Throttle>> {
+    if !args.opt_present("throttle") { return None; }
+
+    let max_read_rate = args.opt_str("throttle").map_or(0, |rate| {
+        let rate = rate.parse::<f64>()
+            .unwrap_or_else(|_| panic!("Value passed to throttle is not a number: {}", rate));
+
+        assert!(rate > 0f64, "Value passed to throttle must be greater than 0");
+
+        (rate * 1_000_000f64) as u64
+    });
+
+    Some(Arc::new(Throttle::new(max_read_rate)))
+}
+
+/// A date means midnight at the start of that day, in the local time zone.
+fn time_arg(args: &Matches, arg: &str) -> Option<SystemTime> {
+    args.opt_str(arg).map(|time| {
+        let parsed = match NaiveDate::parse_from_str(&time, "%Y-%m-%d") {
+            Ok(date) => date.and_time(NaiveTime::MIN).and_local_timezone(Local).earliest()
+                .map(SystemTime::from),
+            Err(_) => DateTime::parse_from_rfc3339(&time).ok().map(SystemTime::from),
+        };
+
+        parsed.unwrap_or_else(|| panic!("Value passed to {} is not a date: {}", arg, time))
+    })
+}
+
+fn crop_arg(args: &Matches) -> f32 {
+    let percent = args.opt_str("crop").map_or(0f32, |percent| 
+        percent.trim_end_matches('%').parse::<f32>()
+            .unwrap_or_else(|_| panic!("Value passed to crop is not a number: {}", percent))
+    );
+
+    assert!((0f32..=40f32).contains(&percent), "Value passed to crop must be between 0 and 40");
+
+    percent / 100f32
+}
+
+/// `--bursts`, which has to be more than `threshold` to find anything the groups don't.
+fn bursts_arg(args: &Matches, threshold: f32) -> Option<f32> {
+    if !args.opt_present("bursts") { return None; }
+
+    let bursts = pos_f32_arg(args, "bursts", 15f32) / 100f32;
+    assert!(bursts > threshold, "Value of bursts must be greater than the threshold");
+
+    Some(bursts)
 }
 
 fn pos_f32_arg(args: &Matches, arg: &str, default: f32) -> f32 {
@@ -217,7 +1719,7 @@ fn pos_f32_arg(args: &Matches, arg: &str, default: f32) -> f32 {
     val
 }
 
-fn exts_args<'a>(args: &'a Matches, arg: &'a str, default: Vec<&'static str>) -> Vec<String> {
+fn exts_args(args: &Matches, arg: &str, default: Vec<&'static str>) -> Vec<String> {
     if args.opt_present(arg) {
         args.opt_strs(arg)
     } else {
@@ -225,10 +1727,95 @@ fn exts_args<'a>(args: &'a Matches, arg: &'a str, default: Vec<&'static str>) ->
     }
 }
 
+fn dirs_args(args: &Matches, arg: &str) -> Vec<PathBuf> {
+    args.opt_strs(arg).iter()
+        .map(|dir| {
+            let dir = std::path::absolute(dir).unwrap();
+            assert!(dir.is_dir(), "Value passed to {} is not a directory: {}", arg, dir.display());
+            dir
+        })
+        .collect()
+}
+
+/// The default directories to skip, unless `--no-default-skip-dirs`, and the given ones.
+fn skip_dirs_args(args: &Matches, arg: &str) -> Vec<String> {
+    let mut skip_dirs: Vec<String> = if args.opt_present("no-default-skip-dirs") {
+        Vec::new()
+    } else {
+        search::DEFAULT_SKIP_DIRS.iter().map(|&dir| dir.to_owned()).collect()
+    };
+
+    skip_dirs.extend(args.opt_strs(arg));
+
+    skip_dirs
+}
+
+#[cfg(feature = "video")]
+fn video_exts() -> &'static [&'static str] {
+    crate::video::VIDEO_EXTS
+}
+
+#[cfg(not(feature = "video"))]
+fn video_exts() -> &'static [&'static str] {
+    panic!("img_dup was not compiled with video support!")
+}
+
+#[cfg(feature = "gpu")]
+fn open_gpu(backend: Backend) -> io::Result<Option<GpuHash>> {
+    if backend == Backend::Cpu { return Ok(None); }
+
+    let gpu = crate::gpu::GpuHasher::new()?;
+
+    Ok(Some(Arc::new(move |img: &DynamicImage, hash_size, fast| gpu.hash(img, hash_size, fast))))
+}
+
+#[cfg(not(feature = "gpu"))]
+fn open_gpu(backend: Backend) -> io::Result<Option<GpuHash>> {
+    match backend {
+        Backend::Cpu => Ok(None),
+        Backend::Gpu => Err(io::Error::new(io::ErrorKind::Unsupported, "img_dup was not compiled with GPU support")),
+    }
+}
+
+fn combine_args(args: &Matches, arg: &str) -> Vec<(HashType, f32)> {
+    args.opt_strs(arg).iter()
+        .map(|val| {
+            let mut parts = val.splitn(2, ':');
+
+            let hash_type = parts.next().and_then(HashType::from_str)
+                .unwrap_or_else(|| panic!("Value passed to {} must start with dct, fast or color: {}", arg, val));
+
+            let weight = parts.next().map_or(1f32, |weight| weight.parse::<f32>().unwrap());
+
+            assert!(weight > 0f32, "Weight passed to {} must be positive: {}", arg, val);
+
+            (hash_type, weight)
+        })
+        .collect()
+}
+
+fn glob_args(args: &Matches, arg: &str) -> Vec<Pattern> {
+    args.opt_strs(arg).iter()
+        .map(|pattern| match Pattern::new(pattern) {
+            Ok(pattern) => pattern,
+            Err(err) => panic!("Value passed to {} is not a valid glob pattern: {}", arg, err),
+        })
+        .collect()
+}
+
+fn regex_args(args: &Matches, arg: &str) -> Vec<Regex> {
+    args.opt_strs(arg).iter()
+        .map(|regex| match Regex::new(regex) {
+            Ok(regex) => regex,
+            Err(err) => panic!("Value passed to {} is not a valid regex: {}", arg, err),
+        })
+        .collect()
+}
+
 fn json_arg(args: &Matches, arg: &str, default: JsonSettings) -> JsonSettings {
     if args.opt_present(arg) {
         match args.opt_str(arg) {
-            Some(indent) => JsonSettings::PrettyJson(indent.parse::<uint>().unwrap()),
+            Some(indent) => JsonSettings::PrettyJson(indent.parse::<u32>().unwrap()),
             None => JsonSettings::CompactJson,
         }
     } else {
@@ -236,7 +1823,26 @@ fn json_arg(args: &Matches, arg: &str, default: JsonSettings) -> JsonSettings {
     }   
 }
 
-fn print_help_and_exit(opts: &[OptGroup]) {
-    println!("{}", usage("Duplicate Image Finder", opts));
-    super::exit();
+fn format_arg(args: &Matches, arg: &str, json: JsonSettings) -> OutputFormat {
+    match args.opt_str(arg) {
+        Some(format) => OutputFormat::from_str(&format)
+            .unwrap_or_else(|| panic!("Unknown value passed to {}: {}", arg, format)),
+        None if json.is_json() => OutputFormat::Json,
+        None => OutputFormat::Text,
+    }
+}
+
+fn log_level_arg(args: &Matches) -> LevelFilter {
+    match (args.opt_present("quiet"), args.opt_count("verbose")) {
+        (true, 0) => LevelFilter::Warn,
+        (true, _) => panic!("Only one of verbose and quiet may be given"),
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    }
+}
+
+fn print_help_and_exit(opts: &Options) {
+    println!("{}", opts.usage("Duplicate Image Finder"));
+    crate::exit(0);
 }
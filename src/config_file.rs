@@ -0,0 +1,112 @@
+use getopts::{Matches, Options};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Looked for in the current directory if `--config` isn't given.
+pub const DEFAULT_CONFIG_FILE: &str = "img-dup.toml";
+
+/// Options that can't be given together, so setting one on the command line
+/// overrides the other in the config file too.
+const EXCLUSIVE: &[(&str, &str)] = &[("threshold", "similarity")];
+
+/// Options read from a TOML config file, e.g.:
+///
+/// ```toml
+/// dir = "/home/me/Pictures"
+/// recurse = true
+/// ext = ["jpg", "png"]
+/// threshold = 2.5
+/// format = "json"
+/// ```
+///
+/// Keys are the long names of command-line options (`hash-size` or `hash_size`).
+/// `true` sets a flag, and arrays give an option more than once.
+pub struct ConfigFile {
+    /// The arguments for each option, by option name
+    options: Vec<(String, Vec<String>)>,
+}
+
+impl ConfigFile {
+
+    /// Read the config file at `path` and check its options against `opts`.
+    pub fn load(path: &Path, opts: &Options) -> io::Result<ConfigFile> {
+        let contents = fs::read_to_string(path)?;
+
+        let table: toml::Table = contents.parse()
+            .map_err(|err: toml::de::Error| invalid_data(err.to_string()))?;
+
+        let mut options = Vec::new();
+
+        for (key, value) in table {
+            let name = key.replace('_', "-");
+
+            if name == "config" {
+                return Err(invalid_data(format!("{} can't be set in a config file", name)));
+            }
+
+            let args = option_args(&name, &value)?;
+            options.push((name, args));
+        }
+
+        let config = ConfigFile { options };
+
+        // Unknown options, and flags with values, would otherwise be blamed on the command line
+        opts.parse(config.all_args()).map_err(|err| invalid_data(err.to_string()))?;
+
+        Ok(config)
+    }
+
+    fn all_args(&self) -> Vec<String> {
+        self.options.iter().flat_map(|(_, args)| args.iter().cloned()).collect()
+    }
+
+    /// The arguments for every option in the file that isn't given in `matches`.
+    pub fn args_not_in(&self, matches: &Matches) -> Vec<String> {
+        let overridden = |name: &str| matches.opt_present(name) ||
+            EXCLUSIVE.iter().any(|&(left, right)|
+                (name == left && matches.opt_present(right)) || (name == right && matches.opt_present(left))
+            );
+
+        self.options.iter()
+            .filter(|&(name, _)| !overridden(name))
+            .flat_map(|(_, args)| args.iter().cloned())
+            .collect()
+    }
+}
+
+/// `--config`, or the default config file if it exists.
+pub fn config_path(matches: &Matches) -> Option<PathBuf> {
+    match matches.opt_str("config") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.is_file()),
+    }
+}
+
+fn option_args(name: &str, value: &toml::Value) -> io::Result<Vec<String>> {
+    use toml::Value;
+
+    let arg = |value: &dyn ToString| format!("--{}={}", name, value.to_string());
+
+    match *value {
+        Value::Boolean(true) => Ok(vec![format!("--{}", name)]),
+        Value::Boolean(false) => Ok(Vec::new()),
+        Value::String(ref string) => Ok(vec![arg(string)]),
+        Value::Integer(int) => Ok(vec![arg(&int)]),
+        Value::Float(float) => Ok(vec![arg(&float)]),
+        Value::Array(ref values) => values.iter()
+            .map(|value| match *value {
+                Value::String(ref string) => Ok(arg(string)),
+                Value::Integer(int) => Ok(arg(&int)),
+                Value::Float(float) => Ok(arg(&float)),
+                _ => Err(invalid_data(format!("{} must be an array of strings or numbers", name))),
+            })
+            .collect(),
+        _ => Err(invalid_data(format!("{} must be a string, number, boolean or array", name))),
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
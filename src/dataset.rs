@@ -0,0 +1,102 @@
+//! `dataset [dir]`: split the images of a dataset into a keep list and a drop list, for
+//! cleaning training data. One image of each group of similar images is kept, and the rest
+//! are dropped, up to `--action-tier`.
+//!
+//! Both lists are sorted by path, so the same images and settings always give the same lists.
+//! With `--seed`, images that `--keep` ranks the same are chosen between at random, in an order
+//! that only depends on the seed and their paths relative to the dataset.
+
+use crate::config::{ProgramSettings, Tier};
+use crate::img::{self, Image, UniqueImage};
+use crate::processing::Results;
+
+use log::info;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// The images to keep and to drop, sorted by path, with how close each one dropped is to
+/// the image kept in its place.
+pub struct Split<'a> {
+    pub keep: Vec<&'a Image>,
+    pub drop: Vec<(&'a Image, Tier)>,
+}
+
+/// Choose the images to keep out of every group in `uniques`.
+pub fn split<'a>(settings: &ProgramSettings, uniques: &'a [UniqueImage]) -> Split<'a> {
+    let mut keep = Vec::new();
+    let mut drop = Vec::new();
+
+    for unique in uniques {
+        // Images past the tier stay, whatever happens to the rest of the group
+        let (mut group, past_tier): (Vec<_>, Vec<_>) = Some((&unique.img, Tier::Exact)).into_iter()
+            .chain(unique.similars.iter().map(|similar| (&similar.img, similar.tier)))
+            .partition(|&(_, tier)| tier <= settings.action_tier);
+
+        keep.extend(past_tier.into_iter().map(|(image, _)| image));
+
+        // `choose_representative()` picks the first of the images it ranks the same
+        if let Some(seed) = settings.seed {
+            group.sort_by_cached_key(|&(image, _)| seeded_hash(seed, &image.relative_path(&settings.dir)));
+        }
+
+        let group: Vec<&Image> = group.into_iter().map(|(image, _)| image).collect();
+        let kept = img::choose_representative(&group, &settings.keep, &settings.prefer);
+        let pairs = settings.action_pairs(&group, kept);
+
+        for (idx, &image) in group.iter().enumerate() {
+            match pairs.iter().find(|&&(_, dropped)| dropped == idx) {
+                // Measured from the image kept in its place rather than the original
+                Some(&(kept, _)) => drop.push((image, Tier::of(group[kept].dist_ratio(image), settings.near_threshold))),
+                None => keep.push(image),
+            }
+        }
+    }
+
+    keep.sort_by(|a, b| a.path.cmp(&b.path));
+    drop.sort_by(|a, b| a.0.path.cmp(&b.0.path));
+
+    Split { keep, drop }
+}
+
+/// Write the keep list to `out`, and the drop list to `--drop-list` if it was given,
+/// one path per line, relative to the dataset.
+pub fn write_lists(settings: &ProgramSettings, results: &Results, out: &mut dyn Write) -> io::Result<()> {
+    let split = split(settings, &results.uniques);
+
+    let count = |of: Tier| split.drop.iter().filter(|&&(_, tier)| tier == of).count();
+
+    info!("Keeping {} images, dropping {} exact duplicates, {} near and {} possible.",
+          split.keep.len(), count(Tier::Exact), count(Tier::Near), count(Tier::Possible));
+
+    write_paths(&settings.dir, split.keep.iter().copied(), out)?;
+
+    if let Some(ref drop_list) = settings.drop_list {
+        let mut file = BufWriter::new(File::create(drop_list)?);
+        write_paths(&settings.dir, split.drop.iter().map(|&(image, _)| image), &mut file)?;
+        file.flush()?;
+    }
+
+    out.flush()
+}
+
+fn write_paths<'a, I: Iterator<Item = &'a Image>>(dir: &Path, images: I, out: &mut dyn Write) -> io::Result<()> {
+    for image in images {
+        writeln!(out, "{}", image.relative_path(dir).display())?;
+    }
+
+    Ok(())
+}
+
+/// FNV-1a of the seed and the path with `/` between its parts, so the order is the same
+/// on every platform and with every version of Rust.
+fn seeded_hash(seed: u64, path: &Path) -> u64 {
+    let path = path.components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    seed.to_le_bytes().iter().chain(path.as_bytes())
+        .fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
@@ -0,0 +1,272 @@
+use crate::config::HashSettings;
+use crate::hash::ImageHash;
+use crate::img::{self, Image};
+use crate::thumbnails;
+
+use log::warn;
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// v3 and older were hashed by a different implementation and can't be compared with current hashes.
+const HEADER: &str = "img_dup hash database v4";
+
+/// Hashes of images saved to disk (conventionally as a `.imgdup` file), 
+/// so they can be reused or queried later without hashing the images again.
+///
+/// Entries are keyed by the absolute path of each image, and store the size and modification time 
+/// of the file, the hash size and type, the dimensions of the image and its hash.
+///
+/// When used as a cache, an entry is only reused if the file's size and modification time are unchanged
+/// and it was hashed with the same settings. Combined and rotated hashes aren't stored, and neither
+/// are the hashes of images whose paths can't be stored as text (see `storable_path()`).
+#[derive(Default)]
+pub struct HashDb {
+    entries: HashMap<PathBuf, DbEntry>,
+}
+
+/// What's stored for each image.
+pub struct DbEntry {
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Modification time in milliseconds since the Unix epoch
+    pub modified: u64,
+    pub hash_size: u32,
+    pub fast: bool,
+    pub width: u32,
+    pub height: u32,
+    pub hash: ImageHash,
+}
+
+impl HashDb {
+
+    pub fn new() -> HashDb {
+        HashDb::default()
+    }
+
+    /// How many images there are hashes of, with any settings.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// If there's an entry for the image at the absolute `path`, with any settings.
+    pub fn contains(&self, path: &Path) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// Every entry, by absolute path, in any order.
+    pub fn entries(&self) -> impl Iterator<Item = (&PathBuf, &DbEntry)> {
+        self.entries.iter()
+    }
+
+    /// Add or replace the entry for the image at the absolute `path`, e.g. read from other storage.
+    pub fn insert_entry(&mut self, path: PathBuf, entry: DbEntry) {
+        self.entries.insert(path, entry);
+    }
+
+    /// Load the database at `path`. A missing file is treated as an empty database,
+    /// and so is a file of an unknown format or version. Lines that can't be parsed are skipped.
+    pub fn load(path: &Path) -> io::Result<HashDb> {
+        let mut db = HashDb::new();
+
+        if !path.exists() { return Ok(db); }
+
+        let reader = BufReader::new(File::open(path)?);
+
+        let mut lines = reader.lines();
+
+        match lines.next() {
+            Some(Ok(ref header)) if header.trim() == HEADER => (),
+            // Start over
+            _ => return Ok(db),
+        }
+
+        for line in lines {
+            let line = line?;
+
+            if let Some((path, entry)) = parse_entry(&line) {
+                db.entries.insert(path, entry);
+            }
+        }
+
+        Ok(db)
+    }
+
+    /// Add the entries of `other`, e.g. a database from another machine, and return how many were added.
+    /// Where both have an entry for the same path, the one for the most recently modified file is kept.
+    pub fn merge(&mut self, other: HashDb) -> usize {
+        let mut added = 0;
+
+        for (path, entry) in other.entries {
+            if self.entries.get(&path).is_none_or(|ours| ours.modified < entry.modified) {
+                self.entries.insert(path, entry);
+                added += 1;
+            }
+        }
+
+        added
+    }
+
+    /// Remove the entries for images in `dir` or below it that no longer exist,
+    /// and return how many were removed.
+    pub fn remove_missing(&mut self, dir: &Path) -> usize {
+        let dir = absolute(dir);
+
+        let missing: Vec<PathBuf> = self.entries.keys()
+            .filter(|path| path.starts_with(&dir) && !path.exists())
+            .cloned()
+            .collect();
+
+        for path in &missing {
+            self.entries.remove(path);
+        }
+
+        missing.len()
+    }
+
+    /// Split `paths` into images that can be restored from the database and paths that still need hashing.
+    pub fn partition(&self, paths: Vec<PathBuf>, settings: &HashSettings) -> (Vec<Image>, Vec<PathBuf>) {
+        let mut cached = Vec::new();
+        let mut uncached = Vec::new();
+
+        for path in paths {
+            match self.get(&path, settings) {
+                Some(image) => cached.push(image),
+                None => uncached.push(path),
+            }
+        }
+
+        (cached, uncached)
+    }
+
+    /// Every image in the database that was hashed with the same hash size and type,
+    /// without checking if it changed since. None with a custom hasher.
+    pub fn images(&self, settings: &HashSettings) -> Vec<Image> {
+        if settings.hasher.is_some() { return Vec::new(); }
+
+        self.entries.iter()
+            .filter(|&(_, entry)| entry.hash_size == settings.hash_size && entry.fast == settings.fast)
+            .map(|(path, entry)| 
+                Image::new(path.clone(), entry.hash.clone(), entry.width, entry.height, entry.size)
+            )
+            .collect()
+    }
+
+    fn get(&self, path: &Path, settings: &HashSettings) -> Option<Image> {
+        if !settings.is_cacheable() { return None; }
+
+        let (size, modified) = stat(path)?;
+
+        let entry = self.entries.get(&absolute(path))?;
+
+        if entry.size != size || entry.modified != modified
+            || entry.hash_size != settings.hash_size || entry.fast != settings.fast {
+            return None;
+        }
+
+        let mut image = Image::new(path.to_path_buf(), entry.hash.clone(), entry.width, entry.height, entry.size);
+
+        // Decode the image again if its thumbnail is missing
+        if let Some(ref dir) = settings.thumbnails {
+            image.thumbnail = Some(thumbnails::existing(dir, path)?);
+        }
+
+        Some(image)
+    }
+
+    pub fn insert(&mut self, image: &Image, settings: &HashSettings) {
+        // Videos have combined hashes even without `--combine`
+        if !settings.is_cacheable() || !image.combined.is_empty() { return; }
+
+        let (size, modified) = match stat(&image.path) {
+            Some(stat) => stat,
+            None => return,
+        };
+
+        let path = absolute(&image.path);
+
+        if storable_path(&path).is_none() {
+            warn!("Not storing the hash of {}: its path isn't valid UTF-8 or has a line break", path.display());
+            return;
+        }
+
+        self.entries.insert(path, DbEntry {
+            size,
+            modified,
+            hash_size: settings.hash_size,
+            fast: settings.fast,
+            width: image.width,
+            height: image.height,
+            hash: image.hash.clone(),
+        });
+    }
+
+    /// Write to a temporary file first, so the database isn't lost if the program stops while saving.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+
+        self.write_to(&tmp_path)?;
+
+        fs::rename(&tmp_path, path)
+    }
+
+    fn write_to(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        writeln!(out, "{}", HEADER)?;
+
+        // Entries added with `insert_entry()` can have any path
+        for (path, entry) in self.entries.iter().filter_map(|(path, entry)| Some((storable_path(path)?, entry))) {
+            // Path goes last since it's the only field that could contain a tab
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                entry.size, entry.modified, entry.hash_size, entry.fast,
+                entry.width, entry.height, entry.hash.to_base64(), path
+            )?;
+        }
+
+        out.flush()
+    }
+}
+
+/// `path` as it's stored, if it can be read back as the same path: valid UTF-8, without line breaks,
+/// which would end the line of its entry. Other paths are left out of every kind of storage.
+pub fn storable_path(path: &Path) -> Option<&str> {
+    path.to_str().filter(|path| !path.contains(['\n', '\r']))
+}
+
+fn parse_entry(line: &str) -> Option<(PathBuf, DbEntry)> {
+    let fields: Vec<&str> = line.splitn(8, '\t').collect();
+
+    if fields.len() != 8 { return None; }
+
+    let hash_size = fields[2].parse().ok()?;
+
+    let entry = DbEntry {
+        size: fields[0].parse().ok()?,
+        modified: fields[1].parse().ok()?,
+        hash_size,
+        fast: fields[3].parse().ok()?,
+        width: fields[4].parse().ok()?,
+        height: fields[5].parse().ok()?,
+        hash: ImageHash::from_base64(fields[6], hash_size)?,
+    };
+
+    Some((PathBuf::from(fields[7]), entry))
+}
+
+/// The size of the file at `path` and its modification time in milliseconds since the Unix epoch.
+fn stat(path: &Path) -> Option<(u64, u64)> {
+    let size = fs::metadata(path).ok()?.len();
+
+    Some((size, img::modified_ms(path)?))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
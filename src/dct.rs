@@ -1,7 +1,6 @@
-use std::f64::consts::{PI, SQRT2};
-use std::num::FloatMath;
+use std::f64::consts::{PI, SQRT_2};
 
-pub fn dct_2d(packed_2d: &[f64], width: uint, height: uint) -> Vec<f64> {
+pub fn dct_2d(packed_2d: &[f64], width: usize, height: usize) -> Vec<f64> {
     assert!(packed_2d.len() == width * height, 
             "Slice length must be width * height!");
 
@@ -9,35 +8,35 @@ pub fn dct_2d(packed_2d: &[f64], width: uint, height: uint) -> Vec<f64> {
 
     let rows = rows(packed_2d, width, height);
     let dct_rows: Vec<Vec<f64>> = rows.iter()
-        .map(|row| dct_1d(row.as_slice())).collect();
+        .map(|row| dct_1d(row)).collect();
 
     let columns = columns(dct_rows, width, height);
     let dct_columns: Vec<Vec<f64>> = columns.iter()
-        .map(|col| dct_1d(col.as_slice())).collect();
+        .map(|col| dct_1d(col)).collect();
 
     from_columns(dct_columns, width, height)
 }
 
-fn rows(packed_2d: Vec<f64>, width: uint, height: uint) -> Vec<Vec<f64>> {
+fn rows(packed_2d: Vec<f64>, width: usize, height: usize) -> Vec<Vec<f64>> {
     let mut rows: Vec<Vec<f64>> = Vec::new();
 
-    for y in range(0, height) {
+    for y in 0..height {
         let start = y * width;
         let end = start + width;
-        rows.insert(y, packed_2d.slice(start, end).to_vec());         
+        rows.insert(y, packed_2d[start..end].to_vec());         
     }
 
     rows
 }
 
-fn columns(rows: Vec<Vec<f64>>, width: uint, height: uint) -> Vec<Vec<f64>> {
+fn columns(rows: Vec<Vec<f64>>, width: usize, height: usize) -> Vec<Vec<f64>> {
     let mut columns: Vec<Vec<f64>> = Vec::new();
 
-    for x in range(0, width) {
+    for x in 0..width {
         let mut column = Vec::new();
 
-        for y in range(0, height) {
-            column.insert(y, rows[y][x]);        
+        for (y, row) in rows.iter().enumerate().take(height) {
+            column.insert(y, row[x]);        
         }
 
         columns.insert(x, column);
@@ -46,12 +45,12 @@ fn columns(rows: Vec<Vec<f64>>, width: uint, height: uint) -> Vec<Vec<f64>> {
     columns
 }
 
-fn from_columns(columns: Vec<Vec<f64>>, width: uint, height: uint) -> Vec<f64> {
+fn from_columns(columns: Vec<Vec<f64>>, width: usize, height: usize) -> Vec<f64> {
     let mut packed = Vec::new();
 
-    for y in range(0, height) {
-        for x in range(0, width) {
-            packed.insert(y * width + x, columns[x][y]);
+    for y in 0..height {
+        for (x, column) in columns.iter().enumerate().take(width) {
+            packed.insert(y * width + x, column[y]);
         }
     }
 
@@ -67,16 +66,16 @@ fn dct_1d(vec: &[f64]) -> Vec<f64> {
     let mut out = Vec::new();
 
 
-    for u in range(0, vec.len()) {
+    for u in 0..vec.len() {
         let mut z = 0f64;
 
-        for x in range(0, vec.len()) {
-            z += vec[x] * (PI * u as f64 * (2 * x + 1) as f64 
+        for (x, &val) in vec.iter().enumerate() {
+            z += val * (PI * u as f64 * (2 * x + 1) as f64 
                 / (2 * vec.len()) as f64).cos(); 
         }
 
         if u == 0 {
-            z *= 1f64 / SQRT2;
+            z *= 1f64 / SQRT_2;
         }
 
         out.insert(u, z / 2f64);
@@ -85,7 +84,27 @@ fn dct_1d(vec: &[f64]) -> Vec<f64> {
     out
 }
 
-pub fn crop_dct(dct: Vec<f64>, original: (uint, uint), new: (uint, uint)) 
+/// What `dct_1d()` multiplies each of `len` values by for the first `count` frequencies,
+/// row by row, so the cropped DCT of a square can be taken as two matrix products.
+pub fn basis(len: usize, count: usize) -> Vec<f64> {
+    let mut out = Vec::with_capacity(len * count);
+
+    for u in 0..count {
+        for x in 0..len {
+            let mut z = (PI * u as f64 * (2 * x + 1) as f64 / (2 * len) as f64).cos();
+
+            if u == 0 {
+                z *= 1f64 / SQRT_2;
+            }
+
+            out.push(z / 2f64);
+        }
+    }
+
+    out
+}
+
+pub fn crop_dct(dct: Vec<f64>, original: (usize, usize), new: (usize, usize)) 
     -> Vec<f64> {
     let mut out = Vec::new();
 
@@ -97,13 +116,12 @@ pub fn crop_dct(dct: Vec<f64>, original: (uint, uint), new: (uint, uint))
 
     assert!(new_width < orig_width && new_height < orig_height);
 
-    for y in range(0, new_height) {
+    for y in 0..new_height {
         let start = y * orig_width;
         let end = start + new_width;
 
-        out.push_all(dct.slice(start, end));
+        out.extend_from_slice(&dct[start..end]);
     }
 
     out
 }
-
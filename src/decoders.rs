@@ -0,0 +1,225 @@
+use crate::config::FrameStrategy;
+use crate::img;
+
+use image::{AnimationDecoder, DynamicImage, ImageError, ImageResult, Pixel, Rgba, RgbaImage};
+use image::{ImageFormat, ImageReader};
+use image::codecs::gif::GifDecoder;
+use image::error::{DecodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+
+use memmap2::Mmap;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Seek};
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+
+/// Decoders registered with `ProgramSettings::add_decoder()`.
+pub type Decoders = Vec<Arc<dyn ImageDecoder + Send + Sync>>;
+
+/// Decodes images in a format the `image` crate doesn't support.
+pub trait ImageDecoder {
+    /// The extensions of files this decoder handles, without the leading dot.
+    fn extensions(&self) -> &'static [&'static str];
+
+    fn decode(&self, path: &Path) -> ImageResult<DynamicImage>;
+}
+
+/// Decodes images by converting them to PNG with an external program,
+/// which is called as `program [input] [output.png]`.
+pub struct CommandDecoder {
+    pub program: &'static str,
+    pub extensions: &'static [&'static str],
+}
+
+impl ImageDecoder for CommandDecoder {
+
+    fn extensions(&self) -> &'static [&'static str] {
+        self.extensions
+    }
+
+    fn decode(&self, path: &Path) -> ImageResult<DynamicImage> {
+        let tmp_dir = tempfile::Builder::new().prefix("img_dup").tempdir().map_err(ImageError::IoError)?;
+        let converted = tmp_dir.path().join("converted.png");
+
+        let output = Command::new(self.program).arg(path).arg(&converted).output()
+            .map_err(|err| ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Unknown,
+                UnsupportedErrorKind::GenericFeature(
+                    format!("Could not run {} to decode this image: {}", self.program, err)
+                ),
+            )))?;
+
+        if !output.status.success() {
+            return Err(decoding_error(format!("{} failed: {}", 
+                self.program, String::from_utf8_lossy(&output.stderr))));
+        }
+
+        image::open(&converted)
+    }
+}
+
+/// Decoders for formats that have to be handled outside of the `image` crate.
+static BUILTIN: &[CommandDecoder] = &[
+    CommandDecoder { program: "avifdec", extensions: &["avif"] },
+    CommandDecoder { program: "heif-convert", extensions: &["heic", "heif"] },
+];
+
+/// Open the image at `path` with the decoder for its extension,
+/// falling back to the `image` crate. Registered decoders take precedence over the built-in ones.
+///
+/// For animated GIFs, `frames` decides which frames make up the image.
+///
+/// With `sniff`, the format is taken from the contents of the file first,
+/// if it's one the `image` crate can decode.
+///
+/// If the contents of the file are already in memory as `data`, e.g. from `map()`,
+/// the `image` crate decodes them from there. Other decoders still read the file.
+pub fn open(path: &Path, data: Option<&[u8]>, registered: &Decoders, frames: FrameStrategy, sniff: bool)
+    -> ImageResult<DynamicImage> {
+    if sniff {
+        let sniffed = match data {
+            Some(data) => image::guess_format(data).ok(),
+            None => img::sniff_format(path),
+        };
+
+        if let Some(format) = sniffed.filter(|format| format.reading_enabled()) {
+            return open_as(path, data, format, frames);
+        }
+    }
+
+    let ext = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext,
+        None => return open_image(path, data),
+    };
+
+    if frames != FrameStrategy::First && ext.eq_ignore_ascii_case("gif") {
+        return open_gif_frames(path, data, frames);
+    }
+
+    let handles = |decoder: &dyn ImageDecoder| 
+        decoder.extensions().iter().any(|known| known.eq_ignore_ascii_case(ext));
+
+    let registered = registered.iter().find(|decoder| handles(&***decoder));
+
+    if let Some(decoder) = registered {
+        return decoder.decode(path);
+    }
+
+    match BUILTIN.iter().find(|decoder| handles(*decoder)) {
+        Some(decoder) => decoder.decode(path),
+        None => open_image(path, data),
+    }
+}
+
+/// Map the file at `path` into memory, for `open()`.
+///
+/// The file must not be changed while it's mapped: reading a part that was truncated
+/// away crashes the program on some platforms.
+pub fn map(path: &Path) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+
+    // Safe as long as the file isn't changed, which is up to the user with `--mmap`
+    unsafe { Mmap::map(&file) }
+}
+
+trait BufReadSeek: BufRead + Seek {}
+
+impl<R: BufRead + Seek> BufReadSeek for R {}
+
+/// Read `data` if it's given, otherwise the file at `path`.
+fn reader<'a>(path: &Path, data: Option<&'a [u8]>) -> ImageResult<Box<dyn BufReadSeek + 'a>> {
+    Ok(match data {
+        Some(data) => Box::new(Cursor::new(data)),
+        None => Box::new(BufReader::new(File::open(path).map_err(ImageError::IoError)?)),
+    })
+}
+
+/// `image::open()`, but with the format from `img::guess_format()`.
+fn open_image(path: &Path, data: Option<&[u8]>) -> ImageResult<DynamicImage> {
+    let mut reader = ImageReader::new(reader(path, data)?);
+
+    if let Some(format) = img::guess_format(path) {
+        reader.set_format(format);
+    }
+
+    reader.decode()
+}
+
+/// Decode the image at `path` as `format`, whatever its extension.
+fn open_as(path: &Path, data: Option<&[u8]>, format: ImageFormat, frames: FrameStrategy) -> ImageResult<DynamicImage> {
+    if frames != FrameStrategy::First && format == ImageFormat::Gif {
+        return open_gif_frames(path, data, frames);
+    }
+
+    let mut reader = ImageReader::new(reader(path, data)?);
+    reader.set_format(format);
+    reader.decode()
+}
+
+fn open_gif_frames(path: &Path, data: Option<&[u8]>, strategy: FrameStrategy) -> ImageResult<DynamicImage> {
+    let decoder = GifDecoder::new(reader(path, data)?)?;
+
+    let frames: Vec<RgbaImage> = decoder.into_frames().collect_frames()?
+        .into_iter()
+        .map(|frame| frame.into_buffer())
+        .collect();
+
+    if frames.is_empty() {
+        return Err(decoding_error("The GIF has no frames".to_string()));
+    }
+
+    let frame = match strategy {
+        FrameStrategy::First => frames.into_iter().next().unwrap(),
+        FrameStrategy::Middle => { 
+            let middle = frames.len() / 2;
+            frames.into_iter().nth(middle).unwrap()
+        },
+        FrameStrategy::Average(count) => average_frames(&frames, count),
+    };
+
+    Ok(DynamicImage::ImageRgba8(frame))
+}
+
+/// Average `count` evenly spaced frames into one image, pixel by pixel.
+/// Frames that aren't the same size as the first are skipped.
+fn average_frames(frames: &[RgbaImage], count: usize) -> RgbaImage {
+    let (width, height) = frames[0].dimensions();
+
+    let step = if count >= frames.len() { 1 } else { frames.len() / count };
+
+    let selected: Vec<&RgbaImage> = frames.iter()
+        .enumerate()
+        .filter(|&(idx, _)| idx % step == 0)
+        .map(|(_, frame)| frame)
+        .take(count)
+        .filter(|frame| frame.dimensions() == (width, height))
+        .collect();
+
+    let mut average = RgbaImage::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut sums = [0u32; 4];
+
+            for frame in &selected {
+                for (sum, &channel) in sums.iter_mut().zip(frame.get_pixel(x, y).channels().iter()) {
+                    *sum += channel as u32;
+                }
+            }
+
+            let len = selected.len() as u32;
+
+            average.put_pixel(x, y, Rgba([
+                (sums[0] / len) as u8, (sums[1] / len) as u8, 
+                (sums[2] / len) as u8, (sums[3] / len) as u8
+            ]));
+        }
+    }
+
+    average
+}
+
+fn decoding_error(msg: String) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Unknown, msg))
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::path::PathBuf;
+
+/// Paths of files with exactly the same contents as the key.
+pub type ExactCopies = HashMap<PathBuf, Vec<PathBuf>>;
+
+/// Find files that are byte-for-byte identical, so only one of each group has to be hashed.
+///
+/// Returns the paths that still need hashing (one per group of identical files),
+/// and the copies of each of them. Only files with the same size are read.
+pub fn find_exact_copies(paths: Vec<PathBuf>) -> (Vec<PathBuf>, ExactCopies) {
+    let mut unique = Vec::new();
+    let mut copies = HashMap::new();
+
+    for (_, same_size) in group_by(paths, |path| fs::metadata(path).map(|meta| meta.len()).ok()) {
+        if same_size.len() == 1 {
+            unique.extend(same_size);
+            continue;
+        }
+
+        for (_, same_digest) in group_by(same_size, digest) {
+            group_identical(same_digest, &mut unique, &mut copies);
+        }
+    }
+
+    (unique, copies)
+}
+
+/// Group `paths` by `key`. Paths without a key get a group of their own.
+fn group_by<K: Hash + Eq, F>(paths: Vec<PathBuf>, key: F) -> Vec<(Option<K>, Vec<PathBuf>)>
+where F: Fn(&PathBuf) -> Option<K> {
+    let mut groups: HashMap<K, Vec<PathBuf>> = HashMap::new();
+    let mut ungrouped = Vec::new();
+
+    for path in paths {
+        match key(&path) {
+            Some(key) => groups.entry(key).or_default().push(path),
+            None => ungrouped.push((None, vec![path])),
+        }
+    }
+
+    groups.into_iter()
+        .map(|(key, paths)| (Some(key), paths))
+        .chain(ungrouped)
+        .collect()
+}
+
+/// Compare the contents of every path to the first;
+/// a matching digest doesn't guarantee matching contents.
+fn group_identical(mut paths: Vec<PathBuf>, unique: &mut Vec<PathBuf>, copies: &mut ExactCopies) {
+    let first = paths.remove(0);
+
+    let first_data = match fs::read(&first) {
+        Ok(data) => data,
+        Err(_) => {
+            unique.push(first);
+            unique.extend(paths);
+            return;
+        },
+    };
+
+    let mut first_copies = Vec::new();
+
+    for path in paths {
+        match fs::read(&path) {
+            Ok(ref data) if *data == first_data => first_copies.push(path),
+            _ => unique.push(path),
+        }
+    }
+
+    if !first_copies.is_empty() {
+        copies.insert(first.clone(), first_copies);
+    }
+
+    unique.push(first);
+}
+
+/// 64-bit FNV-1a hash of the file's contents.
+fn digest(path: &PathBuf) -> Option<u64> {
+    fs::read(path).ok().map(|data| fnv1a(&data))
+}
+
+/// 64-bit FNV-1a hash; fast, but not meant to resist deliberate collisions.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    data.iter().fold(OFFSET_BASIS, |hash, &byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
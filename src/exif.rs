@@ -0,0 +1,139 @@
+use crate::scratch;
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The EXIF data is at the start of the file, so there's no need to read more than this.
+const MAX_HEADER_LEN: u64 = 128 * 1024;
+
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// How to rotate or mirror an image so it displays upright, from the EXIF `Orientation` tag.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Orientation {
+    Normal,
+    FlipHorizontal,
+    Rotate180,
+    FlipVertical,
+    /// Rotate 90 degrees clockwise, then flip horizontally
+    Transpose,
+    Rotate90,
+    /// Rotate 270 degrees clockwise, then flip horizontally
+    Transverse,
+    Rotate270,
+}
+
+impl Orientation {
+
+    fn from_tag(value: u16) -> Orientation {
+        match value {
+            2 => Orientation::FlipHorizontal,
+            3 => Orientation::Rotate180,
+            4 => Orientation::FlipVertical,
+            5 => Orientation::Transpose,
+            6 => Orientation::Rotate90,
+            7 => Orientation::Transverse,
+            8 => Orientation::Rotate270,
+            // 1, or invalid
+            _ => Orientation::Normal,
+        }
+    }
+}
+
+/// Read the orientation of a JPEG file. Anything else, or a JPEG without EXIF data, is `Normal`.
+pub fn read_orientation(path: &Path) -> io::Result<Orientation> {
+    let mut file = File::open(path)?;
+
+    // Don't bother reading the rest if it's not a JPEG
+    let mut magic = [0u8; 2];
+    file.read_exact(&mut magic)?;
+    if magic != [0xFF, 0xD8] { return Ok(Orientation::Normal); }
+
+    file.seek(SeekFrom::Start(0))?;
+
+    scratch::read(file, 2 + MAX_HEADER_LEN, orientation_of)
+}
+
+/// The orientation of a JPEG file that's already in memory, like `read_orientation()`.
+pub fn orientation_of(data: &[u8]) -> Orientation {
+    find_exif(data)
+        .and_then(read_orientation_tag)
+        .map_or(Orientation::Normal, Orientation::from_tag)
+}
+
+/// Find the TIFF structure in the APP1 segment of a JPEG.
+fn find_exif(data: &[u8]) -> Option<&[u8]> {
+    // Start of image
+    if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 { return None; }
+
+    let mut pos = 2;
+
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF { return None; }
+
+        let marker = data[pos + 1];
+        let len = read_u16(&data[pos + 2..], true) as usize;
+
+        // Start of scan; the metadata segments are all before this
+        if marker == 0xDA || len < 2 { return None; }
+
+        let segment_end = pos + 2 + len;
+        if segment_end > data.len() { return None; }
+
+        let segment = &data[pos + 4..segment_end];
+
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return Some(&segment[6..]);
+        }
+
+        pos = segment_end;
+    }
+
+    None
+}
+
+fn read_orientation_tag(tiff: &[u8]) -> Option<u16> {
+    if tiff.len() < 8 { return None; }
+
+    let big_endian = if tiff.starts_with(b"MM") {
+        true
+    } else if tiff.starts_with(b"II") {
+        false
+    } else {
+        return None;
+    };
+
+    let ifd = read_u32(&tiff[4..], big_endian) as usize;
+    if ifd + 2 > tiff.len() { return None; }
+
+    let entries = read_u16(&tiff[ifd..], big_endian) as usize;
+
+    for i in 0..entries {
+        let entry = ifd + 2 + i * 12;
+        if entry + 12 > tiff.len() { return None; }
+
+        if read_u16(&tiff[entry..], big_endian) == ORIENTATION_TAG {
+            // A SHORT, stored at the start of the value field
+            return Some(read_u16(&tiff[entry + 8..], big_endian));
+        }
+    }
+
+    None
+}
+
+fn read_u16(data: &[u8], big_endian: bool) -> u16 {
+    let (hi, lo) = if big_endian { (data[0], data[1]) } else { (data[1], data[0]) };
+
+    ((hi as u16) << 8) | lo as u16
+}
+
+fn read_u32(data: &[u8], big_endian: bool) -> u32 {
+    let (hi, lo) = (read_u16(data, big_endian), read_u16(&data[2..], big_endian));
+
+    if big_endian {
+        ((hi as u32) << 16) | lo as u32
+    } else {
+        ((lo as u32) << 16) | hi as u32
+    }
+}
@@ -0,0 +1,572 @@
+//! With the `gpu` feature, `--backend=gpu`: resize images and take the DCT of their main hashes
+//! on the GPU, in batches of the images the hashing threads have decoded at the same time.
+//!
+//! Images are still decoded and converted to gray on the CPU, since picking pixels and then
+//! converting them gives the same values as the other way around. The GPU picks the same pixels
+//! as `FilterType::Nearest`, so fast hashes are the same as on the CPU. The DCT is taken in
+//! single rather than double precision, along with how far rounding can put each frequency off,
+//! and the few images with a frequency that could be on the other side of the mean have their
+//! DCT taken again on the CPU, so DCT hashes are the same too. Combined hashes are made on the CPU.
+
+use crate::dct;
+use crate::hash::{self, ImageHash};
+
+use image::DynamicImage;
+
+use log::warn;
+
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use wgpu::util::DeviceExt;
+
+const SHADER: &str = "
+    struct Params {
+        size: u32,
+        hash_size: u32,
+        count: u32,
+        padding: u32,
+    }
+
+    @group(0) @binding(0) var<uniform> params: Params;
+    // The gray pixels of every image, one after another, four to a word
+    @group(0) @binding(1) var<storage, read> pixels: array<u32>;
+    // Where each image starts in `pixels`, and its width
+    @group(0) @binding(2) var<storage, read> images: array<vec2<u32>>;
+    // For each image, the column and then the row each pixel of the resized image is taken from
+    @group(0) @binding(3) var<storage, read> indices: array<u32>;
+    // From `dct::basis()`, one frequency per row
+    @group(0) @binding(4) var<storage, read> basis: array<f32>;
+    @group(0) @binding(5) var<storage, read_write> gray: array<f32>;
+    // The DCT with how far each value can be off from rounding
+    @group(0) @binding(6) var<storage, read_write> columns: array<vec2<f32>>;
+    @group(0) @binding(7) var<storage, read_write> frequencies: array<vec2<f32>>;
+
+    // Twice the most rounding can change a result by, in case it isn't to the nearest
+    const EPSILON = 1.1920929e-7;
+
+    fn thread_index(id: vec3<u32>, groups: vec3<u32>) -> u32 {
+        return id.y * groups.x * 64u + id.x;
+    }
+
+    // Resize every image to `size * size`
+    @compute @workgroup_size(64)
+    fn resize(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+        let i = thread_index(id, groups);
+        let cells = params.size * params.size;
+        if i >= params.count * cells { return; }
+
+        let image = i / cells;
+        let y = (i % cells) / params.size;
+        let x = i % params.size;
+        let start = image * params.size * 2u;
+
+        let pixel = images[image].x + indices[start + params.size + y] * images[image].y + indices[start + x];
+
+        gray[i] = f32((pixels[pixel / 4u] >> (pixel % 4u * 8u)) & 0xffu);
+    }
+
+    // The DCT of every column, only for the lowest `hash_size` frequencies
+    @compute @workgroup_size(64)
+    fn dct_columns(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+        let i = thread_index(id, groups);
+        let cells = params.hash_size * params.size;
+        if i >= params.count * cells { return; }
+
+        let image = i / cells;
+        let v = (i % cells) / params.size;
+        let x = i % params.size;
+        let start = image * params.size * params.size;
+
+        var sum = 0.0;
+        var magnitude = 0.0;
+        for (var y = 0u; y < params.size; y += 1u) {
+            let product = basis[v * params.size + y] * gray[start + y * params.size + x];
+            sum += product;
+            magnitude += abs(product);
+        }
+
+        // The gray values are exact, the basis and each addition are rounded
+        columns[i] = vec2(sum, f32(params.size + 2u) * EPSILON * magnitude);
+    }
+
+    // Then of every row of those
+    @compute @workgroup_size(64)
+    fn dct_rows(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+        let i = thread_index(id, groups);
+        let cells = params.hash_size * params.hash_size;
+        if i >= params.count * cells { return; }
+
+        let image = i / cells;
+        let v = (i % cells) / params.hash_size;
+        let u = i % params.hash_size;
+        let start = image * params.hash_size * params.size;
+
+        var sum = 0.0;
+        var magnitude = 0.0;
+        var error = 0.0;
+        for (var x = 0u; x < params.size; x += 1u) {
+            let column = columns[start + v * params.size + x];
+            let product = column.x * basis[u * params.size + x];
+            sum += product;
+            magnitude += abs(product);
+            error += column.y * abs(basis[u * params.size + x]);
+        }
+
+        frequencies[i] = vec2(sum, f32(params.size + 2u) * EPSILON * magnitude + error);
+    }
+";
+
+/// A GPU to make hashes on, shared by every hashing thread: each image is hashed in a batch
+/// with the others sent while the GPU was busy. Clones share the same GPU.
+#[derive(Clone)]
+pub struct GpuHasher {
+    requests: Sender<Request>,
+    /// Larger images are hashed on the CPU, since they don't fit in one buffer
+    max_pixels: u64,
+}
+
+/// An image to hash, in gray.
+struct Request {
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+    hash_size: u32,
+    fast: bool,
+    hash: Sender<ImageHash>,
+}
+
+impl GpuHasher {
+
+    /// Open the first GPU found, preferring a discrete one, and start the thread that
+    /// sends it batches.
+    pub fn new() -> io::Result<GpuHasher> {
+        let instance = wgpu::Instance::default();
+
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        })).map_err(io::Error::other)?;
+
+        // As large buffers as it can have, to fit large images
+        let limits = adapter.limits();
+
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("img_dup"),
+            required_limits: limits.clone(),
+            ..Default::default()
+        })).map_err(io::Error::other)?;
+
+        let gpu = Gpu::new(device, queue, &limits);
+        let max_pixels = gpu.max_bytes;
+
+        let (requests, received) = channel();
+
+        thread::Builder::new()
+            .name("img_dup gpu".to_string())
+            .spawn(move || gpu.run(received))?;
+
+        Ok(GpuHasher { requests, max_pixels })
+    }
+
+    /// Hash `img` like `ImageHash::hash()`, waiting for its batch to be done.
+    pub fn hash(&self, img: &DynamicImage, hash_size: u32, fast: bool) -> ImageHash {
+        self.hash_batch(&[img], hash_size, fast).remove(0)
+    }
+
+    /// Hash every image in `images` like `ImageHash::hash()`, in as few batches as they fit in.
+    /// Images too large for the GPU, and any after it fails, are hashed on the CPU.
+    pub fn hash_batch(&self, images: &[&DynamicImage], hash_size: u32, fast: bool) -> Vec<ImageHash> {
+        let hashes: Vec<Option<Receiver<ImageHash>>> = images.iter()
+            .map(|img| self.send(img, hash_size, fast))
+            .collect();
+
+        images.iter().zip(hashes)
+            .map(|(img, hash)| hash.and_then(|hash| hash.recv().ok())
+                .unwrap_or_else(|| ImageHash::hash(img, hash_size, fast)))
+            .collect()
+    }
+
+    fn send(&self, img: &DynamicImage, hash_size: u32, fast: bool) -> Option<Receiver<ImageHash>> {
+        let (width, height) = (img.width(), img.height());
+
+        if width == 0 || height == 0 || width as u64 * height as u64 > self.max_pixels { return None; }
+
+        let (hash, received) = channel();
+
+        self.requests.send(Request { pixels: img.to_luma8().into_raw(), width, height, hash_size, fast, hash }).ok()?;
+
+        Some(received)
+    }
+}
+
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    layout: wgpu::BindGroupLayout,
+    resize: wgpu::ComputePipeline,
+    dct_columns: wgpu::ComputePipeline,
+    dct_rows: wgpu::ComputePipeline,
+    /// The most bytes the shaders can use in one buffer
+    max_bytes: u64,
+    max_workgroups: u32,
+}
+
+impl Gpu {
+
+    fn new(device: wgpu::Device, queue: wgpu::Queue, limits: &wgpu::Limits) -> Gpu {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("img_dup hashes"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+
+        let entries: Vec<wgpu::BindGroupLayoutEntry> = (0..8)
+            .map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: match binding {
+                        0 => wgpu::BufferBindingType::Uniform,
+                        1..=4 => wgpu::BufferBindingType::Storage { read_only: true },
+                        _ => wgpu::BufferBindingType::Storage { read_only: false },
+                    },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            })
+            .collect();
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("img_dup hashes"),
+            entries: &entries,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("img_dup hashes"),
+            bind_group_layouts: &[Some(&layout)],
+            immediate_size: 0,
+        });
+
+        let pipeline = |entry_point: &str| device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let (resize, dct_columns, dct_rows) = (pipeline("resize"), pipeline("dct_columns"), pipeline("dct_rows"));
+
+        Gpu {
+            // Indexed with 32 bits in the shaders
+            max_bytes: limits.max_storage_buffer_binding_size.min(limits.max_buffer_size).min(u32::MAX as u64) & !3,
+            max_workgroups: limits.max_compute_workgroups_per_dimension,
+            device,
+            queue,
+            layout,
+            resize,
+            dct_columns,
+            dct_rows,
+        }
+    }
+
+    /// Hash the images sent until every `GpuHasher` is dropped. Whatever was sent while a batch
+    /// was being hashed goes in the next one.
+    fn run(self, requests: Receiver<Request>) {
+        while let Ok(first) = requests.recv() {
+            let mut pending = vec![first];
+            pending.extend(requests.try_iter());
+
+            // Dropping the requests that weren't done makes their threads hash them on the CPU
+            if panic::catch_unwind(AssertUnwindSafe(|| self.hash_all(pending))).is_err() {
+                warn!("The GPU failed, hashing on the CPU instead");
+                return;
+            }
+        }
+    }
+
+    fn hash_all(&self, mut pending: Vec<Request>) {
+        while let Some(first) = pending.first() {
+            let (hash_size, fast) = (first.hash_size, first.fast);
+
+            let (same, other): (Vec<Request>, Vec<Request>) = pending.into_iter()
+                .partition(|request| request.hash_size == hash_size && request.fast == fast);
+            pending = other;
+
+            for batch in self.batches(same, hash_size, fast) {
+                for (request, hash) in batch.iter().zip(self.hash(&batch, hash_size, fast)) {
+                    let _ = request.hash.send(hash);
+                }
+            }
+        }
+    }
+
+    /// Split `requests` into batches that each fit in the buffers.
+    fn batches(&self, requests: Vec<Request>, hash_size: u32, fast: bool) -> Vec<Vec<Request>> {
+        let size = resized_size(hash_size, fast) as u64;
+        let mut batches: Vec<Vec<Request>> = Vec::new();
+        let mut bytes = 0;
+
+        for request in requests {
+            let request_bytes = request.pixels.len() as u64;
+
+            let fits = batches.last().is_some_and(|batch|
+                bytes + request_bytes <= self.max_bytes && (batch.len() as u64 + 1) * size * size * 4 <= self.max_bytes
+            );
+
+            if !fits {
+                batches.push(Vec::new());
+                bytes = 0;
+            }
+
+            bytes += request_bytes;
+            batches.last_mut().unwrap().push(request);
+        }
+
+        batches
+    }
+
+    fn hash(&self, batch: &[Request], hash_size: u32, fast: bool) -> Vec<ImageHash> {
+        let size = resized_size(hash_size, fast);
+        let count = batch.len() as u32;
+
+        let mut images: Vec<u32> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        let mut pixel_count = 0;
+
+        for request in batch {
+            images.extend([pixel_count, request.width]);
+            indices.extend(nearest(request.width, size).chain(nearest(request.height, size)));
+            pixel_count += request.width * request.height;
+        }
+
+        let pixels = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pixels"),
+            size: (pixel_count as u64).div_ceil(4) * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: true,
+        });
+
+        {
+            let mut mapped = pixels.slice(..).get_mapped_range_mut().expect("Could not write the images to the GPU");
+            let mut start = 0;
+
+            for request in batch {
+                mapped.slice(start..start + request.pixels.len()).copy_from_slice(&request.pixels);
+                start += request.pixels.len();
+            }
+        }
+
+        pixels.unmap();
+
+        let params = [size, hash_size, count, 0];
+        let basis: Vec<f32> = dct::basis(size as usize, hash_size as usize).into_iter().map(|x| x as f32).collect();
+
+        let params = self.init_buffer("params", &words(&params), wgpu::BufferUsages::UNIFORM);
+        let images = self.init_buffer("images", &words(&images), wgpu::BufferUsages::STORAGE);
+        let indices = self.init_buffer("indices", &words(&indices), wgpu::BufferUsages::STORAGE);
+        let basis = self.init_buffer("basis", &floats(&basis), wgpu::BufferUsages::STORAGE);
+
+        let gray = self.buffer("gray", count * size * size, wgpu::BufferUsages::COPY_SRC);
+        let columns = self.buffer("columns", 2 * count * hash_size * size, wgpu::BufferUsages::empty());
+        let frequencies = self.buffer("frequencies", 2 * count * hash_size * hash_size, wgpu::BufferUsages::COPY_SRC);
+
+        let buffers = [&params, &pixels, &images, &indices, &basis, &gray, &columns, &frequencies];
+
+        let entries: Vec<wgpu::BindGroupEntry> = buffers.iter().enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry { binding: binding as u32, resource: buffer.as_entire_binding() })
+            .collect();
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("img_dup hashes"),
+            layout: &self.layout,
+            entries: &entries,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None, timestamp_writes: None });
+            pass.set_bind_group(0, &bind_group, &[]);
+
+            self.dispatch(&mut pass, &self.resize, count * size * size);
+
+            // The fast hash is of the resized image itself
+            if !fast {
+                self.dispatch(&mut pass, &self.dct_columns, count * hash_size * size);
+                self.dispatch(&mut pass, &self.dct_rows, count * hash_size * hash_size);
+            }
+        }
+
+        // The DCT hashes are checked against the gray values, in case they're too close to call
+        let hash_len = if fast { hash_size * hash_size } else { 2 * hash_size * hash_size } as u64;
+        let gray_len = if fast { 0 } else { (size * size) as u64 };
+        let output = if fast { &gray } else { &frequencies };
+
+        let read = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("read"),
+            size: count as u64 * (hash_len + gray_len) * 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_buffer_to_buffer(output, 0, &read, 0, count as u64 * hash_len * 4);
+
+        if !fast {
+            encoder.copy_buffer_to_buffer(&gray, 0, &read, count as u64 * hash_len * 4, count as u64 * gray_len * 4);
+        }
+
+        self.queue.submit([encoder.finish()]);
+
+        let (mapped, is_mapped) = channel();
+        read.slice(..).map_async(wgpu::MapMode::Read, move |result| { let _ = mapped.send(result); });
+
+        self.device.poll(wgpu::PollType::wait_indefinitely()).expect("GPU stopped responding");
+        is_mapped.recv().expect("GPU stopped responding").expect("Could not read the hashes back from the GPU");
+
+        let values: Vec<f32> = read.slice(..).get_mapped_range().expect("Could not read the hashes back from the GPU").chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+
+        let (hashes, grays) = values.split_at((count as u64 * hash_len) as usize);
+
+        if fast {
+            return hashes.chunks(hash_len as usize)
+                .map(|values| ImageHash::from_bits(hash::fast_bits(&values.iter().map(|&value| value as u8).collect::<Vec<u8>>())))
+                .collect();
+        }
+
+        hashes.chunks(hash_len as usize).zip(grays.chunks(gray_len as usize))
+            .map(|(frequencies, gray)| {
+                let (frequencies, errors): (Vec<f64>, Vec<f64>) = frequencies.chunks_exact(2)
+                    .map(|pair| (pair[0] as f64, pair[1] as f64))
+                    .unzip();
+
+                let mean = frequencies.iter().sum::<f64>() / frequencies.len() as f64;
+                // The bounds are rounded too, and the mean is off by as much as the frequencies are on average
+                let mean_error = errors.iter().sum::<f64>() / errors.len() as f64;
+
+                let too_close = frequencies.iter().zip(&errors)
+                    .any(|(&x, &error)| (x - mean).abs() <= (error + mean_error) * 1.01);
+
+                ImageHash::from_bits(if too_close {
+                    hash::gray_dct_bits(&gray.iter().map(|&value| value as f64).collect::<Vec<f64>>(), hash_size)
+                } else {
+                    hash::dct_bits(&frequencies)
+                })
+            })
+            .collect()
+    }
+
+    fn init_buffer(&self, label: &str, contents: &[u8], usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some(label), contents, usage })
+    }
+
+    /// A buffer of `len` floats for the shaders to write.
+    fn buffer(&self, label: &str, len: u32, usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: len as u64 * 4,
+            usage: wgpu::BufferUsages::STORAGE | usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Run `pipeline` on `threads` threads, in rows of workgroups if there are too many for one.
+    fn dispatch(&self, pass: &mut wgpu::ComputePass, pipeline: &wgpu::ComputePipeline, threads: u32) {
+        let workgroups = threads.div_ceil(64);
+
+        pass.set_pipeline(pipeline);
+
+        if workgroups <= self.max_workgroups {
+            pass.dispatch_workgroups(workgroups, 1, 1);
+        } else {
+            pass.dispatch_workgroups(self.max_workgroups, workgroups.div_ceil(self.max_workgroups), 1);
+        }
+    }
+}
+
+/// The side of the square an image is resized to: the DCT is taken of a larger one.
+fn resized_size(hash_size: u32, fast: bool) -> u32 {
+    if fast { hash_size } else { hash_size * 4 }
+}
+
+/// Which of `len` columns or rows `FilterType::Nearest` takes each of `new_len` from,
+/// computed exactly as the `image` crate does.
+fn nearest(len: u32, new_len: u32) -> impl Iterator<Item = u32> {
+    let ratio = len as f32 / new_len as f32;
+
+    (0..new_len).map(move |out| (((out as f32 + 0.5) * ratio).floor() as i64).clamp(0, len as i64 - 1) as u32)
+}
+
+fn words(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn floats(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{ImageBuffer, Rgb, RgbImage};
+
+    /// A gradient with a few rectangles on it, different for every seed.
+    fn image(seed: u32, width: u32, height: u32) -> RgbImage {
+        let mut img = RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, (seed * 70) as u8])
+        });
+
+        for i in 1..=4 {
+            let (left, top) = (width * i / 7, height * (5 - i) / 7);
+            let color = Rgb([(seed * 40 + i * 60) as u8, (i * 90) as u8, (seed * 110 + i * 20) as u8]);
+
+            for y in top..(top + height / 4).min(height) {
+                for x in left..(left + width / 3).min(width) {
+                    img.put_pixel(x, y, color);
+                }
+            }
+        }
+
+        img
+    }
+
+    #[test]
+    #[ignore = "needs a GPU, run with --features gpu -- --ignored"]
+    fn hashes_match_cpu() {
+        let gpu = GpuHasher::new().expect("no GPU to test on");
+
+        let mut images: Vec<DynamicImage> = Vec::new();
+
+        for (seed, (width, height)) in [(640, 480), (37, 1000), (5, 3), (1024, 768)].into_iter().enumerate() {
+            let rgb = image(seed as u32, width, height);
+            let rgb16 = ImageBuffer::from_fn(width, height, |x, y| Rgb(rgb.get_pixel(x, y).0.map(|c| c as u16 * 257)));
+
+            images.push(DynamicImage::ImageRgba8(DynamicImage::ImageRgb8(rgb.clone()).to_rgba8()));
+            images.push(DynamicImage::ImageLuma8(DynamicImage::ImageRgb8(rgb.clone()).to_luma8()));
+            images.push(DynamicImage::ImageRgb16(rgb16));
+            images.push(DynamicImage::ImageRgb8(rgb));
+        }
+
+        for name in ["test_1.png", "test_1_resize.png", "test_2.png", "test_2_resize.png"] {
+            images.push(image::open(format!("test_images/{}", name)).unwrap());
+        }
+
+        let images: Vec<&DynamicImage> = images.iter().collect();
+
+        for hash_size in [8, 16] {
+            for fast in [true, false] {
+                let cpu: Vec<ImageHash> = images.iter().map(|img| ImageHash::hash(img, hash_size, fast)).collect();
+
+                assert_eq!(gpu.hash_batch(&images, hash_size, fast), cpu, "hash size {}, fast {}", hash_size, fast);
+                assert_eq!(gpu.hash(images[0], hash_size, fast), cpu[0]);
+            }
+        }
+    }
+}
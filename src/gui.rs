@@ -0,0 +1,351 @@
+//! With the `gui` feature, `--gui`: a window to review the groups of similar images after a run.
+//!
+//! Each group is shown side by side, with the dimensions, size and match of every image.
+//! One image in each group is kept, chosen by `--keep` to begin with, and the others can have
+//! an action applied to them, against the kept image, by `actions::apply_to_image()`.
+
+use crate::actions::{self, ActionOptions};
+use crate::config::{Action, FrameStrategy, ProgramSettings};
+use crate::decoders::{self, Decoders};
+use crate::exif::{self, Orientation};
+use crate::img::{self, Image, UniqueImage};
+use crate::processing::{self, Results};
+
+use eframe::egui::{self, Color32, ColorImage, Key, RichText, TextureHandle, TextureOptions};
+use eframe::egui::load::SizedTexture;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// Previews are scaled down to fit in this many pixels, which is 100% zoom.
+const PREVIEW_SIZE: u32 = 512;
+
+const ACTIONS: [Action; 6] = [Action::Trash, Action::Delete, Action::Hardlink, Action::Symlink, Action::Reflink,
+                               Action::Quarantine];
+
+/// A decoded preview, or why it couldn't be decoded.
+type Loaded = (PathBuf, Result<ColorImage, String>);
+
+/// Open the review window with the groups of `results` that have similar images,
+/// and block until it's closed. The action is `--action`, or trash if it isn't set.
+pub fn review(settings: &ProgramSettings, results: &Results) -> eframe::Result<()> {
+    let groups: Vec<Group> = results.groups(settings).into_iter()
+        .filter(|unique| !unique.similars.is_empty())
+        .map(|unique| Group::new(unique, settings))
+        .collect();
+
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([1280.0, 800.0]),
+        ..Default::default()
+    };
+
+    let settings = settings.clone();
+
+    eframe::run_native("img_dup", options, Box::new(move |creation| {
+        Ok(Box::new(Review::new(&settings, groups, creation.egui_ctx.clone())))
+    }))
+}
+
+struct Review {
+    /// Paths are shown relative to this
+    dir: PathBuf,
+    groups: Vec<Group>,
+    selected: usize,
+    zoom: f32,
+    action: Action,
+    options: ActionOptions,
+    previews: HashMap<PathBuf, Preview>,
+    requests: Sender<PathBuf>,
+    loaded: Receiver<Loaded>,
+}
+
+struct Group {
+    /// The original first, then its similars
+    images: Vec<Entry>,
+    /// Index in `images` of the image to keep
+    keep: usize,
+}
+
+struct Entry {
+    image: Image,
+    /// How it matched the original, or `None` for the original
+    matched: Option<String>,
+    state: State,
+}
+
+enum State {
+    Untouched,
+    /// What was done, e.g. "Trashed"
+    Done(String),
+    Failed(String),
+}
+
+enum Preview {
+    Loading,
+    Loaded(TextureHandle),
+    Failed(String),
+}
+
+impl Group {
+
+    fn new(unique: &UniqueImage, settings: &ProgramSettings) -> Group {
+        let mut images = vec![Entry { image: unique.img.clone(), matched: None, state: State::Untouched }];
+
+        images.extend(unique.similars.iter().map(|similar| Entry {
+            image: similar.img.clone(),
+            matched: Some(format!("{:.2}% similar, {}, {}",
+                                  similar.similarity(), similar.tier.name(), similar.reason.name())),
+            state: State::Untouched,
+        }));
+
+        let keep = {
+            let group: Vec<&Image> = images.iter().map(|entry| &entry.image).collect();
+            img::choose_representative(&group, &settings.keep, &settings.prefer)
+        };
+
+        Group { images, keep }
+    }
+
+    /// If every image but the kept one has been dealt with.
+    fn is_resolved(&self) -> bool {
+        self.images.iter().enumerate()
+            .all(|(idx, entry)| idx == self.keep || matches!(entry.state, State::Done(_)))
+    }
+}
+
+impl Review {
+
+    fn new(settings: &ProgramSettings, groups: Vec<Group>, ctx: egui::Context) -> Review {
+        let (requests, loaded) = spawn_loader(settings.decoders.clone(), settings.frames, settings.sniff, ctx);
+
+        Review {
+            dir: settings.dir.clone(),
+            groups,
+            selected: 0,
+            zoom: 0.5,
+            action: settings.action.unwrap_or(Action::Trash),
+            options: ActionOptions::new(settings),
+            previews: HashMap::new(),
+            requests,
+            loaded,
+        }
+    }
+
+    fn toolbar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("◀").clicked() { self.select(self.selected.saturating_sub(1)); }
+            ui.label(format!("Group {} of {}", self.selected + 1, self.groups.len()));
+            if ui.button("▶").clicked() { self.select(self.selected + 1); }
+
+            ui.separator();
+
+            egui::ComboBox::from_label("Action")
+                .selected_text(self.action.verb())
+                .show_ui(ui, |ui| {
+                    // Only with somewhere to move the images
+                    for action in ACTIONS.into_iter()
+                        .filter(|&action| action != Action::Quarantine || self.options.quarantine.is_some()) {
+                        ui.selectable_value(&mut self.action, action, action.verb());
+                    }
+                });
+
+            ui.checkbox(&mut self.options.dry_run, "Dry run");
+
+            ui.separator();
+
+            ui.add(egui::Slider::new(&mut self.zoom, 0.1..=4.0).text("Zoom").custom_formatter(|zoom, _|
+                format!("{:.0}%", zoom * 100.0)));
+
+            ui.separator();
+
+            if ui.button(format!("{} the rest of the group", capitalize(self.action.verb()))).clicked() {
+                let untouched: Vec<usize> = match self.groups.get(self.selected) {
+                    Some(group) => (0..group.images.len())
+                        .filter(|&idx| idx != group.keep && !matches!(group.images[idx].state, State::Done(_)))
+                        .collect(),
+                    None => Vec::new(),
+                };
+
+                for idx in untouched {
+                    self.apply(self.selected, idx);
+                }
+            }
+        });
+    }
+
+    fn group_list(&mut self, ui: &mut egui::Ui) {
+        let mut clicked = None;
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (idx, group) in self.groups.iter().enumerate() {
+                let original = &group.images[0].image;
+                let mut label = format!("{} ({} images)", original.relative_path(&self.dir).display(), group.images.len());
+
+                if group.is_resolved() { label.push_str(" ✔"); }
+
+                if ui.selectable_label(idx == self.selected, label).clicked() {
+                    clicked = Some(idx);
+                }
+            }
+        });
+
+        if let Some(idx) = clicked { self.select(idx); }
+    }
+
+    fn group_view(&mut self, ui: &mut egui::Ui) {
+        if self.groups.is_empty() {
+            ui.centered_and_justified(|ui| ui.label("No similar images were found."));
+            return;
+        }
+
+        let mut keep = None;
+        let mut apply = None;
+
+        let paths: Vec<PathBuf> = self.groups[self.selected].images.iter()
+            .map(|entry| preview_path(&entry.image))
+            .collect();
+
+        for path in &paths {
+            self.request_preview(path);
+        }
+
+        let group = &self.groups[self.selected];
+
+        egui::ScrollArea::both().show(ui, |ui| {
+            ui.horizontal_top(|ui| {
+                for (idx, entry) in group.images.iter().enumerate() {
+                    ui.vertical(|ui| {
+                        ui.set_max_width((PREVIEW_SIZE as f32 * self.zoom).max(200.0));
+
+                        match self.previews.get(&paths[idx]) {
+                            Some(Preview::Loaded(texture)) => {
+                                let size = texture.size_vec2() * self.zoom;
+                                ui.add(egui::Image::from_texture(SizedTexture::new(texture.id(), size)));
+                            },
+                            Some(Preview::Failed(err)) => { ui.label(format!("No preview: {}", err)); },
+                            _ => { ui.spinner(); },
+                        }
+
+                        let image = &entry.image;
+
+                        ui.label(RichText::new(image.relative_path(&self.dir).display().to_string()).strong());
+                        ui.label(format!("{}x{} {}, {} bytes", image.width, image.height, image.format_name(), image.file_size));
+                        ui.label(entry.matched.as_deref().unwrap_or("Original"));
+
+                        if idx == group.keep {
+                            ui.label(RichText::new("Kept").color(Color32::GREEN));
+                            return;
+                        }
+
+                        match entry.state {
+                            State::Done(ref done) => { ui.label(RichText::new(done).color(Color32::GRAY)); },
+                            ref state => {
+                                if let State::Failed(ref err) = *state {
+                                    ui.label(RichText::new(err).color(Color32::RED));
+                                }
+
+                                ui.horizontal(|ui| {
+                                    if ui.button("Keep").clicked() { keep = Some(idx); }
+                                    if ui.button(capitalize(self.action.verb())).clicked() { apply = Some(idx); }
+                                });
+                            },
+                        }
+                    });
+                }
+            });
+        });
+
+        if let Some(idx) = keep { self.groups[self.selected].keep = idx; }
+        if let Some(idx) = apply { self.apply(self.selected, idx); }
+    }
+
+    fn select(&mut self, idx: usize) {
+        self.selected = idx.min(self.groups.len().saturating_sub(1));
+    }
+
+    /// Apply the action to image `idx` of group `group`, against the kept image.
+    fn apply(&mut self, group: usize, idx: usize) {
+        let group = &mut self.groups[group];
+        let keep = group.images[group.keep].image.clone();
+        let entry = &mut group.images[idx];
+
+        entry.state = match actions::apply_to_image(self.action, &keep, &entry.image, &self.options) {
+            Ok((applied, _)) => State::Done(applied.describe(self.options.dry_run)),
+            Err(err) => State::Failed(format!("Could not {}: {}", self.action.verb(), err)),
+        };
+    }
+
+    fn request_preview(&mut self, path: &Path) {
+        if self.previews.contains_key(path) { return; }
+
+        self.previews.insert(path.to_path_buf(), Preview::Loading);
+        let _ = self.requests.send(path.to_path_buf());
+    }
+
+    fn receive_previews(&mut self, ctx: &egui::Context) {
+        for (path, loaded) in self.loaded.try_iter() {
+            let preview = match loaded {
+                Ok(image) => Preview::Loaded(ctx.load_texture(path.display().to_string(), image, TextureOptions::LINEAR)),
+                Err(err) => Preview::Failed(err),
+            };
+
+            self.previews.insert(path, preview);
+        }
+    }
+}
+
+impl eframe::App for Review {
+    fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        self.receive_previews(ui.ctx());
+
+        if ui.input(|input| input.key_pressed(Key::ArrowLeft)) { self.select(self.selected.saturating_sub(1)); }
+        if ui.input(|input| input.key_pressed(Key::ArrowRight)) { self.select(self.selected + 1); }
+
+        egui::Panel::top("toolbar").show(ui, |ui| self.toolbar(ui));
+        egui::Panel::left("groups").resizable(true).show(ui, |ui| self.group_list(ui));
+        egui::CentralPanel::default().show(ui, |ui| self.group_view(ui));
+    }
+}
+
+/// The thumbnail from `--thumbnails` if there is one, since it's smaller to load.
+fn preview_path(image: &Image) -> PathBuf {
+    image.thumbnail.clone().unwrap_or_else(|| image.path.clone())
+}
+
+/// Decode previews on a thread of their own, so the window doesn't freeze while they load.
+fn spawn_loader(decoders: Decoders, frames: FrameStrategy, sniff: bool, ctx: egui::Context)
+    -> (Sender<PathBuf>, Receiver<Loaded>) {
+    let (request_tx, request_rx) = channel::<PathBuf>();
+    let (loaded_tx, loaded_rx) = channel();
+
+    thread::spawn(move || {
+        for path in request_rx {
+            let loaded = decoders::open(&path, None, &decoders, frames, sniff)
+                .map(|image| {
+                    let image = processing::orient(exif::read_orientation(&path).unwrap_or(Orientation::Normal), image)
+                        .thumbnail(PREVIEW_SIZE, PREVIEW_SIZE)
+                        .to_rgba8();
+
+                    ColorImage::from_rgba_unmultiplied([image.width() as usize, image.height() as usize], &image)
+                })
+                .map_err(|err| err.to_string());
+
+            if loaded_tx.send((path, loaded)).is_err() { return; }
+
+            ctx.request_repaint();
+        }
+    });
+
+    (request_tx, loaded_rx)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
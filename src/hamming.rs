@@ -0,0 +1,44 @@
+/// The bits of an image hash packed into 64-bit words, the first bit as the most significant
+/// of the first word, so the Hamming distance takes one XOR and `count_ones` per word
+/// instead of a loop over every bit. Bits past the end of the hash are always 0.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct PackedHash {
+    words: Vec<u64>,
+}
+
+impl PackedHash {
+
+    /// Pack the first `bits` bits of `bytes`, the first bit as the most significant of each byte.
+    pub fn from_bytes(bytes: &[u8], bits: usize) -> PackedHash {
+        let mut words: Vec<u64> = bytes[..bits.div_ceil(8)].chunks(8)
+            .map(|chunk| {
+                let mut word = [0; 8];
+                word[..chunk.len()].copy_from_slice(chunk);
+                u64::from_be_bytes(word)
+            })
+            .collect();
+
+        // Bits of the last byte past the end
+        if let Some(last) = words.last_mut().filter(|_| !bits.is_multiple_of(64)) {
+            *last &= !(u64::MAX >> (bits % 64));
+        }
+
+        PackedHash { words }
+    }
+
+    /// The first `bits` bits, as packed by `from_bytes()`.
+    pub fn to_bytes(&self, bits: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self.words.iter().flat_map(|word| word.to_be_bytes()).collect();
+        bytes.truncate(bits.div_ceil(8));
+        bytes
+    }
+
+    pub fn dist(&self, other: &PackedHash) -> usize {
+        assert!(self.words.len() == other.words.len(),
+                "Hashes must be the same length for proper comparison!");
+
+        self.words.iter().zip(other.words.iter())
+            .map(|(&left, &right)| (left ^ right).count_ones() as usize)
+            .sum()
+    }
+}
@@ -1,29 +1,50 @@
-use dct::{dct_2d, crop_dct};
+use crate::dct::{dct_2d, crop_dct};
+use crate::hamming::PackedHash;
 
-use image::{GenericImage, DynamicImage, 
-    ImageBuf, Luma, Pixel, FilterType, Nearest, Rgba};
-use image::imageops::{grayscale, resize};
+use bit_vec::BitVec;
 
-use serialize::base64::{ToBase64, STANDARD};
+use image::{DynamicImage, GrayImage};
+use image::imageops::FilterType;
 
-use std::collections::Bitv;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 
-const FILTER_TYPE: FilterType = Nearest;
+use std::sync::Arc;
 
-#[deriving(PartialEq, Eq, Hash, Show, Clone)]
+const FILTER_TYPE: FilterType = FilterType::Nearest;
+
+/// How far from gray a cell has to be, out of 255, for the color hash to count it as colored,
+/// so noise and compression artifacts in black and white images don't set bits.
+const GRAY_CHROMA: f32 = 12.0;
+
+/// A hasher set with `ProgramSettings::set_hasher()`.
+pub type Hasher = Arc<dyn PerceptualHasher + Send + Sync>;
+
+/// The GPU set with `ProgramSettings::set_backend()`: hashes an image like `ImageHash::hash()`.
+pub type GpuHash = Arc<dyn Fn(&DynamicImage, u32, bool) -> ImageHash + Send + Sync>;
+
+/// A perceptual hash algorithm to use instead of the built-in DCT and fast hashes,
+/// like a wavelet, block-mean or color-moment hash.
+///
+/// Similar images must get hashes that differ in few bits, and every image must get a hash
+/// of the same length, but it can be any length: `--hash-size` doesn't apply.
+pub trait PerceptualHasher {
+    fn hash(&self, img: &DynamicImage) -> BitVec;
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
 pub struct ImageHash {
     size: u32,
-    bitv: Bitv,
+    bits: PackedHash,
 }
 
 impl ImageHash {
 
-    pub fn dist(&self, other: &ImageHash) -> uint {
-        assert!(self.bitv.len() == other.bitv.len(), 
+    pub fn dist(&self, other: &ImageHash) -> usize {
+        assert!(self.size == other.size, 
                 "ImageHashes must be the same length for proper comparison!");
 
-        self.bitv.iter().zip(other.bitv.iter())
-            .filter(|&(left, right)| left != right).count()
+        self.bits.dist(&other.bits)
     }
 
     pub fn dist_ratio(&self, other: &ImageHash) -> f32 {
@@ -31,21 +52,16 @@ impl ImageHash {
     }    
 
     
-    fn fast_hash<Img: GenericImage<Rgba<u8>>>(img: &Img, hash_size: u32) -> Bitv {
+    fn fast_hash(img: &DynamicImage, hash_size: u32) -> BitVec {
         let temp = square_resize_and_gray(img, hash_size);
 
-        let hash_values: Vec<u8> = temp.pixels().map(|(_, _, x)| x.channel())
+        let hash_values: Vec<u8> = temp.pixels().map(|x| x.0[0])
             .collect();
 
-        let hash_sq = (hash_size * hash_size) as uint;
-
-        let mean = hash_values.iter().fold(0u, |b, &a| a as uint + b) 
-            / hash_sq;
-
-        hash_values.into_iter().map(|x| x as uint >= mean).collect()
+        fast_bits(&hash_values)
     }
 
-    fn dct_hash<Img: GenericImage<Rgba<u8>>>(img: &Img, hash_size: u32) -> Bitv {
+    fn dct_hash(img: &DynamicImage, hash_size: u32) -> BitVec {
         let large_size = hash_size * 4;
 
         // We take a bigger resize than fast_hash, 
@@ -54,46 +70,114 @@ impl ImageHash {
 
         // Our hash values are converted to doubles for the DCT
         let hash_values: Vec<f64> = temp.pixels()
-            .map(|(_, _, x)| x.channel() as f64).collect();
-
-        let dct = dct_2d(hash_values.as_slice(),
-            large_size as uint, large_size as uint);
-
-        let original = (large_size as uint, large_size as uint);
-        let new = (hash_size as uint, hash_size as uint);
+            .map(|x| x.0[0] as f64).collect();
 
-        let cropped_dct = crop_dct(dct, original, new);
-
-        let mean = cropped_dct.iter().fold(0f64, |b, &a| a + b) 
-            / (hash_size * hash_size) as f64;
-
-        cropped_dct.into_iter().map(|x| x >= mean).collect()
+        gray_dct_bits(&hash_values, hash_size)
     }    
 
-    pub fn hash<Img: GenericImage<Rgba<u8>>>(img: &Img, hash_size: u32, fast: bool) -> ImageHash {
+    pub fn hash(img: &DynamicImage, hash_size: u32, fast: bool) -> ImageHash {
         let hash = if fast { 
             ImageHash::fast_hash(img, hash_size)   
         } else { 
             ImageHash::dct_hash(img, hash_size)             
         };
 
-        assert!((hash_size * hash_size) as uint == hash.len());
+        assert!((hash_size * hash_size) as usize == hash.len());
+
+        ImageHash::from_bits(hash)
+    }
+
+    /// A hash of the colors of `img` rather than its brightness, for `--combine=color`.
+    /// Each of `hash_size * hash_size` cells gets 4 bits: whether it's noticeably red, green,
+    /// yellow or blue. Gray cells have none set, so a black and white edit or a recolored copy
+    /// differs from the original, while the DCT and fast hashes find them the same.
+    pub fn color_hash(img: &DynamicImage, hash_size: u32) -> ImageHash {
+        // Averaged rather than sampled like the other hashes: one stray pixel shouldn't color a cell
+        let small = img.resize_exact(hash_size, hash_size, FilterType::Triangle).to_rgb8();
+
+        let bitv = small.pixels()
+            .flat_map(|pixel| {
+                let [red, green, blue] = pixel.0.map(f32::from);
+                let red_green = red - green;
+                let yellow_blue = (red + green) / 2.0 - blue;
+
+                [red_green > GRAY_CHROMA, red_green < -GRAY_CHROMA, 
+                 yellow_blue > GRAY_CHROMA, yellow_blue < -GRAY_CHROMA]
+            })
+            .collect();
+
+        ImageHash::from_bits(bitv)
+    }
+
+    /// A hash made by a `PerceptualHasher` or on the GPU, of however many bits it returned.
+    pub fn from_bits(bitv: BitVec) -> ImageHash {
+        ImageHash { size: bitv.len() as u32, bits: PackedHash::from_bytes(&bitv.to_bytes(), bitv.len()) }
+    }
 
-        ImageHash {
-            size: hash_size * hash_size,
-            bitv: hash,
-        }
+    /// How many bits the hash has.
+    pub fn bits(&self) -> usize {
+        self.size as usize
+    }
+
+    /// The bits of the hash, packed into bytes with the first bit as the most significant.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bits.to_bytes(self.size as usize)
     }
 
     pub fn to_base64(&self) -> String {
-        let self_bytes = self.bitv.to_bytes();
+        STANDARD.encode(self.to_bytes())
+    }
+
+    /// Decode a hash of `hash_size * hash_size` bits from the output of `to_base64()`.
+    pub fn from_base64(encoded: &str, hash_size: u32) -> Option<ImageHash> {
+        let bytes = STANDARD.decode(encoded).ok()?;
+
+        ImageHash::from_bytes(&bytes, hash_size)
+    }
 
-        self_bytes.as_slice().to_base64(STANDARD)
+    /// Unpack a hash of `hash_size * hash_size` bits from the output of `to_bytes()`.
+    pub fn from_bytes(bytes: &[u8], hash_size: u32) -> Option<ImageHash> {
+        ImageHash::from_packed(bytes, (hash_size * hash_size) as usize)
+    }
+
+    /// Unpack a hash of any number of bits, like `bits()`, from the output of `to_bytes()`.
+    pub fn from_packed(bytes: &[u8], bits: usize) -> Option<ImageHash> {
+        if bytes.len() * 8 < bits { return None; }
+
+        Some(ImageHash { size: bits as u32, bits: PackedHash::from_bytes(bytes, bits) })
     }
 }
 
-fn square_resize_and_gray<Img: GenericImage<Rgba<u8>>>(img: &Img, size: u32) -> ImageBuf<Luma<u8>> {
-        let small = resize(img, size, size, FILTER_TYPE);
-        grayscale(&small)
+/// The fast hash of the gray values of an image resized to `hash_size * hash_size`:
+/// which of them are at least their mean.
+pub fn fast_bits(values: &[u8]) -> BitVec {
+    let mean = values.iter().fold(0usize, |b, &a| a as usize + b) 
+        / values.len();
+
+    values.iter().map(|&x| x as usize >= mean).collect()
 }
 
+/// The DCT hash of the gray values of an image resized to `4 * hash_size` square.
+pub fn gray_dct_bits(values: &[f64], hash_size: u32) -> BitVec {
+    let large_size = hash_size as usize * 4;
+
+    let dct = dct_2d(values, large_size, large_size);
+
+    let cropped_dct = crop_dct(dct, (large_size, large_size), (hash_size as usize, hash_size as usize));
+
+    dct_bits(&cropped_dct)
+}
+
+/// The DCT hash of the lowest `hash_size * hash_size` frequencies of the DCT:
+/// which of them are at least their mean.
+pub fn dct_bits(frequencies: &[f64]) -> BitVec {
+    let mean = frequencies.iter().fold(0f64, |b, &a| a + b) 
+        / frequencies.len() as f64;
+
+    frequencies.iter().map(|&x| x >= mean).collect()
+}
+
+fn square_resize_and_gray(img: &DynamicImage, size: u32) -> GrayImage {
+        let small = img.resize_exact(size, size, FILTER_TYPE);
+        small.to_luma8()
+}
@@ -0,0 +1,79 @@
+use crate::scratch;
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// JPEG metadata before the frame header can be large, e.g. embedded thumbnails.
+const MAX_HEADER_LEN: u64 = 256 * 1024;
+
+/// Read the width and height of an image from its header, without decoding it.
+/// Returns `None` for formats other than PNG, JPEG, GIF and BMP, or if the header is invalid.
+pub fn read_dimensions(path: &Path) -> io::Result<Option<(u32, u32)>> {
+    scratch::read(File::open(path)?, MAX_HEADER_LEN, dimensions)
+}
+
+/// The same, for a file that's already in memory.
+pub fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") && data.len() >= 24 {
+        // IHDR is always the first chunk
+        Some((be_u32(&data[16..]), be_u32(&data[20..])))
+    } else if data.starts_with(b"GIF8") && data.len() >= 10 {
+        Some((le_u16(&data[6..]) as u32, le_u16(&data[8..]) as u32))
+    } else if data.starts_with(b"BM") && data.len() >= 26 {
+        // The height is negative for top-down bitmaps
+        let height = le_u32(&data[22..]) as i32;
+        Some((le_u32(&data[18..]), height.unsigned_abs()))
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        jpeg_dimensions(data)
+    } else {
+        None
+    }
+}
+
+/// Find the start of frame segment.
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+
+    while pos + 9 <= data.len() {
+        if data[pos] != 0xFF { return None; }
+
+        let marker = data[pos + 1];
+
+        // Padding before a marker
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+
+        match marker {
+            // SOF0 to SOF15, except DHT, JPG and DAC which share the range
+            0xC0..=0xCF if marker != 0xC4 && marker != 0xC8 && marker != 0xCC => {
+                let height = be_u16(&data[pos + 5..]) as u32;
+                let width = be_u16(&data[pos + 7..]) as u32;
+                return Some((width, height));
+            },
+            // Start of scan, the frame header should have come before
+            0xDA => return None,
+            _ => pos += 2 + be_u16(&data[pos + 2..]) as usize,
+        }
+    }
+
+    None
+}
+
+fn be_u16(data: &[u8]) -> u16 {
+    ((data[0] as u16) << 8) | data[1] as u16
+}
+
+fn le_u16(data: &[u8]) -> u16 {
+    ((data[1] as u16) << 8) | data[0] as u16
+}
+
+fn be_u32(data: &[u8]) -> u32 {
+    ((be_u16(data) as u32) << 16) | be_u16(&data[2..]) as u32
+}
+
+fn le_u32(data: &[u8]) -> u32 {
+    ((le_u16(&data[2..]) as u32) << 16) | le_u16(data) as u32
+}
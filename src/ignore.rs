@@ -0,0 +1,67 @@
+use crate::img::Image;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// Known false positives that are never reported as similar, loaded from a file given to `--ignore`.
+///
+/// Each line of the file is either the hash of an image as output in the results,
+/// to never match any image with that hash (e.g. screenshots of a solid color),
+/// or two hashes separated by whitespace, to never match those two with each other.
+/// Empty lines and lines starting with `#` are skipped.
+#[derive(Clone, Default)]
+pub struct IgnoreList {
+    hashes: HashSet<String>,
+    // Sorted, so either order matches
+    pairs: HashSet<(String, String)>,
+}
+
+impl IgnoreList {
+
+    pub fn new() -> IgnoreList {
+        IgnoreList::default()
+    }
+
+    pub fn load(path: &Path) -> io::Result<IgnoreList> {
+        let mut list = IgnoreList::new();
+
+        let reader = BufReader::new(File::open(path)?);
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let hashes: Vec<&str> = line.split_whitespace().collect();
+
+            match hashes[..] {
+                [hash] => { list.hashes.insert(hash.to_string()); },
+                [left, right] => { list.pairs.insert(sorted_pair(left.to_string(), right.to_string())); },
+                _ => (),
+            }
+        }
+
+        Ok(list)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty() && self.pairs.is_empty()
+    }
+
+    /// Whether `left` and `right` should never be reported as similar.
+    pub fn is_ignored(&self, left: &Image, right: &Image) -> bool {
+        if self.is_empty() { return false; }
+
+        let (left, right) = (left.hash.to_base64(), right.hash.to_base64());
+
+        self.hashes.contains(&left) || self.hashes.contains(&right)
+            || self.pairs.contains(&sorted_pair(left, right))
+    }
+}
+
+fn sorted_pair(left: String, right: String) -> (String, String) {
+    if left <= right { (left, right) } else { (right, left) }
+}
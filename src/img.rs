@@ -1,48 +1,259 @@
-use img_hash::ImageHash;
+use crate::classify;
+use crate::config::{DirPreference, HashSettings, RepresentativePolicy, Tier};
+use crate::hash::ImageHash;
+use crate::search;
 
-use serialize::json::{Json, ToJson};
+use image::{DynamicImage, ImageFormat};
 
-use std::collections::BTreeMap;
-use std::io::IoResult;
+use serde::{Deserialize, Serialize};
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, Read, Write};
 use std::mem;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
-#[deriving(Eq, PartialEq, Clone)]
+/// Deserialized from the same fields that `serialize` writes for it.
+#[derive(PartialEq, Clone, Deserialize)]
 pub struct Image {
-    pub path: Path,
+    pub path: PathBuf,
+    /// With its length from `hash_bits`
+    #[serde(flatten, deserialize_with = "crate::serialize::deserialize_hash")]
     pub hash: ImageHash,
+    /// Additional hashes and their weights, from `--combine`
+    #[serde(skip)]
+    pub combined: Vec<(ImageHash, f32)>,
+    /// Hashes of this image rotated and mirrored, from `--rotations`
+    #[serde(skip)]
+    pub variants: Vec<ImageHash>,
+    /// Hashes of central crops of this image, from `--crop`
+    #[serde(skip)]
+    pub crops: Vec<ImageHash>,
+    /// The first of the files with exactly the same contents as this one, from `--exact-first`
+    #[serde(skip)]
+    pub same_contents: Option<PathBuf>,
     pub width: u32,
     pub height: u32,
+    /// Size of the file in bytes
+    #[serde(rename = "size")]
+    pub file_size: u64,
+    #[serde(deserialize_with = "crate::serialize::deserialize_format")]
+    pub format: Option<ImageFormat>,
+    /// From `--thumbnails`
+    #[serde(default)]
+    pub thumbnail: Option<PathBuf>,
+    /// RAW files of the same photo, from `--pair-raw`
+    #[serde(default)]
+    pub sidecars: Vec<PathBuf>,
+    /// Other paths to the same file, like hard links
+    #[serde(default)]
+    pub links: Vec<PathBuf>,
+    /// How long this image took to load and hash, from `--timings`
+    #[serde(skip)]
+    pub timing: Option<ImageTiming>,
+    /// Probably a screenshot, from `--screenshots`
+    #[serde(default)]
+    pub screenshot: bool,
+    /// The largest image in the same group that this is a scaled-down copy of
+    #[serde(default)]
+    pub thumbnail_of: Option<PathBuf>,
+}
+
+/// Nanoseconds
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub struct ImageTiming {
+    /// Reading and decoding the file
+    pub load: u64,
+    pub hash: u64,
 }
 
 impl Image {
 
-    pub fn new(path: Path, hash: ImageHash, width: u32, height: u32) -> Image {
+    pub fn new(path: PathBuf, hash: ImageHash, width: u32, height: u32, file_size: u64) -> Image {
+        let format = guess_format(&path);
+
         Image {
-            path: path,
-            hash: hash,
-            width: width,
-            height: height,
+            path,
+            hash,
+            combined: Vec::new(),
+            variants: Vec::new(),
+            crops: Vec::new(),
+            same_contents: None,
+            width,
+            height,
+            file_size,
+            format,
+            thumbnail: None,
+            sidecars: Vec::new(),
+            links: Vec::new(),
+            timing: None,
+            screenshot: false,
+            thumbnail_of: None,
         } 
     }
 
-    fn relative_path(&self, relative_to: &Path) -> Path {
-        self.path.path_relative_from(relative_to).unwrap_or(self.path.clone())
+    /// Hash an image that's already decoded, e.g. by a server from an upload,
+    /// with the hash type or custom hasher, `--combine`, `--rotations` and `--crop` from `settings`.
+    ///
+    /// The path is empty and the file size is 0, to be set by the caller.
+    pub fn hash_from_dynamic_image(img: &DynamicImage, settings: &HashSettings) -> Image {
+        let hash = settings.hash(img);
+
+        let mut image = Image::new(PathBuf::new(), hash, img.width(), img.height(), 0);
+
+        image.combined = settings.combine.iter()
+            .map(|&(hash_type, weight)| (hash_type.hash(img, settings.hash_size), weight))
+            .collect();
+
+        if settings.rotations {
+            image.variants = hash_variants(img, settings);
+        }
+
+        if settings.crop > 0f32 {
+            image.crops = hash_crops(img, settings);
+        }
+
+        if settings.screenshots {
+            image.screenshot = classify::is_screenshot(img);
+        }
+
+        image
     }
 
-    pub fn to_treemap(&self, relative_to: &Path) -> BTreeMap<String, Json> {
-        let mut json = BTreeMap::new();
+    pub fn format_name(&self) -> &'static str {
+        match self.format {
+            Some(ImageFormat::Png) => "png",
+            Some(ImageFormat::Jpeg) => "jpeg",
+            Some(ImageFormat::Gif) => "gif",
+            Some(ImageFormat::WebP) => "webp",
+            Some(ImageFormat::Pnm) => "ppm",
+            Some(ImageFormat::Tiff) => "tiff",
+            Some(ImageFormat::Tga) => "tga",
+            Some(ImageFormat::Bmp) => "bmp",
+            Some(ImageFormat::Ico) => "ico",
+            _ => "unknown",
+        }
+    }
+
+    /// The weighted average of the differences between each pair of hashes.
+    ///
+    /// The main hashes are the closest pair: with the rotated and mirrored variants of `other`,
+    /// and the crops of either image.
+    pub fn dist_ratio(&self, other: &Image) -> f32 {
+        let mut total = self.closest_main(other).0 as f32 / self.hash.bits() as f32;
+        let mut weights = 1f32;
+
+        for (&(ref hash, weight), (other_hash, _)) in 
+            self.combined.iter().zip(other.combined.iter()) {
+            total += hash.dist_ratio(other_hash) * weight;
+            weights += weight;
+        }
+
+        total / weights
+    }
+
+    /// The number of bits that differ between the closest pair of main hashes.
+    pub fn dist(&self, other: &Image) -> usize {
+        self.closest_main(other).0
+    }
 
-        json_insert!(json, "path", self.relative_path(relative_to).display().to_string());
-        json_insert!(json, "hash", self.hash.to_base64());
-        json_insert!(json, "width", &self.width);
-        json_insert!(json, "height", &self.height);
+    /// How `other` matches this image: by having the same contents, or else by which pair
+    /// of main hashes is the closest.
+    pub fn match_reason(&self, other: &Image) -> MatchReason {
+        if self.same_contents.is_some() && self.same_contents == other.same_contents {
+            return MatchReason::Identical;
+        }
 
-        json
+        self.closest_main(other).1
+    }
+
+    /// The distance between the closest pair of main hashes, and which kind of pair it is.
+    /// Variants and crops are only used if they're strictly closer.
+    fn closest_main(&self, other: &Image) -> (usize, MatchReason) {
+        let whole = (self.hash.dist(&other.hash), MatchReason::Hash);
+
+        let rotated = other.variants.iter()
+            .map(|variant| (self.hash.dist(variant), MatchReason::Rotated));
+
+        let cropped = other.crops.iter().map(|crop| self.hash.dist(crop))
+            .chain(self.crops.iter().map(|crop| crop.dist(&other.hash)))
+            .map(|dist| (dist, MatchReason::Cropped));
+
+        rotated.chain(cropped)
+            .fold(whole, |closest, pair| if pair.0 < closest.0 { pair } else { closest })
+    }
+
+    pub fn relative_path(&self, relative_to: &Path) -> PathBuf {
+        relative(&self.path, relative_to)
+    }
+
+    /// What the image was tagged as, to add to its description in the text output.
+    fn tags(&self, relative_to: &Path) -> String {
+        let mut tags = String::new();
+
+        if self.screenshot { tags.push_str(", screenshot"); }
+
+        if let Some(ref larger) = self.thumbnail_of {
+            tags.push_str(&format!(", thumbnail of {}", relative(larger, relative_to).display()));
+        }
+
+        tags
+    }
+
+    /// If this image is a scaled-down copy of `other`: at most half as wide and as high, with
+    /// the same aspect ratio give or take a pixel of rounding, and hashes closer than `near_threshold`.
+    pub fn is_thumbnail_of(&self, other: &Image, near_threshold: f32) -> bool {
+        if self.width == 0 || self.height * 2 > other.height || self.width * 2 > other.width {
+            return false;
+        }
+
+        let scaled_height = other.height as f32 * self.width as f32 / other.width as f32;
+
+        (scaled_height - self.height as f32).abs() <= 1f32 && self.dist_ratio(other) < near_threshold
+    }
+
+    /// List the paired RAW files and the links under the image in the text output.
+    fn write_sidecars(&self, out: &mut dyn Write, relative_to: &Path) -> io::Result<()> {
+        for sidecar in &self.sidecars {
+            writeln!(out, "    + {}", relative(sidecar, relative_to).display())?;
+        }
+
+        for link in &self.links {
+            writeln!(out, "    = {}", relative(link, relative_to).display())?;
+        }
+
+        Ok(())
+    }
+
+    /// A copy with every path relative to `relative_to`, for output.
+    pub fn relative_to(&self, relative_to: &Path) -> Image {
+        Image {
+            path: self.relative_path(relative_to),
+            thumbnail: self.thumbnail.as_ref().map(|thumbnail| relative(thumbnail, relative_to)),
+            sidecars: self.sidecars.iter().map(|path| relative(path, relative_to)).collect(),
+            links: self.links.iter().map(|path| relative(path, relative_to)).collect(),
+            thumbnail_of: self.thumbnail_of.as_ref().map(|larger| relative(larger, relative_to)),
+            ..self.clone()
+        }
+    }
+
+    /// The opposite of `relative_to()`: paths that are relative are joined to `dir`.
+    pub fn in_dir(&self, dir: &Path) -> Image {
+        Image {
+            path: dir.join(&self.path),
+            thumbnail: self.thumbnail.as_ref().map(|thumbnail| dir.join(thumbnail)),
+            sidecars: self.sidecars.iter().map(|path| dir.join(path)).collect(),
+            links: self.links.iter().map(|path| dir.join(path)).collect(),
+            thumbnail_of: self.thumbnail_of.as_ref().map(|larger| dir.join(larger)),
+            ..self.clone()
+        }
     }
 }
 
+#[derive(Clone, Deserialize)]
 pub struct UniqueImage {
+    #[serde(flatten)]
     pub img: Image,
     pub similars: Vec<SimilarImage>,
 }
@@ -51,19 +262,19 @@ impl UniqueImage {
 
     pub fn from_image(img: Image) -> UniqueImage {
         UniqueImage {
-           img: img,
+           img,
            similars: Vec::new(),
         }
     }
     
     pub fn is_similar(&self, img: &Image, thresh: f32) -> bool {
-        self.img.hash.dist_ratio(&img.hash) < thresh
+        self.img.dist_ratio(img) < thresh
     }
  
-    pub fn add_similar(&mut self, img: Image) {
-        let dist_ratio = self.img.hash.dist_ratio(&img.hash);
+    pub fn add_similar(&mut self, img: Image, near_threshold: f32) {
+        let similar = SimilarImage::compared(&self.img, img, near_threshold);
 
-        self.similars.push(SimilarImage::from_image(img, dist_ratio));
+        self.similars.push(similar);
     }
 
     pub fn similars(&self) -> Vec<SimilarImage> {
@@ -72,88 +283,301 @@ impl UniqueImage {
         temp    
     }
 
-    pub fn write_self(&self, out: &mut Writer, relative_to: &Path) -> IoResult<()> {
-        try!(writeln!(out, "Original: ({}x{}) {} ", 
-                    self.img.width, self.img.height,
-                    self.img.relative_path(relative_to).display()
-                ));
+    /// How many bytes would be freed by deleting every image in the group but the original.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.similars.iter().fold(0, |total, similar| total + similar.img.file_size)
+    }
+
+    pub fn write_self(&self, out: &mut dyn Write, relative_to: &Path) -> io::Result<()> {
+        writeln!(out, "Original: ({}x{} {}, {} bytes{}) {} ", 
+                 self.img.width, self.img.height,
+                 self.img.format_name(), self.img.file_size, self.img.tags(relative_to),
+                 self.img.relative_path(relative_to).display()
+        )?;
+        self.img.write_sidecars(out, relative_to)?;
+
+        if !self.similars.is_empty() {
+            writeln!(out, "Wasted space: {} bytes", self.wasted_bytes())?;
+        }
         
-        try!(out.write_line("Similars [% different]:"));
+        writeln!(out, "Similars [% different]:")?;
     
-        for similar in self.similars().iter() {
-            try!(similar.write_self(out, relative_to));
+        for similar in &self.similars() {
+            similar.write_self(out, relative_to)?;
         }
 
-        out.write_char('\n')
+        writeln!(out)
+    }
+
+    /// A copy with every path relative to `relative_to`, for output.
+    pub fn relative_to(&self, relative_to: &Path) -> UniqueImage {
+        UniqueImage {
+            img: self.img.relative_to(relative_to),
+            similars: self.similars.iter()
+                .map(|similar| SimilarImage { img: similar.img.relative_to(relative_to), ..similar.clone() })
+                .collect(),
+        }
     }
 
-    pub fn to_json(&self, relative_to: &Path) -> Json {
-        let mut json = self.img.to_treemap(relative_to);
+    pub fn in_dir(&self, dir: &Path) -> UniqueImage {
+        UniqueImage {
+            img: self.img.in_dir(dir),
+            similars: self.similars.iter()
+                .map(|similar| SimilarImage { img: similar.img.in_dir(dir), ..similar.clone() })
+                .collect(),
+        }
+    }
+
+    /// Make the image chosen by `prefer` and `policy` the original of this group.
+    pub fn choose_original(&mut self, policy: &RepresentativePolicy, prefer: &DirPreference, near_threshold: f32) {
+        if self.similars.is_empty() { return; }
+
+        let idx = {
+            let group: Vec<&Image> = Some(&self.img).into_iter()
+                .chain(self.similars.iter().map(|similar| &similar.img))
+                .collect();
+
+            choose_representative(&group, policy, prefer)
+        };
+
+        if idx > 0 {
+            self.promote(idx - 1, near_threshold);
+        }
+    }
 
-        let similars_json: Vec<Json> = self.similars.iter()
-            .map( |similar| similar.to_json(relative_to) )
+    /// Mark each image in the group that is a thumbnail of another with the largest one it's a thumbnail of.
+    pub fn pair_thumbnails(&mut self, near_threshold: f32) {
+        let group: Vec<Image> = Some(&self.img).into_iter()
+            .chain(self.similars.iter().map(|similar| &similar.img))
+            .cloned()
             .collect();
 
-        json_insert!(json, "similars", similars_json);
+        let larger_of = |img: &Image| group.iter()
+            .filter(|other| img.is_thumbnail_of(other, near_threshold))
+            .max_by_key(|other| other.width as u64 * other.height as u64)
+            .map(|other| other.path.clone());
 
-        Json::Object(json)
+        self.img.thumbnail_of = larger_of(&self.img);
+
+        for similar in self.similars.iter_mut() {
+            similar.img.thumbnail_of = larger_of(&similar.img);
+        }
     }
 
-    pub fn promote(&mut self, idx: uint) {
+    pub fn promote(&mut self, idx: usize, near_threshold: f32) {
         mem::swap(&mut self.similars[idx].img, &mut self.img);
         for similar in self.similars.iter_mut() {
-            let dist_ratio = self.img.hash.dist_ratio(&similar.img.hash);
+            let dist_ratio = self.img.dist_ratio(&similar.img);
+            let dist = self.img.dist(&similar.img);
             similar.dist_ratio = dist_ratio;
+            similar.dist = dist;
+            similar.tier = Tier::of(dist_ratio, near_threshold);
+            similar.reason = self.img.match_reason(&similar.img);
         }
         
         self.similars.sort()
     } 
 }
 
-#[deriving(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Deserialize)]
 pub struct SimilarImage {
+   #[serde(flatten)]
    pub img: Image, 
    // Distance from the containing UniqueImage
+   #[serde(rename = "diff")]
    pub dist_ratio: f32,
+   // Hamming distance between the main hashes
+   #[serde(rename = "distance")]
+   pub dist: usize,
+   pub tier: Tier,
+   /// How this image matched the original
+   #[serde(default, rename = "match")]
+   pub reason: MatchReason,
 }
 
 impl SimilarImage {
 
-    fn from_image(img: Image, dist_ratio: f32) -> SimilarImage {
+    pub fn from_image(img: Image, dist_ratio: f32, dist: usize, near_threshold: f32) -> SimilarImage {
         SimilarImage {
-            img: img,
-            dist_ratio: dist_ratio,
+            img,
+            dist_ratio,
+            dist,
+            tier: Tier::of(dist_ratio, near_threshold),
+            reason: MatchReason::Hash,
         }
     }
 
-    fn write_self(&self, out: &mut Writer, relative_to: &Path) -> IoResult<()> {
-        writeln!(out, "[{0:.2}%] ({1}x{2}) {3}",
-            self.dist_ratio * 100f32,
-            self.img.width, self.img.height,
-            self.img.relative_path(relative_to).display()
-        )
+    /// `img` as a similar of `original`, with the distances measured between them.
+    pub fn compared(original: &Image, img: Image, near_threshold: f32) -> SimilarImage {
+        let (dist_ratio, dist) = (original.dist_ratio(&img), original.dist(&img));
+        let reason = original.match_reason(&img);
+
+        SimilarImage { reason, ..SimilarImage::from_image(img, dist_ratio, dist, near_threshold) }
+    }
+
+    /// How similar this image is to the containing UniqueImage, from 0 to 100,
+    /// independent of the hash size.
+    pub fn similarity(&self) -> f32 {
+        (1f32 - self.dist_ratio) * 100f32
     }
 
-    fn to_json(&self, relative_to: &Path) -> Json {
-        let mut json = self.img.to_treemap(relative_to);
+    /// How similar the pair of hashes that matched is, from 0 to 100: the main hashes,
+    /// or a rotation or crop of one of them, without `--combine`. 100 for identical files.
+    pub fn match_score(&self) -> f32 {
+        match self.reason {
+            MatchReason::Identical => 100f32,
+            _ => (1f32 - self.dist as f32 / self.img.hash.bits() as f32) * 100f32,
+        }
+    }
 
-        json_insert!(json, "diff", self.dist_ratio);
+    fn write_self(&self, out: &mut dyn Write, relative_to: &Path) -> io::Result<()> {
+        writeln!(out, "[{0:.2}%, {1}, {2}] ({3}x{4} {5}, {6} bytes{7}) {8}",
+            self.dist_ratio * 100f32, self.tier.name(), self.reason.name(),
+            self.img.width, self.img.height,
+            self.img.format_name(), self.img.file_size, self.img.tags(relative_to),
+            self.img.relative_path(relative_to).display()
+        )?;
 
-        Json::Object(json)
+        self.img.write_sidecars(out, relative_to)
     }
 }
 
 impl Ord for SimilarImage {
     fn cmp(&self, other: &SimilarImage) -> Ordering {
-        self.partial_cmp(other).unwrap_or(Equal)   
+        self.dist_ratio.partial_cmp(&other.dist_ratio).unwrap_or(Ordering::Equal)
     }
 }
 
 impl PartialOrd for SimilarImage {
     fn partial_cmp(&self, other: &SimilarImage) -> Option<Ordering> {
-        self.dist_ratio.partial_cmp(&other.dist_ratio)                    
+        Some(self.cmp(other))
     }    
 }
 
 impl Eq for SimilarImage {}
 
+/// Which strategy matched a similar image to its original.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchReason {
+    /// The files have exactly the same contents, found by `--exact-first`
+    Identical,
+    /// The hashes of the whole images
+    #[default]
+    Hash,
+    /// A rotated or mirrored variant, with `--rotations`
+    Rotated,
+    /// A crop of either image, with `--crop`
+    Cropped,
+}
+
+impl MatchReason {
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MatchReason::Identical => "identical",
+            MatchReason::Hash => "hash",
+            MatchReason::Rotated => "rotated",
+            MatchReason::Cropped => "cropped",
+        }
+    }
+}
+
+
+/// Get the index of the image in `group` chosen by `policy`, out of the ones `prefer` ranks highest.
+/// The first image is the original of the group, and wins ties.
+pub fn choose_representative(group: &[&Image], policy: &RepresentativePolicy, prefer: &DirPreference) -> usize {
+    let key = |image: &Image| -> (u64, u64, u64) {
+        let (first, second) = match *policy {
+            // Tie-break by file size
+            RepresentativePolicy::Largest => (image.width as u64 * image.height as u64, image.file_size),
+            RepresentativePolicy::LargestFile => (image.file_size, 0),
+            // Larger keys win, so invert the modification time and length
+            RepresentativePolicy::Oldest => (!modified_ms(&image.path).unwrap_or(!0), 0),
+            RepresentativePolicy::ShortestPath => (!(image.path.as_os_str().len() as u64), 0),
+            RepresentativePolicy::InDir(ref dir) => (image.path.starts_with(dir) as u64, 0),
+            RepresentativePolicy::First => (0, 0),
+        };
+
+        (prefer.rank(&image.path), first, second)
+    };
+
+    let mut best = 0;
+    let mut best_key = key(group[0]);
+
+    for (idx, image) in group.iter().enumerate().skip(1) {
+        let image_key = key(image);
+
+        if image_key > best_key {
+            best = idx;
+            best_key = image_key;
+        }
+    }
+
+    best
+}
+
+fn relative(path: &Path, relative_to: &Path) -> PathBuf {
+    path.strip_prefix(relative_to).unwrap_or(path).to_path_buf()
+}
+
+/// The modification time of the file at `path`, in milliseconds since the Unix epoch.
+pub fn modified_ms(path: &Path) -> Option<u64> {
+    let modified = path.metadata().and_then(|meta| meta.modified()).ok()?;
+
+    modified.duration_since(UNIX_EPOCH).ok().map(|since| since.as_millis() as u64)
+}
+
+/// Hash the other 3 rotations of the image, and all 4 rotations of its mirror image.
+fn hash_variants(img: &DynamicImage, settings: &HashSettings) -> Vec<ImageHash> {
+    let hash = |img: &DynamicImage| settings.hash(img);
+
+    let mirrored = img.fliph();
+
+    vec![
+        hash(&img.rotate90()),
+        hash(&img.rotate180()),
+        hash(&img.rotate270()),
+        hash(&mirrored.rotate90()),
+        hash(&mirrored.rotate180()),
+        hash(&mirrored.rotate270()),
+        hash(&mirrored),
+    ]
+}
+
+/// Hash crops of the image with `settings.crop` and half as much cut off each side:
+/// from every side, from the top and bottom only, and from the left and right only.
+fn hash_crops(img: &DynamicImage, settings: &HashSettings) -> Vec<ImageHash> {
+    let (width, height) = (img.width(), img.height());
+
+    [settings.crop / 2f32, settings.crop].iter()
+        .flat_map(|&margin| {
+            let (x, y) = ((width as f32 * margin) as u32, (height as f32 * margin) as u32);
+
+            [(x, y), (0, y), (x, 0)]
+        })
+        .filter(|&(x, y)| (x > 0 || y > 0) && x * 2 < width && y * 2 < height)
+        .map(|(x, y)| settings.hash(&img.crop_imm(x, y, width - x * 2, height - y * 2)))
+        .collect()
+}
+
+/// Enough for the signatures of every format `image::guess_format()` knows.
+const SNIFF_LEN: u64 = 512;
+
+/// The format of the image at `path` from its first bytes, if it's one the `image` crate knows.
+pub fn sniff_format(path: &Path) -> Option<ImageFormat> {
+    let mut start = Vec::new();
+    File::open(path).ok()?.take(SNIFF_LEN).read_to_end(&mut start).ok()?;
+
+    image::guess_format(&start).ok()
+}
+
+/// Guess the format of an image from its extension, the same way `image::open()` does,
+/// and also from aliases it doesn't know, like `jpe`.
+pub fn guess_format(path: &Path) -> Option<ImageFormat> {
+    ImageFormat::from_path(path).ok().or_else(|| {
+        let ext = path.extension()?.to_str()?;
+
+        search::ext_aliases(ext).iter().find_map(|&alias| ImageFormat::from_extension(alias))
+    })
+}
@@ -0,0 +1,83 @@
+//! The duplicate image finder behind the `img_dup` command, for embedding it elsewhere:
+//! `config::parse_args()` builds the settings from the same arguments, and `search` and
+//! `processing` find and compare the images.
+
+#[cfg(feature = "cli")]
+pub mod actions;
+#[cfg(feature = "server")]
+pub mod agent;
+pub mod archives;
+#[cfg(feature = "cli")]
+pub mod audit;
+pub mod bktree;
+pub mod check;
+pub mod classify;
+pub mod cluster;
+pub mod config;
+pub mod config_file;
+pub mod dataset;
+pub mod db;
+pub mod dct;
+pub mod decoders;
+pub mod exact;
+pub mod exif;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod hamming;
+pub mod hash;
+pub mod header;
+pub mod ignore;
+pub mod img;
+pub mod links;
+pub mod objects;
+pub mod output;
+pub mod processing;
+#[cfg(feature = "cli")]
+pub mod progress;
+pub mod quarantine;
+pub mod query;
+pub mod par_queue;
+pub mod raw;
+pub mod scratch;
+pub mod search;
+pub mod serialize;
+pub mod storage;
+pub mod synthetic;
+pub mod throttle;
+pub mod thumbnails;
+#[cfg(feature = "cli")]
+pub mod trash;
+pub mod update;
+pub mod watch;
+
+#[cfg(feature = "video")]
+pub mod video;
+
+#[cfg(feature = "async")]
+pub mod nonblocking;
+
+#[cfg(feature = "server")]
+pub mod server;
+
+#[cfg(feature = "s3")]
+pub mod s3;
+
+#[cfg(feature = "parquet")]
+pub mod parquet;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+
+#[cfg(feature = "gui")]
+pub mod gui;
+
+#[cfg(feature = "tui")]
+pub mod tui;
+
+// Exit immediately, don't leave any threads alive
+pub fn exit(code: i32) -> ! {    
+    std::process::exit(code)
+}
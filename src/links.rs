@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Other paths to the same file as the key: hard links to it, or the same file
+/// reached again through a symbolic link or an overlapping search directory.
+pub type Links = HashMap<PathBuf, Vec<PathBuf>>;
+
+/// Keeps the first path to each file, so a file is only hashed once however it's reached.
+#[derive(Default)]
+pub struct LinkFinder {
+    first_paths: HashMap<FileId, PathBuf>,
+    links: Links,
+}
+
+impl LinkFinder {
+
+    pub fn new() -> LinkFinder {
+        LinkFinder::default()
+    }
+
+    /// Returns `true` for the first path to a file. Later paths are recorded as its links,
+    /// except the same path given again, which is dropped.
+    pub fn is_first(&mut self, path: &Path) -> bool {
+        // A file that can't be read is left for processing to report
+        let Some(id) = file_id(path) else { return true };
+
+        match self.first_paths.entry(id) {
+            Entry::Vacant(entry) => {
+                entry.insert(path.to_path_buf());
+                true
+            },
+            Entry::Occupied(entry) => {
+                let first = entry.get();
+
+                if first != path {
+                    let links = self.links.entry(first.clone()).or_default();
+
+                    if !links.iter().any(|link| link == path) {
+                        links.push(path.to_path_buf());
+                    }
+                }
+
+                false
+            },
+        }
+    }
+
+    pub fn into_links(self) -> Links {
+        self.links
+    }
+}
+
+/// Keep the first path to each file in `paths`, and return the others as its links.
+pub fn find_links(paths: Vec<PathBuf>) -> (Vec<PathBuf>, Links) {
+    let mut finder = LinkFinder::new();
+
+    let firsts = paths.into_iter().filter(|path| finder.is_first(path)).collect();
+
+    (firsts, finder.into_links())
+}
+
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+/// The device and inode, which hard links share.
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path).ok().map(|meta| (meta.dev(), meta.ino()))
+}
+
+// Without inodes, only the same file reached through different paths is found
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+#[cfg(not(unix))]
+fn file_id(path: &Path) -> Option<FileId> {
+    fs::canonicalize(path).ok()
+}
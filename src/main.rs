@@ -1,106 +1,538 @@
-#![feature(macro_rules, globs, unsafe_destructor, phase)]
+use img_dup::{actions, audit, exit, check, output, processing, progress, quarantine, query, search, serialize, storage, synthetic, update, watch};
+use img_dup::config::{parse_args, Mode, OutputFormat, ProgramSettings, Root};
+use img_dup::db::HashDb;
+use img_dup::processing::{CancelToken, Results};
+use img_dup::progress::ProcessingBar;
+use img_dup::search::SearchError;
+use img_dup::watch::WatchEvent;
+use img_dup::output::test_outfile;
 
-extern crate getopts;
-extern crate image;
-extern crate img_hash;
-extern crate libc;
-extern crate serialize;
-extern crate time;
+use indicatif::MultiProgress;
 
-use config::{parse_args, ProgramSettings};
-use output::{output_results, test_outfile};
-use processing::process;
+use indicatif_log_bridge::LogWrapper;
 
-use std::io::util::NullWriter;
+use log::{info, warn, Level, LevelFilter};
 
-use std::os;
+use std::env;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-macro_rules! json_insert(
-    ($map:expr, $key:expr, $val:expr) => (
-        $map.insert(::std::borrow::ToOwned::to_owned($key), $val.to_json())
-    );
-);
+/// No similar images were found, or the mode doesn't look for them.
+pub const EXIT_NO_DUPLICATES: i32 = 0;
+/// At least one group of similar images was found.
+pub const EXIT_DUPLICATES: i32 = 1;
+/// Some images couldn't be processed, or something else failed. Takes precedence over duplicates.
+pub const EXIT_ERRORS: i32 = 2;
+/// The arguments or the config file were invalid.
+pub const EXIT_INVALID_ARGS: i32 = 3;
 
-mod config;
-mod img;
-mod output;
-mod processing;
-mod par_queue;
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
 
-#[cfg(feature = "gui")]
-mod ui;
+    let settings = match parse_settings(&args) {
+        Some(settings) => settings,
+        None => exit(EXIT_INVALID_ARGS),
+    };
 
-fn main() {
-    run();
+    // A panic has already printed its message
+    let code = panic::catch_unwind(AssertUnwindSafe(|| run(&settings)))
+        .unwrap_or(EXIT_ERRORS);
 
-    exit();
+    exit(code);
 }
 
-// Exit immediately, don't leave any threads alive
-pub fn exit() {    
-    unsafe { libc::exit(0); }   
+/// Arguments are checked by panicking, so report the panic as an invalid argument.
+fn parse_settings(args: &[String]) -> Option<ProgramSettings> {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(|info| 
+        eprintln!("Invalid arguments: {}", info.payload_as_str().unwrap_or("unknown error"))
+    ));
+
+    let settings = panic::catch_unwind(|| parse_args(args)).ok();
+
+    panic::set_hook(default_hook);
+
+    settings
+}
+
+fn run(settings: &ProgramSettings) -> i32 {
+    let multi = MultiProgress::new();
+
+    init_logger(settings, &multi);
+
+    // The generated images are deleted when the run is over
+    let synthetic;
+    let settings = if settings.bench_synthetic > 0 {
+        synthetic = generate_synthetic(settings);
+        &synthetic.1
+    } else {
+        settings
+    };
+
+    if let Some(ref outfile) = settings.outfile {
+        info!("Testing output file ({})...", outfile.display());
+        test_outfile(outfile).unwrap();
+    }
+    
+    if let Mode::Watch = settings.mode {
+        info!("Watching {} for new images...", settings.dir.display());
+        watch::watch(settings, &CancelToken::new(), |event| print_watch_event(settings, event));
+        return EXIT_NO_DUPLICATES;
+    }
+
+    if let Mode::Serve(ref db_path) = settings.mode {
+        serve(settings, db_path);
+        return EXIT_NO_DUPLICATES;
+    }
+
+    if let Mode::Agent = settings.mode {
+        serve_agent(settings);
+        return EXIT_NO_DUPLICATES;
+    }
+
+    if let Mode::Review(ref report) = settings.mode {
+        review_report(settings, report);
+        return EXIT_NO_DUPLICATES;
+    }
+
+    if let Mode::Apply(ref report) = settings.mode {
+        return apply_report(settings, report);
+    }
+
+    if let Mode::Restore(ref quarantine_dir) = settings.mode {
+        let failed = quarantine::restore(quarantine_dir, settings.dry_run)
+            .unwrap_or_else(|err| panic!("Could not restore from {}: {}", quarantine_dir.display(), err));
+
+        return if failed > 0 { EXIT_ERRORS } else { EXIT_NO_DUPLICATES };
+    }
+
+    if let Mode::Undo(ref audit_log) = settings.mode {
+        let failed = audit::undo(audit_log, settings.dry_run)
+            .unwrap_or_else(|err| panic!("Could not undo from {}: {}", audit_log.display(), err));
+
+        return if failed > 0 { EXIT_ERRORS } else { EXIT_NO_DUPLICATES };
+    }
+
+    let results = match settings.mode {
+        Mode::Search if settings.s3.is_some() => process_s3(settings, &multi),
+        Mode::Search | Mode::Dataset if processing::can_stream(settings) && settings.files_from.is_none() => 
+            search_and_process(settings, &multi),
+        Mode::Search | Mode::Dataset => {
+            let (image_paths, search_secs) = processing::timed(|| find_images(settings));
+            print_processing(settings);
+
+            let mut results = process(settings, image_paths, &multi);
+            results.phases.search_secs = Some(search_secs);
+            results
+        },
+        Mode::Compare(ref other) => {
+            let ((image_paths, other_paths), search_secs) = processing::timed(|| 
+                (search_images(settings, &settings.dir), search_images(settings, other)));
+            print_processing(settings);
+
+            let mut results = processing::compare(settings, image_paths, other_paths);
+            results.phases.search_secs = Some(search_secs);
+            results
+        },
+        Mode::Query(ref image, ref against) => {
+            let (haystack, errors) = if against.is_dir() {
+                let image_paths = search_images(settings, against);
+                print_processing(settings);
+                processing::hash_all(settings, image_paths)
+            } else {
+                info!("Loading hashes from {}...", against.display());
+                let db = HashDb::load(against).unwrap();
+                (db.images(&settings.hash_settings()), Vec::new())
+            };
+
+            query::query(settings, image.clone(), haystack, errors)
+        },
+        Mode::Update(ref db_path) => {
+            let (image_paths, search_secs) = processing::timed(|| find_images(settings));
+            print_processing(settings);
+
+            let (mut results, stats) = update::update(settings, db_path, image_paths).unwrap();
+
+            info!("Hashed {} new or modified images, removed {} deleted images from {}.",
+                  stats.hashed, stats.removed, db_path.display());
+
+            results.phases.search_secs = Some(search_secs);
+            results
+        },
+        Mode::Check(ref baseline) => {
+            let (image_paths, search_secs) = processing::timed(|| find_images(settings));
+            print_processing(settings);
+
+            let mut results = check::check(settings, baseline.as_deref(), image_paths).unwrap();
+            results.phases.search_secs = Some(search_secs);
+            results
+        },
+        Mode::Cross(ref db_path, ref other_path) => {
+            info!("Loading hashes from {} and {}...", db_path.display(), other_path.display());
+            let (db, other) = (HashDb::load(db_path).unwrap(), HashDb::load(other_path).unwrap());
+
+            processing::cross(settings, &db, &other)
+        },
+        Mode::Collate(ref agents) if agents.is_empty() => collate_stored(settings),
+        Mode::Collate(ref agents) => collate(settings, agents),
+        Mode::Watch | Mode::Serve(_) | Mode::Agent | Mode::Review(_) | Mode::Apply(_) | Mode::Restore(_) | Mode::Undo(_) => unreachable!(),
+    };
+
+    output::output_results(settings, &results).unwrap();
+
+    if let Some(ref path) = settings.export_parquet {
+        export_parquet(settings, &results, path);
+    }
+
+    if let Some(ref path) = settings.results_db {
+        info!("Saving the results to {}...", path.display());
+        save_results(settings, &results);
+    }
+
+    print_stats(settings, &results);
+
+    if settings.gui {
+        review(settings, &results);
+    } else if settings.action.is_some() && settings.format != OutputFormat::Script {
+        actions::apply_actions(settings, &results);
+    }
+
+    exit_code(settings, &results)
+}
+
+/// Apply `--action` to the groups in a report from an earlier run.
+fn apply_report(settings: &ProgramSettings, report: &Path) -> i32 {
+    info!("Reading {}...", report.display());
+
+    let (_, groups) = serialize::read_json_report(report)
+        .unwrap_or_else(|err| panic!("Could not read {}: {}", report.display(), err));
+
+    if actions::apply_to_groups(settings, &groups) > 0 { EXIT_ERRORS } else { EXIT_NO_DUPLICATES }
+}
+
+/// Generate the images for `--bench-synthetic` in a temporary directory, and the settings
+/// to process them instead of searching.
+fn generate_synthetic(settings: &ProgramSettings) -> (tempfile::TempDir, ProgramSettings) {
+    let tmp_dir = tempfile::Builder::new().prefix("img_dup_bench").tempdir().unwrap();
+
+    info!("Generating {} synthetic images in {}...", settings.bench_synthetic, tmp_dir.path().display());
+
+    let start = Instant::now();
+    let written = synthetic::generate(tmp_dir.path(), settings.bench_synthetic).unwrap();
+
+    info!("Wrote {} files in {:.2}s.", written, start.elapsed().as_secs_f64());
+
+    let dir = tmp_dir.path().to_path_buf();
+    let settings = ProgramSettings { roots: vec![Root { dir: dir.clone(), recurse: false }], dir, ..settings.clone() };
+
+    (tmp_dir, settings)
 }
 
 #[cfg(feature = "gui")]
-fn show_gui(settings: ProgramSettings) {   
-	ui::show_gui(settings);
+fn review(settings: &ProgramSettings, results: &Results) {
+    img_dup::gui::review(settings, results).unwrap();
 }
 
+// Checked when the arguments are parsed
 #[cfg(not(feature = "gui"))]
-fn show_gui(_: ProgramSettings) {
-    println!("img_dup was not compiled with GUI support!");    
+fn review(_: &ProgramSettings, _: &Results) {
+    unreachable!()
 }
 
-fn run() {
-    let args = os::args();
+#[cfg(feature = "tui")]
+fn review_report(settings: &ProgramSettings, report: &Path) {
+    img_dup::tui::review(settings, report).unwrap();
+}
 
-    let settings = parse_args(args.as_slice());
+// Checked when the arguments are parsed
+#[cfg(not(feature = "tui"))]
+fn review_report(_: &ProgramSettings, _: &Path) {
+    unreachable!()
+}
 
-	if settings.gui {
-        show_gui(settings);
-		return;
-	}
+#[cfg(feature = "server")]
+fn serve(settings: &ProgramSettings, db_path: &Path) {
+    img_dup::server::serve(settings, db_path).unwrap();
+}
 
-    // Silence standard messages if we're outputting JSON
-    let mut out = get_output(&settings);    
+// Checked when the arguments are parsed
+#[cfg(not(feature = "server"))]
+fn serve(_: &ProgramSettings, _: &Path) {
+    unreachable!()
+}
 
-    match settings.outfile {
-        Some(ref outfile) => {
-            (writeln!(out, "Testing output file ({})...",
-                outfile.display())).unwrap();
-            test_outfile(outfile).unwrap();
-        },
-        None => (),        
-    };
-    
-    out.write_line("Searching for images...").unwrap();
+#[cfg(feature = "server")]
+fn serve_agent(settings: &ProgramSettings) {
+    img_dup::agent::serve_agent(settings).unwrap();
+}
+
+// Checked when the arguments are parsed
+#[cfg(not(feature = "server"))]
+fn serve_agent(_: &ProgramSettings) {
+    unreachable!()
+}
 
-    let mut image_paths = processing::find_images(&settings);
+#[cfg(feature = "server")]
+fn collate(settings: &ProgramSettings, agents: &[String]) -> Results {
+    img_dup::agent::collate(settings, agents)
+}
+
+// Checked when the arguments are parsed
+#[cfg(not(feature = "server"))]
+fn collate(_: &ProgramSettings, _: &[String]) -> Results {
+    unreachable!()
+}
 
-    let image_count = image_paths.len();
+/// A summary on stderr, so it's seen whatever the output format.
+fn print_stats(settings: &ProgramSettings, results: &Results) {
+    let stats = results.stats();
 
-    (writeln!(out, "Images found: {}", image_count)).unwrap();
+    info!("Hashed {} of {} images in {:.2}s ({:.1} images/s): {} duplicates in {} groups, {} bytes wasted.",
+          stats.hashed, stats.scanned, stats.elapsed_secs, stats.images_per_sec,
+          stats.duplicates, stats.groups, stats.wasted_bytes);
+
+    if !stats.failed.is_empty() {
+        let failed: Vec<String> = stats.failed.iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect();
+
+        info!("Failed: {}.", failed.join(", "));
+    }
+
+    if settings.timings {
+        let timings = results.timings();
+
+        if let Some(load) = timings.load {
+            info!("Load: {}.", load);
+        }
+
+        if let Some(hash) = timings.hash {
+            info!("Hash: {}.", hash);
+        }
+    }
+}
+
+/// Only the groups that are output count as duplicates, e.g. with `--min-group-size`.
+fn exit_code(settings: &ProgramSettings, results: &Results) -> i32 {
+    if results.has_errors() {
+        EXIT_ERRORS
+    } else if results.groups(settings).iter().any(|unique| !unique.similars.is_empty()) {
+        EXIT_DUPLICATES
+    } else {
+        EXIT_NO_DUPLICATES
+    }
+}
+
+/// Status messages go to stderr, so they never mix with results written to stdout.
+/// Info messages are printed as they are, like the results.
+/// `--verbose` only applies to this crate; dependencies only log warnings and errors.
+///
+/// Messages are printed above the progress bars in `multi`, instead of over them.
+fn init_logger(settings: &ProgramSettings, multi: &MultiProgress) {
+    let logger = env_logger::Builder::new()
+        .filter_level(settings.log_level.min(LevelFilter::Warn))
+        .filter_module(env!("CARGO_CRATE_NAME"), settings.log_level)
+        .parse_default_env()
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "{}: {}", level, record.args()),
+        })
+        .build();
+
+    let level = logger.filter();
+
+    LogWrapper::new(multi.clone(), logger).try_init().unwrap();
+    log::set_max_level(level);
+}
+
+/// Process the images with a progress bar, if it's enabled.
+fn process(settings: &ProgramSettings, paths: Vec<PathBuf>, multi: &MultiProgress) -> Results {
+    if !progress::enabled(settings) {
+        return processing::process(settings, paths);
+    }
+
+    let bar = ProcessingBar::new(multi, paths.len());
+
+    let results = processing::process_with_progress(settings, paths, &CancelToken::new(),
+                                                    |progress| bar.update(&progress));
+    bar.finish();
+
+    results
+}
+
+#[cfg(feature = "s3")]
+fn process_s3(settings: &ProgramSettings, multi: &MultiProgress) -> Results {
+    use img_dup::objects::ObjectSource;
+    use img_dup::s3::S3Source;
+    use std::sync::Arc;
+
+    let url = settings.s3.as_deref().unwrap();
+    info!("Listing the objects in {}...", url);
+
+    let source: Arc<dyn ObjectSource> = Arc::new(S3Source::new(url).unwrap());
+
+    let (mut objects, search_secs) = processing::timed(|| search::find_objects(settings, &*source).unwrap());
+    info!("Images found: {}", objects.len());
 
     if settings.limit > 0 {
-        (writeln!(out, "Limiting to: {}", settings.limit)).unwrap();
-        image_paths.truncate(settings.limit);
+        info!("Limiting to: {}", settings.limit);
+        objects.truncate(settings.limit);
     }
 
-    (writeln!(out, "Processing images in {} threads. Please wait...\n", 
-             settings.threads)).unwrap();
+    print_processing(settings);
+
+    let mut results = if progress::enabled(settings) {
+        let bar = ProcessingBar::new(multi, objects.len());
+
+        let results = processing::process_objects(settings, source, objects, &CancelToken::new(), 
+                                                  |progress| bar.update(&progress));
+        bar.finish();
+
+        results
+    } else {
+        processing::process_objects(settings, source, objects, &CancelToken::new(), |_| ())
+    };
+
+    results.phases.search_secs = Some(search_secs);
+    results
+}
+
+// Checked when the arguments are parsed
+#[cfg(not(feature = "s3"))]
+fn process_s3(_: &ProgramSettings, _: &MultiProgress) -> Results {
+    unreachable!()
+}
+
+/// `collate --db`: group every image in the database, from every machine that stores hashes in it.
+fn collate_stored(settings: &ProgramSettings) -> Results {
+    let mut storage = storage::open(settings).unwrap().expect("--db was given");
+    info!("Loading hashes from {}...", storage.name());
+
+    storage::collate(settings, &mut *storage).unwrap()
+}
 
-    let results = processing::process(&settings, image_paths);
+fn save_results(settings: &ProgramSettings, results: &Results) {
+    let mut storage = storage::open(settings).unwrap().expect("--db was given");
+    storage.save_results(settings, results).unwrap();
+}
 
-    out.write_line("").unwrap();
+#[cfg(feature = "parquet")]
+fn export_parquet(settings: &ProgramSettings, results: &Results, path: &Path) {
+    info!("Writing {}...", path.display());
+    img_dup::parquet::export(settings, results, path).unwrap();
+}
 
-    output::output_results(&settings, &results).unwrap()   
+// Checked when the arguments are parsed
+#[cfg(not(feature = "parquet"))]
+fn export_parquet(_: &ProgramSettings, _: &Results, _: &Path) {
+    unreachable!()
 }
 
-fn get_output(settings: &ProgramSettings) -> Box<Writer> {
-    if settings.silent_stdout() {
-        box NullWriter as Box<Writer> 
+/// Start processing images as soon as the search finds them, with a progress bar if it's enabled.
+fn search_and_process(settings: &ProgramSettings, multi: &MultiProgress) -> Results {
+    print_searching(settings);
+    print_processing(settings);
+
+    let (paths, search) = search::stream_images_in(settings, &settings.roots);
+    let cancel = CancelToken::new();
+
+    let results = if progress::enabled(settings) {
+        let bar = ProcessingBar::new(multi, 0);
+
+        let results = processing::process_stream(settings, paths, &search, &cancel, 
+                                                 |progress| bar.update(&progress));
+        bar.finish();
+
+        results
     } else {
-        box std::io::stdio::stdout() as Box<Writer>
-    }    
+        processing::process_stream(settings, paths, &search, &cancel, |_| ())
+    };
+
+    let (found, search_errors) = search.join();
+    print_search_errors(&search_errors);
+
+    // With a limit, the search stops once it's reached
+    if settings.limit > 0 {
+        info!("Limited to: {}", settings.limit);
+    } else {
+        info!("Images found: {}", found);
+    }
+
+    results
 }
 
+fn search_images(settings: &ProgramSettings, dir: &Path) -> Vec<PathBuf> {
+    info!("Searching for images in {}...", dir.display());
+
+    let found = search::find_images_in(settings, dir);
+
+    limit_found(settings, found)
+}
+
+/// The images from `--files-from`, or the ones found in the roots.
+fn find_images(settings: &ProgramSettings) -> Vec<PathBuf> {
+    let Some(ref list) = settings.files_from else { return search_roots(settings) };
+
+    let source = if list == Path::new("-") { "stdin".to_string() } else { list.display().to_string() };
+    info!("Reading image paths from {}...", source);
+
+    let paths = search::read_path_list(list)
+        .unwrap_or_else(|err| panic!("Could not read {}: {}", source, err));
+
+    limit_found(settings, (paths, Vec::new()))
+}
+
+/// Search every root in `settings` together.
+fn search_roots(settings: &ProgramSettings) -> Vec<PathBuf> {
+    print_searching(settings);
+
+    let found = search::find_images_in_roots(settings, &settings.roots);
+
+    limit_found(settings, found)
+}
+
+fn print_searching(settings: &ProgramSettings) {
+    let dirs: Vec<String> = settings.roots.iter().map(|root| root.dir.display().to_string()).collect();
+
+    info!("Searching for images in {}...", dirs.join(", "));
+}
+
+fn limit_found(settings: &ProgramSettings, (mut image_paths, search_errors): (Vec<PathBuf>, Vec<SearchError>)) 
+    -> Vec<PathBuf> {
+    print_search_errors(&search_errors);
+
+    info!("Images found: {}", image_paths.len());
+
+    if settings.limit > 0 {
+        info!("Limiting to: {}", settings.limit);
+        image_paths.truncate(settings.limit);
+    }
+
+    image_paths
+}
+
+fn print_search_errors(errors: &[SearchError]) {
+    for (dir, err) in errors {
+        warn!("Could not search {}: {}", dir.display(), err);
+    }
+}
+
+/// Always printed to stdout, even with `--quiet`.
+fn print_watch_event(settings: &ProgramSettings, event: WatchEvent) {
+    let dir = &settings.dir;
+
+    match event {
+        WatchEvent::Duplicate(original, similar) => println!("Duplicate: {} is {:.2}% similar ({}) to {}",
+            similar.img.relative_path(dir).display(), similar.similarity(), similar.tier.name(),
+            original.relative_path(dir).display()),
+        WatchEvent::Error(err) => println!("Error: {}: {}", 
+            err.relative_path(dir).display(), err.err_msg()),
+    }
+}
+
+fn print_processing(settings: &ProgramSettings) {
+    info!("Processing images in {} threads. Please wait...", settings.threads);
+}
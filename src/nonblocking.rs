@@ -0,0 +1,155 @@
+//! With the `async` feature, processing and hash database lookups for services running
+//! on a Tokio runtime. The work is done on other threads, so the runtime is never blocked.
+
+use crate::config::ProgramSettings;
+use crate::db::HashDb;
+use crate::img::{Image, SimilarImage};
+use crate::processing::{self, CancelToken, ImageResult, Results};
+use crate::query;
+
+use tokio::sync::mpsc;
+use tokio::task;
+
+use tokio_stream::wrappers::ReceiverStream;
+
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+/// Hash `paths`, yielding each image as soon as a worker thread is done with it,
+/// like `processing::results_iter()`. Dropping the stream stops the workers.
+///
+/// Doesn't need to be called from inside a runtime.
+pub fn process_async(settings: &ProgramSettings, paths: Vec<PathBuf>) -> ReceiverStream<ImageResult> {
+    let (tx, rx) = mpsc::channel(settings.queue_depth);
+
+    let cancel = CancelToken::new();
+    let results = processing::results_iter(settings, paths, cancel.clone());
+
+    thread::spawn(move || {
+        for img_result in results {
+            if tx.blocking_send(img_result).is_err() {
+                cancel.cancel();
+                return;
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+/// Like `processing::process()`, but waits for the results without blocking the runtime.
+pub async fn process_all_async(settings: &ProgramSettings, paths: Vec<PathBuf>) -> Results {
+    let settings = settings.clone();
+
+    blocking(move || processing::process(&settings, paths)).await
+}
+
+/// A hash database that tasks can look images up in, and add images to, concurrently.
+#[derive(Clone)]
+pub struct AsyncHashDb {
+    db: Arc<RwLock<HashDb>>,
+}
+
+impl AsyncHashDb {
+
+    pub fn new(db: HashDb) -> AsyncHashDb {
+        AsyncHashDb { db: Arc::new(RwLock::new(db)) }
+    }
+
+    pub async fn load(path: &Path) -> io::Result<AsyncHashDb> {
+        let path = path.to_path_buf();
+
+        blocking(move || HashDb::load(&path)).await.map(AsyncHashDb::new)
+    }
+
+    pub async fn save(&self, path: &Path) -> io::Result<()> {
+        let (db, path) = (self.db.clone(), path.to_path_buf());
+
+        blocking(move || read(&db).save(&path)).await
+    }
+
+    /// Hash the image at `path` and find the images in the database that are similar to it,
+    /// like the query mode with a hash database. The results have the image as their only
+    /// unique image, with its matches as its similars.
+    pub async fn query(&self, settings: &ProgramSettings, path: PathBuf) -> Results {
+        let (db, settings) = (self.db.clone(), settings.clone());
+
+        blocking(move || {
+            let haystack = read(&db).images(&settings.hash_settings());
+            query::query(&settings, path, haystack, Vec::new())
+        }).await
+    }
+
+    /// The images in the database that are similar to an image that's already hashed,
+    /// e.g. with `Image::hash_from_dynamic_image()`, closest first.
+    pub async fn find_matches(&self, settings: &ProgramSettings, needle: Image) -> Vec<SimilarImage> {
+        let (db, settings) = (self.db.clone(), settings.clone());
+
+        blocking(move || {
+            let haystack = read(&db).images(&settings.hash_settings());
+            query::find_matches(&needle, &haystack, &settings)
+        }).await
+    }
+
+    /// Add or replace the hash of `image`, made with `settings`.
+    pub async fn insert(&self, settings: &ProgramSettings, image: Image) {
+        let (db, settings) = (self.db.clone(), settings.hash_settings());
+
+        blocking(move || {
+            db.write().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(&image, &settings)
+        }).await
+    }
+}
+
+// A panic while holding the lock can't have left the database half-changed,
+// since entries are replaced whole
+fn read(db: &RwLock<HashDb>) -> std::sync::RwLockReadGuard<'_, HashDb> {
+    db.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Run `f` on Tokio's blocking threads, passing on any panic to the caller.
+async fn blocking<T, F>(f: F) -> T
+where T: Send + 'static, F: FnOnce() -> T + Send + 'static {
+    match task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(err) => panic::resume_unwind(err.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_args;
+    use crate::hash::ImageHash;
+    use crate::search;
+
+    use tokio_stream::StreamExt;
+
+    /// The path and hash of every image, sorted by path.
+    fn hashes<'a, I: IntoIterator<Item = &'a Image>>(images: I) -> Vec<(PathBuf, ImageHash)> {
+        let mut hashes: Vec<_> = images.into_iter().map(|image| (image.path.clone(), image.hash.clone())).collect();
+        hashes.sort_by(|a, b| a.0.cmp(&b.0));
+        hashes
+    }
+
+    #[tokio::test]
+    async fn stream_matches_process() {
+        let settings = parse_args(&["scan".to_string(), "test_images".to_string()]);
+        let paths = search::find_images(&settings);
+        assert!(!paths.is_empty());
+
+        let streamed: Vec<ImageResult> = process_async(&settings, paths.clone()).collect().await;
+        let streamed_images: Vec<Image> = streamed.into_iter().map(|result| result.unwrap()).collect();
+
+        let results = processing::process(&settings, paths);
+        assert!(results.errors.is_empty());
+
+        let processed = results.uniques.iter()
+            .flat_map(|unique| Some(&unique.img).into_iter().chain(unique.similars.iter().map(|similar| &similar.img)));
+
+        assert_eq!(hashes(&streamed_images), hashes(processed));
+    }
+}
@@ -0,0 +1,31 @@
+//! Images stored as objects in a bucket, like S3, instead of files: they're listed and
+//! downloaded into memory one at a time as they're hashed, without syncing them to disk first.
+//!
+//! `ObjectSource` is implemented for S3 and services compatible with it with the `s3` feature;
+//! other object stores, like GCS or Azure Blob Storage, can be searched by implementing it.
+
+use std::io;
+use std::path::PathBuf;
+
+/// An object found by `ObjectSource::list()`.
+#[derive(Clone, Debug)]
+pub struct ObjectInfo {
+    /// The key of the object, relative to the prefix of the source
+    pub key: String,
+    /// The size of the object in bytes
+    pub size: u64,
+}
+
+/// A bucket, or a prefix within one, of an object store. Shared between the threads
+/// that download and hash the objects.
+pub trait ObjectSource: Send + Sync {
+
+    /// Every object under the prefix, at any depth, in any order.
+    fn list(&self) -> io::Result<Vec<ObjectInfo>>;
+
+    /// The contents of the object `key`, relative to the prefix.
+    fn fetch(&self, key: &str) -> io::Result<Vec<u8>>;
+
+    /// The name the object `key` is reported under, like `s3://bucket/prefix/key`.
+    fn path(&self, key: &str) -> PathBuf;
+}
@@ -0,0 +1,150 @@
+use crate::config::ProgramSettings;
+use crate::img::{Image, UniqueImage};
+use crate::processing::{self, Results};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table.info td { padding: 0 1em 0 0; }
+.group { border: 1px solid #ccc; border-radius: 4px; margin: 1em 0; padding: 0.5em; }
+.group h2 { font-size: 1em; margin: 0 0 0.5em 0; }
+.images { display: flex; flex-wrap: wrap; }
+.image { width: 220px; margin: 0 1em 1em 0; font-size: 0.85em; word-wrap: break-word; }
+.image img { max-width: 200px; max-height: 200px; display: block; margin-bottom: 0.3em; }
+.image.original { font-weight: bold; }
+.errors td { padding: 0 1em 0 0; }
+";
+
+/// Write a self-contained HTML page showing each group of similar images side-by-side,
+/// so matches can be checked by eye. Only groups with similar images are shown.
+pub fn html_output(settings: &ProgramSettings, results: &Results, out: &mut dyn Write) -> io::Result<()> {
+    let dir = &settings.dir;
+
+    writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>img-dup results</title>")?;
+    writeln!(out, "<style>{}</style>\n</head>\n<body>", STYLE)?;
+
+    writeln!(out, "<h1>img-dup results</h1>\n<table class=\"info\">")?;
+    info_row(out, "Directory", &dir.display().to_string())?;
+    info_row(out, "Start time", &processing::ctime(&results.start_time))?;
+    info_row(out, "End time", &processing::ctime(&results.end_time))?;
+    let stats = results.stats();
+
+    info_row(out, "Elapsed", &format!("{:.2}s ({:.1} images/s)", stats.elapsed_secs, stats.images_per_sec))?;
+    info_row(out, "Images found", &stats.scanned.to_string())?;
+    info_row(out, "Hashed", &stats.hashed.to_string())?;
+    info_row(out, "Duplicates", &format!("{} in {} groups", stats.duplicates, stats.groups))?;
+    info_row(out, "Wasted space", &format!("{} bytes", stats.wasted_bytes))?;
+    info_row(out, "Errors", &stats.failed_total().to_string())?;
+    writeln!(out, "</table>")?;
+
+    let groups: Vec<&UniqueImage> = results.groups(settings).into_iter()
+        .filter(|unique| !unique.similars.is_empty())
+        .collect();
+
+    writeln!(out, "<h2>Similar images ({} groups)</h2>", groups.len())?;
+
+    for (num, unique) in groups.iter().enumerate() {
+        write_group(out, num + 1, unique, dir)?;
+    }
+
+    if !results.errors.is_empty() {
+        writeln!(out, "<h2>Errors</h2>\n<table class=\"errors\">")?;
+
+        for error in &results.errors {
+            writeln!(out, "<tr><td>{}</td><td>{}</td></tr>",
+                escape(&error.relative_path(dir).display().to_string()),
+                escape(&error.err_msg())
+            )?;
+        }
+
+        writeln!(out, "</table>")?;
+    }
+
+    writeln!(out, "</body>\n</html>")
+}
+
+fn info_row(out: &mut dyn Write, name: &str, value: &str) -> io::Result<()> {
+    writeln!(out, "<tr><td>{}</td><td>{}</td></tr>", name, escape(value))
+}
+
+fn write_group(out: &mut dyn Write, num: usize, unique: &UniqueImage, dir: &Path) -> io::Result<()> {
+    writeln!(out, "<div class=\"group\">\n<h2>Group {} ({} images, {} bytes wasted)</h2>\n<div class=\"images\">",
+        num, unique.similars.len() + 1, unique.wasted_bytes())?;
+
+    write_image(out, &unique.img, dir, "original", "Original")?;
+
+    for similar in &unique.similars() {
+        let label = format!("{:.2}% similar ({}, {}), distance {}",
+                            similar.similarity(), similar.tier.name(), similar.reason.name(), similar.dist);
+        write_image(out, &similar.img, dir, "similar", &label)?;
+    }
+
+    writeln!(out, "</div>\n</div>")
+}
+
+fn write_image(out: &mut dyn Write, image: &Image, dir: &Path, class: &str, label: &str) -> io::Result<()> {
+    let path = escape(&image.relative_path(dir).display().to_string());
+
+    writeln!(out, 
+        "<div class=\"image {}\"><a href=\"{url}\"><img src=\"{src}\" alt=\"{path}\"></a>\
+        {label}<br>{path}<br>{}x{} {}, {} bytes</div>",
+        class, image.width, image.height, image.format_name(), image.file_size,
+        url = image_url(&image.path), src = preview_src(image), path = path, label = escape(label)
+    )
+}
+
+/// Embed the thumbnail in the page if there is one, otherwise link to the original image.
+fn preview_src(image: &Image) -> String {
+    let thumbnail = image.thumbnail.as_ref()
+        .and_then(|thumbnail| fs::read(thumbnail).ok());
+
+    match thumbnail {
+        Some(data) => format!("data:image/jpeg;base64,{}", STANDARD.encode(data)),
+        None => image_url(&image.path),
+    }
+}
+
+/// A `file://` URL for the image, so the report works wherever it's saved.
+fn image_url(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let bytes = path.as_os_str().as_encoded_bytes();
+
+    let mut url = String::from("file://");
+
+    if !bytes.starts_with(b"/") { url.push('/'); }
+
+    for &byte in bytes {
+        match byte {
+            b'\\' => url.push('/'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+                | b'-' | b'_' | b'.' | b'~' | b'/' | b':' => url.push(byte as char),
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    url
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+
+    escaped
+}
@@ -0,0 +1,136 @@
+use crate::check;
+use crate::config::{Mode, ProgramSettings, JsonSettings, OutputFormat};
+use crate::dataset;
+use crate::processing::Results;
+use crate::serialize::{self, BurstEntry, ErrorEntry, Report};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+mod html;
+mod script;
+
+pub fn newline_before_after<F>(out: &mut dyn Write, what: F) -> io::Result<()>
+where F: FnOnce(&mut dyn Write) -> io::Result<()> {
+    writeln!(out)?;
+    what(out)?;
+    writeln!(out)
+}
+
+pub fn output_results(settings: &ProgramSettings, results: &Results) -> io::Result<()>{
+    let out_writer = &mut *open_output(settings)?;
+
+    match settings.format {
+        OutputFormat::Text if matches!(settings.mode, Mode::Check(_)) => 
+            check::write_diff(settings, results, out_writer),
+        OutputFormat::Text if matches!(settings.mode, Mode::Dataset) =>
+            dataset::write_lists(settings, results, out_writer),
+        OutputFormat::Text => write_output(settings, results, out_writer),
+        OutputFormat::Json => json_output(settings, results, out_writer),
+        OutputFormat::JsonLines => json_lines_output(settings, results, out_writer),
+        OutputFormat::Csv => csv_output(settings, results, out_writer),
+        OutputFormat::Html => html::html_output(settings, results, out_writer),
+        OutputFormat::Toml => serialize::write_toml(&Report::new(settings, results), out_writer),
+        OutputFormat::Bincode => serialize::write_bincode(&Report::new(settings, results), out_writer),
+        OutputFormat::Script => script::script_output(settings, results, out_writer),
+    }
+}
+
+fn json_output(settings: &ProgramSettings, results: &Results, out: &mut dyn Write) -> io::Result<()> { 
+    serialize::write_json(&settings.json, &Report::new(settings, results), out)?;
+    //Blank line at the end of the file
+    writeln!(out)
+}
+
+fn json_lines_output(settings: &ProgramSettings, results: &Results, out: &mut dyn Write) -> io::Result<()> {
+    let dir = &settings.dir;
+
+    for unique in &results.groups(settings) {
+        serialize::write_json(&JsonSettings::CompactJson, &unique.relative_to(dir), out)?;
+        writeln!(out)?;
+    }
+
+    for burst in &results.bursts(settings) {
+        serialize::write_json(&JsonSettings::CompactJson, &BurstEntry { burst: burst.relative_to(dir) }, out)?;
+        writeln!(out)?;
+    }
+
+    for error in &results.errors {
+        serialize::write_json(&JsonSettings::CompactJson, &ErrorEntry::new(error, dir), out)?;
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+fn csv_output(settings: &ProgramSettings, results: &Results, out: &mut dyn Write) -> io::Result<()> {
+    let dir = &settings.dir;
+
+    writeln!(out, "original_path,duplicate_path,distance,similarity_percent,tier,wasted_bytes,match,match_score")?;
+
+    for unique in &results.groups(settings) {
+        let original = unique.img.relative_path(dir).display().to_string();
+
+        for similar in &unique.similars() {
+            let duplicate = similar.img.relative_path(dir).display().to_string();
+
+            writeln!(out, "{},{},{},{:.2},{},{},{},{:.2}",
+                csv_escape(&original), csv_escape(&duplicate),
+                similar.dist, similar.similarity(), similar.tier.name(), similar.img.file_size,
+                similar.reason.name(), similar.match_score()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Quote the field if it contains a comma, quote or line break.
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_output(settings: &ProgramSettings, results: &Results, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "img-dup results follow.\nStats:")?;
+    results.write_info(out)?;
+
+    if settings.timings {
+        writeln!(out, "\nTimings:")?;
+        results.write_timings(out)?;
+    }
+
+    writeln!(out, "\nImages:\n")?;
+    results.write_uniques(out, settings)?;
+
+    if settings.bursts.is_some() {
+        writeln!(out, "\nBursts:\n")?;
+        results.write_bursts(out, settings)?;
+    }
+
+    writeln!(out, "\nErrors:\n")?;
+    results.write_errors(out, &settings.dir)?;
+    writeln!(out, "\nTimed out:\n")?;
+    results.write_timed_out(out, &settings.dir)?;
+    writeln!(out, "\nToo large:\n")?;
+    results.write_too_large(out, &settings.dir)?;
+    writeln!(out, "\nBlank:\n")?;
+    results.write_blank(out, &settings.dir)
+}
+
+fn open_output(settings: &ProgramSettings) -> io::Result<Box<dyn Write>> {
+    Ok(match settings.outfile {
+        Some(ref file) => Box::new(BufWriter::new(File::create(file)?)),
+        None => Box::new(io::stdout()),
+    })
+}
+
+/// Test if the outfile is writable by trying to open it in write mode.
+pub fn test_outfile(outfile: &Path) -> io::Result<()> {
+    File::create(outfile).map(|_| ())
+}
+
@@ -0,0 +1,170 @@
+use crate::config::{Action, ProgramSettings, ScriptStyle};
+use crate::img::{self, Image};
+use crate::processing::{self, Results};
+use crate::quarantine::Quarantine;
+
+use std::io::{self, Write};
+use std::path::{self, Path, PathBuf};
+
+/// Write a script that applies `--action` (delete if it isn't given) to every group of similar
+/// images, keeping the one `--keep` chooses, like `--action` would itself. Each group is
+/// commented with what's kept and how every other image matched, so it can be looked over
+/// and edited before it's run. Paths are absolute, so it can be run from anywhere.
+pub fn script_output(settings: &ProgramSettings, results: &Results, out: &mut dyn Write) -> io::Result<()> {
+    let action = settings.action.unwrap_or(Action::Delete);
+    let style = settings.script_style;
+
+    if action == Action::Reflink && style == ScriptStyle::PowerShell {
+        return Err(io::Error::other("PowerShell has no command to make reflinks, use --script-style=posix"));
+    }
+
+    let quarantine = match (action, settings.quarantine.as_ref()) {
+        (Action::Quarantine, None) => return Err(io::Error::other("No quarantine directory was given with --quarantine-dir")),
+        (_, quarantine) => quarantine,
+    };
+
+    write_header(out, style, action, results)?;
+
+    let mut groups = 0;
+
+    for unique in &results.groups(settings) {
+        let similars: Vec<_> = unique.similars().into_iter()
+            .filter(|similar| similar.tier <= settings.action_tier)
+            .collect();
+
+        let mut group: Vec<(&Image, Option<String>)> = vec![(&unique.img, None)];
+        group.extend(similars.iter().map(|similar|
+            (&similar.img, Some(format!("{:.2}% similar to the original, {}", similar.similarity(), similar.tier.name())))));
+        group.retain(|&(image, _)| !settings.is_protected(image));
+
+        if group.len() < 2 { continue; }
+
+        let keep = {
+            let images: Vec<&Image> = group.iter().map(|&(image, _)| image).collect();
+            img::choose_representative(&images, &settings.keep, &settings.prefer)
+        };
+
+        let pairs = {
+            let images: Vec<&Image> = group.iter().map(|&(image, _)| image).collect();
+            settings.action_pairs(&images, keep)
+        };
+
+        if pairs.is_empty() { continue; }
+
+        groups += 1;
+
+        writeln!(out)?;
+        if settings.thumbnails_only {
+            writeln!(out, "# Group {}: thumbnails only", groups)?;
+        } else {
+            writeln!(out, "# Group {}: keeping {}", groups, absolute(&group[keep].0.path).display())?;
+        }
+
+        for (keep, image_idx) in pairs {
+            let keep_path = absolute(&group[keep].0.path);
+            let (image, ref matched) = group[image_idx];
+
+            write!(out, "# {}x{} {}, {} bytes, {}", image.width, image.height, image.format_name(), image.file_size,
+                   matched.as_deref().unwrap_or("the original"))?;
+            if settings.thumbnails_only {
+                write!(out, ", thumbnail of {}", keep_path.display())?;
+            }
+            writeln!(out)?;
+
+            write_command(out, style, action, &keep_path, &image.path, quarantine)?;
+
+            for link in &image.links {
+                write_command(out, style, action, &keep_path, link, quarantine)?;
+            }
+
+            // A paired RAW file can't be linked to a different photo, so it's only removed
+            if action.removes() {
+                for sidecar in &image.sidecars {
+                    write_command(out, style, action, &keep_path, sidecar, quarantine)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_header(out: &mut dyn Write, style: ScriptStyle, action: Action, results: &Results) -> io::Result<()> {
+    if style == ScriptStyle::Posix {
+        writeln!(out, "#!/bin/sh")?;
+    }
+
+    writeln!(out, "# Written by img-dup on {}, to {} the images similar to the ones kept.",
+             processing::ctime(&results.end_time), action.verb())?;
+    writeln!(out, "# Look it over before running it, and delete the lines of anything that should stay.")?;
+
+    match (style, action) {
+        (ScriptStyle::Posix, Action::Trash) => {
+            writeln!(out, "TRASH_DIR=\"${{TRASH_DIR:-$HOME/.local/share/Trash/files}}\"")?;
+            writeln!(out, "mkdir -p \"$TRASH_DIR\"")?;
+        },
+        (ScriptStyle::PowerShell, Action::Trash) => {
+            writeln!(out, "$TrashDir = if ($env:TRASH_DIR) {{ $env:TRASH_DIR }} else {{ Join-Path $HOME 'img_dup_trash' }}")?;
+            writeln!(out, "New-Item -ItemType Directory -Force -Path $TrashDir | Out-Null")?;
+        },
+        _ => (),
+    }
+
+    match style {
+        ScriptStyle::Posix => writeln!(out, "set -e"),
+        ScriptStyle::PowerShell => writeln!(out, "$ErrorActionPreference = 'Stop'"),
+    }
+}
+
+/// `quarantine` must be given for `Action::Quarantine`.
+fn write_command(out: &mut dyn Write, style: ScriptStyle, action: Action, keep: &Path, path: &Path,
+                 quarantine: Option<&Quarantine>) -> io::Result<()> {
+    let path = absolute(path);
+    let dest = quarantine.map(|quarantine| absolute(&quarantine.destination(&path))).unwrap_or_default();
+    let dest_dir = dest.parent().unwrap_or(&dest).to_path_buf();
+
+    match style {
+        ScriptStyle::Posix => {
+            let tmp = posix_quote(&PathBuf::from(format!("{}.img_dup_tmp", path.display())));
+            let (keep, path) = (posix_quote(keep), posix_quote(&path));
+
+            match action {
+                Action::Delete => writeln!(out, "rm -- {}", path),
+                Action::Trash => writeln!(out, "mv -- {} \"$TRASH_DIR/\"", path),
+                Action::Hardlink => writeln!(out, "ln -f -- {} {}", keep, path),
+                Action::Symlink => writeln!(out, "ln -sf -- {} {}", keep, path),
+                // cp truncates the file before it tries to clone, so clone next to it first
+                Action::Reflink => writeln!(out, "cp --reflink=always -- {} {} && mv -f -- {} {}", keep, tmp, tmp, path),
+                Action::Quarantine => writeln!(out, "mkdir -p -- {} && mv -- {} {}",
+                                               posix_quote(&dest_dir), path, posix_quote(&dest)),
+            }
+        },
+        ScriptStyle::PowerShell => {
+            let (keep, path) = (powershell_quote(keep), powershell_quote(&path));
+
+            match action {
+                Action::Delete => writeln!(out, "Remove-Item -LiteralPath {}", path),
+                Action::Trash => writeln!(out, "Move-Item -LiteralPath {} -Destination $TrashDir", path),
+                Action::Hardlink => writeln!(out, "New-Item -ItemType HardLink -Force -Path {} -Target {} | Out-Null", path, keep),
+                Action::Symlink => writeln!(out, "New-Item -ItemType SymbolicLink -Force -Path {} -Target {} | Out-Null", path, keep),
+                Action::Reflink => unreachable!(),
+                Action::Quarantine => writeln!(out, "New-Item -ItemType Directory -Force -Path {} | Out-Null; Move-Item -LiteralPath {} -Destination {}",
+                                               powershell_quote(&dest_dir), path, powershell_quote(&dest)),
+            }
+        },
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// In single quotes, where nothing is special but the closing quote.
+fn posix_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// In single quotes, where a quote is escaped by doubling it.
+fn powershell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "''"))
+}
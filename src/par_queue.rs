@@ -1,85 +1,54 @@
-use std::{mem, ptr};
-use std::rt::heap;
-use std::sync::atomic::{AtomicUint, Ordering};
-use std::sync::Arc;
-
-
-pub struct ParQueue<T> where T: Send + Sync {
-    ptr: *const T,
-    len: uint,
-    cap: uint,
-    cur: AtomicUint,    
+use std::sync::{Arc, Mutex};
+use std::vec;
+
+/// A queue that several threads can take items from, each item going to exactly one of them.
+///
+/// The items can come from any iterator, e.g. a channel that's still being filled.
+pub struct ParQueue<I> where I: Iterator + Send {
+    items: Mutex<I>,
 }
 
-unsafe impl<T: Send + Sync> Send for ParQueue<T> {}
-
-unsafe impl<T: Send + Sync> Sync for ParQueue<T> {}
-
-impl<T> ParQueue<T> where T: Send + Sync {
-    pub fn from_vec(vec: Vec<T>) -> ParQueue<T> {
-        let len = vec.len();
-        let cap = vec.capacity();
-        let ptr = vec.as_slice().as_ptr();
-
-        unsafe { mem::forget(vec); }
-
-        ParQueue { ptr: ptr, len: len, cap: cap, cur: AtomicUint::new(0) }
+impl<T> ParQueue<vec::IntoIter<T>> where T: Send {
+    pub fn from_vec(vec: Vec<T>) -> ParQueue<vec::IntoIter<T>> {
+        ParQueue::new(vec.into_iter())
     }
+}
 
-    pub fn pop(&self) -> Option<T> {
-        let cur = self.cur.fetch_add(1, Ordering::Relaxed);
-        if cur >= self.len { return None; }
-        
-        unsafe { // Adapted from `std::vec::MoveItems::next()` 
-            if mem::size_of::<T>() == 0 {
-                Some(ptr::read(mem::transmute(1u)))
-            } else {
-                Some(ptr::read(self.ptr.offset(cur as int)))
-            }
-        }             
+impl<I> ParQueue<I> where I: Iterator + Send {
+    pub fn new(items: I) -> ParQueue<I> {
+        ParQueue { items: Mutex::new(items) }
     }
-    
-    pub fn into_iter(self) -> ParQueueIter<T> {
-        ParQueueIter { queue: Arc::new(self) }    
+
+    pub fn pop(&self) -> Option<I::Item> {
+        // A thread that panicked while holding the lock can't have left the iterator half-advanced
+        let mut items = self.items.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        items.next()
     }
 }
 
-#[unsafe_destructor]
-impl<T> Drop for ParQueue<T> where T: Send + Sync {
-    fn drop(&mut self) {
-        if self.cap != 0 {       
-            while let Some(_) = self.pop() {}
-            unsafe {
-                dealloc(self.ptr, self.cap);
-            }
-        }
-    }    
-}
+impl<I> IntoIterator for ParQueue<I> where I: Iterator + Send {
+    type Item = I::Item;
+    type IntoIter = ParQueueIter<I>;
 
-// Copied from `std::vec` source
-#[inline]
-unsafe fn dealloc<T>(ptr: *const T, len: uint) {
-    if mem::size_of::<T>() != 0 {
-        heap::deallocate(ptr as *mut u8,
-                   len * mem::size_of::<T>(),
-                   mem::min_align_of::<T>())
+    fn into_iter(self) -> ParQueueIter<I> {
+        ParQueueIter { queue: Arc::new(self) }
     }
 }
 
-pub struct ParQueueIter<T: Send + Sync> {
-    queue: Arc<ParQueue<T>>,
-}
-
-impl<T> Clone for ParQueueIter<T> where T: Send + Sync {
-    fn clone(&self) -> ParQueueIter<T> {
-        ParQueueIter { queue: self.queue.clone() }    
-    }    
+pub struct ParQueueIter<I> where I: Iterator + Send {
+    queue: Arc<ParQueue<I>>,
 }
 
-impl<T> Iterator<T> for ParQueueIter<T> where T: Send + Sync {
-    fn next(&mut self) -> Option<T> {
-        self.queue.pop()    
+impl<I> Clone for ParQueueIter<I> where I: Iterator + Send {
+    fn clone(&self) -> ParQueueIter<I> {
+        ParQueueIter { queue: self.queue.clone() }
     }
 }
 
+impl<I> Iterator for ParQueueIter<I> where I: Iterator + Send {
+    type Item = I::Item;
 
+    fn next(&mut self) -> Option<I::Item> {
+        self.queue.pop()
+    }
+}
@@ -0,0 +1,83 @@
+//! With the `parquet` feature, `--export-parquet`: every image of this run, with its hash
+//! and the group it's in, as one Parquet table, to join with other metadata in DuckDB, Spark
+//! or pandas.
+//!
+//! Each row is one image: `path`, `hash`, `width`, `height`, `size` and `format` as in the
+//! JSON output, `group`, numbering the groups from 0, and `original`, the path of the original
+//! of its group. `distance`, `similarity`, `tier` and `match` are measured from the original,
+//! and null for the original itself. Images without similars are groups of their own.
+//!
+//! Only the images of this run are written, not the rest of the hashes in `--cache` or `--db`.
+
+use crate::config::ProgramSettings;
+use crate::img::{Image, SimilarImage};
+use crate::processing::Results;
+
+use arrow_array::{ArrayRef, Float32Array, RecordBatch, StringArray, UInt32Array, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Write the table to `path`, replacing it if it exists.
+pub fn export(settings: &ProgramSettings, results: &Results, path: &Path) -> io::Result<()> {
+    let dir = &settings.dir;
+
+    let mut rows: Vec<(usize, &Image, &Image, Option<&SimilarImage>)> = Vec::new();
+
+    for (group, unique) in results.uniques.iter().enumerate() {
+        rows.push((group, &unique.img, &unique.img, None));
+        rows.extend(unique.similars.iter().map(|similar| (group, &unique.img, &similar.img, Some(similar))));
+    }
+
+    let relative = |image: &Image| image.relative_path(dir).display().to_string();
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|&(_, _, image, _)| relative(image)))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|&(_, _, image, _)| image.hash.to_base64()))),
+        Arc::new(UInt32Array::from_iter_values(rows.iter().map(|&(_, _, image, _)| image.width))),
+        Arc::new(UInt32Array::from_iter_values(rows.iter().map(|&(_, _, image, _)| image.height))),
+        Arc::new(UInt64Array::from_iter_values(rows.iter().map(|&(_, _, image, _)| image.file_size))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|&(_, _, image, _)| image.format_name()))),
+        Arc::new(UInt64Array::from_iter_values(rows.iter().map(|&(group, _, _, _)| group as u64))),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|&(_, original, _, _)| relative(original)))),
+        Arc::new(UInt64Array::from_iter(rows.iter().map(|&(_, _, _, similar)| similar.map(|similar| similar.dist as u64)))),
+        Arc::new(Float32Array::from_iter(rows.iter().map(|&(_, _, _, similar)| similar.map(SimilarImage::similarity)))),
+        Arc::new(StringArray::from_iter(rows.iter().map(|&(_, _, _, similar)| similar.map(|similar| similar.tier.name())))),
+        Arc::new(StringArray::from_iter(rows.iter().map(|&(_, _, _, similar)| similar.map(|similar| similar.reason.name())))),
+    ];
+
+    let batch = RecordBatch::try_new(Arc::new(schema()), columns).map_err(io::Error::other)?;
+
+    let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, batch.schema(), Some(properties))
+        .map_err(io::Error::other)?;
+
+    writer.write(&batch).map_err(io::Error::other)?;
+    writer.close().map_err(io::Error::other)?;
+
+    Ok(())
+}
+
+fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("path", DataType::Utf8, false),
+        Field::new("hash", DataType::Utf8, false),
+        Field::new("width", DataType::UInt32, false),
+        Field::new("height", DataType::UInt32, false),
+        Field::new("size", DataType::UInt64, false),
+        Field::new("format", DataType::Utf8, false),
+        Field::new("group", DataType::UInt64, false),
+        Field::new("original", DataType::Utf8, false),
+        Field::new("distance", DataType::UInt64, true),
+        Field::new("similarity", DataType::Float32, true),
+        Field::new("tier", DataType::Utf8, true),
+        Field::new("match", DataType::Utf8, true),
+    ])
+}
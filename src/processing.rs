@@ -1,148 +1,467 @@
-use config::{ProgramSettings, HashSettings};
-use img::{Image, UniqueImage};
-use output::newline_before_after;
-use par_queue::ParQueue;
+#[cfg(feature = "archives")]
+use crate::archives;
+use crate::bktree::BkTree;
+use crate::classify;
+use crate::cluster;
+use crate::config::{ProgramSettings, HashSettings, RepresentativePolicy};
+use crate::db::HashDb;
+use crate::decoders;
+use crate::exact::{self, ExactCopies};
+use crate::exif::{self, Orientation};
+use crate::header;
+use crate::img::{self, Image, ImageTiming, UniqueImage};
+use crate::links::{self, LinkFinder, Links};
+use crate::objects::{ObjectInfo, ObjectSource};
+use crate::output::newline_before_after;
+use crate::par_queue::{ParQueue, ParQueueIter};
+use crate::raw::{self, Sidecars};
+use crate::search::SearchHandle;
+use crate::storage::{self, Storage};
+use crate::throttle;
+use crate::thumbnails;
 
-use image;
-use image::{DynamicImage, GenericImage, ImageError};
+#[cfg(feature = "video")]
+use crate::video;
 
-use img_hash::ImageHash;
- 
-use serialize::json::{ToJson, Json};
+use chrono::{DateTime, Local};
+
+use image::{DynamicImage, ImageError};
 
-use time::{Tm, now, precise_time_ns};
+use log::{debug, error, info, warn};
 
-use std::ascii::AsciiExt;
-use std::boxed::BoxAny;
-use std::collections::BTreeMap;
-use std::io::IoResult;
-use std::io::fs::PathExtensions;
-use std::rt::unwind::try;
-use std::thread::Thread;
+use serde::Serialize;
 
+use std::any::Any;
+use std::cmp::{self, Reverse};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::iter;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize)]
 pub struct Results {
     pub total: Total,
-    pub start_time: Tm,
-    pub end_time: Tm,
+    #[serde(serialize_with = "crate::serialize::serialize_ctime")]
+    pub start_time: DateTime<Local>,
+    #[serde(serialize_with = "crate::serialize::serialize_ctime")]
+    pub end_time: DateTime<Local>,
     pub uniques: Vec<UniqueImage>,
     pub errors: Vec<ProcessingError>,    
+    /// How long each phase took, where it was measured
+    pub phases: Phases,
 }
 
-unsafe impl Send for Results {}
-
 impl Results {
 
-    fn start_time(&self) -> String {
-        self.start_time.ctime().to_string()
+    pub fn start_time(&self) -> String {
+        ctime(&self.start_time)
     }
 
-    fn end_time(&self) -> String {
-        self.end_time.ctime().to_string()
+    pub fn end_time(&self) -> String {
+        ctime(&self.end_time)
     }    
 
-    pub fn info_json(&self) -> Json {
-        let mut info = BTreeMap::new();
-        json_insert!(info, "start", self.start_time());
-        json_insert!(info, "end", self.end_time());
-        json_insert!(info, "found", self.total);
-        json_insert!(info, "processed", self.uniques.len());
-        json_insert!(info, "errors", self.errors.len());
+    /// How many bytes would be freed by keeping only the original of every group.
+    pub fn wasted_bytes(&self) -> u64 {
+        self.uniques.iter().fold(0, |total, unique| total + unique.wasted_bytes())
+    }
+
+    /// The groups to output, after `--dup-only`, `--min-group-size` and `--max-groups`.
+    /// With `--max-groups`, the groups wasting the most space come first.
+    pub fn groups(&self, settings: &ProgramSettings) -> Vec<&UniqueImage> {
+        let min_size = if settings.dup_only {
+            cmp::max(settings.min_group_size, 2)
+        } else {
+            settings.min_group_size
+        };
 
-        Json::Object(info)
+        let mut groups: Vec<&UniqueImage> = self.uniques.iter()
+            .filter(|unique| unique.similars.len() + 1 >= min_size)
+            .collect();
+
+        if settings.max_groups > 0 {
+            groups.sort_by_key(|unique| Reverse(unique.wasted_bytes()));
+            groups.truncate(settings.max_groups);
+        }
+
+        groups
     }
 
-    pub fn uniques_json(&self, relative_to: &Path, dup_only: bool) -> Json {
-        let uniques_json: Vec<Json> = self.uniques.iter()
-        .filter_map( |unique| 
-                if dup_only && unique.similars.is_empty() {
-                    None
-                } else {
-                    Some(unique.to_json(relative_to))
-                }  
-        ).collect();
+    /// With `--bursts`, the originals of the groups, and the images in no group, that are less
+    /// different than the burst threshold, grouped the same way as the images themselves.
+    /// Only bursts of more than one are returned.
+    pub fn bursts(&self, settings: &ProgramSettings) -> Vec<UniqueImage> {
+        let Some(threshold) = settings.bursts else { return Vec::new() };
 
-        Json::Array(uniques_json)
+        // Everything in a burst is more different than the threshold, so none of it is near
+        let settings = ProgramSettings { threshold, near_threshold: settings.threshold, ..settings.clone() };
+        let originals = self.uniques.iter().map(|unique| unique.img.clone()).collect();
+
+        find_uniques_indexed(originals, &settings).into_iter()
+            .filter(|burst| !burst.similars.is_empty())
+            .collect()
     }
 
-    pub fn errors_json(&self, relative_to: &Path) -> Json {
-        let errors_json: Vec<Json> = self.errors.iter()
-            .map( |error| error.to_json(relative_to) )
-            .collect();
+    /// If any image failed to load or timed out. Images over the size limits, and blank ones,
+    /// were skipped on purpose.
+    pub fn has_errors(&self) -> bool {
+        self.errors.iter().any(|error| !error.is_too_large() && !error.is_blank())
+    }
+
+    /// Errors other than timeouts, images over the size limits and blank images, which are
+    /// reported separately.
+    pub fn failed(&self) -> Vec<&ProcessingError> {
+        self.errors.iter().filter(|error| !error.is_timeout() && !error.is_too_large() && !error.is_blank()).collect()
+    }
+
+    pub fn timed_out(&self) -> Vec<&ProcessingError> {
+        self.errors.iter().filter(|error| error.is_timeout()).collect()
+    }
+
+    pub fn too_large(&self) -> Vec<&ProcessingError> {
+        self.errors.iter().filter(|error| error.is_too_large()).collect()
+    }
 
-        Json::Array(errors_json)        
+    pub fn blank(&self) -> Vec<&ProcessingError> {
+        self.errors.iter().filter(|error| error.is_blank()).collect()
     }
 
-    pub fn write_info(&self, out: &mut Writer) -> IoResult<()> {
-        try!(writeln!(out, "Start time: {}", self.start_time()));
-        try!(writeln!(out, "End time: {}", self.end_time()));
-        try!(writeln!(out, "Images found: {}", self.total));
-        try!(writeln!(out, "Processed: {}", self.uniques.len()));
-        writeln!(out, "Errors: {}", self.errors.len())
+    /// The totals of the run, before `--dup-only`, `--min-group-size` and `--max-groups`.
+    pub fn stats(&self) -> Stats {
+        let mut failed = BTreeMap::new();
+
+        for error in &self.errors {
+            *failed.entry(error.kind()).or_insert(0) += 1;
+        }
+
+        let duplicates = self.uniques.iter().fold(0, |total, unique| total + unique.similars.len());
+        let hashed = self.uniques.len() + duplicates;
+        let elapsed_secs = (self.end_time - self.start_time).num_milliseconds().max(0) as f64 / 1000f64;
+
+        Stats {
+            scanned: self.total + self.errors.len(),
+            hashed,
+            failed,
+            elapsed_secs,
+            images_per_sec: if elapsed_secs > 0f64 { hashed as f64 / elapsed_secs } else { 0f64 },
+            groups: self.uniques.iter().filter(|unique| !unique.similars.is_empty()).count(),
+            duplicates,
+            wasted_bytes: self.wasted_bytes(),
+        }
     }
 
-    pub fn write_uniques(&self, out: &mut Writer, relative_to: &Path, dup_only: bool) -> IoResult<()> {
-        for unique in self.uniques.iter() {
-            if dup_only && unique.similars.is_empty() {
-                continue;
-            } else {
-                try!(
-                    newline_before_after(out, 
-                        |outa| unique.write_self(outa, relative_to))
-                );
+    /// The phase times, and percentiles of the per-image times recorded with `--timings`.
+    /// Cached images and exact copies weren't loaded or hashed, so they aren't counted.
+    pub fn timings(&self) -> Timings {
+        let timed: Vec<_> = self.uniques.iter()
+            .flat_map(|unique| iter::once(&unique.img).chain(unique.similars.iter().map(|similar| &similar.img)))
+            .filter_map(|image| image.timing)
+            .collect();
+
+        Timings {
+            phases: self.phases,
+            load: Percentiles::of(timed.iter().map(|timing| timing.load).collect()),
+            hash: Percentiles::of(timed.iter().map(|timing| timing.hash).collect()),
+        }
+    }
+
+    pub fn write_timings(&self, out: &mut dyn Write) -> io::Result<()> {
+        let timings = self.timings();
+
+        let phases = [("Search", timings.phases.search_secs), ("Hashing", timings.phases.hashing_secs),
+                      ("Grouping", timings.phases.grouping_secs)];
+
+        for (name, secs) in phases {
+            if let Some(secs) = secs {
+                writeln!(out, "{}: {:.3}s", name, secs)?;
+            }
+        }
+
+        for (name, percentiles) in [("Load", timings.load), ("Hash", timings.hash)] {
+            match percentiles {
+                Some(percentiles) => writeln!(out, "{}: {}", name, percentiles)?,
+                None => writeln!(out, "{}: no images timed", name)?,
             }
         }
 
         Ok(())
     }
 
-    pub fn write_errors(&self, out: &mut Writer, relative_to: &Path) -> IoResult<()> {
-        for error in self.errors.iter() {
-            try!(
-                newline_before_after(out, 
-                    |outa| error.write_self(outa, relative_to))
-            );
+    pub fn write_info(&self, out: &mut dyn Write) -> io::Result<()> {
+        let stats = self.stats();
+
+        writeln!(out, "Start time: {}", self.start_time())?;
+        writeln!(out, "End time: {}", self.end_time())?;
+        writeln!(out, "Elapsed: {:.2}s ({:.1} images/s)", stats.elapsed_secs, stats.images_per_sec)?;
+        writeln!(out, "Images found: {}", stats.scanned)?;
+        writeln!(out, "Hashed: {}", stats.hashed)?;
+        writeln!(out, "Processed: {}", self.uniques.len())?;
+        writeln!(out, "Duplicate groups: {}", stats.groups)?;
+        writeln!(out, "Duplicates: {}", stats.duplicates)?;
+        writeln!(out, "Wasted space: {} bytes", stats.wasted_bytes)?;
+        writeln!(out, "Errors: {}", self.failed().len())?;
+        writeln!(out, "Timed out: {}", self.timed_out().len())?;
+        writeln!(out, "Too large: {}", self.too_large().len())?;
+        writeln!(out, "Blank: {}", self.blank().len())
+    }
+
+    pub fn write_uniques(&self, out: &mut dyn Write, settings: &ProgramSettings) -> io::Result<()> {
+        for unique in self.groups(settings) {
+            newline_before_after(out, 
+                |outa| unique.write_self(outa, &settings.dir))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_bursts(&self, out: &mut dyn Write, settings: &ProgramSettings) -> io::Result<()> {
+        for burst in self.bursts(settings) {
+            newline_before_after(out,
+                |outa| burst.write_self(outa, &settings.dir))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_errors(&self, out: &mut dyn Write, relative_to: &Path) -> io::Result<()> {
+        for error in self.failed() {
+            newline_before_after(out, 
+                |outa| error.write_self(outa, relative_to))?;
         }
 
         Ok(())
     }
+
+    pub fn write_timed_out(&self, out: &mut dyn Write, relative_to: &Path) -> io::Result<()> {
+        write_paths(&self.timed_out(), out, relative_to)
+    }
+
+    pub fn write_too_large(&self, out: &mut dyn Write, relative_to: &Path) -> io::Result<()> {
+        write_paths(&self.too_large(), out, relative_to)
+    }
+
+    pub fn write_blank(&self, out: &mut dyn Write, relative_to: &Path) -> io::Result<()> {
+        write_paths(&self.blank(), out, relative_to)
+    }
 } 
 
-pub enum ProcessingError {
-    Decoding(Path, ImageError),
-    Misc(Path, String),
+/// The totals of a run, from `Results::stats()`.
+#[derive(Serialize, Clone, Debug)]
+pub struct Stats {
+    /// Files looked at, including the ones that failed, RAW sidecars and other paths to the same file
+    pub scanned: usize,
+    /// Images hashed or loaded from the cache
+    pub hashed: usize,
+    /// Images that couldn't be processed, by `ProcessingError::kind()`
+    pub failed: BTreeMap<&'static str, usize>,
+    /// Wall time from the start of the search to the end of grouping
+    pub elapsed_secs: f64,
+    /// Images hashed per second of `elapsed_secs`
+    pub images_per_sec: f64,
+    /// Groups with at least one similar image
+    pub groups: usize,
+    /// Images similar to the original of their group
+    pub duplicates: usize,
+    /// Bytes freed by keeping only the original of every group
+    pub wasted_bytes: u64,
 }
 
-unsafe impl Send for ProcessingError {}
+impl Stats {
+
+    /// Every error, of any kind.
+    pub fn failed_total(&self) -> usize {
+        self.failed.values().sum()
+    }
+}
+
+/// Seconds spent in each phase of a run. Phases that overlap, like searching and hashing
+/// when hashing starts before the search is done, or that weren't measured, are `None`.
+#[derive(Serialize, Clone, Copy, Default, Debug)]
+pub struct Phases {
+    pub search_secs: Option<f64>,
+    pub hashing_secs: Option<f64>,
+    pub grouping_secs: Option<f64>,
+}
+
+/// The timings of a run with `--timings`, from `Results::timings()`.
+#[derive(Serialize, Clone, Debug)]
+pub struct Timings {
+    pub phases: Phases,
+    /// Reading and decoding each image, or `None` if no image was timed
+    pub load: Option<Percentiles>,
+    /// Hashing each decoded image
+    pub hash: Option<Percentiles>,
+}
+
+/// The distribution of a per-image time, in milliseconds.
+#[derive(Serialize, Clone, Debug)]
+pub struct Percentiles {
+    /// How many images were timed
+    pub count: usize,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub total_ms: f64,
+}
+
+impl Percentiles {
+
+    /// From times in nanoseconds, in any order.
+    pub fn of(mut nanos: Vec<u64>) -> Option<Percentiles> {
+        if nanos.is_empty() { return None; }
+
+        nanos.sort_unstable();
+
+        // Nearest rank
+        let rank = |percent: usize| nanos[(nanos.len() * percent).div_ceil(100).max(1) - 1];
+        let ms = |nanos: u64| nanos as f64 / 1_000_000f64;
+
+        Some(Percentiles {
+            count: nanos.len(),
+            p50_ms: ms(rank(50)),
+            p90_ms: ms(rank(90)),
+            p99_ms: ms(rank(99)),
+            max_ms: ms(nanos[nanos.len() - 1]),
+            total_ms: ms(nanos.iter().sum()),
+        })
+    }
+}
+
+impl fmt::Display for Percentiles {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "p50 {:.1} ms, p90 {:.1} ms, p99 {:.1} ms, max {:.1} ms, total {:.1} ms ({} images)",
+               self.p50_ms, self.p90_ms, self.p99_ms, self.max_ms, self.total_ms, self.count)
+    }
+}
+
+/// Format a time like C's `ctime()`, e.g. `Thu Jan  1 00:00:00 1970`.
+pub fn ctime(time: &DateTime<Local>) -> String {
+    time.format("%a %b %e %H:%M:%S %Y").to_string()
+}
+
+fn write_paths(errors: &[&ProcessingError], out: &mut dyn Write, relative_to: &Path) -> io::Result<()> {
+    for error in errors {
+        writeln!(out, "{}", error.relative_path(relative_to).display())?;
+    }
+
+    Ok(())
+}
+
+/// Why an image couldn't be processed. Every variant has the path of the image.
+#[derive(Debug)]
+pub enum ProcessingError {
+    /// The image data is invalid or corrupt
+    Decoding(PathBuf, ImageError),
+    /// The file couldn't be read
+    Io(PathBuf, io::Error),
+    /// The format, or a feature of it, isn't supported by the decoder
+    UnsupportedFormat(PathBuf, String),
+    /// Loading and hashing took longer than `--timeout` seconds
+    TimedOut(PathBuf, u64),
+    /// The file or its dimensions exceed `--max-file-size` or `--max-pixels`
+    TooLarge(PathBuf, String),
+    /// The image is a single color, by `--blank-threshold`
+    Blank(PathBuf),
+    /// Anything else, like the hasher panicking
+    Misc(PathBuf, String),
+}
 
 impl ProcessingError {
-    
-    pub fn relative_path(&self, relative_to: &Path) -> Path {
-        let path = match *self {
+
+    /// Sort out the decoder's error into the matching variant.
+    pub fn from_image_error(path: PathBuf, err: ImageError) -> ProcessingError {
+        match err {
+            ImageError::IoError(io_err) => ProcessingError::Io(path, io_err),
+            ImageError::Unsupported(unsupported) => 
+                ProcessingError::UnsupportedFormat(path, unsupported.to_string()),
+            err => ProcessingError::Decoding(path, err),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match *self {
             ProcessingError::Decoding(ref path, _) => path,
+            ProcessingError::Io(ref path, _) => path,
+            ProcessingError::UnsupportedFormat(ref path, _) => path,
+            ProcessingError::TimedOut(ref path, _) => path,
+            ProcessingError::TooLarge(ref path, _) => path,
+            ProcessingError::Blank(ref path) => path,
             ProcessingError::Misc(ref path, _) => path,
-        };
+        }
+    }
+
+    /// A short name for the variant, for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            ProcessingError::Decoding(..) => "decoding",
+            ProcessingError::Io(..) => "io",
+            ProcessingError::UnsupportedFormat(..) => "unsupported",
+            ProcessingError::TimedOut(..) => "timed_out",
+            ProcessingError::TooLarge(..) => "too_large",
+            ProcessingError::Blank(..) => "blank",
+            ProcessingError::Misc(..) => "misc",
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(*self, ProcessingError::TimedOut(..))
+    }
+
+    pub fn is_too_large(&self) -> bool {
+        matches!(*self, ProcessingError::TooLarge(..))
+    }
 
-        path.path_relative_from(relative_to).unwrap_or(path.clone())
+    pub fn is_blank(&self) -> bool {
+        matches!(*self, ProcessingError::Blank(..))
+    }
+    
+    pub fn relative_path(&self, relative_to: &Path) -> PathBuf {
+        let path = self.path();
+
+        path.strip_prefix(relative_to).unwrap_or(path).to_path_buf()
     }
 
     pub fn err_msg(&self) -> String {
         match *self {
             ProcessingError::Decoding(_, ref img_err) => format!("Loading error: {}", img_err),
+            ProcessingError::Io(_, ref io_err) => format!("Could not read file: {}", io_err),
+            ProcessingError::UnsupportedFormat(_, ref msg) => format!("Unsupported format: {}", msg),
+            ProcessingError::TimedOut(_, secs) => format!("Timed out after {} seconds", secs),
+            ProcessingError::TooLarge(_, ref msg) => format!("Skipped, too large: {}", msg),
+            ProcessingError::Blank(_) => "Skipped, blank".to_string(),
             ProcessingError::Misc(_, ref misc_err) => format!("Hashing error: {}", misc_err),
         }
     }
 
-    pub fn to_json(&self, relative_to: &Path) -> Json {
-        let mut json = BTreeMap::new();
-
-        json_insert!(json, "path", self.relative_path(relative_to).display().to_string());
-        json_insert!(json, "error", self.err_msg());
+    pub fn write_self(&self, out: &mut dyn Write, relative_to: &Path) -> io::Result<()> {
+        writeln!(out, "Image: {}\n {}\n", self.relative_path(relative_to).display(), self.err_msg())
+    }
+}
 
-        Json::Object(json)        
+impl fmt::Display for ProcessingError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {}", self.path().display(), self.err_msg())
     }
+}
+
+impl Error for ProcessingError {
 
-    pub fn write_self(&self, out: &mut Writer, relative_to: &Path) -> IoResult<()> {
-        writeln!(out, "Image: {}\n {}\n", self.relative_path(relative_to).display().to_string(), self.err_msg())
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            ProcessingError::Decoding(_, ref img_err) => Some(img_err),
+            ProcessingError::Io(_, ref io_err) => Some(io_err),
+            _ => None,
+        }
     }
 }
 
@@ -154,151 +473,1086 @@ pub type ImageResult = Result<Image, ProcessingError>;
 
 pub type TimedImageResult = Result<(Image, LoadTime, HashTime), ProcessingError>;
 
-pub type Total = uint;
+pub type Total = usize;
+
+/// A snapshot of the processing status, sent to the progress callback after every image.
+pub struct Progress {
+    pub hashed: usize,
+    pub errors: usize,
+    /// The number of images queued for processing
+    pub total: Total,
+    /// The image that just finished
+    pub path: PathBuf,
+}
+
+/// Shared flag to stop processing early, or pause it.
+/// Worker threads finish the image they're on, then stop taking new ones.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<TokenState>);
+
+#[derive(Default)]
+struct TokenState {
+    canceled: AtomicBool,
+    paused: Mutex<bool>,
+    resumed: Condvar,
+}
+
+impl CancelToken {
+
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.canceled.store(true, Ordering::Relaxed);
+
+        // Paused workers wake up to stop
+        let _paused = self.lock_paused();
+        self.0.resumed.notify_all();
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.0.canceled.load(Ordering::Relaxed)
+    }
+
+    /// Hold the workers after the image they're on, without losing anything processed so far,
+    /// until `resume()` or `cancel()`. A search that's still going carries on.
+    pub fn pause(&self) {
+        *self.lock_paused() = true;
+    }
 
-pub fn process(settings: &ProgramSettings, paths: Vec<Path>) -> Results {
-    let start_time = now();
+    pub fn resume(&self) {
+        *self.lock_paused() = false;
+        self.0.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.lock_paused()
+    }
+
+    /// Block while paused, unless canceled.
+    pub fn wait_while_paused(&self) {
+        let mut paused = self.lock_paused();
+
+        while *paused && !self.is_canceled() {
+            paused = self.0.resumed.wait(paused).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    fn lock_paused(&self) -> MutexGuard<'_, bool> {
+        self.0.paused.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A hashing worker, for a `Spawner` to run.
+pub type Task = Box<dyn FnOnce() + Send>;
+
+/// Runs hashing workers on an executor of the application's, set with `ProgramSettings::set_spawner()`.
+pub type Spawner = Arc<dyn Fn(Task) + Send + Sync>;
+
+pub fn process(settings: &ProgramSettings, paths: Vec<PathBuf>) -> Results {
+    process_with_progress(settings, paths, &CancelToken::new(), |_| ())
+}
+
+/// Like `process()`, but calls `on_progress` every time an image finishes processing.
+/// If `cancel` is canceled, the results only include the images processed up to that point.
+pub fn process_with_progress<F>(settings: &ProgramSettings, paths: Vec<PathBuf>, 
+                                cancel: &CancelToken, on_progress: F) -> Results
+where F: FnMut(Progress) {
+    let start_time = Local::now();
    
-    let (total, uniques, errors) = process_multithread(settings, paths);
+    let (total, uniques, errors, phases) = process_multithread(settings, paths, cancel, on_progress);
 
     Results {
-        total: total,
-        start_time: start_time,
-        end_time: now(),
-        uniques: uniques,
-        errors: errors,
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors,
+        phases,
     }    
 }
 
-fn process_multithread(settings: &ProgramSettings, paths: Vec<Path>)
-    -> (Total, Vec<UniqueImage>, Vec<ProcessingError>) {                
-    let rx = spawn_threads(settings, paths);
+fn process_multithread<F>(settings: &ProgramSettings, paths: Vec<PathBuf>, 
+                          cancel: &CancelToken, on_progress: F)
+    -> (Total, Vec<UniqueImage>, Vec<ProcessingError>, Phases)
+where F: FnMut(Progress) {                
+    let queued = paths.len();
+    let results = results_iter(settings, paths, cancel.clone());
+
+    let (hashed, hashing_secs) = timed(|| receive_images(results, &|| queued, on_progress));
+    let (total, images, errors) = hashed;
+    let (uniques, grouping_secs) = timed(|| group_images(images, settings));
+
+    let phases = Phases { search_secs: None, hashing_secs: Some(hashing_secs), grouping_secs: Some(grouping_secs) };
+
+    (total, uniques, errors, phases)
+}
+
+/// Run `f`, and how many seconds it took.
+pub fn timed<T, F: FnOnce() -> T>(f: F) -> (T, f64) {
+    let start = Instant::now();
+    let value = f();
+
+    (value, start.elapsed().as_secs_f64())
+}
+
+/// If hashing can start before the search is done: `--pair-raw`, `--exact-first`, `--cache`
+/// and `--db` need every path first.
+pub fn can_stream(settings: &ProgramSettings) -> bool {
+    !settings.pair_raw && !settings.exact_first && settings.cache.is_none() && settings.results_db.is_none()
+}
+
+/// Like `process_with_progress()`, but hash `paths` as they arrive from a search that's 
+/// still going, instead of waiting for all of them. The progress total is the number of
+/// images `search` found so far. Only for settings where `can_stream()` is `true`.
+pub fn process_stream<F>(settings: &ProgramSettings, paths: Receiver<PathBuf>, search: &SearchHandle,
+                         cancel: &CancelToken, on_progress: F) -> Results
+where F: FnMut(Progress) {
+    let start_time = Local::now();
+
+    let limit = if settings.limit > 0 { settings.limit } else { usize::MAX };
+
+    // Links to a file can turn up anywhere in the search, so they're attached at the end
+    let finder = Arc::new(Mutex::new(LinkFinder::new()));
+    let work_finder = finder.clone();
+
+    let firsts = paths.into_iter()
+        .filter(move |path| work_finder.lock().unwrap().is_first(path))
+        .take(limit);
 
-    receive_images(rx, settings)       
+    let work = ParQueue::new(firsts).into_iter();
+
+    let results = ResultsIter { rx: spawn_worker_pool(settings, work, cancel.clone()) };
+
+    let queued = || cmp::min(search.found(), limit);
+    // The search runs alongside hashing, so this includes the part of it hashing waited for
+    let (hashed, hashing_secs) = timed(|| receive_images(results, &queued, on_progress));
+    let (mut total, mut images, errors) = hashed;
+
+    let mut links = std::mem::take(&mut *finder.lock().unwrap()).into_links();
+
+    for image in &mut images {
+        image.links = links.remove(&image.path).unwrap_or_default();
+        total += image.links.len();
+    }
+
+    let (uniques, grouping_secs) = timed(|| group_images(images, settings));
+
+    Results {
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors,
+        phases: Phases { search_secs: None, hashing_secs: Some(hashing_secs), grouping_secs: Some(grouping_secs) },
+    }
+}
+
+/// An image to process that's already in memory, e.g. from an upload.
+pub enum InMemoryImage {
+    Decoded(DynamicImage),
+    /// The contents of an image file, in any format the `image` crate can decode
+    Encoded(Vec<u8>),
 }
 
-pub fn spawn_threads(settings: &ProgramSettings, paths: Vec<Path>) 
+/// Like `process_with_progress()`, for images that are already in memory instead of in files.
+/// Each one is named by the path it's given with, which identifies it in the results.
+///
+/// Only the hashing settings, and the settings for grouping and choosing originals, apply.
+/// Encoded JPEGs are rotated according to their EXIF data; decoded images are hashed as they are.
+pub fn process_in_memory<F>(settings: &ProgramSettings, images: Vec<(PathBuf, InMemoryImage)>,
+                            cancel: &CancelToken, on_progress: F) -> Results
+where F: FnMut(Progress) {
+    let start_time = Local::now();
+
+    let queued = images.len();
+    let work = ParQueue::from_vec(images).into_iter();
+
+    let results = ResultsIter { rx: spawn_pool(settings, work, cancel.clone(), hash_in_memory) };
+    let (hashed, hashing_secs) = timed(|| receive_images(results, &|| queued, on_progress));
+    let (total, images, errors) = hashed;
+    let (uniques, grouping_secs) = timed(|| group_images(images, settings));
+
+    Results {
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors,
+        phases: Phases { search_secs: None, hashing_secs: Some(hashing_secs), grouping_secs: Some(grouping_secs) },
+    }
+}
+
+/// Like `process_with_progress()`, for `objects` in `source`, e.g. from `search::find_objects()`.
+/// Each worker downloads an object into memory and hashes it, so only as many objects as
+/// there are threads are in memory at once. Downloading is counted as loading time.
+///
+/// Objects aren't cached, and `--pair-raw` and `--thumbnails` don't apply to them.
+pub fn process_objects<F>(settings: &ProgramSettings, source: Arc<dyn ObjectSource>, objects: Vec<ObjectInfo>,
+                          cancel: &CancelToken, on_progress: F) -> Results
+where F: FnMut(Progress) {
+    let start_time = Local::now();
+
+    let queued = objects.len();
+    let work = ParQueue::from_vec(objects.into_iter().map(|object| (source.clone(), object)).collect()).into_iter();
+
+    let results = ResultsIter { rx: spawn_pool(settings, work, cancel.clone(), hash_object) };
+    let (hashed, hashing_secs) = timed(|| receive_images(results, &|| queued, on_progress));
+    let (total, images, errors) = hashed;
+    let (uniques, grouping_secs) = timed(|| group_images(images, settings));
+
+    Results {
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors,
+        phases: Phases { search_secs: None, hashing_secs: Some(hashing_secs), grouping_secs: Some(grouping_secs) },
+    }
+}
+
+/// Objects over `--max-file-size` aren't downloaded at all.
+fn hash_object(settings: &HashSettings, (source, object): (Arc<dyn ObjectSource>, ObjectInfo)) -> TimedImageResult {
+    let name = source.path(&object.key);
+
+    if settings.max_file_size > 0 && object.size > settings.max_file_size {
+        return Err(ProcessingError::TooLarge(name,
+            format!("{} bytes, the limit is {}", object.size, settings.max_file_size)));
+    }
+
+    let start_fetch = Instant::now();
+    let bytes = source.fetch(&object.key).map_err(|err| ProcessingError::Io(name.clone(), err))?;
+    let fetch_time = nanos_since(start_fetch);
+
+    hash_in_memory(settings, (name, InMemoryImage::Encoded(bytes)))
+        .map(|(image, load_time, hash_time)| (image, fetch_time + load_time, hash_time))
+}
+
+/// Like `hash_image()`, for an image that's already in memory, named `name`.
+pub fn hash_in_memory_image(settings: &ProgramSettings, name: PathBuf, image: InMemoryImage) -> ImageResult {
+    hash_in_memory(&settings.hash_settings(), (name, image)).map(|(image, _, _)| image)
+}
+
+/// Decoding an encoded image is counted as loading time.
+fn hash_in_memory(settings: &HashSettings, (name, image): (PathBuf, InMemoryImage)) -> TimedImageResult {
+    let start_load = Instant::now();
+
+    let (image, file_size, format) = match image {
+        InMemoryImage::Decoded(image) => (image, 0, None),
+        InMemoryImage::Encoded(bytes) => match try_fn(|| image::load_from_memory(&bytes)) {
+            Ok(Ok(image)) => 
+                (orient(exif::orientation_of(&bytes), image), bytes.len() as u64, image::guess_format(&bytes).ok()),
+            Ok(Err(img_err)) => return Err(ProcessingError::from_image_error(name, img_err)),
+            Err(cause) => return Err(ProcessingError::Misc(name, cause)),
+        },
+    };
+
+    let load_time = nanos_since(start_load);
+
+    check_pixels(settings, &name, image.width(), image.height())?;
+    check_blank(settings, &name, &image)?;
+
+    let start_hash = Instant::now();
+    let hashed = try_fn(|| Image::hash_from_dynamic_image(&image, settings));
+    let hash_time = nanos_since(start_hash);
+
+    match hashed {
+        Ok(hashed) => Ok((Image { path: name, file_size, format, ..hashed }, load_time, hash_time)),
+        Err(cause) => Err(ProcessingError::Misc(name, cause)),
+    }
+}
+
+/// Find images in `others` that are similar to images in `paths`, 
+/// without comparing images within either set to each other.
+///
+/// Each image in `paths` is reported with the images in `others` that duplicate it;
+/// images in `others` without a match are reported on their own.
+pub fn compare(settings: &ProgramSettings, paths: Vec<PathBuf>, others: Vec<PathBuf>) -> Results {
+    let start_time = Local::now();
+
+    let originals: HashSet<PathBuf> = paths.iter().cloned().collect();
+
+    let mut all_paths = paths;
+    all_paths.extend(others);
+
+    let mut total = 0;
+    let mut images = Vec::new();
+    let mut compared = Vec::new();
+    let mut errors = Vec::new();
+
+    let hashing_start = Instant::now();
+
+    for img_result in results_iter(settings, all_paths, CancelToken::new()) {
+        match img_result {
+            Ok(image) => {
+                total += 1 + image.sidecars.len() + image.links.len();
+
+                if originals.contains(&image.path) {
+                    images.push(image);
+                } else {
+                    compared.push(image);
+                }
+            },
+            Err(img_err) => errors.push(img_err),
+        }
+    }
+
+    sort_by_path(&mut images);
+    sort_by_path(&mut compared);
+    sort_errors_by_path(&mut errors);
+
+    let hashing_secs = hashing_start.elapsed().as_secs_f64();
+    let grouping_start = Instant::now();
+
+    let uniques = match_against(settings, images, compared);
+
+    let phases = Phases {
+        search_secs: None,
+        hashing_secs: Some(hashing_secs),
+        grouping_secs: Some(grouping_start.elapsed().as_secs_f64()),
+    };
+
+    Results {
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors,
+        phases,
+    }
+}
+
+/// Match every image in `compared` with the closest image in `images` that it's similar to.
+/// Images in `compared` without a match are returned as groups of their own, after the others.
+fn match_against(settings: &ProgramSettings, images: Vec<Image>, compared: Vec<Image>) -> Vec<UniqueImage> {
+    let mut uniques: Vec<UniqueImage> = images.into_iter().map(UniqueImage::from_image).collect();
+    let mut index = BkTree::new();
+
+    for (idx, unique) in uniques.iter().enumerate() {
+        index.insert_image(&unique.img, idx);
+    }
+
+    let mut unmatched = Vec::new();
+
+    for image in compared {
+        let max_dist = max_dist(settings, &image);
+
+        // If more than one image is similar enough, the closest gets the match
+        let closest = index.find_image(&image, max_dist).into_iter()
+            .filter(|&idx| settings.may_match(&uniques[idx].img, &image))
+            .map(|idx| (idx, uniques[idx].img.dist_ratio(&image)))
+            .filter(|&(_, dist_ratio)| dist_ratio < settings.threshold)
+            .fold(None, |closest: Option<(usize, f32)>, (idx, dist_ratio)| match closest {
+                Some((_, closest_ratio)) if closest_ratio <= dist_ratio => closest,
+                _ => Some((idx, dist_ratio)),
+            });
+
+        match closest {
+            Some((idx, _)) => uniques[idx].add_similar(image, settings.near_threshold),
+            None => unmatched.push(UniqueImage::from_image(image)),
+        }
+    }
+
+    uniques.extend(unmatched);
+    pair_thumbnails(&mut uniques, settings);
+
+    uniques
+}
+
+/// Find the images in `db` that are similar to images in `other`, without comparing
+/// images within either database, or touching the images themselves: the databases
+/// can come from different machines. Only the groups with a match are kept.
+pub fn cross(settings: &ProgramSettings, db: &HashDb, other: &HashDb) -> Results {
+    let start_time = Local::now();
+    let hash_settings = settings.hash_settings();
+
+    let (mut images, mut others) = (db.images(&hash_settings), other.images(&hash_settings));
+    let total = images.len() + others.len();
+
+    sort_by_path(&mut images);
+    sort_by_path(&mut others);
+
+    let grouping_start = Instant::now();
+
+    let mut uniques = match_against(settings, images, others);
+    uniques.retain(|unique| !unique.similars.is_empty());
+
+    let phases = Phases {
+        search_secs: None,
+        hashing_secs: None,
+        grouping_secs: Some(grouping_start.elapsed().as_secs_f64()),
+    };
+
+    Results {
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors: Vec::new(),
+        phases,
+    }
+}
+
+/// Start processing `paths` and yield each image as soon as a worker thread is done with it.
+pub fn results_iter(settings: &ProgramSettings, paths: Vec<PathBuf>, cancel: CancelToken) -> ResultsIter {
+    ResultsIter { rx: spawn_threads(settings, paths, cancel) }
+}
+
+/// Hash every image in `paths` without comparing them to each other.
+pub fn hash_all(settings: &ProgramSettings, paths: Vec<PathBuf>) -> (Vec<Image>, Vec<ProcessingError>) {
+    let mut images = Vec::new();
+    let mut errors = Vec::new();
+
+    for img_result in results_iter(settings, paths, CancelToken::new()) {
+        match img_result {
+            Ok(image) => images.push(image),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    sort_by_path(&mut images);
+    sort_errors_by_path(&mut errors);
+
+    (images, errors)
+}
+
+/// Images arrive in whatever order the threads finish them, so they're sorted
+/// before they're grouped to get the same results every run.
+pub fn sort_by_path(images: &mut [Image]) {
+    images.sort_by(|left, right| left.path.cmp(&right.path));
+}
+
+pub fn sort_errors_by_path(errors: &mut [ProcessingError]) {
+    errors.sort_by(|left, right| left.path().cmp(right.path()));
+}
+
+pub struct ResultsIter {
+    rx: Receiver<TimedImageResult>,
+}
+
+impl Iterator for ResultsIter {
+    type Item = ImageResult;
+
+    fn next(&mut self) -> Option<ImageResult> {
+        self.rx.recv().ok()
+            .map(|result| result.map(|(image, _, _)| image))
+    }
+}
+
+/// Each file is only hashed once, with any other paths to it as its links.
+pub fn spawn_threads(settings: &ProgramSettings, paths: Vec<PathBuf>, cancel: CancelToken) 
     -> Receiver<TimedImageResult> {
-    
-    let work = ParQueue::from_vec(paths).into_iter();
+    let (paths, links) = links::find_links(paths);
 
-    let (tx, rx) = channel();
+    let rx = if settings.pair_raw {
+        let (paths, sidecars) = raw::pair_sidecars(paths);
+        let rx = spawn_unpaired(settings, paths, cancel);
+        relay_sidecars(rx, sidecars, settings.queue_depth)
+    } else {
+        spawn_unpaired(settings, paths, cancel)
+    };
+
+    if links.is_empty() {
+        rx
+    } else {
+        relay_links(rx, links, settings.queue_depth)
+    }
+}
+
+fn spawn_unpaired(settings: &ProgramSettings, paths: Vec<PathBuf>, cancel: CancelToken)
+    -> Receiver<TimedImageResult> {
+    if settings.exact_first {
+        let (paths, copies) = exact::find_exact_copies(paths);
+
+        info!("Found {} files with exact copies, {} files to hash", copies.len(), paths.len());
+        let rx = spawn_hashing(settings, paths, cancel);
+        relay_exact_copies(rx, copies, settings.queue_depth)
+    } else {
+        spawn_hashing(settings, paths, cancel)
+    }
+}
+
+/// Attach the RAW files paired with each image.
+fn relay_sidecars(rx: Receiver<TimedImageResult>, mut sidecars: Sidecars, queue_depth: usize) 
+    -> Receiver<TimedImageResult> {
+    let (tx, relay_rx) = sync_channel(queue_depth);
+
+    thread::spawn(move || {
+        for mut img_result in rx.iter() {
+            if let Ok((ref mut image, _, _)) = img_result {
+                image.sidecars = sidecars.remove(&image.path).unwrap_or_default();
+            }
+
+            if tx.send(img_result).is_err() { return; }
+        }
+    });
+
+    relay_rx
+}
+
+/// Attach the other paths to each file.
+fn relay_links(rx: Receiver<TimedImageResult>, mut links: Links, queue_depth: usize)
+    -> Receiver<TimedImageResult> {
+    let (tx, relay_rx) = sync_channel(queue_depth);
+
+    thread::spawn(move || {
+        for mut img_result in rx.iter() {
+            if let Ok((ref mut image, _, _)) = img_result {
+                image.links = links.remove(&image.path).unwrap_or_default();
+            }
+
+            if tx.send(img_result).is_err() { return; }
+        }
+    });
+
+    relay_rx
+}
+
+/// After each image, send its exact copies with the same hash.
+fn relay_exact_copies(rx: Receiver<TimedImageResult>, mut copies: ExactCopies, queue_depth: usize)
+    -> Receiver<TimedImageResult> {
+    let (tx, relay_rx) = sync_channel(queue_depth);
+
+    thread::spawn(move || {
+        for mut img_result in rx.iter() {
+            let copy_results: Vec<TimedImageResult> = match img_result {
+                Ok((ref mut image, _, _)) => {
+                    let image_copies = copies.remove(&image.path).unwrap_or_default();
+
+                    if !image_copies.is_empty() {
+                        image.same_contents = Some(image.path.clone());
+                    }
+
+                    image_copies.into_iter()
+                        .map(|copy| Ok((Image { path: copy, timing: None, .. image.clone() }, 0, 0)))
+                        .collect()
+                },
+                Err(ref err) => 
+                    copies.remove(err.path()).unwrap_or_default().into_iter()
+                        .map(|copy| Err(ProcessingError::Misc(copy, 
+                            format!("Same contents as {}, which could not be processed", 
+                                    err.path().display()))))
+                        .collect(),
+            };
+
+            if tx.send(img_result).is_err() { return; }
+
+            for copy_result in copy_results {
+                if tx.send(copy_result).is_err() { return; }
+            }
+        }
+    });
+
+    relay_rx
+}
+
+fn spawn_hashing(settings: &ProgramSettings, paths: Vec<PathBuf>, cancel: CancelToken)
+    -> Receiver<TimedImageResult> {
+    match storage::open(settings) {
+        Ok(Some(storage)) => spawn_cached(settings, storage, paths, cancel),
+        Ok(None) => spawn_workers(settings, paths, cancel),
+        Err(err) => {
+            error!("Failed to open hash database, hashing every image: {}", err);
+            spawn_workers(settings, paths, cancel)
+        },
+    }
+}
+
+/// Send cached images first, then hash the rest and add them to the cache.
+/// The cache is saved every `settings.checkpoint` seconds, and before the channel closes.
+fn spawn_cached(settings: &ProgramSettings, mut storage: Box<dyn Storage>, paths: Vec<PathBuf>, 
+                cancel: CancelToken)
+    -> Receiver<TimedImageResult> {
+    let hash_settings = settings.hash_settings();
+    let checkpoint = Duration::from_secs(settings.checkpoint);
+
+    let mut cache = storage.load_hashes().unwrap_or_else(|err| {
+        warn!("Failed to load hash database {}, starting over: {}", storage.name(), err);
+        HashDb::new()
+    });
+
+    let (cached, paths) = cache.partition(paths, &hash_settings);
+
+    info!("{} images are unchanged since they were cached, {} to hash", cached.len(), paths.len());
+
+    let worker_rx = spawn_workers(settings, paths, cancel);
+
+    let (tx, rx) = sync_channel(settings.queue_depth);
+
+    thread::spawn(move || {
+        let mut connected = cached.into_iter()
+            .all(|image| tx.send(Ok((image, 0, 0))).is_ok());
+
+        let mut last_save = Instant::now();
+
+        for img_result in worker_rx.iter() {
+            if !connected { break; }
+
+            if let Ok((ref image, _, _)) = img_result {
+                cache.insert(image, &hash_settings);
+            }
+
+            connected = tx.send(img_result).is_ok();
+
+            // So an interrupted run can pick up where it left off
+            if !checkpoint.is_zero() && last_save.elapsed() >= checkpoint {
+                debug!("Saving checkpoint to {}", storage.name());
+                save_cache(&mut *storage, &cache);
+                last_save = Instant::now();
+            }
+        }
+
+        save_cache(&mut *storage, &cache);
+    });
+
+    rx
+}
+
+fn save_cache(storage: &mut dyn Storage, cache: &HashDb) {
+    if let Err(err) = storage.save_hashes(cache) {
+        error!("Failed to save hash database: {}", err);
+    }
+}
+
+fn spawn_workers(settings: &ProgramSettings, paths: Vec<PathBuf>, cancel: CancelToken) 
+    -> Receiver<TimedImageResult> {
+    spawn_worker_pool(settings, ParQueue::from_vec(largest_first(paths)).into_iter(), cancel)
+}
+
+/// Start `settings.threads` workers that take paths from `work` until it runs out.
+fn spawn_worker_pool<I>(settings: &ProgramSettings, work: ParQueueIter<I>, cancel: CancelToken)
+    -> Receiver<TimedImageResult> 
+where I: Iterator<Item = PathBuf> + Send + 'static {
+    spawn_pool(settings, work, cancel, load_and_hash_with_timeout)
+}
+
+/// Start `settings.threads` workers that pass each item from `work` to `hash` until it runs out.
+fn spawn_pool<I, H>(settings: &ProgramSettings, work: ParQueueIter<I>, cancel: CancelToken, hash: H)
+    -> Receiver<TimedImageResult> 
+where I: Iterator + Send + 'static, H: Fn(&HashSettings, I::Item) -> TimedImageResult + Copy + Send + 'static {
+    // Workers block once this many results are waiting,
+    // so decoded images don't pile up if the receiver falls behind
+    let (tx, rx) = sync_channel(settings.queue_depth);
 
     let hash_settings = settings.hash_settings();
+    let timings = settings.timings;
+    let spawner = settings.spawner.clone();
+    // Only threads of our own: an application's executor is left as it is
+    let lower_priority = settings.throttle.is_some() && spawner.is_none();
 
-    for _ in range(0, settings.threads) {
+    for _ in 0..settings.threads {
         let task_tx = tx.clone();
         let mut task_work = work.clone();
 
-        Thread::spawn(move || {            
-            for path in task_work {
-                let img_result = load_and_hash_image(&hash_settings, path);
-                                                
-                if task_tx.send_opt(img_result).is_err() { break; }
+        let hash_settings = hash_settings.clone();
+        let cancel = cancel.clone();
+
+        spawn(&spawner, move || {
+            if lower_priority { throttle::lower_priority(); }
+
+            loop {
+                // Before taking the next item, so other workers can have it if this one is canceled
+                cancel.wait_while_paused();
+
+                if cancel.is_canceled() { break; }
+
+                let Some(item) = task_work.next() else { break };
+
+                let mut img_result = hash(&hash_settings, item);
+
+                match img_result {
+                    Ok((ref mut image, load_time, hash_time)) => {
+                        debug!("{}: loaded in {} ms, hashed in {} ms",
+                            image.path.display(), load_time / 1_000_000, hash_time / 1_000_000);
+
+                        if timings {
+                            image.timing = Some(ImageTiming { load: load_time, hash: hash_time });
+                        }
+                    },
+                    Err(ref err) => warn!("{}", err),
+                }
+
+                if task_tx.send(img_result).is_err() { break; }
             }
-        }).detach();
+        });
     }
 
     rx
 }
 
-type ImageLoadResult = Result<DynamicImage, ImageError>;
+/// On the `spawner` if there is one, or a new thread.
+fn spawn<F: FnOnce() + Send + 'static>(spawner: &Option<Spawner>, task: F) {
+    match *spawner {
+        Some(ref spawner) => spawner(Box::new(task)),
+        None => { thread::spawn(task); },
+    }
+}
+
+/// Threads take the next path from the queue when they're done with one,
+/// so starting with the slowest files means no thread is left with a huge one at the end.
+/// The file size is a rough estimate of how long an image takes.
+fn largest_first(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut sized: Vec<(u64, PathBuf)> = paths.into_iter()
+        .map(|path| (fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0), path))
+        .collect();
+
+    sized.sort_by_key(|&(size, _)| Reverse(size));
+
+    sized.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Run `f`, catching a panic and returning its message instead.
+fn try_fn<T, F: FnOnce() -> T>(f: F) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(panic_message)
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("Unknown panic", |msg| *msg).to_string(),
+    }
+}
+
+/// Nanoseconds since `start`.
+fn nanos_since(start: Instant) -> u64 {
+    start.elapsed().as_nanos() as u64
+}
+
+/// Load and hash a single image on the current thread.
+pub fn hash_image(settings: &ProgramSettings, path: PathBuf) -> ImageResult {
+    load_and_hash_image(&settings.hash_settings(), path).map(|(image, _, _)| image)
+}
+
+/// Load and hash on a separate thread, and give up on it after `settings.timeout` seconds.
+///
+/// The thread can't be stopped, so if the decoder hangs it keeps running in the background
+/// until it finishes or the program exits.
+fn load_and_hash_with_timeout(settings: &HashSettings, path: PathBuf) -> TimedImageResult {
+    if settings.timeout == 0 { return load_and_hash_image(settings, path); }
 
+    let (tx, rx) = channel();
 
-fn try_fn<'a, T>(f: || -> T) -> Result<T, Box<&'a str>> {
-    let mut maybe: Option<T> = None;
+    let thread_settings = settings.clone();
+    let thread_path = path.clone();
 
-    let err = unsafe { try(|| maybe = Some(f())) };
+    thread::spawn(move || {
+        if thread_settings.throttle.is_some() { throttle::lower_priority(); }
 
-    match maybe {
-        Some(val) => Ok(val),
-        None => Err(err.unwrap_err().downcast::<&str>().unwrap()),
-    }        
+        let _ = tx.send(load_and_hash_image(&thread_settings, thread_path));
+    });
+
+    match rx.recv_timeout(Duration::from_secs(settings.timeout)) {
+        Ok(img_result) => img_result,
+        Err(RecvTimeoutError::Timeout) => Err(ProcessingError::TimedOut(path, settings.timeout)),
+        Err(RecvTimeoutError::Disconnected) => 
+            Err(ProcessingError::Misc(path, "Hashing thread failed".to_string())),
+    }
 }
 
-fn load_and_hash_image(settings: &HashSettings, path: Path) -> TimedImageResult {
-    let start_load = precise_time_ns();    
-    let image = try_fn(|| image::open(&path));
-    let load_time =  precise_time_ns() - start_load;
+fn load_and_hash_image(settings: &HashSettings, path: PathBuf) -> TimedImageResult {
+    let in_archive = read_from_archive(&path)?;
+
+    // Everything that's read from the file is then read from the mapping
+    let mapped = if settings.mmap && in_archive.is_none() {
+        Some(decoders::map(&path).map_err(|err| ProcessingError::Io(path.clone(), err))?)
+    } else {
+        None
+    };
+    let data = in_archive.as_deref().or(mapped.as_deref());
+
+    check_limits(settings, &path, data)?;
+
+    if let Some(ref throttle) = settings.throttle {
+        throttle.wait_to_read(data.map_or_else(|| fs::metadata(&path).map_or(0, |meta| meta.len()), |data| data.len() as u64));
+    }
+
+    if let Some(result) = try_hash_video(settings, &path) {
+        return result;
+    }
+
+    let start_load = Instant::now();    
+    let image = try_fn(|| 
+        decoders::open(&path, data, &settings.decoders, settings.frames, settings.sniff)
+            .map(|image| match data {
+                Some(data) => orient(exif::orientation_of(data), image),
+                None => apply_orientation(&path, image),
+            })
+    );
+    let load_time = nanos_since(start_load);
  
     match image {
         Ok(Ok(image)) => {
-            let start_hash = precise_time_ns();
-            let hash = try!(try_hash_image(path, &image, settings.hash_size, settings.fast));
-            let hash_time = precise_time_ns() - start_hash;
+            // The header of some formats can't be read without decoding
+            check_pixels(settings, &path, image.width(), image.height())?;
+            check_blank(settings, &path, &image)?;
+
+            let start_hash = Instant::now();
+            let mut hash = try_hash_image(path, &image, settings)?;
+            let hash_time = nanos_since(start_hash);
+
+            if let Some(ref bytes) = in_archive {
+                hash.file_size = bytes.len() as u64;
+            }
+
+            // The extension can be wrong, or missing
+            if settings.sniff {
+                let sniffed = match data {
+                    Some(data) => image::guess_format(data).ok(),
+                    None => img::sniff_format(&hash.path),
+                };
+
+                hash.format = sniffed.or(hash.format);
+            }
+
+            if let Some(ref dir) = settings.thumbnails {
+                // Without a thumbnail, the report falls back to the original image
+                hash.thumbnail = thumbnails::existing(dir, &hash.path)
+                    .or_else(|| thumbnails::generate(dir, &hash.path, &image).ok());
+            }
 
             Ok((hash, load_time, hash_time))
         },
-        Ok(Err(img_err)) => Err(ProcessingError::Decoding(path, img_err)),
-        Err(cause) => Err(ProcessingError::Misc(path, cause.to_string())),
+        Ok(Err(img_err)) => Err(ProcessingError::from_image_error(path, img_err)),
+        Err(cause) => Err(ProcessingError::Misc(path, cause)),
     }
 }
 
-fn try_hash_image(path: Path, img: &DynamicImage, hash_size: u32, fast: bool) -> ImageResult {
-    let (width, height) = img.dimensions(); 
-    
-    match try_fn(|| ImageHash::hash(img, hash_size, fast)) {
-        Ok(hash) => Ok(Image::new(path, hash, width, height)),
-        Err(cause) => Err(ProcessingError::Misc(path, cause.to_string())),    
+/// With `--archives`, the contents of the image if it's inside an archive.
+#[cfg(feature = "archives")]
+fn read_from_archive(path: &Path) -> Result<Option<Vec<u8>>, ProcessingError> {
+    // A directory could be named like an archive
+    match archives::split(path) {
+        Some((archive, member)) if !path.exists() => archives::read_member(&archive, &member)
+            .map(Some)
+            .map_err(|err| ProcessingError::Io(path.to_path_buf(), err)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(not(feature = "archives"))]
+fn read_from_archive(_: &Path) -> Result<Option<Vec<u8>>, ProcessingError> {
+    Ok(None)
+}
+
+/// Skip the image before decoding it if it's larger than the limits.
+/// The header is read from `data` if the file is already in memory.
+fn check_limits(settings: &HashSettings, path: &Path, data: Option<&[u8]>) -> Result<(), ProcessingError> {
+    if settings.max_file_size > 0 {
+        let size = match data {
+            Some(data) => data.len() as u64,
+            None => fs::metadata(path).map_err(|err| ProcessingError::Io(path.to_path_buf(), err))?.len(),
+        };
+
+        if size > settings.max_file_size {
+            return Err(ProcessingError::TooLarge(path.to_path_buf(),
+                format!("{} bytes, the limit is {}", size, settings.max_file_size)));
+        }
+    }
+
+    if settings.max_pixels > 0 {
+        let dimensions = match data {
+            Some(data) => header::dimensions(data),
+            None => header::read_dimensions(path).ok().flatten(),
+        };
+
+        if let Some((width, height)) = dimensions {
+            check_pixels(settings, path, width, height)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_pixels(settings: &HashSettings, path: &Path, width: u32, height: u32) -> Result<(), ProcessingError> {
+    let pixels = width as u64 * height as u64;
+
+    if settings.max_pixels > 0 && pixels > settings.max_pixels {
+        Err(ProcessingError::TooLarge(path.to_path_buf(),
+            format!("{}x{} pixels, the limit is {}", width, height, settings.max_pixels)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Skip the image if it's blank, so blank images aren't all grouped together.
+fn check_blank(settings: &HashSettings, path: &Path, image: &DynamicImage) -> Result<(), ProcessingError> {
+    if settings.blank_threshold > 0f32 && classify::is_blank(image, settings.blank_threshold) {
+        Err(ProcessingError::Blank(path.to_path_buf()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Hash the file as a video if it is one. 
+/// Extracting the frames is counted as loading time.
+#[cfg(feature = "video")]
+fn try_hash_video(settings: &HashSettings, path: &Path) -> Option<TimedImageResult> {
+    if !video::is_video(path) { return None; }
+
+    let start_load = Instant::now();
+    let image = video::hash_video(path, settings);
+    let load_time = nanos_since(start_load);
+
+    Some(match image {
+        Ok(image) => Ok((image, load_time, 0)),
+        Err(err) => Err(ProcessingError::Misc(path.to_path_buf(), err)),
+    })
+}
+
+#[cfg(not(feature = "video"))]
+fn try_hash_video(_: &HashSettings, _: &Path) -> Option<TimedImageResult> {
+    None
+}
+
+/// Rotate or mirror the image so it's upright, according to its EXIF data.
+fn apply_orientation(path: &Path, img: DynamicImage) -> DynamicImage {
+    orient(exif::read_orientation(path).unwrap_or(Orientation::Normal), img)
+}
+
+/// Rotate or mirror the image so it's upright, given its EXIF orientation.
+pub fn orient(orientation: Orientation, img: DynamicImage) -> DynamicImage {
+    match orientation {
+        Orientation::Normal => img,
+        Orientation::FlipHorizontal => img.fliph(),
+        Orientation::Rotate180 => img.rotate180(),
+        Orientation::FlipVertical => img.flipv(),
+        Orientation::Transpose => img.rotate90().fliph(),
+        Orientation::Rotate90 => img.rotate90(),
+        Orientation::Transverse => img.rotate270().fliph(),
+        Orientation::Rotate270 => img.rotate270(),
+    }
+}
+
+fn try_hash_image(path: PathBuf, img: &DynamicImage, settings: &HashSettings) -> ImageResult {
+    match try_fn(|| Image::hash_from_dynamic_image(img, settings)) {
+        Ok(mut image) => {
+            image.file_size = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+            image.format = img::guess_format(&path);
+            image.path = path;
+            Ok(image)
+        },
+        Err(cause) => Err(ProcessingError::Misc(path, cause)),    
     }      
 }
 
-fn receive_images(rx: Receiver<TimedImageResult>, settings: &ProgramSettings) 
-    -> (Total, Vec<UniqueImage>, Vec<ProcessingError>){
-    let mut unique_images = Vec::new();
+/// Collect every result, sorted by path.
+fn receive_images<F>(results: ResultsIter, queued: &dyn Fn() -> Total, mut on_progress: F) 
+    -> (Total, Vec<Image>, Vec<ProcessingError>)
+where F: FnMut(Progress) {
+    let mut images = Vec::new();
     let mut errors = Vec::new();
-    let mut total = 0u;
+    let mut total = 0;
    
-    for img_result in rx.iter() {
-        match img_result {
-            Ok((image, _, _)) => {
-                manage_images(&mut unique_images, image, settings);
-                total += 1;
+    for img_result in results {
+        let path = match img_result {
+            Ok(image) => {
+                let path = image.path.clone();
+                // Paired RAW files and links were queued too
+                total += 1 + image.sidecars.len() + image.links.len();
+                images.push(image);
+                path
             },
-            Err(img_err) => errors.push(img_err),
-        }                
+            Err(img_err) => {
+                let path = img_err.path().to_path_buf();
+                errors.push(img_err);
+                path
+            },
+        };
+
+        on_progress(Progress {
+            hashed: total,
+            errors: errors.len(),
+            total: queued(),
+            path,
+        });
     }
 
-    (total, unique_images, errors)
+    info!("Processed {} images, {} errors", total, errors.len());
+
+    // Grouped only once every image is in, so the order they finished in doesn't matter
+    sort_by_path(&mut images);
+    sort_errors_by_path(&mut errors);
+
+    (total, images, errors)
 }
 
-pub fn manage_images(images: &mut Vec<UniqueImage>, 
-                 image: Image, settings: &ProgramSettings) { 
-    let parent_idx = images
-        .iter()
-        .enumerate()
-        .find(|&(_, parent)| parent.is_similar(&image, settings.threshold))
-        .map(|(idx, _)| idx);
+/// Group `images`, sorted by path, with `--cluster` if it's set, and choose their originals.
+pub fn group_images(images: Vec<Image>, settings: &ProgramSettings) -> Vec<UniqueImage> {
+    info!("Grouping similar images...");
 
-    match parent_idx {
-        Some(index) => images[index].add_similar(image),
-        None => images.push(UniqueImage::from_image(image)),
+    let mut uniques = if settings.cluster {
+        cluster::cluster(images, settings)
+    } else {
+        find_uniques_indexed(images, settings)
+    };
+
+    choose_originals(&mut uniques, settings);
+    pair_thumbnails(&mut uniques, settings);
+
+    uniques
+}
+
+/// Collate `images` into groups, each image joining the first earlier group it's similar to.
+/// Similar images are looked up through a BK-tree instead of comparing against every unique image.
+pub fn find_uniques_indexed(images: Vec<Image>, settings: &ProgramSettings) -> Vec<UniqueImage> {
+    let mut uniques = Vec::new();
+    let mut index = BkTree::new();
+
+    for image in images {
+        manage_images_indexed(&mut uniques, &mut index, image, settings);
     }
+
+    uniques
 }
 
-pub fn find_images(settings: &ProgramSettings) -> Vec<Path> {
-    use std::io::fs;
+/// `index` must contain the hash of every image in `images`, tagged with its index.
+pub fn manage_images_indexed(images: &mut Vec<UniqueImage>, index: &mut BkTree,
+                             image: Image, settings: &ProgramSettings) {
+    let max_dist = max_dist(settings, &image);
 
-    let exts: Vec<&str> = settings.exts.iter().map(|string| string.as_slice()).collect();
+    // Take the lowest index so the result is the same as the linear search
+    let parent_idx = index.find_image(&image, max_dist).into_iter()
+        .filter(|&idx| images[idx].is_similar(&image, settings.threshold)
+                && settings.may_match(&images[idx].img, &image))
+        .min();
 
-    if settings.recurse {
-        fs::walk_dir(&settings.dir)
-            .unwrap()
-            .filter(|file| check_ext(file, &*exts))
-            .collect()   
-    } else {
-        fs::readdir(&settings.dir)
-            .unwrap()
-            .into_iter()
-            .filter(|file| !file.is_dir() && check_ext(file, &*exts))
-            .collect()
-    } 
+    match parent_idx {
+        Some(idx) => images[idx].add_similar(image, settings.near_threshold),
+        None => {
+            let idx = images.len();
+
+            // New images are only looked up by their main hash and crops,
+            // so they have to be able to find each rotation of this one
+            index.insert_image(&image, idx);
+            images.push(UniqueImage::from_image(image));
+        },
+    }
 }
 
-fn check_ext(file: &Path, exts: &[&str]) -> bool {   
-    match file.extension_str() {
-        Some(ext) => exts.iter().any(|&a| a.eq_ignore_ascii_case(ext)),
-        None => false
+/// Apply `--original`, and `--prefer-keep` and `--prefer-remove`, to every group.
+pub fn choose_originals(uniques: &mut [UniqueImage], settings: &ProgramSettings) {
+    if settings.original == RepresentativePolicy::First && settings.prefer.is_empty() { return; }
+
+    for unique in uniques.iter_mut() {
+        unique.choose_original(&settings.original, &settings.prefer, settings.near_threshold);
+    }
+}
+
+/// Find the images in every group that are scaled-down copies of another image in it.
+pub fn pair_thumbnails(uniques: &mut [UniqueImage], settings: &ProgramSettings) {
+    for unique in uniques.iter_mut().filter(|unique| !unique.similars.is_empty()) {
+        unique.pair_thumbnails(settings.near_threshold);
     }
 }
 
+/// The largest number of differing bits in the main hash that could still be under the threshold.
+pub fn max_dist(settings: &ProgramSettings, image: &Image) -> usize {
+    let bits = image.hash.bits() as f32;
+
+    // If every combined hash matched exactly, the main hash could differ by this much.
+    // Videos have combined hashes of their own, so use the image's rather than `--combine`.
+    let weights = image.combined.iter().fold(1f32, |total, &(_, weight)| total + weight);
+
+    (settings.threshold * weights * bits).ceil() as usize
+}
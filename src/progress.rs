@@ -0,0 +1,50 @@
+use crate::config::{OutputFormat, ProgramSettings};
+use crate::processing::Progress;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
+
+use log::LevelFilter;
+
+use std::fmt::Write;
+use std::io::{self, IsTerminal};
+
+const TEMPLATE: &str = "{wide_bar} {pos}/{len} images, {rate}, {msg}, ETA {eta}";
+
+/// Only for someone watching the terminal: not when the output is piped,
+/// JSON is being written, or status messages are turned off with `--quiet`.
+pub fn enabled(settings: &ProgramSettings) -> bool {
+    io::stdout().is_terminal()
+        && settings.format != OutputFormat::Json
+        && settings.log_level >= LevelFilter::Info
+}
+
+/// Shows how many images have been processed, how fast, and how long the rest will take.
+pub struct ProcessingBar {
+    bar: ProgressBar,
+}
+
+impl ProcessingBar {
+
+    /// Status messages are logged above the bar through `multi`.
+    pub fn new(multi: &MultiProgress, total: usize) -> ProcessingBar {
+        let style = ProgressStyle::with_template(TEMPLATE).unwrap()
+            .with_key("rate", |state: &ProgressState, out: &mut dyn Write| {
+                let _ = write!(out, "{:.1} img/s", state.per_sec());
+            });
+
+        let bar = multi.add(ProgressBar::new(total as u64).with_style(style));
+        bar.set_message("0 errors");
+
+        ProcessingBar { bar }
+    }
+
+    pub fn update(&self, progress: &Progress) {
+        self.bar.set_length(progress.total as u64);
+        self.bar.set_position((progress.hashed + progress.errors) as u64);
+        self.bar.set_message(format!("{} errors", progress.errors));
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
@@ -0,0 +1,200 @@
+//! `--action=quarantine`: move images into a directory instead of deleting them, under the same
+//! paths they had relative to the directory searched, and `img-dup restore` to put them back.
+//!
+//! Every move is appended to a manifest in the quarantine directory, one JSON object per line,
+//! so the quarantine directory can itself be moved without breaking it.
+
+use log::{error, info, warn};
+
+use serde::{Deserialize, Serialize};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{self, Component, Path, PathBuf};
+
+/// The name of the manifest in the quarantine directory.
+pub const MANIFEST: &str = "img_dup_manifest.jsonl";
+
+/// A directory images are moved into, under their paths relative to `root`.
+#[derive(Clone, Debug)]
+pub struct Quarantine {
+    dir: PathBuf,
+    root: PathBuf,
+}
+
+/// A line of the manifest.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// Where the image was, in full
+    original: PathBuf,
+    /// Where it is now, relative to the quarantine directory
+    quarantined: PathBuf,
+}
+
+impl Quarantine {
+
+    pub fn new(dir: PathBuf, root: PathBuf) -> Quarantine {
+        Quarantine { dir, root }
+    }
+
+    /// Where `path` goes in the quarantine directory: its path relative to the root, or for
+    /// images outside it, its full path without the root or drive.
+    pub fn destination(&self, path: &Path) -> PathBuf {
+        let path = path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+        let relative: PathBuf = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => path.components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .collect(),
+        };
+
+        self.dir.join(relative)
+    }
+
+    /// Move `path` into the quarantine directory and add it to the manifest.
+    /// Returns where it was moved to, which is numbered if something is there already.
+    pub fn move_in(&self, path: &Path) -> io::Result<PathBuf> {
+        let original = path::absolute(path)?;
+        let dest = unused_path(&self.destination(&original));
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        move_file(&original, &dest)?;
+
+        let entry = Entry {
+            original,
+            quarantined: dest.strip_prefix(&self.dir).unwrap_or(&dest).to_path_buf(),
+        };
+
+        let mut manifest = OpenOptions::new().create(true).append(true).open(self.dir.join(MANIFEST))?;
+        writeln!(manifest, "{}", serde_json::to_string(&entry)?)?;
+
+        Ok(dest)
+    }
+}
+
+/// Move every image listed in the manifest of the quarantine directory `dir` back to where it
+/// was, unless something is there now, and remove it from the manifest. With `dry_run`, only
+/// log what would be done. Returns how many couldn't be moved back.
+pub fn restore(dir: &Path, dry_run: bool) -> io::Result<u32> {
+    let manifest_path = dir.join(MANIFEST);
+
+    let mut entries = Vec::new();
+
+    for line in BufReader::new(File::open(&manifest_path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() { continue; }
+
+        entries.push(serde_json::from_str::<Entry>(&line)?);
+    }
+
+    let mut left = Vec::new();
+    let (mut restored, mut failed) = (0u32, 0u32);
+
+    for entry in entries {
+        let quarantined = dir.join(&entry.quarantined);
+
+        if !quarantined.exists() {
+            warn!("{} is no longer in the quarantine directory, forgetting it", quarantined.display());
+            continue;
+        }
+
+        if entry.original.exists() {
+            error!("Not restoring {}: {} exists", quarantined.display(), entry.original.display());
+            failed += 1;
+            left.push(entry);
+            continue;
+        }
+
+        if dry_run {
+            info!("Would restore: {} (from {})", entry.original.display(), quarantined.display());
+            restored += 1;
+            left.push(entry);
+            continue;
+        }
+
+        let res = match entry.original.parent() {
+            Some(parent) => fs::create_dir_all(parent).and_then(|_| move_file(&quarantined, &entry.original)),
+            None => move_file(&quarantined, &entry.original),
+        };
+
+        match res {
+            Ok(()) => {
+                info!("Restored: {} (from {})", entry.original.display(), quarantined.display());
+                remove_empty_dirs(dir, &quarantined);
+                restored += 1;
+            },
+            Err(err) => {
+                error!("Failed to restore {}: {}", entry.original.display(), err);
+                failed += 1;
+                left.push(entry);
+            },
+        }
+    }
+
+    if dry_run {
+        info!("Dry run, {} images would be restored.", restored);
+        return Ok(failed);
+    }
+
+    if left.is_empty() {
+        fs::remove_file(&manifest_path)?;
+    } else {
+        let mut out = BufWriter::new(File::create(&manifest_path)?);
+
+        for entry in &left {
+            writeln!(out, "{}", serde_json::to_string(entry)?)?;
+        }
+
+        out.flush()?;
+    }
+
+    info!("{} images restored, {} failed.", restored, failed);
+
+    Ok(failed)
+}
+
+/// Rename `from` to `to`, or copy it and remove `from` if they're on different filesystems.
+pub(crate) fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            let modified = from.metadata()?.modified()?;
+
+            fs::copy(from, to)?;
+            File::options().write(true).open(to)?.set_modified(modified)?;
+
+            fs::remove_file(from).inspect_err(|_| {
+                let _ = fs::remove_file(to);
+            })
+        },
+        res => res,
+    }
+}
+
+/// Remove the directories `path` was in that are empty now, up to `dir` itself.
+fn remove_empty_dirs(dir: &Path, path: &Path) {
+    for parent in path.ancestors().skip(1).take_while(|&parent| parent != dir) {
+        if fs::remove_dir(parent).is_err() { return; }
+    }
+}
+
+/// `path`, or if it's taken, `path` with a number added to the name.
+fn unused_path(path: &Path) -> PathBuf {
+    let mut unused = path.to_path_buf();
+    let mut count = 1u32;
+
+    while unused.exists() {
+        count += 1;
+
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        unused = match path.extension() {
+            Some(ext) => path.with_file_name(format!("{} {}.{}", stem, count, ext.to_string_lossy())),
+            None => path.with_file_name(format!("{} {}", stem, count)),
+        };
+    }
+
+    unused
+}
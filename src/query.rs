@@ -0,0 +1,60 @@
+use crate::config::ProgramSettings;
+use crate::img::{Image, SimilarImage, UniqueImage};
+use crate::processing::{self, Phases, ProcessingError, Results};
+
+use chrono::Local;
+
+use std::path::PathBuf;
+
+/// The images in `haystack` that are similar to `needle`, closest first.
+pub fn find_matches(needle: &Image, haystack: &[Image], settings: &ProgramSettings) -> Vec<SimilarImage> {
+    let mut matches: Vec<SimilarImage> = haystack.iter()
+        .filter(|image| image.path != needle.path && settings.may_match(image, needle))
+        // Measured from the haystack's side, so the rotations of `needle` are used with `--rotations`, 
+        // even if the haystack came from a hash database, which doesn't store them
+        .map(|image| SimilarImage {
+            reason: image.match_reason(needle),
+            ..SimilarImage::from_image(image.clone(), image.dist_ratio(needle), image.dist(needle),
+                                       settings.near_threshold)
+        })
+        .filter(|similar| similar.dist_ratio < settings.threshold)
+        .collect();
+
+    matches.sort();
+
+    matches
+}
+
+/// Hash the image at `needle` and find its matches in `haystack`.
+///
+/// The results have a single unique image, `needle`, with its matches as its similars.
+/// `errors` are from hashing the haystack, and are included in the results.
+pub fn query(settings: &ProgramSettings, needle: PathBuf, mut haystack: Vec<Image>, 
+             mut errors: Vec<ProcessingError>) -> Results {
+    let start_time = Local::now();
+    let total = haystack.len() + 1;
+
+    // Matches at the same distance are listed in this order
+    processing::sort_by_path(&mut haystack);
+
+    let uniques = match processing::hash_image(settings, needle) {
+        Ok(needle) => {
+            let matches = find_matches(&needle, &haystack, settings);
+
+            vec![UniqueImage { img: needle, similars: matches }]
+        },
+        Err(err) => {
+            errors.push(err);
+            Vec::new()
+        },
+    };
+
+    Results {
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors,
+        phases: Phases::default(),
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Extensions of camera RAW files, searched for with `--pair-raw`.
+pub static RAW_EXTS: &[&str] = &["cr2", "nef", "arw", "dng"];
+
+static SIDECAR_EXTS: &[&str] = &["jpg", "jpeg"];
+
+/// RAW files that were paired with the JPEG image in the key.
+pub type Sidecars = HashMap<PathBuf, Vec<PathBuf>>;
+
+/// Pair each RAW file with a JPEG in the same directory with the same name except for the extension,
+/// as cameras save them. Paired RAW files are removed from `paths` so only the JPEG is hashed.
+///
+/// RAW files without a JPEG are left in `paths`.
+pub fn pair_sidecars(paths: Vec<PathBuf>) -> (Vec<PathBuf>, Sidecars) {
+    let (raws, mut others): (Vec<PathBuf>, Vec<PathBuf>) = paths.into_iter()
+        .partition(|path| has_ext(path, RAW_EXTS));
+
+    let mut jpegs = HashMap::new();
+
+    for path in others.iter() {
+        if has_ext(path, SIDECAR_EXTS) {
+            jpegs.insert(stem_key(path), path.clone());
+        }
+    }
+
+    let mut sidecars: Sidecars = HashMap::new();
+
+    for raw in raws {
+        match jpegs.get(&stem_key(&raw)) {
+            Some(jpeg) => sidecars.entry(jpeg.clone()).or_default().push(raw),
+            None => others.push(raw),
+        }
+    }
+
+    (others, sidecars)
+}
+
+fn has_ext(path: &Path, exts: &[&str]) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => exts.iter().any(|known| known.eq_ignore_ascii_case(ext)),
+        None => false,
+    }
+}
+
+/// The path without its extension, ignoring case since cameras usually use uppercase names.
+fn stem_key(path: &Path) -> (PathBuf, String) {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("").to_lowercase();
+
+    (path.parent().map_or_else(PathBuf::new, Path::to_path_buf), stem)
+}
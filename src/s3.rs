@@ -0,0 +1,92 @@
+//! With the `s3` feature, `--s3`: search a bucket of S3, or a service compatible with it,
+//! like MinIO or Cloudflare R2.
+//!
+//! The credentials are read the same way as by the AWS command line tools: from
+//! `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`, or the profile in `~/.aws/credentials`
+//! named by `AWS_PROFILE`. The region is read from `AWS_REGION` or `AWS_DEFAULT_REGION`,
+//! `us-east-1` by default, and other services are reached through `AWS_ENDPOINT_URL`.
+
+use crate::objects::{ObjectInfo, ObjectSource};
+
+use s3::creds::Credentials;
+use s3::{Bucket, Region};
+
+use std::env;
+use std::io;
+use std::path::PathBuf;
+
+pub struct S3Source {
+    bucket: Box<Bucket>,
+    /// Empty, or ends with `/`
+    prefix: String,
+}
+
+impl S3Source {
+
+    /// The objects under `url`, `s3://bucket` or `s3://bucket/prefix`.
+    pub fn new(url: &str) -> io::Result<S3Source> {
+        let (name, prefix) = parse_url(url)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Not an S3 URL: {}", url)))?;
+
+        let region_name = env::var("AWS_REGION").or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".to_string());
+
+        let region = match env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => Region::Custom { region: region_name, endpoint },
+            Err(_) => region_name.parse().map_err(io::Error::other)?,
+        };
+
+        let credentials = Credentials::default().map_err(io::Error::other)?;
+        let mut bucket = Bucket::new(&name, region, credentials).map_err(io::Error::other)?;
+
+        // Most other services only support path-style URLs
+        if env::var("AWS_ENDPOINT_URL").is_ok() {
+            bucket = bucket.with_path_style();
+        }
+
+        Ok(S3Source { bucket, prefix })
+    }
+}
+
+impl ObjectSource for S3Source {
+
+    fn list(&self) -> io::Result<Vec<ObjectInfo>> {
+        let pages = self.bucket.list(self.prefix.clone(), None).map_err(io::Error::other)?;
+
+        Ok(pages.into_iter()
+            .flat_map(|page| page.contents)
+            // Directory markers made by some clients
+            .filter(|object| !object.key.ends_with('/'))
+            .filter_map(|object| Some(ObjectInfo {
+                key: object.key.strip_prefix(&self.prefix)?.to_string(),
+                size: object.size,
+            }))
+            .collect())
+    }
+
+    fn fetch(&self, key: &str) -> io::Result<Vec<u8>> {
+        let response = self.bucket.get_object(format!("{}{}", self.prefix, key)).map_err(io::Error::other)?;
+
+        match response.status_code() {
+            200 => Ok(response.to_vec()),
+            404 => Err(io::Error::new(io::ErrorKind::NotFound, "The object no longer exists")),
+            status => Err(io::Error::other(format!("HTTP status {}", status))),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        PathBuf::from(format!("s3://{}/{}{}", self.bucket.name(), self.prefix, key))
+    }
+}
+
+/// The bucket name and the prefix, with a `/` added unless it's empty.
+fn parse_url(url: &str) -> Option<(String, String)> {
+    let rest = url.strip_prefix("s3://")?;
+    let (name, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+
+    if name.is_empty() { return None; }
+
+    let prefix = if prefix.is_empty() || prefix.ends_with('/') { prefix.to_string() } else { format!("{}/", prefix) };
+
+    Some((name.to_string(), prefix))
+}
@@ -0,0 +1,21 @@
+use std::cell::RefCell;
+use std::io::{self, Read};
+
+thread_local! {
+    /// Kept between files, so reading the start of each one doesn't allocate
+    static BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Read up to `len` bytes from `reader` into this thread's scratch buffer, and pass them to `f`.
+///
+/// `f` must not call this itself.
+pub fn read<R: Read, T, F: FnOnce(&[u8]) -> T>(reader: R, len: u64, f: F) -> io::Result<T> {
+    BUFFER.with(|buffer| {
+        let mut buffer = buffer.borrow_mut();
+
+        buffer.clear();
+        reader.take(len).read_to_end(&mut buffer)?;
+
+        Ok(f(&buffer))
+    })
+}
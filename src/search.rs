@@ -0,0 +1,556 @@
+use crate::archives;
+use crate::config::{ProgramSettings, Root};
+use crate::img;
+use crate::objects::{ObjectInfo, ObjectSource};
+
+use ::ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread::{self, JoinHandle};
+
+pub type SearchError = (PathBuf, io::Error);
+
+/// Extensions that mean the same format, so searching for one finds them all
+/// unless `--strict-ext` is given.
+pub const EXT_ALIASES: &[&[&str]] = &[
+    &["jpg", "jpeg", "jpe", "jfif"],
+    &["tif", "tiff"],
+];
+
+/// The other extensions for the same format as `ext`, ignoring case, including `ext` itself.
+pub fn ext_aliases(ext: &str) -> &'static [&'static str] {
+    EXT_ALIASES.iter()
+        .find(|aliases| aliases.iter().any(|alias| alias.eq_ignore_ascii_case(ext)))
+        .map_or(&[], |aliases| aliases)
+}
+
+/// Files and directories listed in this file, in `.gitignore` syntax, aren't searched.
+pub const IGNORE_FILE: &str = ".imgdupignore";
+
+/// Directories of thumbnails, metadata and dependencies that are skipped unless
+/// `--no-default-skip-dirs` is given. Names are compared ignoring case.
+pub const DEFAULT_SKIP_DIRS: &[&str] = &[
+    // Version control and package managers
+    ".git", ".hg", ".svn", "node_modules",
+    // Synology and QNAP thumbnails
+    "@eaDir", ".@__thumb",
+    // Linux desktop thumbnails
+    ".thumbnails",
+    // Windows recycle bin and system files
+    "$RECYCLE.BIN", "System Volume Information",
+    // macOS metadata and zip leftovers
+    ".Spotlight-V100", ".Trashes", ".fseventsd", "__MACOSX",
+];
+
+pub fn find_images(settings: &ProgramSettings) -> Vec<PathBuf> {
+    find_images_with_errors(settings).0
+}
+
+/// Also return the directories that couldn't be read, and why.
+pub fn find_images_with_errors(settings: &ProgramSettings) -> (Vec<PathBuf>, Vec<SearchError>) {
+    find_images_in_roots(settings, &settings.roots)
+}
+
+/// Search `dir` instead of the directory in `settings`, with the same options.
+pub fn find_images_in(settings: &ProgramSettings, dir: &Path) -> (Vec<PathBuf>, Vec<SearchError>) {
+    find_images_in_roots(settings, &[Root { dir: dir.to_path_buf(), recurse: settings.recurse }])
+}
+
+/// Search every one of `roots`, in order. A directory inside more than one of them
+/// is only searched once.
+pub fn find_images_in_roots(settings: &ProgramSettings, roots: &[Root]) -> (Vec<PathBuf>, Vec<SearchError>) {
+    let mut found = Vec::new();
+
+    let errors = search_with(settings, roots, |path| {
+        found.push(path);
+        true
+    });
+
+    (found, errors)
+}
+
+/// The images among `paths`, files and directories that changed inside `roots`, that a search
+/// of `roots` would find: the directories among them are searched, and the rest are only found
+/// if no directory above them would have been skipped. Paths outside every root, or that no
+/// longer exist, are left out.
+pub fn find_images_among(settings: &ProgramSettings, roots: &[Root], paths: &[PathBuf]) -> (Vec<PathBuf>, Vec<SearchError>) {
+    let exts = search_exts(settings);
+    let mut found = Vec::new();
+
+    let mut search = Search {
+        settings,
+        root: Path::new(""),
+        recurse: false,
+        track_visited: true,
+        exts: &exts,
+        visited: HashSet::new(),
+        ignores: Vec::new(),
+        found: |path| {
+            found.push(path);
+            true
+        },
+        stopped: false,
+        errors: Vec::new(),
+    };
+
+    for path in paths {
+        let Some(root) = roots.iter().find(|root| path.starts_with(&root.dir)) else { continue };
+
+        if path == &root.dir || fs::symlink_metadata(path).is_err() { continue; }
+
+        search.root = &root.dir;
+        search.recurse = root.recurse;
+
+        search.search_within(path);
+    }
+
+    let errors = search.errors;
+
+    // A file can be among the paths as well as in a directory among them
+    found.sort();
+    found.dedup();
+
+    (found, errors)
+}
+
+/// Read the paths listed in `file`, or stdin if it's `-`, one per line,
+/// or separated by NUL characters if there are any. Relative paths are made absolute,
+/// from the current directory.
+pub fn read_path_list(file: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut list = Vec::new();
+
+    if file == Path::new("-") {
+        io::stdin().lock().read_to_end(&mut list)?;
+    } else {
+        list = fs::read(file)?;
+    }
+
+    let paths = if list.contains(&0) {
+        list.split(|&byte| byte == 0).map(path_from_bytes).collect::<Vec<_>>()
+    } else {
+        list.split(|&byte| byte == b'\n')
+            .map(|line| path_from_bytes(line.strip_suffix(b"\r").unwrap_or(line)))
+            .collect()
+    };
+
+    paths.into_iter()
+        .filter(|path| !path.as_os_str().is_empty())
+        .map(std::path::absolute)
+        .collect()
+}
+
+// Paths don't have to be UTF-8
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Search `roots` on another thread, and send each image as soon as it's found,
+/// so the images can be processed while the search is still going.
+///
+/// The search stops early if the receiver is dropped.
+pub fn stream_images_in(settings: &ProgramSettings, roots: &[Root]) -> (Receiver<PathBuf>, SearchHandle) {
+    let (tx, rx) = sync_channel(settings.queue_depth);
+    let found = Arc::new(AtomicUsize::new(0));
+
+    let thread_settings = settings.clone();
+    let thread_roots = roots.to_vec();
+    let thread_found = found.clone();
+
+    let thread = thread::spawn(move || 
+        search_with(&thread_settings, &thread_roots, |path| {
+            thread_found.fetch_add(1, Ordering::Relaxed);
+            tx.send(path).is_ok()
+        })
+    );
+
+    (rx, SearchHandle { found, thread })
+}
+
+/// A search running on another thread, from `stream_images_in()`.
+pub struct SearchHandle {
+    found: Arc<AtomicUsize>,
+    thread: JoinHandle<Vec<SearchError>>,
+}
+
+impl SearchHandle {
+
+    /// The number of images found so far.
+    pub fn found(&self) -> usize {
+        self.found.load(Ordering::Relaxed)
+    }
+
+    /// Wait for the search to finish, and return the number of images found
+    /// and the directories that couldn't be read.
+    pub fn join(self) -> (usize, Vec<SearchError>) {
+        let errors = self.thread.join().unwrap_or_default();
+
+        (self.found.load(Ordering::Relaxed), errors)
+    }
+}
+
+/// The objects in `source` that would be found as images if they were files, with their keys
+/// as the paths relative to the root: with one of the extensions, within `--min-size` and
+/// `--max-size`, matching `--include` and not `--exclude`, and only directly under the prefix
+/// unless `--recurse` is given. Objects aren't sniffed, and have no modification time to filter by.
+pub fn find_objects(settings: &ProgramSettings, source: &dyn ObjectSource) -> io::Result<Vec<ObjectInfo>> {
+    let exts = search_exts(settings);
+    let root = Path::new("");
+
+    let mut objects: Vec<ObjectInfo> = source.list()?.into_iter()
+        .filter(|object| {
+            let key = Path::new(&object.key);
+
+            (settings.recurse || !object.key.contains('/'))
+                && !(settings.skip_hidden && object.key.split('/').any(|part| part.starts_with('.')))
+                && check_ext(key, &exts, settings.strict_ext)
+                && matches_size(object.size, settings)
+                && is_included(key, settings, root) && !is_excluded(key, settings, root, false)
+        })
+        .collect();
+
+    objects.sort_by(|a, b| a.key.cmp(&b.key));
+
+    Ok(objects)
+}
+
+/// `--exts`, and unless `--strict-ext` is given, the other extensions for the same formats.
+fn search_exts(settings: &ProgramSettings) -> Vec<&str> {
+    let mut exts: Vec<&str> = settings.exts.iter().map(|string| string.as_str()).collect();
+
+    if !settings.strict_ext {
+        let aliases: Vec<&str> = exts.iter().flat_map(|ext| ext_aliases(ext)).copied().collect();
+        exts.extend(aliases);
+    }
+
+    exts
+}
+
+/// Pass every image found in `roots` to `found`, until it returns `false`.
+fn search_with<F: FnMut(PathBuf) -> bool>(settings: &ProgramSettings, roots: &[Root], found: F) -> Vec<SearchError> {
+    let exts = search_exts(settings);
+
+    let mut search = Search {
+        settings,
+        root: Path::new(""),
+        recurse: false,
+        // Roots can be inside each other
+        track_visited: settings.follow_symlinks || roots.len() > 1,
+        exts: &exts,
+        visited: HashSet::new(),
+        ignores: Vec::new(),
+        found,
+        stopped: false,
+        errors: Vec::new(),
+    };
+
+    for root in roots {
+        search.root = &root.dir;
+        search.recurse = root.recurse;
+
+        search.search_dir(&root.dir);
+    }
+
+    search.errors
+}
+
+struct Search<'a, F> {
+    settings: &'a ProgramSettings,
+    /// Include and exclude patterns are matched relative to this
+    root: &'a Path,
+    /// Whether to search the subdirectories of `root`
+    recurse: bool,
+    /// Whether to check for directories that were already searched
+    track_visited: bool,
+    exts: &'a [&'a str],
+    /// (device, inode) of every directory searched so far, when `track_visited`
+    visited: HashSet<(u64, u64)>,
+    /// The ignore files of the directories being searched, innermost last
+    ignores: Vec<Gitignore>,
+    found: F,
+    /// Set once `found` returns `false`
+    stopped: bool,
+    errors: Vec<SearchError>,
+}
+
+impl<'a, F: FnMut(PathBuf) -> bool> Search<'a, F> {
+
+    fn search_dir(&mut self, dir: &Path) {
+        // Symlink loop, or two links to the same directory
+        if self.stopped || !self.mark_visited(dir) { return; }
+
+        let paths = match read_dir(dir) {
+            Ok(paths) => paths,
+            Err(err) => return self.errors.push((dir.to_path_buf(), err)),
+        };
+
+        let ignore = self.load_ignores(dir);
+        let pushed = ignore.is_some();
+
+        self.ignores.extend(ignore);
+        self.search_paths(paths);
+
+        if pushed { self.ignores.pop(); }
+    }
+
+    /// Search `path`, inside `root`, as if it had been come across searching `root`: not if a
+    /// directory on the way there would have been skipped, and with their ignore files.
+    fn search_within(&mut self, path: &Path) {
+        let Ok(relative) = path.strip_prefix(self.root) else { return };
+        let parents: Vec<_> = relative.parent().map_or(Vec::new(), |parent| parent.components().collect());
+
+        // Without recursing, only what's directly in `root`
+        if !self.recurse && !parents.is_empty() { return; }
+
+        let depth = self.ignores.len();
+        let mut dir = self.root.to_path_buf();
+        let mut skipped = false;
+
+        let ignore = self.load_ignores(&dir);
+        self.ignores.extend(ignore);
+
+        for component in parents {
+            dir.push(component);
+
+            if (!self.settings.follow_symlinks && is_symlink(&dir)) || (self.settings.skip_hidden && is_hidden(&dir))
+                || self.is_ignored(&dir, true) || is_skipped_dir(&dir, &self.settings.skip_dirs)
+                || is_excluded(&dir, self.settings, self.root, true) {
+                skipped = true;
+                break;
+            }
+
+            let ignore = self.load_ignores(&dir);
+            self.ignores.extend(ignore);
+        }
+
+        if !skipped {
+            self.search_paths(vec![path.to_path_buf()]);
+        }
+
+        self.ignores.truncate(depth);
+    }
+
+    /// The patterns from `.imgdupignore` in `dir`, and `.gitignore` with `--gitignore`.
+    fn load_ignores(&mut self, dir: &Path) -> Option<Gitignore> {
+        let mut files = Vec::new();
+
+        // Later files take precedence, so `.imgdupignore` can re-include what git ignores
+        if self.settings.gitignore { files.push(dir.join(".gitignore")); }
+        files.push(dir.join(IGNORE_FILE));
+
+        files.retain(|file| file.is_file());
+        if files.is_empty() { return None; }
+
+        let mut builder = GitignoreBuilder::new(dir);
+
+        for file in &files {
+            // Invalid lines are reported, and the rest of the file still applies
+            if let Some(err) = builder.add(file) {
+                self.errors.push((file.clone(), io::Error::other(err)));
+            }
+        }
+
+        match builder.build() {
+            Ok(ignore) => Some(ignore),
+            Err(err) => {
+                self.errors.push((dir.to_path_buf(), io::Error::other(err)));
+                None
+            },
+        }
+    }
+
+    /// The innermost ignore file with a pattern that matches `path` decides.
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.ignores.iter().rev()
+            .map(|ignore| ignore.matched(path, is_dir))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore())
+    }
+
+    fn search_paths(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            if self.stopped { return; }
+
+            if !self.settings.follow_symlinks && is_symlink(&path) { continue; }
+
+            if self.settings.skip_hidden && is_hidden(&path) { continue; }
+
+            // Following symlinks, like `Path::is_dir()`
+            let meta = fs::metadata(&path).ok();
+            let is_dir = meta.as_ref().is_some_and(|meta| meta.is_dir());
+
+            if self.is_ignored(&path, is_dir) { continue; }
+
+            if is_dir {
+                if is_skipped_dir(&path, &self.settings.skip_dirs) { continue; }
+
+                // Excluded directories aren't descended into at all
+                if self.recurse && !is_excluded(&path, self.settings, self.root, true) {
+                    self.search_dir(&path);
+                }
+            } else if self.settings.archives && archives::is_archive(&path) {
+                self.search_archive(&path);
+            } else if self.matches_format(&path) && is_included(&path, self.settings, self.root)
+                && !is_excluded(&path, self.settings, self.root, false) 
+                && meta.as_ref().is_none_or(|meta| matches_meta(meta, self.settings)) {
+                self.stopped = !(self.found)(path);
+            }
+        }
+    }
+
+    /// The images in the archive at `archive`, filtered like the files in a directory would be,
+    /// except that they aren't sniffed and have no modification time.
+    #[cfg(feature = "archives")]
+    fn search_archive(&mut self, archive: &Path) {
+        let members = match archives::members(archive) {
+            Ok(members) => members,
+            Err(err) => return self.errors.push((archive.to_path_buf(), err)),
+        };
+
+        for (member, size) in members {
+            if self.stopped { return; }
+
+            let path = archives::member_path(archive, &member);
+
+            if check_ext(&path, self.exts, self.settings.strict_ext)
+                && !(self.settings.skip_hidden && member.split('/').any(|part| part.starts_with('.')))
+                && matches_size(size, self.settings)
+                && is_included(&path, self.settings, self.root) && !is_excluded(&path, self.settings, self.root, false) {
+                self.stopped = !(self.found)(path);
+            }
+        }
+    }
+
+    // Checked when the arguments are parsed
+    #[cfg(not(feature = "archives"))]
+    fn search_archive(&mut self, _: &Path) {
+        unreachable!()
+    }
+
+    /// Whether `path` has one of the extensions, or with `--sniff`, 
+    /// starts like an image in a format with one of them.
+    fn matches_format(&self, path: &Path) -> bool {
+        if check_ext(path, self.exts, self.settings.strict_ext) { return true; }
+
+        if !self.settings.sniff { return false; }
+
+        img::sniff_format(path).is_some_and(|format| 
+            format.extensions_str().iter().any(|ext| self.exts.iter().any(|known| known.eq_ignore_ascii_case(ext)))
+        )
+    }
+
+    /// Returns `false` if `dir` was already visited.
+    fn mark_visited(&mut self, dir: &Path) -> bool {
+        // Without following symlinks, the same directory can't be reached twice from one root
+        if !self.track_visited { return true; }
+
+        match dir_id(dir) {
+            Some(id) => self.visited.insert(id),
+            None => true,
+        }
+    }
+}
+
+/// The paths in `dir`, sorted so the search order doesn't depend on the filesystem.
+fn read_dir(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+#[cfg(unix)]
+fn dir_id(dir: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(dir).ok().map(|meta| (meta.dev(), meta.ino()))
+}
+
+// Not every platform reports inodes
+#[cfg(not(unix))]
+fn dir_id(_: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).map(|meta| meta.file_type().is_symlink()).unwrap_or(false)
+}
+
+/// Whether the size and modification time of a file are within the limits in `settings`.
+fn matches_meta(meta: &fs::Metadata, settings: &ProgramSettings) -> bool {
+    if !matches_size(meta.len(), settings) { return false; }
+
+    if settings.modified_after.is_none() && settings.modified_before.is_none() { return true; }
+
+    // Where the modification time isn't available, the filters can't apply
+    let Ok(modified) = meta.modified() else { return true };
+
+    settings.modified_after.is_none_or(|after| modified >= after)
+        && settings.modified_before.is_none_or(|before| modified < before)
+}
+
+/// Whether `size` is within `--min-size` and `--max-size`.
+fn matches_size(size: u64, settings: &ProgramSettings) -> bool {
+    size >= settings.min_size && (settings.max_size == 0 || size <= settings.max_size)
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with('.'))
+}
+
+fn is_skipped_dir(dir: &Path, skip_dirs: &[String]) -> bool {
+    match dir.file_name().and_then(|name| name.to_str()) {
+        Some(name) => skip_dirs.iter().any(|skip| skip.eq_ignore_ascii_case(name)),
+        None => false,
+    }
+}
+
+/// With `strict`, the case has to match too.
+fn check_ext(file: &Path, exts: &[&str], strict: bool) -> bool {
+    match file.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if strict => exts.contains(&ext),
+        Some(ext) => exts.iter().any(|&a| a.eq_ignore_ascii_case(ext)),
+        None => false
+    }
+}
+
+/// With no `--include` patterns, every file is included.
+fn is_included(file: &Path, settings: &ProgramSettings, root: &Path) -> bool {
+    if settings.include.is_empty() { return true; }
+
+    let relative = relative_path(file, root);
+
+    settings.include.iter().any(|pattern| pattern.matches_path(&relative))
+}
+
+fn is_excluded(path: &Path, settings: &ProgramSettings, root: &Path, is_dir: bool) -> bool {
+    if settings.exclude.is_empty() { return false; }
+
+    let mut relative = relative_path(path, root).display().to_string();
+
+    // So patterns like `thumbs/` match the directory itself
+    if is_dir { relative.push('/'); }
+
+    settings.exclude.iter().any(|regex| regex.is_match(&relative))
+}
+
+fn relative_path(path: &Path, root: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
@@ -0,0 +1,354 @@
+//! Serde support for the results, and the documents written by the `json`, `jsonl`,
+//! `toml` and `bincode` output formats.
+//!
+//! Paths are written as they are stored; the documents built here make them
+//! relative to the search directory first.
+
+use crate::config::{JsonSettings, ProgramSettings};
+use crate::hash::ImageHash;
+use crate::img::{Image, SimilarImage, UniqueImage};
+use crate::processing::{self, ProcessingError, Results, Stats, Timings, Total};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use chrono::{DateTime, Local};
+
+use image::ImageFormat;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde::ser::SerializeStruct;
+
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// The whole output of a run: `--format=json`, `toml` or `bincode`.
+#[derive(Serialize)]
+pub struct Report<'a> {
+    pub settings: &'a ProgramSettings,
+    pub info: Info,
+    pub stats: Stats,
+    /// Only with `--timings`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Timings>,
+    pub images: Vec<UniqueImage>,
+    /// Only with `--bursts`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bursts: Option<Vec<UniqueImage>>,
+    pub errors: Vec<ErrorEntry>,
+    pub timed_out: Vec<String>,
+    pub too_large: Vec<String>,
+    pub blank: Vec<String>,
+}
+
+impl<'a> Report<'a> {
+
+    pub fn new(settings: &'a ProgramSettings, results: &Results) -> Report<'a> {
+        let dir = &settings.dir;
+
+        Report {
+            settings,
+            info: Info::new(results),
+            stats: results.stats(),
+            timings: if settings.timings { Some(results.timings()) } else { None },
+            images: results.groups(settings).iter()
+                .map(|unique| unique.relative_to(dir))
+                .collect(),
+            bursts: settings.bursts.map(|_| results.bursts(settings).iter()
+                .map(|burst| burst.relative_to(dir))
+                .collect()),
+            errors: results.failed().iter()
+                .map(|error| ErrorEntry::new(error, dir))
+                .collect(),
+            timed_out: relative_paths(&results.timed_out(), dir),
+            too_large: relative_paths(&results.too_large(), dir),
+            blank: relative_paths(&results.blank(), dir),
+        }
+    }
+}
+
+/// The parts of a `--format=json` report needed to read its groups back.
+#[derive(Deserialize)]
+struct SavedReport {
+    settings: SavedSettings,
+    images: Vec<UniqueImage>,
+}
+
+#[derive(Deserialize)]
+struct SavedSettings {
+    dir: PathBuf,
+}
+
+/// The directory searched for a report written with `--format=json`, and its groups
+/// with full paths again.
+pub fn read_json_report(path: &Path) -> io::Result<(PathBuf, Vec<UniqueImage>)> {
+    let report: SavedReport = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    let dir = report.settings.dir;
+
+    let images = report.images.iter().map(|unique| unique.in_dir(&dir)).collect();
+
+    Ok((dir, images))
+}
+
+/// The totals of a run, the same as the stats in the text output.
+#[derive(Serialize)]
+pub struct Info {
+    pub start: String,
+    pub end: String,
+    pub found: Total,
+    pub processed: usize,
+    pub wasted_bytes: u64,
+    pub errors: usize,
+    pub timed_out: usize,
+    pub too_large: usize,
+    pub blank: usize,
+}
+
+impl Info {
+
+    pub fn new(results: &Results) -> Info {
+        Info {
+            start: results.start_time(),
+            end: results.end_time(),
+            found: results.total,
+            processed: results.uniques.len(),
+            wasted_bytes: results.wasted_bytes(),
+            errors: results.failed().len(),
+            timed_out: results.timed_out().len(),
+            too_large: results.too_large().len(),
+            blank: results.blank().len(),
+        }
+    }
+}
+
+/// A line of `--format=jsonl` for a burst, so it isn't mistaken for a group of duplicates.
+#[derive(Serialize)]
+pub struct BurstEntry {
+    pub burst: UniqueImage,
+}
+
+/// An image that couldn't be processed, with its path relative to the search directory.
+#[derive(Serialize)]
+pub struct ErrorEntry {
+    pub path: String,
+    pub error: String,
+    pub kind: &'static str,
+}
+
+impl ErrorEntry {
+
+    pub fn new(error: &ProcessingError, relative_to: &Path) -> ErrorEntry {
+        ErrorEntry {
+            path: error.relative_path(relative_to).display().to_string(),
+            error: error.err_msg(),
+            kind: error.kind(),
+        }
+    }
+}
+
+fn relative_paths(errors: &[&ProcessingError], relative_to: &Path) -> Vec<String> {
+    errors.iter()
+        .map(|error| error.relative_path(relative_to).display().to_string())
+        .collect()
+}
+
+pub fn write_json<T: Serialize>(json_config: &JsonSettings, value: &T, out: &mut dyn Write) -> io::Result<()> {
+    match *json_config {
+        JsonSettings::PrettyJson(indent) => {
+            let indent = vec![b' '; indent as usize];
+            let formatter = serde_json::ser::PrettyFormatter::with_indent(&indent);
+            let mut serializer = serde_json::Serializer::with_formatter(&mut *out, formatter);
+            value.serialize(&mut serializer).map_err(io::Error::from)
+        },
+        JsonSettings::CompactJson => serde_json::to_writer(out, value).map_err(io::Error::from),
+        JsonSettings::NoJson => Ok(()),
+    }
+}
+
+pub fn write_toml<T: Serialize>(value: &T, out: &mut dyn Write) -> io::Result<()> {
+    let toml = toml::to_string(value).map_err(io::Error::other)?;
+    out.write_all(toml.as_bytes())
+}
+
+pub fn write_bincode<T: Serialize>(value: &T, out: &mut dyn Write) -> io::Result<()> {
+    bincode::serialize_into(out, value).map_err(io::Error::other)
+}
+
+/// Only the settings that affect which images are found and how similar they are.
+impl Serialize for ProgramSettings {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ProgramSettings", 9)?;
+        state.serialize_field("threads", &self.threads)?;
+        state.serialize_field("dir", &self.dir.display().to_string())?;
+        state.serialize_field("recurse", &self.recurse)?;
+        state.serialize_field("exts", &self.exts)?;
+        state.serialize_field("hash_size", &self.hash_size)?;
+        state.serialize_field("threshold", &self.threshold)?;
+        state.serialize_field("near_threshold", &self.near_threshold)?;
+        state.serialize_field("fast", &self.fast)?;
+        state.serialize_field("limit", &self.limit)?;
+        state.end()
+    }
+}
+
+/// The number of fields written by `serialize_image_fields()`.
+const IMAGE_FIELDS: usize = 14;
+
+/// Write the fields of `img` into the struct being serialized, so similars and
+/// uniques can extend it. `Image` is deserialized from the same fields.
+fn serialize_image_fields<S: SerializeStruct>(img: &Image, state: &mut S) -> Result<(), S::Error> {
+    state.serialize_field("path", &img.path.display().to_string())?;
+    state.serialize_field("hash", &img.hash)?;
+    state.serialize_field("hash_bits", &img.hash.bits())?;
+    state.serialize_field("width", &img.width)?;
+    state.serialize_field("height", &img.height)?;
+    state.serialize_field("size", &img.file_size)?;
+    state.serialize_field("format", img.format_name())?;
+
+    match img.thumbnail {
+        Some(ref thumbnail) => state.serialize_field("thumbnail", &thumbnail.display().to_string())?,
+        None => state.skip_field("thumbnail")?,
+    }
+
+    serialize_paths("sidecars", &img.sidecars, state)?;
+    serialize_paths("links", &img.links, state)?;
+
+    // Only with `--screenshots`, for the images it tagged
+    if img.screenshot {
+        state.serialize_field("screenshot", &true)?;
+    } else {
+        state.skip_field("screenshot")?;
+    }
+
+    match img.thumbnail_of {
+        Some(ref larger) => state.serialize_field("thumbnail_of", &larger.display().to_string())?,
+        None => state.skip_field("thumbnail_of")?,
+    }
+
+    // Only with `--timings`, for images that were loaded and hashed in this run
+    match img.timing {
+        Some(timing) => {
+            state.serialize_field("load_ms", &(timing.load as f64 / 1_000_000f64))?;
+            state.serialize_field("hash_ms", &(timing.hash as f64 / 1_000_000f64))
+        },
+        None => {
+            state.skip_field("load_ms")?;
+            state.skip_field("hash_ms")
+        },
+    }
+}
+
+/// Left out if there are none.
+fn serialize_paths<S: SerializeStruct>(key: &'static str, paths: &[PathBuf], state: &mut S) -> Result<(), S::Error> {
+    if paths.is_empty() {
+        state.skip_field(key)
+    } else {
+        let paths: Vec<String> = paths.iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        state.serialize_field(key, &paths)
+    }
+}
+
+impl Serialize for Image {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Image", IMAGE_FIELDS)?;
+        serialize_image_fields(self, &mut state)?;
+        state.end()
+    }
+}
+
+impl Serialize for UniqueImage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("UniqueImage", IMAGE_FIELDS + 2)?;
+        serialize_image_fields(&self.img, &mut state)?;
+        state.serialize_field("similars", &self.similars())?;
+        state.serialize_field("wasted_bytes", &self.wasted_bytes())?;
+        state.end()
+    }
+}
+
+impl Serialize for SimilarImage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("SimilarImage", IMAGE_FIELDS + 6)?;
+        serialize_image_fields(&self.img, &mut state)?;
+        state.serialize_field("diff", &self.dist_ratio)?;
+        state.serialize_field("distance", &self.dist)?;
+        state.serialize_field("similarity", &self.similarity())?;
+        state.serialize_field("tier", &self.tier)?;
+        state.serialize_field("match", &self.reason)?;
+        state.serialize_field("match_score", &self.match_score())?;
+        state.end()
+    }
+}
+
+/// The path as it's stored, and the message and kind of the error.
+impl Serialize for ProcessingError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ErrorEntry::new(self, Path::new("")).serialize(serializer)
+    }
+}
+
+/// The same base64 as in the hash database.
+impl Serialize for ImageHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_base64())
+    }
+}
+
+/// The fields an image's hash is read back from.
+#[derive(Deserialize)]
+struct SavedHash {
+    hash: String,
+    hash_bits: Option<usize>,
+}
+
+/// The hash of an image from its `hash` and `hash_bits` fields, so hashes of any length,
+/// like those of a `PerceptualHasher`, are read back as they were.
+pub fn deserialize_hash<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ImageHash, D::Error> {
+    let saved = SavedHash::deserialize(deserializer)?;
+    let bytes = STANDARD.decode(&saved.hash).map_err(de::Error::custom)?;
+
+    // Reports from before `hash_bits` only have square hashes of whole bytes, and for a hash
+    // size of 4 or more, that many bytes hold less than the next square
+    let bits = saved.hash_bits.unwrap_or_else(|| {
+        let hash_size = ((bytes.len() * 8) as f64).sqrt() as usize;
+        hash_size * hash_size
+    });
+
+    ImageHash::from_packed(&bytes, bits)
+        .ok_or_else(|| de::Error::custom(format!("invalid hash: {}", saved.hash)))
+}
+
+/// The reverse of `Image::format_name()`.
+pub fn deserialize_format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<ImageFormat>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+
+    Ok(ImageFormat::from_extension(name))
+}
+
+pub fn serialize_ctime<S: Serializer>(time: &DateTime<Local>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&processing::ctime(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bit_vec::BitVec;
+
+    #[test]
+    fn hashes_of_any_length_round_trip() {
+        // 67 bits, from a `PerceptualHasher` rather than a square `--hash-size`
+        let hash = ImageHash::from_bits(BitVec::from_fn(67, |bit| bit % 3 == 0));
+        let mut unique = UniqueImage::from_image(Image::new(PathBuf::from("a.png"), hash.clone(), 100, 100, 1000));
+        unique.similars.push(SimilarImage::from_image(Image::new(PathBuf::from("b.png"), hash.clone(), 50, 50, 500), 0.0, 0, 0.0));
+
+        let json = serde_json::to_string(&unique).unwrap();
+        let read: UniqueImage = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(read.img.hash, hash);
+        assert_eq!(read.similars[0].img.hash, hash);
+    }
+}
@@ -0,0 +1,201 @@
+//! With the `server` feature, `serve`: a small HTTP API over a hash database.
+//!
+//! * `POST /query` with an image as the body: the images in the database similar to it,
+//!   as a report like `--format=json` with the upload as its only unique image
+//! * `GET /groups`: the groups of similar images in the database
+//! * `POST /rescan`: update the database from `dir`, like the update mode
+//!
+//! Errors are returned as `{"error": "..."}`.
+
+use crate::config::{JsonSettings, ProgramSettings};
+use crate::db::HashDb;
+use crate::img::UniqueImage;
+use crate::processing::{self, InMemoryImage, Phases, Results};
+use crate::query;
+use crate::search;
+use crate::serialize::{self, Report};
+use crate::update;
+
+use chrono::Local;
+
+use log::{info, warn};
+
+use serde::Serialize;
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::thread;
+
+/// The name uploaded images are given in query results.
+const UPLOAD_NAME: &str = "upload";
+
+struct ServerState {
+    settings: ProgramSettings,
+    db_path: PathBuf,
+    db: RwLock<HashDb>,
+    /// Held while rescanning, so only one rescan runs at a time
+    rescan: Mutex<()>,
+}
+
+/// Answer requests on `settings.listen` until the process is stopped,
+/// each on its own thread.
+pub fn serve(settings: &ProgramSettings, db_path: &Path) -> io::Result<()> {
+    let state = Arc::new(ServerState {
+        settings: settings.clone(),
+        db_path: db_path.to_path_buf(),
+        db: RwLock::new(HashDb::load(db_path)?),
+        rescan: Mutex::new(()),
+    });
+
+    let server = Server::http(&settings.listen).map_err(io::Error::other)?;
+
+    info!("Serving {} on http://{}", db_path.display(), settings.listen);
+
+    for request in server.incoming_requests() {
+        let state = state.clone();
+
+        thread::spawn(move || handle(&state, request));
+    }
+
+    Ok(())
+}
+
+fn handle(state: &ServerState, mut request: Request) {
+    info!("{} {}", request.method(), request.url());
+
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+
+    let response = match (request.method(), &*path) {
+        (Method::Post, "/query") => query(state, &mut request),
+        (Method::Get, "/groups") => groups(state),
+        (Method::Post, "/rescan") => rescan(state),
+        (_, "/query" | "/groups" | "/rescan") => error(405, "Method not allowed"),
+        _ => error(404, "Not found"),
+    };
+
+    if let Err(err) = request.respond(response) {
+        warn!("Could not send response: {}", err);
+    }
+}
+
+type JsonResponse = Response<io::Cursor<Vec<u8>>>;
+
+fn query(state: &ServerState, request: &mut Request) -> JsonResponse {
+    let start_time = Local::now();
+    let settings = &state.settings;
+
+    let mut data = Vec::new();
+
+    if let Err(err) = request.as_reader().read_to_end(&mut data) {
+        return error(400, &format!("Could not read the image: {}", err));
+    }
+
+    let needle = match processing::hash_in_memory_image(settings, UPLOAD_NAME.into(), InMemoryImage::Encoded(data)) {
+        Ok(needle) => needle,
+        Err(err) => return error(422, &err.err_msg()),
+    };
+
+    let mut haystack = read(&state.db).images(&settings.hash_settings());
+    processing::sort_by_path(&mut haystack);
+
+    let matches = query::find_matches(&needle, &haystack, settings);
+
+    let results = Results {
+        total: haystack.len() + 1,
+        start_time,
+        end_time: Local::now(),
+        uniques: vec![UniqueImage { img: needle, similars: matches }],
+        errors: Vec::new(),
+        phases: Phases::default(),
+    };
+
+    json(200, settings, &Report::new(settings, &results))
+}
+
+fn groups(state: &ServerState) -> JsonResponse {
+    let start_time = Local::now();
+    let settings = &state.settings;
+
+    let images = read(&state.db).images(&settings.hash_settings());
+
+    let results = Results {
+        total: images.len(),
+        start_time,
+        end_time: Local::now(),
+        uniques: processing::group_images(images, settings),
+        errors: Vec::new(),
+        phases: Phases::default(),
+    };
+
+    json(200, settings, &Report::new(settings, &results))
+}
+
+#[derive(Serialize)]
+struct RescanStats {
+    hashed: usize,
+    removed: usize,
+    errors: usize,
+}
+
+fn rescan(state: &ServerState) -> JsonResponse {
+    let _rescanning = state.rescan.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let settings = &state.settings;
+
+    info!("Rescanning {}...", settings.dir.display());
+
+    let paths = search::find_images(settings);
+
+    let updated = update::update(settings, &state.db_path, paths)
+        .and_then(|(results, stats)| {
+            let db = HashDb::load(&state.db_path)?;
+            *state.db.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = db;
+
+            Ok(RescanStats { hashed: stats.hashed, removed: stats.removed, errors: results.errors.len() })
+        });
+
+    match updated {
+        Ok(stats) => {
+            info!("Hashed {} new or modified images, removed {} deleted images.", stats.hashed, stats.removed);
+            json(200, settings, &stats)
+        },
+        Err(err) => error(500, &format!("Could not update {}: {}", state.db_path.display(), err)),
+    }
+}
+
+// The database is only ever replaced whole, so a panic can't have left it half-changed
+fn read(db: &RwLock<HashDb>) -> RwLockReadGuard<'_, HashDb> {
+    db.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn json<T: Serialize>(status: u16, settings: &ProgramSettings, value: &T) -> JsonResponse {
+    let json_config = match settings.json {
+        JsonSettings::NoJson => JsonSettings::CompactJson,
+        json_config => json_config,
+    };
+
+    let mut body = Vec::new();
+
+    if let Err(err) = serialize::write_json(&json_config, value, &mut body) {
+        return error(500, &err.to_string());
+    }
+
+    json_response(status, body)
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+fn error(status: u16, msg: &str) -> JsonResponse {
+    json_response(status, serde_json::to_vec(&ErrorBody { error: msg }).unwrap())
+}
+
+fn json_response(status: u16, body: Vec<u8>) -> JsonResponse {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+
+    Response::from_data(body).with_status_code(status).with_header(content_type)
+}
@@ -0,0 +1,277 @@
+//! With the `sqlite` feature, `--db`: keep hashes and results in a SQLite database, to reuse
+//! the hashes of unchanged images like `--cache` and to query the results with SQL.
+//!
+//! Paths are absolute. The schema is documented in `CLI.md`, and only ever added to.
+
+use crate::config::ProgramSettings;
+use crate::db::{self, DbEntry, HashDb};
+use crate::hash::ImageHash;
+use crate::processing::Results;
+use crate::storage::{self, Storage};
+
+use rusqlite::{params, Connection};
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS images (
+        path TEXT PRIMARY KEY,
+        size INTEGER NOT NULL,
+        modified INTEGER NOT NULL,
+        width INTEGER NOT NULL,
+        height INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS hashes (
+        path TEXT PRIMARY KEY REFERENCES images (path) ON DELETE CASCADE,
+        hash_size INTEGER NOT NULL,
+        fast INTEGER NOT NULL,
+        hash TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        dir TEXT NOT NULL UNIQUE,
+        start_time TEXT NOT NULL,
+        end_time TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS groups (
+        id INTEGER PRIMARY KEY,
+        run INTEGER NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+        original TEXT NOT NULL,
+        wasted_bytes INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS pairs (
+        group_id INTEGER NOT NULL REFERENCES groups (id) ON DELETE CASCADE,
+        original TEXT NOT NULL,
+        duplicate TEXT NOT NULL,
+        distance INTEGER NOT NULL,
+        similarity REAL NOT NULL,
+        tier TEXT NOT NULL,
+        match TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS groups_run ON groups (run);
+    CREATE INDEX IF NOT EXISTS pairs_group ON pairs (group_id);
+";
+
+pub struct SqliteStorage {
+    path: PathBuf,
+    conn: Connection,
+    /// What's stored for each path, to only write what changed
+    stored: HashMap<PathBuf, (u64, u64, u32, bool)>,
+}
+
+impl SqliteStorage {
+
+    /// Open the database at `path`, creating it and its tables if they don't exist.
+    pub fn open(path: &Path) -> io::Result<SqliteStorage> {
+        let conn = Connection::open(path).map_err(io::Error::other)?;
+
+        conn.execute_batch("PRAGMA foreign_keys = ON;").map_err(io::Error::other)?;
+        conn.execute_batch(SCHEMA).map_err(io::Error::other)?;
+
+        Ok(SqliteStorage { path: path.to_path_buf(), conn, stored: HashMap::new() })
+    }
+}
+
+impl Storage for SqliteStorage {
+
+    /// Rows that can't be read, like hashes of the wrong length, are skipped.
+    fn load_hashes(&mut self) -> io::Result<HashDb> {
+        let mut stmt = self.conn.prepare(
+            "SELECT images.path, size, modified, width, height, hash_size, fast, hash
+             FROM images JOIN hashes ON hashes.path = images.path"
+        ).map_err(io::Error::other)?;
+
+        let rows = stmt.query_map([], |row| Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, u32>(3)?,
+            row.get::<_, u32>(4)?,
+            row.get::<_, u32>(5)?,
+            row.get::<_, bool>(6)?,
+            row.get::<_, String>(7)?,
+        ))).map_err(io::Error::other)?;
+
+        let mut db = HashDb::new();
+
+        for row in rows {
+            let (path, size, modified, width, height, hash_size, fast, hash) = row.map_err(io::Error::other)?;
+
+            let hash = match ImageHash::from_base64(&hash, hash_size) {
+                Some(hash) => hash,
+                None => continue,
+            };
+
+            let path = PathBuf::from(path);
+            self.stored.insert(path.clone(), (size as u64, modified as u64, hash_size, fast));
+
+            db.insert_entry(path, DbEntry {
+                size: size as u64,
+                modified: modified as u64,
+                hash_size,
+                fast,
+                width,
+                height,
+                hash,
+            });
+        }
+
+        Ok(db)
+    }
+
+    /// Only the entries that are new or changed since they were loaded or last saved, and the
+    /// removal of those no longer in `db`. In one transaction, so an interrupted save leaves
+    /// the hashes saved before.
+    fn save_hashes(&mut self, db: &HashDb) -> io::Result<()> {
+        let changed: Vec<(&PathBuf, &str, &DbEntry)> = db.entries()
+            .filter(|&(path, entry)| self.stored.get(path) != Some(&(entry.size, entry.modified, entry.hash_size, entry.fast)))
+            .filter_map(|(path, entry)| Some((path, db::storable_path(path)?, entry)))
+            .collect();
+
+        let removed: Vec<PathBuf> = self.stored.keys()
+            .filter(|&path| !db.contains(path))
+            .cloned()
+            .collect();
+
+        if changed.is_empty() && removed.is_empty() { return Ok(()); }
+
+        let tx = self.conn.transaction().map_err(io::Error::other)?;
+
+        {
+            let mut image = tx.prepare_cached(
+                "INSERT INTO images (path, size, modified, width, height) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (path) DO UPDATE SET size = ?2, modified = ?3, width = ?4, height = ?5"
+            ).map_err(io::Error::other)?;
+
+            let mut hash = tx.prepare_cached(
+                "INSERT INTO hashes (path, hash_size, fast, hash) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (path) DO UPDATE SET hash_size = ?2, fast = ?3, hash = ?4"
+            ).map_err(io::Error::other)?;
+
+            // Hashes go with their images
+            let mut remove = tx.prepare_cached("DELETE FROM images WHERE path = ?1").map_err(io::Error::other)?;
+
+            for &(_, path, entry) in &changed {
+                image.execute(params![path, entry.size as i64, entry.modified as i64, entry.width, entry.height])
+                    .map_err(io::Error::other)?;
+                hash.execute(params![path, entry.hash_size, entry.fast, entry.hash.to_base64()])
+                    .map_err(io::Error::other)?;
+            }
+
+            for path in removed.iter().filter_map(|path| db::storable_path(path)) {
+                remove.execute(params![path]).map_err(io::Error::other)?;
+            }
+        }
+
+        tx.commit().map_err(io::Error::other)?;
+
+        for (path, _, entry) in changed {
+            self.stored.insert(path.clone(), (entry.size, entry.modified, entry.hash_size, entry.fast));
+        }
+
+        for path in &removed {
+            self.stored.remove(path);
+        }
+
+        Ok(())
+    }
+
+    /// Only groups with similar images are stored.
+    fn save_results(&mut self, settings: &ProgramSettings, results: &Results) -> io::Result<()> {
+        let tx = self.conn.transaction().map_err(io::Error::other)?;
+
+        let dir = storage::run_dir(settings);
+
+        // Removes the groups and pairs of the last run too
+        tx.execute("DELETE FROM runs WHERE dir = ?1", params![dir]).map_err(io::Error::other)?;
+        tx.execute("INSERT INTO runs (dir, start_time, end_time) VALUES (?1, ?2, ?3)",
+                   params![dir, results.start_time.to_rfc3339(), results.end_time.to_rfc3339()])
+            .map_err(io::Error::other)?;
+
+        let run = tx.last_insert_rowid();
+
+        {
+            let mut group = tx.prepare_cached(
+                "INSERT INTO groups (run, original, wasted_bytes) VALUES (?1, ?2, ?3)"
+            ).map_err(io::Error::other)?;
+
+            let mut pair = tx.prepare_cached(
+                "INSERT INTO pairs (group_id, original, duplicate, distance, similarity, tier, match)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+            ).map_err(io::Error::other)?;
+
+            for unique in results.uniques.iter().filter(|unique| !unique.similars.is_empty()) {
+                let original = storage::result_path(settings, &unique.img.path);
+
+                group.execute(params![run, original, unique.wasted_bytes() as i64]).map_err(io::Error::other)?;
+                let group_id = tx.last_insert_rowid();
+
+                for similar in &unique.similars {
+                    pair.execute(params![
+                        group_id, original, storage::result_path(settings, &similar.img.path),
+                        similar.dist as i64, similar.similarity(), similar.tier.name(), similar.reason.name(),
+                    ]).map_err(io::Error::other)?;
+                }
+            }
+        }
+
+        tx.commit().map_err(io::Error::other)
+    }
+
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_args;
+    use crate::img::{Image, SimilarImage, UniqueImage};
+    use crate::processing::Phases;
+
+    use chrono::Local;
+
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn image(path: &str) -> Image {
+        Image::new(PathBuf::from(path), ImageHash::from_bytes(&[0; 8], 8).unwrap(), 100, 100, 1000)
+    }
+
+    #[test]
+    fn collated_paths_round_trip() {
+        let dir = env::temp_dir().join(format!("img_dup_sqlite_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let db = dir.join("results.sqlite");
+
+        let settings = parse_args(&["collate".to_string(), format!("--db={}", db.display())]);
+
+        let mut unique = UniqueImage::from_image(image("nas1:/photos/a.jpg"));
+        unique.similars.push(SimilarImage::from_image(image("nas2:/backup/a.jpg"), 0.0, 0, settings.near_threshold));
+
+        let results = Results {
+            total: 2,
+            start_time: Local::now(),
+            end_time: Local::now(),
+            uniques: vec![unique],
+            errors: Vec::new(),
+            phases: Phases { search_secs: None, hashing_secs: None, grouping_secs: None },
+        };
+
+        let mut storage = SqliteStorage::open(&db).unwrap();
+        storage.save_results(&settings, &results).unwrap();
+
+        let stored: (String, String, String) = storage.conn.query_row(
+            "SELECT dir, pairs.original, duplicate FROM runs JOIN groups ON run = runs.id JOIN pairs ON group_id = groups.id",
+            [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(stored, ("collate".to_string(), "nas1:/photos/a.jpg".to_string(), "nas2:/backup/a.jpg".to_string()));
+    }
+}
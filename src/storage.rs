@@ -0,0 +1,154 @@
+//! Where hashes, and the results of a run, are kept between runs: a hash database file with
+//! `--cache`, or with `--db`, a SQLite database with the `sqlite` feature, or a PostgreSQL
+//! database shared by several machines with the `postgres` feature.
+//!
+//! Other backends can be added by implementing `Storage` and opening them in `open()`.
+
+use crate::config::{HashSettings, Mode, ProgramSettings};
+use crate::db::HashDb;
+use crate::img::Image;
+use crate::processing::{self, Phases, Results};
+
+use chrono::Local;
+
+use std::io;
+use std::path::{self, Path, PathBuf};
+
+/// Hashes of images to reuse while they're unchanged, and optionally the groups found with them.
+/// Used from the thread that collects the hashed images, so it must be `Send`.
+pub trait Storage: Send {
+
+    /// Every hash stored so far, with any settings. Empty if nothing was stored yet.
+    fn load_hashes(&mut self) -> io::Result<HashDb>;
+
+    /// Store every entry of `db`, replacing those stored for the same paths, and remove those
+    /// that aren't in `db` anymore.
+    fn save_hashes(&mut self, db: &HashDb) -> io::Result<()>;
+
+    /// Store the groups of similar images found in a run, replacing those of the last run
+    /// in the same directory, named by `run_dir()` and `result_path()`. Does nothing if only
+    /// hashes are kept.
+    fn save_results(&mut self, _settings: &ProgramSettings, _results: &Results) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Every image stored that was hashed with the same hash size and type, without checking
+    /// if it changed since. Where several machines store hashes, their paths say which one
+    /// each image is on.
+    fn images(&mut self, settings: &HashSettings) -> io::Result<Vec<Image>> {
+        Ok(self.load_hashes()?.images(settings))
+    }
+
+    /// Where it is, for messages.
+    fn name(&self) -> String;
+}
+
+/// A hash database file, from `--cache`. Only hashes are kept.
+pub struct HashDbFile {
+    path: PathBuf,
+}
+
+impl HashDbFile {
+
+    pub fn new(path: PathBuf) -> HashDbFile {
+        HashDbFile { path }
+    }
+}
+
+impl Storage for HashDbFile {
+
+    fn load_hashes(&mut self) -> io::Result<HashDb> {
+        HashDb::load(&self.path)
+    }
+
+    /// Replaces the whole file, which is written to a temporary file first.
+    fn save_hashes(&mut self, db: &HashDb) -> io::Result<()> {
+        db.save(&self.path)
+    }
+
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+/// The storage given with `--db` or `--cache`, if either was.
+pub fn open(settings: &ProgramSettings) -> io::Result<Option<Box<dyn Storage>>> {
+    if let Some(ref path) = settings.results_db {
+        return match path.to_str() {
+            Some(url) if is_postgres_url(url) => open_postgres(url).map(Some),
+            _ => open_sqlite(path).map(Some),
+        };
+    }
+
+    Ok(settings.cache.clone().map(|path| Box::new(HashDbFile::new(path)) as Box<dyn Storage>))
+}
+
+/// If `--db` names a PostgreSQL database rather than a file.
+pub fn is_postgres_url(db: &str) -> bool {
+    db.starts_with("postgres://") || db.starts_with("postgresql://")
+}
+
+/// Group every image in `storage`, e.g. hashed by several machines, without touching
+/// the images themselves.
+pub fn collate(settings: &ProgramSettings, storage: &mut dyn Storage) -> io::Result<Results> {
+    let start_time = Local::now();
+
+    let mut images = storage.images(&settings.hash_settings())?;
+    let total = images.len();
+
+    processing::sort_by_path(&mut images);
+
+    let (uniques, grouping_secs) = processing::timed(|| processing::group_images(images, settings));
+
+    Ok(Results {
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors: Vec::new(),
+        phases: Phases { search_secs: None, hashing_secs: None, grouping_secs: Some(grouping_secs) },
+    })
+}
+
+/// What a run is stored under in place of its directory: `collate` for `collate`, whose images
+/// can be on any machine, so it never replaces the run of a directory, or the absolute directory.
+pub fn run_dir(settings: &ProgramSettings) -> String {
+    match settings.mode {
+        Mode::Collate(_) => "collate".to_string(),
+        _ => absolute(&settings.dir).display().to_string(),
+    }
+}
+
+/// How the path of an image in the results is stored: as it is for `collate`, where it's
+/// already absolute or names the machine the image is on, like `nas1:/photos/a.jpg`, and
+/// absolute otherwise.
+pub fn result_path(settings: &ProgramSettings, path: &Path) -> String {
+    match settings.mode {
+        Mode::Collate(_) => path.display().to_string(),
+        _ => absolute(path).display().to_string(),
+    }
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(path: &Path) -> io::Result<Box<dyn Storage>> {
+    crate::sqlite::SqliteStorage::open(path).map(|storage| Box::new(storage) as Box<dyn Storage>)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite(_: &Path) -> io::Result<Box<dyn Storage>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "img_dup was not compiled with SQLite support"))
+}
+
+#[cfg(feature = "postgres")]
+fn open_postgres(url: &str) -> io::Result<Box<dyn Storage>> {
+    crate::postgres::PostgresStorage::connect(url).map(|storage| Box::new(storage) as Box<dyn Storage>)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn open_postgres(_: &str) -> io::Result<Box<dyn Storage>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "img_dup was not compiled with PostgreSQL support"))
+}
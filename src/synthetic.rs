@@ -0,0 +1,98 @@
+//! Generated images and hashes, for `--bench-synthetic` and the benchmarks in `benches/`.
+//! Everything is derived from a seed, so runs can be compared with each other.
+
+use crate::hash::ImageHash;
+
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, Rgb, RgbImage};
+
+use std::io;
+use std::path::Path;
+
+/// A small, deterministic random number generator: good enough for test data.
+pub struct Lcg(u64);
+
+impl Lcg {
+
+    pub fn new(seed: u64) -> Lcg {
+        Lcg(seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) as u32
+    }
+
+    /// From 0 up to, but not including, `n`.
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.next_u32() % n.max(1)
+    }
+}
+
+/// A gradient with a few colored rectangles on it, different for every seed.
+pub fn image(seed: u64, width: u32, height: u32) -> RgbImage {
+    let mut rng = Lcg::new(seed);
+
+    let (from, to) = (random_color(&mut rng), random_color(&mut rng));
+
+    let mut img = RgbImage::from_fn(width, height, |x, _| {
+        let t = x as f32 / width.max(1) as f32;
+        Rgb([0, 1, 2].map(|c| (from[c] as f32 * (1.0 - t) + to[c] as f32 * t) as u8))
+    });
+
+    for _ in 0..3 + rng.below(5) {
+        let (left, top) = (rng.below(width), rng.below(height));
+        let (right, bottom) = ((left + 1 + rng.below(width / 2)).min(width), (top + 1 + rng.below(height / 2)).min(height));
+        let color = random_color(&mut rng);
+
+        for y in top..bottom {
+            for x in left..right {
+                img.put_pixel(x, y, Rgb(color));
+            }
+        }
+    }
+
+    img
+}
+
+fn random_color(rng: &mut Lcg) -> [u8; 3] {
+    [rng.below(256) as u8, rng.below(256) as u8, rng.below(256) as u8]
+}
+
+/// A random hash of `hash_size * hash_size` bits.
+pub fn hash(rng: &mut Lcg, hash_size: u32) -> ImageHash {
+    let bytes: Vec<u8> = (0..(hash_size * hash_size).div_ceil(8)).map(|_| rng.below(256) as u8).collect();
+
+    ImageHash::from_bytes(&bytes, hash_size).unwrap()
+}
+
+/// Write `count` different images to `dir` as PNGs, plus a half-size copy of every fourth one
+/// and a JPEG copy of every eighth, so there are duplicates to find. Returns how many files
+/// were written.
+pub fn generate(dir: &Path, count: usize) -> io::Result<usize> {
+    let mut written = 0;
+
+    for i in 0..count {
+        let img = image(i as u64, 256, 192);
+
+        save(&img, &dir.join(format!("synthetic_{:06}.png", i)), ImageFormat::Png)?;
+        written += 1;
+
+        if i % 4 == 0 {
+            let resized = imageops::resize(&img, 128, 96, FilterType::Triangle);
+            save(&resized, &dir.join(format!("synthetic_{:06}_small.png", i)), ImageFormat::Png)?;
+            written += 1;
+        }
+
+        if i % 8 == 0 {
+            save(&img, &dir.join(format!("synthetic_{:06}.jpg", i)), ImageFormat::Jpeg)?;
+            written += 1;
+        }
+    }
+
+    Ok(written)
+}
+
+fn save(img: &RgbImage, path: &Path, format: ImageFormat) -> io::Result<()> {
+    img.save_with_format(path, format).map_err(io::Error::other)
+}
@@ -0,0 +1,65 @@
+//! `--throttle`: run in the background without making the machine unusable, by lowering
+//! the priority of the worker threads and capping how fast files are read.
+
+use log::debug;
+
+use std::cmp;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Shared by every worker of a run, so the cap is for all of them together.
+#[derive(Debug)]
+pub struct Throttle {
+    /// Bytes per second, or 0 for no cap
+    max_read_rate: u64,
+    /// When the reads reserved so far will have been spread out to
+    next_read: Mutex<Instant>,
+}
+
+impl Throttle {
+
+    pub fn new(max_read_rate: u64) -> Throttle {
+        Throttle { max_read_rate, next_read: Mutex::new(Instant::now()) }
+    }
+
+    pub fn max_read_rate(&self) -> u64 {
+        self.max_read_rate
+    }
+
+    /// Wait until `bytes` more can be read without going over the cap on average.
+    /// A file is read in one go once it's its turn, and the files after it wait longer.
+    pub fn wait_to_read(&self, bytes: u64) {
+        if self.max_read_rate == 0 { return; }
+
+        let start = {
+            let mut next_read = self.next_read.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            let start = cmp::max(*next_read, Instant::now());
+            *next_read = start + Duration::from_secs_f64(bytes as f64 / self.max_read_rate as f64);
+            start
+        };
+
+        let wait = start.saturating_duration_since(Instant::now());
+
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// Give the current thread the lowest priority, so anything else that needs the CPU gets it first.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn lower_priority() {
+    use thread_priority::{set_current_thread_priority, ThreadPriority};
+
+    if let Err(err) = set_current_thread_priority(ThreadPriority::Min) {
+        debug!("Could not lower the priority of a worker thread: {:?}", err);
+    }
+}
+
+/// There are no threads to lower the priority of.
+#[cfg(target_arch = "wasm32")]
+pub fn lower_priority() {
+    debug!("Thread priorities aren't supported on this platform");
+}
@@ -0,0 +1,52 @@
+use crate::exact;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Thumbnails fit in a square of this many pixels.
+pub const THUMBNAIL_SIZE: u32 = 200;
+
+/// Where the thumbnail of `path` is stored in `dir`.
+///
+/// Thumbnails are named after a digest of the absolute path of the image,
+/// so images with the same name in different directories don't collide.
+pub fn thumbnail_path(dir: &Path, path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+    dir.join(format!("{:016x}.jpg", exact::fnv1a(path.as_os_str().as_encoded_bytes())))
+}
+
+/// The thumbnail of `path` in `dir`, if it exists and is newer than the image.
+pub fn existing(dir: &Path, path: &Path) -> Option<PathBuf> {
+    let thumbnail = thumbnail_path(dir, path);
+
+    let thumb_modified = fs::metadata(&thumbnail).and_then(|meta| meta.modified()).ok()?;
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok()?;
+
+    if thumb_modified >= modified { Some(thumbnail) } else { None }
+}
+
+/// Scale down the already decoded `img` and save it as the thumbnail of `path` in `dir`.
+pub fn generate(dir: &Path, path: &Path, img: &DynamicImage) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let thumbnail = thumbnail_path(dir, path);
+
+    // JPEG has no alpha channel
+    let scaled = img.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle).to_rgb8();
+
+    let mut file = BufWriter::new(File::create(&thumbnail)?);
+
+    match scaled.write_to(&mut file, ImageFormat::Jpeg) {
+        Ok(()) => Ok(thumbnail),
+        Err(err) => {
+            let _ = fs::remove_file(&thumbnail);
+
+            Err(io::Error::other(format!("Could not encode thumbnail: {}", err)))
+        },
+    }
+}
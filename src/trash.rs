@@ -0,0 +1,297 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Move `path` to the current user's trash, so it can be restored later.
+/// Returns where it is in the trash.
+///
+/// Files on another filesystem than the home trash go to the trash at the top of their own,
+/// `.Trash/$uid` or `.Trash-$uid`.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    // https://specifications.freedesktop.org/trash-spec/trashspec-latest.html
+    let home_trash = match std::env::var_os("XDG_DATA_HOME") {
+        Some(data_home) => PathBuf::from(data_home).join("Trash"),
+        None => home_dir()?.join(".local").join("share").join("Trash"),
+    };
+
+    let path = std::path::absolute(path)?;
+    let device = path.symlink_metadata()?.dev();
+
+    if fs::create_dir_all(&home_trash).is_ok() && home_trash.metadata()?.dev() == device {
+        return trash_into(&home_trash, &path, &path);
+    }
+
+    let top = top_dir(&path, device)?;
+
+    // Paths in these trashes are relative to the top, in case it's mounted somewhere else later
+    trash_into(&top_trash(&top)?, &path, path.strip_prefix(&top).unwrap_or(&path))
+}
+
+/// Move `path` into the `files` directory of `trash`, with its original path `info_path`
+/// recorded in the `info` directory.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn trash_into(trash: &Path, path: &Path, info_path: &Path) -> io::Result<PathBuf> {
+    use std::io::Write;
+    use std::os::unix::ffi::OsStrExt;
+
+    let files_dir = trash.join("files");
+    let info_dir = trash.join("info");
+
+    fs::create_dir_all(&files_dir)?;
+    fs::create_dir_all(&info_dir)?;
+
+    let name = unused_name(&files_dir, path, |name| info_dir.join(format!("{}.trashinfo", name)))?;
+
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(info_path.as_os_str().as_bytes()),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+
+    let info_path = info_dir.join(format!("{}.trashinfo", name));
+
+    fs::File::create(&info_path)?.write_all(info.as_bytes())?;
+
+    let trashed = files_dir.join(&name);
+
+    fs::rename(path, &trashed).inspect_err(|_| {
+        let _ = fs::remove_file(&info_path);
+    })?;
+
+    Ok(trashed)
+}
+
+/// Where the filesystem `path` is on, `device`, is mounted: the highest directory above it
+/// that's still on it.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn top_dir(path: &Path, device: u64) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut top = path.parent().unwrap_or(path);
+
+    while let Some(parent) = top.parent() {
+        if parent.metadata()?.dev() != device { break; }
+        top = parent;
+    }
+
+    Ok(top.to_path_buf())
+}
+
+/// The current user's trash at `top`: `.Trash/$uid` if an administrator made `.Trash` for every
+/// user, or else `.Trash-$uid`, which only the user can get into.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn top_trash(top: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let uid = unsafe { libc::getuid() };
+
+    // Without the sticky bit, other users could take the files out of it; the spec says to
+    // skip it then, and if it's a link
+    let shared = top.join(".Trash");
+
+    if let Ok(meta) = shared.symlink_metadata() {
+        if meta.is_dir() && meta.permissions().mode() & 0o1000 != 0 {
+            let trash = shared.join(uid.to_string());
+
+            if fs::DirBuilder::new().recursive(true).mode(0o700).create(&trash).is_ok() {
+                return Ok(trash);
+            }
+        }
+    }
+
+    let trash = top.join(format!(".Trash-{}", uid));
+
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(&trash)?;
+
+    if trash.symlink_metadata()?.file_type().is_symlink() {
+        return Err(io::Error::other(format!("{} is a link", trash.display())));
+    }
+
+    Ok(trash)
+}
+
+/// Forget a file that was taken back out of the trash from `trashed`.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn forget(trashed: &Path) -> io::Result<()> {
+    let (Some(files_dir), Some(name)) = (trashed.parent(), trashed.file_name()) else { return Ok(()) };
+    let Some(trash) = files_dir.parent() else { return Ok(()) };
+
+    let mut info_name = name.to_os_string();
+    info_name.push(".trashinfo");
+
+    match fs::remove_file(trash.join("info").join(info_name)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// The trash on macOS keeps nothing but the files.
+#[cfg(target_os = "macos")]
+pub fn forget(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Forget a file that was taken back out of the Recycle Bin from `trashed`, its `$R` file.
+#[cfg(windows)]
+pub fn forget(trashed: &Path) -> io::Result<()> {
+    let Some(random) = trashed.file_name().and_then(|name| name.to_str()?.strip_prefix("$R")) else { return Ok(()) };
+
+    match fs::remove_file(trashed.with_file_name(format!("$I{}", random))) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    let trash = home_dir()?.join(".Trash");
+
+    let name = unused_name(&trash, path, |name| trash.join(name))?;
+    let trashed = trash.join(&name);
+
+    fs::rename(path, &trashed)?;
+
+    Ok(trashed)
+}
+
+/// Move `path` to the Recycle Bin through the shell, so it can be restored from Explorer.
+/// Returns where it is in the Recycle Bin.
+///
+/// Fails if its drive isn't a fixed disk, since the shell would delete it from removable
+/// and network drives for good.
+#[cfg(windows)]
+pub fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    use std::os::windows::ffi::OsStrExt;
+
+    use windows_sys::Win32::Storage::FileSystem::GetDriveTypeW;
+    use windows_sys::Win32::System::WindowsProgramming::DRIVE_FIXED;
+    use windows_sys::Win32::UI::Shell::{
+        SHFileOperationW, FOF_ALLOWUNDO, FOF_NOCONFIRMATION, FOF_NOERRORUI, FOF_SILENT, FO_DELETE, SHFILEOPSTRUCTW,
+    };
+
+    let path = std::path::absolute(path)?;
+
+    // The drive, e.g. `C:\`
+    let root: PathBuf = path.components().take(2).collect();
+    let root_wide: Vec<u16> = root.as_os_str().encode_wide().chain(Some(0)).collect();
+
+    if unsafe { GetDriveTypeW(root_wide.as_ptr()) } != DRIVE_FIXED {
+        return Err(io::Error::other(format!("{} has no Recycle Bin", root.display())));
+    }
+
+    // A list of paths, ended by an empty one
+    let from: Vec<u16> = path.as_os_str().encode_wide().chain([0, 0]).collect();
+
+    let mut operation = SHFILEOPSTRUCTW {
+        hwnd: std::ptr::null_mut(),
+        wFunc: FO_DELETE,
+        pFrom: from.as_ptr(),
+        pTo: std::ptr::null(),
+        fFlags: (FOF_ALLOWUNDO | FOF_NOCONFIRMATION | FOF_NOERRORUI | FOF_SILENT) as u16,
+        fAnyOperationsAborted: 0,
+        hNameMappings: std::ptr::null_mut(),
+        lpszProgressTitle: std::ptr::null(),
+    };
+
+    match unsafe { SHFileOperationW(&mut operation) } {
+        0 if operation.fAnyOperationsAborted == 0 => (),
+        0 => return Err(io::Error::other("Moving to the Recycle Bin was cancelled")),
+        code => return Err(io::Error::other(format!("Could not move to the Recycle Bin (error {:#x})", code))),
+    }
+
+    find_recycled(&root.join("$Recycle.Bin"), &path)?
+        .ok_or_else(|| io::Error::other("Moved to the Recycle Bin, but could not find it there"))
+}
+
+/// Where the shell put `path` in `recycle_bin`. Every file in it is renamed to `$R` and a random
+/// name, in a directory for each user, next to a `$I` file of the same name that records its
+/// original path; if the same path was recycled more than once, the latest is taken.
+#[cfg(windows)]
+fn find_recycled(recycle_bin: &Path, path: &Path) -> io::Result<Option<PathBuf>> {
+    use std::os::windows::ffi::OsStringExt;
+
+    let mut found: Option<(u64, PathBuf)> = None;
+
+    // The directories of other users can't be read
+    for user_dir in fs::read_dir(recycle_bin)?.filter_map(Result::ok) {
+        let Ok(entries) = fs::read_dir(user_dir.path()) else { continue };
+
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(random) = name.strip_prefix("$I") else { continue };
+            let Ok(info) = fs::read(entry.path()) else { continue };
+
+            if info.len() < 24 { continue; }
+
+            // Version 1 has a fixed-size path, version 2 (Windows 10 on) its length first
+            let deleted = u64::from_le_bytes(info[16..24].try_into().unwrap());
+            let wide = match info[0] {
+                1 => &info[24..],
+                _ if info.len() >= 28 => &info[28..],
+                _ => continue,
+            };
+
+            let wide: Vec<u16> = wide.chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .take_while(|&c| c != 0)
+                .collect();
+
+            let original = PathBuf::from(std::ffi::OsString::from_wide(&wide));
+
+            if original.as_os_str().eq_ignore_ascii_case(path.as_os_str())
+                && found.as_ref().is_none_or(|&(latest, _)| deleted > latest) {
+                found = Some((deleted, entry.path().with_file_name(format!("$R{}", random))));
+            }
+        }
+    }
+
+    Ok(found.map(|(_, recycled)| recycled))
+}
+
+#[cfg(unix)]
+fn home_dir() -> io::Result<PathBuf> {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::other("Could not find the home directory"))
+}
+
+/// Find a name for `path` that isn't taken in `dir`, by appending a number if necessary.
+/// `other` gives another path that must also be unused for a given name.
+#[cfg(unix)]
+fn unused_name<F: Fn(&str) -> PathBuf>(dir: &Path, path: &Path, other: F) -> io::Result<String> {
+    let filename = match path.file_name().and_then(|name| name.to_str()) {
+        Some(filename) => filename.to_string(),
+        None => return Err(io::Error::other(format!("File name is not valid UTF-8: {}", path.display()))),
+    };
+
+    let mut name = filename.clone();
+    let mut count = 1u32;
+
+    while dir.join(&name).exists() || other(&name).exists() {
+        count += 1;
+        name = match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => format!("{} {}.{}", path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(""), count, ext),
+            None => format!("{} {}", filename, count),
+        };
+    }
+
+    Ok(name)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+
+    for &byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+                | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
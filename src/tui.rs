@@ -0,0 +1,499 @@
+//! With the `tui` feature, `img-dup review [results.json]`: go through the groups of a report
+//! from `--format=json` in the terminal.
+//!
+//! Images are marked for deletion one by one, then either written to a shell script to look
+//! over and run later, or removed right away with `--action` (trash if it isn't set).
+//! Previews use sixel, kitty or iTerm2 graphics if the terminal has them, or half blocks if not.
+
+use crate::actions::{self, ActionOptions};
+use crate::config::{Action, FrameStrategy, ProgramSettings};
+use crate::decoders::{self, Decoders};
+use crate::exif::{self, Orientation};
+use crate::img::{Image, UniqueImage};
+use crate::processing;
+use crate::serialize;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use ratatui_image::picker::Picker;
+use ratatui_image::protocol::StatefulProtocol;
+use ratatui_image::StatefulImage;
+
+use log::{info, LevelFilter};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Previews are scaled down to fit in this many pixels before they're sent to the terminal.
+const PREVIEW_SIZE: u32 = 1024;
+
+/// Where `w` writes the script if `--outfile` isn't given.
+const DEFAULT_SCRIPT: &str = "img_dup_delete.sh";
+
+/// Go through the groups of similar images in `report`, until the user quits.
+pub fn review(settings: &ProgramSettings, report: &Path) -> io::Result<()> {
+    let (dir, uniques) = serialize::read_json_report(report)?;
+
+    let groups: Vec<Group> = uniques.iter()
+        .filter(|unique| !unique.similars.is_empty())
+        .map(Group::new)
+        .collect();
+
+    if groups.is_empty() {
+        info!("No similar images in {}.", report.display());
+        return Ok(());
+    }
+
+    let mut terminal = ratatui::init();
+    let picker = Picker::from_query_stdio().unwrap_or_else(|_| Picker::halfblocks());
+
+    // Anything logged would be drawn over the screen
+    let max_level = log::max_level();
+    log::set_max_level(LevelFilter::Off);
+
+    let mut review = Review::new(settings, dir, groups, picker);
+    let res = review.run(&mut terminal);
+
+    ratatui::restore();
+    log::set_max_level(max_level);
+
+    for msg in &review.log {
+        info!("{}", msg);
+    }
+
+    res
+}
+
+struct Review {
+    /// The directory the report was made for, which paths are shown relative to
+    dir: PathBuf,
+    action: Action,
+    options: ActionOptions,
+    script: PathBuf,
+    decoders: Decoders,
+    frames: FrameStrategy,
+    sniff: bool,
+    groups: Vec<Group>,
+    group_list: ListState,
+    focus: Focus,
+    picker: Picker,
+    /// The path of the image being previewed, and its preview or why it couldn't be loaded
+    preview: Option<(PathBuf, Result<StatefulProtocol, String>)>,
+    status: String,
+    confirming: bool,
+    /// If marks were changed since they were last written or applied
+    unsaved: bool,
+    /// Shown once the terminal is back to normal
+    log: Vec<String>,
+}
+
+#[derive(PartialEq)]
+enum Focus {
+    Groups,
+    Images,
+}
+
+struct Group {
+    /// The original first, then its similars
+    images: Vec<Entry>,
+    image_list: ListState,
+}
+
+struct Entry {
+    image: Image,
+    /// How it matched the original, or `None` for the original
+    matched: Option<String>,
+    marked: bool,
+    /// What was done to it, if anything
+    done: Option<Result<String, String>>,
+}
+
+impl Group {
+
+    fn new(unique: &UniqueImage) -> Group {
+        let mut images = vec![Entry { image: unique.img.clone(), matched: None, marked: false, done: None }];
+
+        images.extend(unique.similars.iter().map(|similar| Entry {
+            image: similar.img.clone(),
+            matched: Some(format!("{:.2}% {}", similar.similarity(), similar.tier.name())),
+            marked: false,
+            done: None,
+        }));
+
+        Group { images, image_list: ListState::default().with_selected(Some(0)) }
+    }
+
+    /// The image everything marked is compared to: the first one that isn't marked.
+    fn kept(&self) -> Option<&Image> {
+        self.images.iter().find(|entry| !entry.marked).map(|entry| &entry.image)
+    }
+
+    fn pending(&self) -> impl Iterator<Item = &Entry> {
+        self.images.iter().filter(|entry| entry.marked && !matches!(entry.done, Some(Ok(_))))
+    }
+
+    fn selected(&self) -> usize {
+        self.image_list.selected().unwrap_or(0)
+    }
+}
+
+impl Review {
+
+    fn new(settings: &ProgramSettings, dir: PathBuf, groups: Vec<Group>, picker: Picker) -> Review {
+        Review {
+            dir,
+            action: settings.action.unwrap_or(Action::Trash),
+            options: ActionOptions::new(settings),
+            script: settings.outfile.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_SCRIPT)),
+            decoders: settings.decoders.clone(),
+            frames: settings.frames,
+            sniff: settings.sniff,
+            groups,
+            group_list: ListState::default().with_selected(Some(0)),
+            focus: Focus::Groups,
+            picker,
+            preview: None,
+            status: String::new(),
+            confirming: false,
+            unsaved: false,
+            log: Vec::new(),
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            self.load_preview();
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key.code,
+                _ => continue,
+            };
+
+            if self.confirming {
+                self.confirming = false;
+
+                if key == KeyCode::Char('y') {
+                    self.apply();
+                } else {
+                    self.status = "Nothing was done.".to_string();
+                }
+
+                continue;
+            }
+
+            self.status.clear();
+
+            match key {
+                KeyCode::Char('q') | KeyCode::Esc if self.unsaved => {
+                    self.unsaved = false;
+                    self.status = "Marks haven't been written or applied, press q again to quit anyway.".to_string();
+                },
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.step(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.step(1),
+                KeyCode::Tab | KeyCode::Left | KeyCode::Right | KeyCode::Char('h') | KeyCode::Char('l') => {
+                    self.focus = if self.focus == Focus::Groups { Focus::Images } else { Focus::Groups };
+                },
+                KeyCode::Char(' ') | KeyCode::Char('d') => self.toggle_mark(),
+                KeyCode::Char('a') => self.mark_all_but_original(),
+                KeyCode::Char('u') => self.unmark_all(),
+                KeyCode::Char('w') => self.write_script(),
+                KeyCode::Char('x') => {
+                    let count: usize = self.groups.iter().map(|group| group.pending().count()).sum();
+
+                    if count == 0 {
+                        self.status = "No images are marked.".to_string();
+                    } else {
+                        self.confirming = true;
+                        self.status = format!("{}{} {} marked images? (y/n)",
+                                              if self.options.dry_run { "Dry run: " } else { "" },
+                                              capitalize(self.action.verb()), count);
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+
+    fn group(&mut self) -> &mut Group {
+        let idx = self.group_list.selected().unwrap_or(0);
+        &mut self.groups[idx]
+    }
+
+    /// Move the selection in the focused list by `by`, stopping at either end.
+    fn step(&mut self, by: isize) {
+        let (list, len) = match self.focus {
+            Focus::Groups => (&mut self.group_list, self.groups.len()),
+            Focus::Images => {
+                let group = &mut self.groups[self.group_list.selected().unwrap_or(0)];
+                (&mut group.image_list, group.images.len())
+            },
+        };
+
+        let selected = list.selected().unwrap_or(0).saturating_add_signed(by).min(len - 1);
+        list.select(Some(selected));
+    }
+
+    fn toggle_mark(&mut self) {
+        let group = self.group();
+        let selected = group.selected();
+        let unmarked = group.images.iter().filter(|entry| !entry.marked).count();
+
+        let entry = &mut group.images[selected];
+
+        if !entry.marked && unmarked == 1 {
+            self.status = "At least one image of each group has to be kept.".to_string();
+            return;
+        }
+
+        entry.marked = !entry.marked;
+        self.unsaved = true;
+    }
+
+    fn mark_all_but_original(&mut self) {
+        let group = self.group();
+
+        for (idx, entry) in group.images.iter_mut().enumerate() {
+            entry.marked = idx != 0;
+        }
+
+        self.unsaved = true;
+    }
+
+    fn unmark_all(&mut self) {
+        for entry in &mut self.group().images {
+            entry.marked = false;
+        }
+
+        self.unsaved = true;
+    }
+
+    /// Write a shell script that deletes the marked images, their other paths
+    /// and their paired RAW files.
+    fn write_script(&mut self) {
+        let res = File::create(&self.script).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            let count = write_script(&mut out, &self.groups)?;
+            out.flush()?;
+            Ok(count)
+        });
+
+        self.status = match res {
+            Ok(count) => {
+                self.unsaved = false;
+                let msg = format!("Wrote {} to delete {} images.", self.script.display(), count);
+                self.log.push(msg.clone());
+                msg
+            },
+            Err(err) => format!("Could not write {}: {}", self.script.display(), err),
+        };
+    }
+
+    /// Apply the action to every marked image that hasn't had it yet.
+    fn apply(&mut self) {
+        let (mut done, mut failed) = (0, 0);
+
+        for group in &mut self.groups {
+            let keep = match group.kept() {
+                Some(keep) => keep.clone(),
+                None => continue,
+            };
+
+            for entry in group.images.iter_mut().filter(|entry| entry.marked) {
+                if let Some(Ok(_)) = entry.done { continue; }
+
+                entry.done = Some(match actions::apply_to_image(self.action, &keep, &entry.image, &self.options) {
+                    Ok((applied, _)) => {
+                        let done = applied.describe(self.options.dry_run);
+
+                        if !self.options.dry_run {
+                            self.log.push(format!("{}: {} (kept {})", done, entry.image.path.display(), keep.path.display()));
+                        }
+
+                        Ok(done)
+                    },
+                    Err(err) => {
+                        self.log.push(format!("Failed to {} {}: {}", self.action.verb(),
+                                              entry.image.path.display(), err));
+                        Err(format!("Could not {}: {}", self.action.verb(), err))
+                    },
+                });
+
+                match entry.done {
+                    Some(Ok(_)) => done += 1,
+                    _ => failed += 1,
+                }
+            }
+        }
+
+        if !self.options.dry_run { self.unsaved = false; }
+
+        self.status = if self.options.dry_run {
+            format!("Dry run, {} images would be affected.", done)
+        } else {
+            format!("{} images affected, {} failed.", done, failed)
+        };
+    }
+
+    /// Decode the selected image if it isn't the one being previewed already.
+    fn load_preview(&mut self) {
+        let group = &self.groups[self.group_list.selected().unwrap_or(0)];
+        let image = &group.images[group.selected()].image;
+        let path = image.thumbnail.clone().unwrap_or_else(|| image.path.clone());
+
+        if matches!(self.preview, Some((ref previewed, _)) if *previewed == path) { return; }
+
+        let preview = decoders::open(&path, None, &self.decoders, self.frames, self.sniff)
+            .map(|image| {
+                let image = processing::orient(exif::read_orientation(&path).unwrap_or(Orientation::Normal), image)
+                    .thumbnail(PREVIEW_SIZE, PREVIEW_SIZE);
+
+                self.picker.new_resize_protocol(image)
+            })
+            .map_err(|err| err.to_string());
+
+        self.preview = Some((path, preview));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(frame.area());
+        let [groups, right] = Layout::horizontal([Constraint::Percentage(30), Constraint::Fill(1)]).areas(main);
+        let [images, preview] = Layout::vertical([Constraint::Length(self.image_list_height()), Constraint::Fill(1)])
+            .areas(right);
+
+        self.draw_groups(frame, groups);
+        self.draw_images(frame, images);
+        self.draw_preview(frame, preview);
+
+        let status_line = if self.status.is_empty() {
+            "↑↓ move  tab switch  space mark  a mark all but original  u unmark  \
+             w write script  x apply  q quit".to_string()
+        } else {
+            self.status.clone()
+        };
+
+        frame.render_widget(Paragraph::new(status_line).style(Style::default().add_modifier(Modifier::REVERSED)), status);
+    }
+
+    fn image_list_height(&self) -> u16 {
+        let group = &self.groups[self.group_list.selected().unwrap_or(0)];
+        (group.images.len() as u16).saturating_add(2).min(12)
+    }
+
+    fn draw_groups(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self.groups.iter()
+            .map(|group| {
+                let marked = group.images.iter().filter(|entry| entry.marked).count();
+                let original = &group.images[0].image;
+
+                ListItem::new(format!("{} ({}, {} marked)", original.relative_path(&self.dir).display(),
+                                      group.images.len(), marked))
+            })
+            .collect();
+
+        let title = format!("Groups ({})", self.groups.len());
+        let list = List::new(items).block(block(title, self.focus == Focus::Groups)).highlight_symbol("> ")
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD));
+
+        frame.render_stateful_widget(list, area, &mut self.group_list);
+    }
+
+    fn draw_images(&mut self, frame: &mut Frame, area: Rect) {
+        let dir = &self.dir;
+        let focused = self.focus == Focus::Images;
+        let group = &mut self.groups[self.group_list.selected().unwrap_or(0)];
+
+        let items: Vec<ListItem> = group.images.iter()
+            .map(|entry| {
+                let image = &entry.image;
+
+                let mut spans = vec![
+                    Span::raw(if entry.marked { "[x] " } else { "[ ] " }),
+                    Span::raw(image.relative_path(dir).display().to_string()),
+                    Span::styled(format!("  {}x{} {}, {} bytes, {}", image.width, image.height, image.format_name(),
+                                         image.file_size, entry.matched.as_deref().unwrap_or("original")),
+                                 Style::default().fg(Color::DarkGray)),
+                ];
+
+                match entry.done {
+                    Some(Ok(ref done)) => spans.push(Span::styled(format!("  {}", done), Style::default().fg(Color::Green))),
+                    Some(Err(ref err)) => spans.push(Span::styled(format!("  {}", err), Style::default().fg(Color::Red))),
+                    None => (),
+                }
+
+                let item = ListItem::new(Line::from(spans));
+                if entry.marked { item.style(Style::default().fg(Color::Yellow)) } else { item }
+            })
+            .collect();
+
+        let list = List::new(items).block(block("Images".to_string(), focused)).highlight_symbol("> ")
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD));
+
+        frame.render_stateful_widget(list, area, &mut group.image_list);
+    }
+
+    fn draw_preview(&mut self, frame: &mut Frame, area: Rect) {
+        let block = block("Preview".to_string(), false);
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        match self.preview {
+            Some((_, Ok(ref mut protocol))) => frame.render_stateful_widget(StatefulImage::default(), inner, protocol),
+            Some((_, Err(ref err))) => frame.render_widget(Paragraph::new(format!("No preview: {}", err)), inner),
+            None => (),
+        }
+    }
+}
+
+fn block(title: String, focused: bool) -> Block<'static> {
+    let style = if focused { Style::default().fg(Color::Cyan) } else { Style::default() };
+    Block::bordered().title(title).border_style(style)
+}
+
+/// One `rm` per path, with the image kept from each group as a comment. Returns how many
+/// images are deleted.
+fn write_script(out: &mut dyn Write, groups: &[Group]) -> io::Result<usize> {
+    writeln!(out, "#!/bin/sh")?;
+    writeln!(out, "# Written by img-dup review")?;
+
+    let mut count = 0;
+
+    for group in groups {
+        let Some(keep) = group.kept() else { continue };
+        let marked: Vec<&Image> = group.pending().map(|entry| &entry.image).collect();
+
+        if marked.is_empty() { continue; }
+
+        writeln!(out, "\n# kept: {}", keep.path.display())?;
+
+        for image in marked {
+            for path in Some(&image.path).into_iter().chain(&image.links).chain(&image.sidecars) {
+                writeln!(out, "rm -- {}", shell_quote(path))?;
+            }
+
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,83 @@
+use crate::bktree::BkTree;
+use crate::config::ProgramSettings;
+use crate::db::HashDb;
+use crate::img::{Image, UniqueImage};
+use crate::processing::{self, Phases, Results};
+
+use chrono::Local;
+
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How the hash database changed in an update.
+pub struct UpdateStats {
+    /// New or modified images that were hashed
+    pub hashed: usize,
+    /// Entries removed because their file no longer exists
+    pub removed: usize,
+}
+
+/// Bring the hash database at `db_path` up to date with `paths`, found in `settings.dir`,
+/// and save it. Only new or modified images are hashed.
+///
+/// The results only contain the groups of similar images that at least one of them is in.
+pub fn update(settings: &ProgramSettings, db_path: &Path, paths: Vec<PathBuf>) 
+    -> io::Result<(Results, UpdateStats)> {
+    let start_time = Local::now();
+    let hash_settings = settings.hash_settings();
+
+    let mut db = HashDb::load(db_path)?;
+
+    let removed = db.remove_missing(&settings.dir);
+
+    let (mut known, changed) = db.partition(paths, &hash_settings);
+    processing::sort_by_path(&mut known);
+
+    let ((hashed, errors), hashing_secs) = processing::timed(|| processing::hash_all(settings, changed));
+
+    let changed: HashSet<PathBuf> = hashed.iter().map(|image| image.path.clone()).collect();
+    let total = known.len() + hashed.len();
+
+    for image in &hashed {
+        db.insert(image, &hash_settings);
+    }
+
+    db.save(db_path)?;
+
+    let (uniques, grouping_secs) = processing::timed(|| changed_groups(settings, known, hashed, &changed));
+
+    let results = Results {
+        total,
+        start_time,
+        end_time: Local::now(),
+        uniques,
+        errors,
+        phases: Phases { search_secs: None, hashing_secs: Some(hashing_secs), grouping_secs: Some(grouping_secs) },
+    };
+
+    Ok((results, UpdateStats { hashed: changed.len(), removed }))
+}
+
+/// Group `known` and `hashed` images, and keep only the groups of similar images
+/// that at least one image in `changed` is in.
+pub fn changed_groups(settings: &ProgramSettings, known: Vec<Image>, hashed: Vec<Image>, 
+                      changed: &HashSet<PathBuf>) -> Vec<UniqueImage> {
+    let mut uniques = Vec::new();
+    let mut index = BkTree::new();
+
+    // Known images first, so they're the originals that changed images are compared to
+    for image in known.into_iter().chain(hashed) {
+        processing::manage_images_indexed(&mut uniques, &mut index, image, settings);
+    }
+
+    processing::choose_originals(&mut uniques, settings);
+    processing::pair_thumbnails(&mut uniques, settings);
+
+    uniques.retain(|unique| 
+        !unique.similars.is_empty() && (changed.contains(&unique.img.path) 
+            || unique.similars.iter().any(|similar| changed.contains(&similar.img.path)))
+    );
+
+    uniques
+}
@@ -0,0 +1,83 @@
+use crate::config::HashSettings;
+use crate::img::Image;
+
+use image::GenericImageView;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Extensions of videos, searched for with `--video`.
+pub static VIDEO_EXTS: &[&str] = &["mp4", "webm", "mkv"];
+
+/// Where frames are taken, as fractions of the duration.
+/// The same for every video, so the frames of two copies of a clip line up.
+static FRAME_POSITIONS: &[f64] = &[0.1, 0.3, 0.5, 0.7, 0.9];
+
+pub fn is_video(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => VIDEO_EXTS.iter().any(|known| known.eq_ignore_ascii_case(ext)),
+        None => false,
+    }
+}
+
+/// Hash frames spread across the video, extracted with `ffmpeg`.
+///
+/// The first frame's hash is the main hash and the rest are combined with it,
+/// so two videos are compared by the average difference of their frames.
+pub fn hash_video(path: &Path, settings: &HashSettings) -> Result<Image, String> {
+    let duration = probe_duration(path)?;
+
+    let tmp_dir = tempfile::Builder::new().prefix("img_dup").tempdir().map_err(|err| err.to_string())?;
+
+    let mut frames = Vec::new();
+
+    for (num, &position) in FRAME_POSITIONS.iter().enumerate() {
+        let frame_path = tmp_dir.path().join(format!("{}.png", num));
+        let timestamp = format!("{:.3}", duration * position);
+
+        run(Command::new("ffmpeg")
+            .args(["-v", "error", "-ss", &timestamp, "-i"]).arg(path)
+            .args(["-frames:v", "1"]).arg(&frame_path))?;
+
+        frames.push(image::open(&frame_path).map_err(|err| err.to_string())?);
+    }
+
+    let (width, height) = frames[0].dimensions();
+
+    let mut hashes = frames.iter()
+        .map(|frame| settings.hash(frame));
+
+    let hash = hashes.next().unwrap();
+    let file_size = fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+
+    let mut image = Image::new(path.to_path_buf(), hash, width, height, file_size);
+    image.combined = hashes.map(|hash| (hash, 1f32)).collect();
+
+    Ok(image)
+}
+
+/// The duration of the video in seconds, from `ffprobe`.
+fn probe_duration(path: &Path) -> Result<f64, String> {
+    let output = run(Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration", 
+               "-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path))?;
+
+    output.trim().parse::<f64>()
+        .map_err(|_| format!("Could not read the duration of the video: {}", output.trim()))
+}
+
+/// Run the command and get its standard output.
+fn run(command: &mut Command) -> Result<String, String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+
+    let output = command.output()
+        .map_err(|err| format!("Could not run {}: {}", program, err))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(format!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr)))
+    }
+}
@@ -0,0 +1,279 @@
+use crate::bktree::BkTree;
+use crate::config::{ProgramSettings, Root};
+use crate::img::{self, Image, SimilarImage};
+use crate::processing::{self, CancelToken, ProcessingError};
+use crate::search;
+
+use log::warn;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+#[allow(clippy::large_enum_variant)]
+pub enum WatchEvent {
+    /// A new or modified image (the `SimilarImage`) is similar to one that was already there
+    Duplicate(Image, SimilarImage),
+    /// A new or modified file couldn't be processed. It's tried again if it's modified again,
+    /// e.g. if it was still being written.
+    Error(ProcessingError),
+}
+
+/// Watch `settings.dir` until `cancel` is canceled, hashing new and modified images
+/// and comparing them to every image seen so far. Nothing is checked while `cancel` is paused.
+///
+/// Images that are already there when watching starts are hashed first, but not reported.
+/// Changes are picked up from the operating system as they happen; if it can't watch the
+/// directory, it's searched again every `settings.interval` seconds instead.
+pub fn watch<F: FnMut(WatchEvent)>(settings: &ProgramSettings, cancel: &CancelToken, mut on_event: F) {
+    let mut state = WatchState {
+        images: Vec::new(),
+        index: BkTree::new(),
+        seen: HashMap::new(),
+    };
+
+    state.scan(settings, |_| ());
+
+    match ChangeWatcher::new(&settings.roots) {
+        Ok(changes) => watch_changes(settings, cancel, &mut state, &changes, &mut on_event),
+        Err(err) => {
+            warn!("Could not watch for changes, checking every {} seconds instead: {}", settings.interval, err);
+            poll(settings, cancel, &mut state, &mut on_event);
+        },
+    }
+}
+
+/// Search again every `settings.interval` seconds.
+fn poll<F: FnMut(WatchEvent)>(settings: &ProgramSettings, cancel: &CancelToken, state: &mut WatchState, on_event: &mut F) {
+    while !cancel.is_canceled() {
+        thread::sleep(Duration::from_secs(settings.interval));
+
+        // Changes made while paused are picked up by the first scan after resuming
+        cancel.wait_while_paused();
+
+        if cancel.is_canceled() { break; }
+
+        state.scan(settings, &mut *on_event);
+    }
+}
+
+/// Check the files and directories the operating system says changed, once they've settled.
+fn watch_changes<F: FnMut(WatchEvent)>(settings: &ProgramSettings, cancel: &CancelToken, state: &mut WatchState,
+                                       changes: &ChangeWatcher, on_event: &mut F) {
+    while !cancel.is_canceled() {
+        // Wake up now and then to see if it was canceled
+        let Some(mut changed) = changes.next(CANCEL_CHECK) else { continue };
+
+        // Files are usually written in more than one go: wait until nothing has changed for a while
+        while let Some(more) = changes.next(SETTLE) {
+            changed.extend(more);
+        }
+
+        // Changes made while paused are picked up after resuming
+        cancel.wait_while_paused();
+
+        if cancel.is_canceled() { break; }
+
+        match changed {
+            Changed::Paths(paths) => state.scan_paths(settings, &paths, &mut *on_event),
+            Changed::Unknown => state.scan(settings, &mut *on_event),
+        }
+    }
+}
+
+/// How long to wait for another change before checking what changed.
+const SETTLE: Duration = Duration::from_millis(500);
+
+const CANCEL_CHECK: Duration = Duration::from_secs(1);
+
+/// What changed in the roots being watched.
+// Nothing is watched on wasm
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+enum Changed {
+    Paths(Vec<PathBuf>),
+    /// Changes were missed, e.g. because there were too many at once
+    Unknown,
+}
+
+impl Changed {
+
+    fn extend(&mut self, other: Changed) {
+        match (self, other) {
+            (Changed::Paths(paths), Changed::Paths(more)) => paths.extend(more),
+            (this, _) => *this = Changed::Unknown,
+        }
+    }
+}
+
+/// Changes in the roots, from the operating system.
+#[cfg(not(target_arch = "wasm32"))]
+struct ChangeWatcher {
+    // Stops watching when dropped
+    _watcher: notify::RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    /// Each root, and the absolute paths the paths of its events can start with
+    roots: Vec<(PathBuf, Vec<PathBuf>)>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ChangeWatcher {
+
+    fn new(roots: &[Root]) -> notify::Result<ChangeWatcher> {
+        use notify::{RecursiveMode, Watcher};
+
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+
+        for root in roots {
+            let mode = if root.recurse { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+            watcher.watch(&root.dir, mode)?;
+        }
+
+        let roots = roots.iter()
+            .map(|root| {
+                let absolute = [std::path::absolute(&root.dir).ok(), root.dir.canonicalize().ok()];
+                (root.dir.clone(), absolute.into_iter().flatten().collect())
+            })
+            .collect();
+
+        Ok(ChangeWatcher { _watcher: watcher, events, roots })
+    }
+
+    /// The changes in the next event, or `None` if there was none within `timeout`.
+    fn next(&self, timeout: Duration) -> Option<Changed> {
+        use notify::EventKind;
+
+        let event = match self.events.recv_timeout(timeout) {
+            Ok(Ok(event)) => event,
+            Ok(Err(err)) => {
+                warn!("Error watching for changes, searching again: {}", err);
+                return Some(Changed::Unknown);
+            },
+            Err(RecvTimeoutError::Timeout) => return None,
+            // The watcher is kept until this is dropped
+            Err(RecvTimeoutError::Disconnected) => unreachable!(),
+        };
+
+        if event.need_rescan() { return Some(Changed::Unknown); }
+
+        if matches!(event.kind, EventKind::Access(_) | EventKind::Remove(_)) { return Some(Changed::Paths(Vec::new())); }
+
+        Some(Changed::Paths(event.paths.iter().filter_map(|path| self.as_searched(path)).collect()))
+    }
+
+    /// `path` as a search of the roots would find it, e.g. relative if the root is.
+    fn as_searched(&self, path: &Path) -> Option<PathBuf> {
+        self.roots.iter()
+            .flat_map(|(root, absolute)| absolute.iter().map(move |absolute| (root, absolute)))
+            .find_map(|(root, absolute)| path.strip_prefix(absolute).ok().map(|relative| root.join(relative)))
+    }
+}
+
+/// There's nothing to watch with.
+#[cfg(target_arch = "wasm32")]
+struct ChangeWatcher;
+
+#[cfg(target_arch = "wasm32")]
+impl ChangeWatcher {
+
+    fn new(_: &[Root]) -> std::io::Result<ChangeWatcher> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "watching isn't supported on this platform"))
+    }
+
+    fn next(&self, _: Duration) -> Option<Changed> {
+        None
+    }
+}
+
+struct WatchState {
+    /// `None` if the image was modified since, and replaced by a later entry
+    images: Vec<Option<Image>>,
+    index: BkTree,
+    /// Modification time and index in `images` of each file seen so far
+    seen: HashMap<PathBuf, (u64, Option<usize>)>,
+}
+
+impl WatchState {
+
+    fn scan<F: FnMut(WatchEvent)>(&mut self, settings: &ProgramSettings, on_event: F) {
+        self.hash_changed(settings, search::find_images(settings), on_event);
+    }
+
+    /// Hash the images among `paths` that are new or modified since they were last seen.
+    fn hash_changed<F: FnMut(WatchEvent)>(&mut self, settings: &ProgramSettings, paths: Vec<PathBuf>, mut on_event: F) {
+        let changed: Vec<PathBuf> = paths.into_iter()
+            .filter(|path| self.is_changed(path))
+            .collect();
+
+        if changed.is_empty() { return; }
+
+        let (images, errors) = processing::hash_all(settings, changed);
+
+        for err in errors {
+            self.mark_seen(err.path(), None);
+            on_event(WatchEvent::Error(err));
+        }
+
+        for image in images {
+            if let Some((original, similar)) = self.find_similar(&image, settings) {
+                on_event(WatchEvent::Duplicate(original, similar));
+            }
+
+            let idx = self.images.len();
+
+            self.index.insert_image(&image, idx);
+            self.mark_seen(&image.path, Some(idx));
+            self.images.push(Some(image));
+        }
+    }
+
+    /// Like `scan()`, but only look at `paths`, files and directories that changed.
+    fn scan_paths<F: FnMut(WatchEvent)>(&mut self, settings: &ProgramSettings, paths: &[PathBuf], on_event: F) {
+        let (found, errors) = search::find_images_among(settings, &settings.roots, paths);
+
+        for (dir, err) in errors {
+            warn!("Could not search {}: {}", dir.display(), err);
+        }
+
+        self.hash_changed(settings, found, on_event);
+    }
+
+    fn is_changed(&self, path: &Path) -> bool {
+        let modified = img::modified_ms(path).unwrap_or(0);
+
+        self.seen.get(path).is_none_or(|&(seen_modified, _)| seen_modified != modified)
+    }
+
+    fn mark_seen(&mut self, path: &Path, idx: Option<usize>) {
+        let modified = img::modified_ms(path).unwrap_or(0);
+
+        // The earlier version of a modified image shouldn't be matched anymore
+        if let Some(&(_, Some(old_idx))) = self.seen.get(path) {
+            self.images[old_idx] = None;
+        }
+
+        self.seen.insert(path.to_path_buf(), (modified, idx));
+    }
+
+    /// The closest image seen so far that `image` is similar to.
+    fn find_similar(&self, image: &Image, settings: &ProgramSettings) -> Option<(Image, SimilarImage)> {
+        let max_dist = processing::max_dist(settings, image);
+
+        self.index.find_image(image, max_dist).into_iter()
+            .filter_map(|idx| self.images[idx].as_ref())
+            .filter(|original| original.path != image.path && settings.may_match(original, image))
+            .map(|original| (original, original.dist_ratio(image)))
+            .filter(|&(_, dist_ratio)| dist_ratio < settings.threshold)
+            .fold(None, |closest: Option<(&Image, f32)>, (original, dist_ratio)| match closest {
+                Some((_, closest_ratio)) if closest_ratio <= dist_ratio => closest,
+                _ => Some((original, dist_ratio)),
+            })
+            .map(|(original, _)| (
+                original.clone(), 
+                SimilarImage::compared(original, image.clone(), settings.near_threshold)
+            ))
+    }
+}
@@ -0,0 +1,125 @@
+//! `img_dup`'s hashing and comparison for the browser, built for `wasm32-unknown-unknown`
+//! with `wasm-pack`. Images are passed as the bytes of the file, e.g. from a `File` being
+//! uploaded; nothing touches the filesystem. See `README.md`.
+
+use img_dup::config::{HashSettings, Tier};
+use img_dup::exif;
+use img_dup::hash::ImageHash;
+use img_dup::img::Image;
+use img_dup::processing;
+
+use wasm_bindgen::prelude::*;
+
+use std::path::PathBuf;
+
+/// Images to check new ones against, by their hashes.
+#[wasm_bindgen]
+pub struct DuplicateIndex {
+    settings: HashSettings,
+    threshold: f32,
+    near_threshold: f32,
+    images: Vec<Image>,
+}
+
+/// An image in a `DuplicateIndex` that's similar to the one being checked.
+#[wasm_bindgen(getter_with_clone)]
+pub struct Match {
+    /// The name it was added with
+    pub name: String,
+    /// From 0 to 100, independent of the hash size
+    pub similarity: f32,
+    /// How many bits of the hashes differ
+    pub distance: usize,
+    /// `exact`, `near` or `possible`
+    pub tier: String,
+}
+
+#[wasm_bindgen]
+impl DuplicateIndex {
+
+    /// `hashSize` and `threshold` are the same as `--hash-size` and `--threshold`
+    /// on the command line: 8 and 3 by default.
+    #[wasm_bindgen(constructor)]
+    pub fn new(hash_size: Option<u32>, threshold: Option<f32>) -> Result<DuplicateIndex, JsError> {
+        let hash_size = hash_size.unwrap_or(8);
+        let threshold = threshold.unwrap_or(3.0);
+
+        if hash_size == 0 { return Err(JsError::new("hashSize must be at least 1")); }
+        if !(0.0..=100.0).contains(&threshold) { return Err(JsError::new("threshold must be from 0 to 100")); }
+
+        Ok(DuplicateIndex {
+            settings: HashSettings::new(hash_size, false),
+            threshold: threshold / 100.0,
+            near_threshold: 0.01,
+            images: Vec::new(),
+        })
+    }
+
+    /// Hash the image in `data` and add it as `name`.
+    pub fn add(&mut self, name: String, data: &[u8]) -> Result<(), JsError> {
+        let image = self.hash(name, data)?;
+        self.images.push(image);
+
+        Ok(())
+    }
+
+    /// Add an image hashed elsewhere as `name`, e.g. by `hashImage()` or from the `hash`
+    /// of the JSON output, made with the same hash size.
+    #[wasm_bindgen(js_name = addHash)]
+    pub fn add_hash(&mut self, name: String, hash: &str) -> Result<(), JsError> {
+        let hash = ImageHash::from_base64(hash, self.settings.hash_size)
+            .ok_or_else(|| JsError::new("not a hash of this hash size"))?;
+
+        self.images.push(Image::new(PathBuf::from(name), hash, 0, 0, 0));
+
+        Ok(())
+    }
+
+    /// The images that are similar to the image in `data`, closest first.
+    #[wasm_bindgen(js_name = findMatches)]
+    pub fn find_matches(&self, data: &[u8]) -> Result<Vec<Match>, JsError> {
+        let needle = self.hash(String::new(), data)?;
+
+        let mut matches: Vec<(f32, usize, &Image)> = self.images.iter()
+            .map(|image| (image.dist_ratio(&needle), image.dist(&needle), image))
+            .filter(|&(dist_ratio, _, _)| dist_ratio < self.threshold)
+            .collect();
+
+        matches.sort_by(|left, right| left.0.total_cmp(&right.0));
+
+        Ok(matches.into_iter()
+            .map(|(dist_ratio, distance, image)| Match {
+                name: image.path.display().to_string(),
+                similarity: (1.0 - dist_ratio) * 100.0,
+                distance,
+                tier: Tier::of(dist_ratio, self.near_threshold).name().to_string(),
+            })
+            .collect())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.images.len()
+    }
+
+    fn hash(&self, name: String, data: &[u8]) -> Result<Image, JsError> {
+        let image = decode(data)?;
+
+        Ok(Image { path: PathBuf::from(name), ..Image::hash_from_dynamic_image(&image, &self.settings) })
+    }
+}
+
+/// The hash of the image in `data`, as it appears in the JSON output.
+#[wasm_bindgen(js_name = hashImage)]
+pub fn hash_image(data: &[u8], hash_size: Option<u32>) -> Result<String, JsError> {
+    let settings = HashSettings::new(hash_size.unwrap_or(8).max(1), false);
+
+    Ok(Image::hash_from_dynamic_image(&decode(data)?, &settings).hash.to_base64())
+}
+
+/// Upright, like images loaded from files.
+fn decode(data: &[u8]) -> Result<image::DynamicImage, JsError> {
+    let image = image::load_from_memory(data).map_err(|err| JsError::new(&err.to_string()))?;
+
+    Ok(processing::orient(exif::orientation_of(data), image))
+}